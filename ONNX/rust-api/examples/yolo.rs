@@ -6,7 +6,7 @@ use anyhow::Result;
 
 fn main() -> Result<()> {
     // Get device info
-    let info = Model::device_info();
+    let info = Model::device_info()?;
     println!("NPU Device: {}", info.name);
     println!("Total Memory: {:.2} GB", info.total_memory as f64 / 1e9);
     println!("Available: {:.2} GB\n", info.available_memory as f64 / 1e9);
@@ -14,26 +14,46 @@ fn main() -> Result<()> {
     // Load YOLO model
     println!("Loading YOLOv8...");
     let model = Model::load("yolov8n.onnx")?;
-    println!("Model loaded!\n");
+    println!("Model loaded!");
+    println!("Inputs: {:?}", model.input_names());
+    println!("Outputs: {:?}\n", model.output_names());
 
     // Create input (1x3x640x640)
-    let input_size = 1 * 3 * 640 * 640;
+    let input_size = 3 * 640 * 640;
     let data: Vec<f32> = (0..input_size)
         .map(|i| i as f32 / input_size as f32)
         .collect();
 
-    let input = Tensor::new(data, vec![1, 3, 640, 640]);
+    let input = Tensor::named(model.input_names()[0].clone(), data, vec![1, 3, 640, 640])?;
 
     // Run inference on NPU
     println!("Running inference on NPU...");
-    let output = model.run(&input)?;
+    let outputs = model.run(&[input.view()])?;
     println!("Inference complete!\n");
 
-    // Print results
-    println!("Output shape: {:?}", output.shape());
-    println!("First 10 values:");
-    for (i, val) in output.data().iter().take(10).enumerate() {
-        println!("  [{i}] = {val:.6}");
+    // Print results (YOLOv8: "output0" with shape [1, 84, 8400])
+    for output in &outputs {
+        println!("Output '{}' ({:?}) shape: {:?}", output.name(), output.dtype(), output.shape());
+        println!("First 10 values:");
+        for (i, val) in output.to_f32_vec().iter().take(10).enumerate() {
+            println!("  [{i}] = {val:.6}");
+        }
+    }
+
+    // Batch a few frames into one submission
+    let frames: Vec<Tensor> = (0..4)
+        .map(|f| {
+            let data = (0..input_size).map(|i| ((i + f) % 255) as f32 / 255.0).collect();
+            Tensor::new(data, vec![1, 3, 640, 640])
+        })
+        .collect::<Result<_>>()?;
+    let views: Vec<_> = frames.iter().map(|f| [f.view()]).collect();
+    let items: Vec<&[_]> = views.iter().map(|v| v.as_slice()).collect();
+
+    println!("\nRunning batch of {} frames...", frames.len());
+    let batch_outputs = model.run_batch(&items)?;
+    for (f, outputs) in batch_outputs.iter().enumerate() {
+        println!("Frame {}: output shape {:?}", f, outputs[0].shape());
     }
 
     Ok(())
@@ -0,0 +1,41 @@
+// Copyright (c) EVA-OS. All rights reserved.
+// Licensed under the MIT License.
+
+//! Print an ONNX model summary and NPU compatibility report
+//!
+//! Usage: npu-inspect <model.onnx>...
+//!
+//! Exits with status 1 if any model cannot run on the NPU.
+
+use rodox_npu::Model;
+
+fn main() {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: npu-inspect <model.onnx>...");
+        std::process::exit(2);
+    }
+
+    let mut compatible = true;
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("== {} ==", path);
+
+        match Model::inspect(path) {
+            Ok(info) => {
+                println!("{}", info);
+                compatible &= info.is_npu_compatible();
+            }
+            Err(e) => {
+                eprintln!("Error: {:#}", e);
+                compatible = false;
+            }
+        }
+    }
+
+    if !compatible {
+        std::process::exit(1);
+    }
+}
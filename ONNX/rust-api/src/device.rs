@@ -0,0 +1,359 @@
+// Copyright (c) EVA-OS. All rights reserved.
+// Licensed under the MIT License.
+
+//! Safe bindings to the EVA-OS NPU driver (`driver-c-api/eva_npu.h`)
+//!
+//! The driver is a process-wide singleton. Every [`Device`] handle keeps it
+//! initialized; `eva_npu_shutdown` runs when the last handle (including the
+//! ones held by [`DeviceBuffer`]s) is dropped, so buffers never outlive the
+//! device and are always freed.
+
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::ptr::NonNull;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::Result;
+
+use crate::Element;
+
+// Link the driver that exports the eva_npu_* symbols
+use eva_npu_c_api as _;
+
+/// Number of live `Device` handles
+static OPEN_HANDLES: Mutex<usize> = Mutex::new(0);
+
+fn open_handles() -> MutexGuard<'static, usize> {
+    OPEN_HANDLES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Handle to the NPU
+pub struct Device(());
+
+impl Device {
+    /// Initialize the NPU (or join the existing initialization)
+    pub fn open() -> Result<Self> {
+        let mut handles = open_handles();
+        if *handles == 0 && unsafe { eva_npu_init() } != 0 {
+            anyhow::bail!("Failed to initialize NPU");
+        }
+        *handles += 1;
+        Ok(Device(()))
+    }
+
+    /// Device name and memory
+    pub fn info(&self) -> DeviceInfo {
+        unsafe {
+            let name = eva_npu_get_device_name();
+            DeviceInfo {
+                name: if name.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(name).to_string_lossy().into_owned()
+                },
+                total_memory: eva_npu_get_total_memory(),
+                available_memory: eva_npu_get_available_memory(),
+            }
+        }
+    }
+
+    /// Allocate `size` bytes of device memory
+    pub fn alloc(&self, size: usize) -> Result<DeviceBuffer> {
+        if size == 0 {
+            anyhow::bail!("Cannot allocate an empty device buffer");
+        }
+
+        let ptr = unsafe { eva_npu_alloc(size) };
+        match NonNull::new(ptr) {
+            Some(ptr) => Ok(DeviceBuffer { ptr, size, _device: self.clone() }),
+            None => anyhow::bail!("Failed to allocate {} bytes of NPU memory", size),
+        }
+    }
+
+    /// Allocate a buffer holding `data`
+    pub fn upload<T: Element>(&self, data: &[T]) -> Result<DeviceBuffer> {
+        let mut buffer = self.alloc(std::mem::size_of_val(data))?;
+        buffer.write(data)?;
+        Ok(buffer)
+    }
+
+    /// Execute a compiled NPU blob
+    ///
+    /// `inputs` and `outputs` are passed in order; the blob defines what
+    /// each buffer must contain.
+    pub fn execute(&self, blob: &[u8], inputs: &[&DeviceBuffer], outputs: &mut [&mut DeviceBuffer]) -> Result<()> {
+        if blob.is_empty() {
+            anyhow::bail!("Empty NPU blob");
+        }
+
+        let input_ptrs: Vec<*const c_void> = inputs.iter().map(|b| b.ptr.as_ptr() as *const c_void).collect();
+        let mut output_ptrs: Vec<*mut c_void> = outputs.iter().map(|b| b.ptr.as_ptr()).collect();
+
+        let status = unsafe {
+            eva_npu_execute(
+                blob.as_ptr() as *const c_void,
+                blob.len(),
+                input_ptrs.as_ptr(),
+                output_ptrs.as_mut_ptr(),
+                input_ptrs.len(),
+                output_ptrs.len(),
+            )
+        };
+
+        if status != 0 {
+            anyhow::bail!("NPU execution failed ({})", status);
+        }
+        Ok(())
+    }
+}
+
+impl Clone for Device {
+    fn clone(&self) -> Self {
+        *open_handles() += 1;
+        Device(())
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        let mut handles = open_handles();
+        *handles -= 1;
+        if *handles == 0 {
+            unsafe { eva_npu_shutdown() };
+        }
+    }
+}
+
+impl std::fmt::Debug for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Device").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub total_memory: u64,
+    pub available_memory: u64,
+}
+
+/// NPU memory allocation, freed on drop
+pub struct DeviceBuffer {
+    ptr: NonNull<c_void>,
+    size: usize,
+    // Keeps the driver initialized while the buffer exists
+    _device: Device,
+}
+
+// The driver serializes allocation; copies only touch this buffer's memory
+unsafe impl Send for DeviceBuffer {}
+unsafe impl Sync for DeviceBuffer {}
+
+impl DeviceBuffer {
+    /// Size in bytes
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Raw device pointer (for passing to other C APIs)
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.ptr.as_ptr()
+    }
+
+    /// Copy host data to the start of the buffer
+    pub fn write<T: Element>(&mut self, data: &[T]) -> Result<()> {
+        self.write_at(0, data)
+    }
+
+    /// Copy host data into the buffer at a byte offset
+    pub fn write_at<T: Element>(&mut self, offset: usize, data: &[T]) -> Result<()> {
+        let bytes = std::mem::size_of_val(data);
+        self.check_range(offset, bytes)?;
+        if bytes == 0 {
+            return Ok(());
+        }
+
+        let status = unsafe {
+            eva_npu_memcpy_to_device(
+                (self.ptr.as_ptr() as *mut u8).add(offset) as *mut c_void,
+                data.as_ptr() as *const c_void,
+                bytes,
+            )
+        };
+        if status != 0 {
+            anyhow::bail!("Copy to NPU failed ({})", status);
+        }
+        Ok(())
+    }
+
+    /// Copy the start of the buffer into `out`
+    pub fn read<T: Element>(&self, out: &mut [T]) -> Result<()> {
+        self.read_at(0, out)
+    }
+
+    /// Copy from a byte offset of the buffer into `out`
+    pub fn read_at<T: Element>(&self, offset: usize, out: &mut [T]) -> Result<()> {
+        let bytes = std::mem::size_of_val(out);
+        self.check_range(offset, bytes)?;
+        if bytes == 0 {
+            return Ok(());
+        }
+
+        let status = unsafe {
+            eva_npu_memcpy_from_device(
+                out.as_mut_ptr() as *mut c_void,
+                (self.ptr.as_ptr() as *const u8).add(offset) as *const c_void,
+                bytes,
+            )
+        };
+        if status != 0 {
+            anyhow::bail!("Copy from NPU failed ({})", status);
+        }
+        Ok(())
+    }
+
+    /// Copy the whole buffer to a new vector
+    pub fn to_vec<T: Element>(&self) -> Result<Vec<T>> {
+        let elem = std::mem::size_of::<T>();
+        if !self.size.is_multiple_of(elem) {
+            anyhow::bail!("Buffer of {} bytes is not a whole number of {:?} elements", self.size, T::DTYPE);
+        }
+        let mut out = vec![T::default(); self.size / elem];
+        self.read(&mut out)?;
+        Ok(out)
+    }
+
+    fn check_range(&self, offset: usize, bytes: usize) -> Result<()> {
+        match offset.checked_add(bytes) {
+            Some(end) if end <= self.size => Ok(()),
+            _ => anyhow::bail!(
+                "Copy of {} bytes at offset {} exceeds buffer of {} bytes",
+                bytes,
+                offset,
+                self.size
+            ),
+        }
+    }
+}
+
+impl Drop for DeviceBuffer {
+    fn drop(&mut self) {
+        unsafe { eva_npu_free(self.ptr.as_ptr()) };
+    }
+}
+
+impl std::fmt::Debug for DeviceBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceBuffer").field("ptr", &self.ptr).field("size", &self.size).finish()
+    }
+}
+
+// FFI to driver-c-api (must match eva_npu.h, see tests)
+extern "C" {
+    fn eva_npu_init() -> i32;
+    fn eva_npu_shutdown();
+    fn eva_npu_alloc(size: usize) -> *mut c_void;
+    fn eva_npu_free(ptr: *mut c_void);
+    fn eva_npu_memcpy_to_device(dst: *mut c_void, src: *const c_void, size: usize) -> i32;
+    fn eva_npu_memcpy_from_device(dst: *mut c_void, src: *const c_void, size: usize) -> i32;
+    fn eva_npu_execute(
+        blob: *const c_void,
+        blob_size: usize,
+        inputs: *const *const c_void,
+        outputs: *mut *mut c_void,
+        num_inputs: usize,
+        num_outputs: usize,
+    ) -> i32;
+    fn eva_npu_get_total_memory() -> u64;
+    fn eva_npu_get_available_memory() -> u64;
+    fn eva_npu_get_device_name() -> *const c_char;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(name, parameter count)` for every function prototype in a C header
+    fn prototypes(source: &str, prefix: &str) -> Vec<(String, usize)> {
+        let flat = source.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut found = Vec::new();
+        let mut rest = flat.as_str();
+        while let Some(start) = rest.find(prefix) {
+            rest = &rest[start..];
+            let Some(open) = rest.find('(') else { break };
+            let name = &rest[..open];
+            let close = rest[open..].find(')').map(|c| open + c).unwrap_or(rest.len());
+            let params = rest[open + 1..close].trim();
+            let count = match params {
+                "" | "void" => 0,
+                _ => params.split(',').filter(|p| !p.trim().is_empty()).count(),
+            };
+            if name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                found.push((name.to_string(), count));
+            }
+            rest = &rest[open..];
+        }
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    #[test]
+    fn test_bindings_match_header() {
+        let header = include_str!("../../../driver-c-api/eva_npu.h");
+        let source = include_str!("device.rs");
+        let extern_block = &source[source.find("extern \"C\" {").unwrap()..source.find("#[cfg(test)]").unwrap()];
+
+        let expected = prototypes(header, "eva_npu_");
+        let bound = prototypes(extern_block, "eva_npu_");
+        assert!(!expected.is_empty());
+        assert_eq!(bound, expected);
+    }
+
+    #[test]
+    fn test_buffer_roundtrip() {
+        let device = Device::open().unwrap();
+        let mut buffer = device.upload(&[1.0f32, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(buffer.len(), 16);
+
+        buffer.write_at(8, &[7.0f32]).unwrap();
+        assert_eq!(buffer.to_vec::<f32>().unwrap(), vec![1.0, 2.0, 7.0, 4.0]);
+
+        let mut bytes = [0u8; 4];
+        buffer.read_at(12, &mut bytes).unwrap();
+        assert_eq!(f32::from_ne_bytes(bytes), 4.0);
+    }
+
+    #[test]
+    fn test_out_of_bounds_copy_rejected() {
+        let device = Device::open().unwrap();
+        let mut buffer = device.alloc(8).unwrap();
+        assert!(buffer.write(&[0i32; 3]).is_err());
+        assert!(buffer.write_at(usize::MAX, &[0u8]).is_err());
+        assert!(buffer.to_vec::<i64>().is_ok());
+        assert!(device.alloc(0).is_err());
+    }
+
+    #[test]
+    fn test_buffer_keeps_device_alive() {
+        let buffer = {
+            let device = Device::open().unwrap();
+            assert!(!device.info().name.is_empty());
+            device.upload(&[5u8; 32]).unwrap()
+        };
+        assert_eq!(buffer.to_vec::<u8>().unwrap(), vec![5u8; 32]);
+    }
+
+    #[test]
+    fn test_execute() {
+        let device = Device::open().unwrap();
+        let input = device.upload(&[1.0f32; 4]).unwrap();
+        let mut output = device.alloc(16).unwrap();
+        device.execute(b"blob", &[&input], &mut [&mut output]).unwrap();
+        assert!(device.execute(&[], &[&input], &mut [&mut output]).is_err());
+    }
+}
@@ -3,99 +3,201 @@
 
 //! High-level Rust API for Rodox NPU + ONNX Runtime
 //!
-//! ```rust
-//! use rodox_npu::Model;
+//! ```rust,ignore
+//! use rodox_npu::{Model, Tensor};
 //!
 //! let model = Model::load("yolo.onnx")?;
-//! let output = model.run(&input)?;
+//! let input = Tensor::named("images", data, vec![1, 3, 640, 640])?;
+//! let outputs = model.run(&[input.view()])?;
 //! ```
 
+mod device;
+pub mod onnx;
+mod session;
+mod tensor;
+
+pub use device::{Device, DeviceBuffer, DeviceInfo};
+pub use session::{Session, TensorSpec};
+pub use tensor::{strides, DataType, Element, QuantParams, Tensor, TensorView};
+#[cfg(feature = "image")]
+pub use tensor::ImageLayout;
+pub use half::f16;
+pub use onnx::ModelInfo;
+
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
 use std::path::Path;
 use anyhow::{Result, Context};
 
 pub struct Model {
     // Internal ONNX Runtime session
-    session: *mut std::ffi::c_void,
-}
-
-pub struct Tensor {
-    data: Vec<f32>,
-    shape: Vec<usize>,
+    session: *mut c_void,
+    input_names: Vec<String>,
+    output_names: Vec<String>,
 }
 
 impl Model {
+    /// Inspect an ONNX file without loading it
+    ///
+    /// Reports inputs/outputs, opset and the operators the NPU cannot run,
+    /// so incompatible models can be rejected before compilation.
+    pub fn inspect<P: AsRef<Path>>(path: P) -> Result<ModelInfo> {
+        ModelInfo::from_file(path)
+    }
+
     /// Load ONNX model and compile for NPU
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path_str = path.as_ref()
             .to_str()
             .context("Invalid path")?;
+        let c_path = CString::new(path_str).context("Path contains a NUL byte")?;
 
         unsafe {
             // Call C API: npu_load()
-            let model_ptr = npu_load(path_str.as_ptr() as *const i8);
+            let model_ptr = npu_load(c_path.as_ptr());
 
             if model_ptr.is_null() {
-                let err = std::ffi::CStr::from_ptr(npu_get_error())
-                    .to_str()
-                    .unwrap_or("Unknown error");
-                anyhow::bail!("Failed to load model: {}", err);
+                anyhow::bail!("Failed to load model: {}", last_error());
+            }
+
+            // Take ownership right away so the session is freed on any error below
+            let mut model = Self {
+                session: model_ptr,
+                input_names: Vec::new(),
+                output_names: Vec::new(),
+            };
+
+            model.input_names = (0..npu_input_count(model_ptr))
+                .map(|i| io_name(npu_input_name(model_ptr, i), "input", i))
+                .collect();
+            model.output_names = (0..npu_output_count(model_ptr))
+                .map(|i| io_name(npu_output_name(model_ptr, i), "output", i))
+                .collect();
+
+            if model.output_names.is_empty() {
+                anyhow::bail!("Model reports no outputs");
             }
 
-            Ok(Self { session: model_ptr })
+            Ok(model)
         }
     }
 
+    /// Names of the model inputs, in graph order
+    pub fn input_names(&self) -> &[String] {
+        &self.input_names
+    }
+
+    /// Names of the model outputs, in graph order
+    pub fn output_names(&self) -> &[String] {
+        &self.output_names
+    }
+
     /// Run inference on NPU
-    pub fn run(&self, input: &Tensor) -> Result<Tensor> {
+    ///
+    /// Inputs are matched to the model inputs by name; unnamed tensors are
+    /// taken positionally. Input data is passed to the runtime without an
+    /// intermediate copy. Returns one tensor per model output, named after it.
+    pub fn run(&self, inputs: &[TensorView<'_>]) -> Result<Vec<Tensor>> {
+        let ordered = order_inputs(&self.input_names, inputs)?;
+
         unsafe {
-            // Create C tensor
-            let c_tensor = npu_tensor_create(
-                input.data.as_ptr() as *const std::ffi::c_void,
-                input.shape.as_ptr() as *const i64,
-                input.shape.len(),
-                0, // NPU_FLOAT32
-            );
+            // Create C tensors (freed by the guard on every path)
+            let mut c_inputs = Vec::with_capacity(ordered.len());
+            for tensor in &ordered {
+                let shape: Vec<i64> = tensor.shape().iter().map(|&d| d as i64).collect();
+                let ptr = npu_tensor_create(
+                    tensor.as_bytes().as_ptr() as *const c_void,
+                    shape.as_ptr(),
+                    shape.len(),
+                    tensor.dtype().code(),
+                );
 
-            if c_tensor.is_null() {
-                anyhow::bail!("Failed to create input tensor");
+                if ptr.is_null() {
+                    anyhow::bail!("Failed to create input tensor '{}': {}", tensor.name(), last_error());
+                }
+                let c_tensor = CTensor(ptr);
+
+                if let Some(q) = tensor.quant_params() {
+                    if npu_tensor_set_quant_params(ptr, q.scale, q.zero_point) != 0 {
+                        anyhow::bail!("Failed to set quantization for '{}': {}", tensor.name(), last_error());
+                    }
+                }
+                c_inputs.push(c_tensor);
             }
 
+            let input_ptrs: Vec<*const c_void> = c_inputs.iter().map(|t| t.0 as *const c_void).collect();
+            let mut output_ptrs = vec![std::ptr::null_mut(); self.output_names.len()];
+
             // Run inference
-            let output_ptr = npu_run(self.session, c_tensor);
+            let status = npu_run_multi(
+                self.session,
+                input_ptrs.as_ptr(),
+                input_ptrs.len(),
+                output_ptrs.as_mut_ptr(),
+                output_ptrs.len(),
+            );
+
+            // Wrap whatever the runtime handed back before checking the status
+            let c_outputs: Vec<CTensor> = output_ptrs.into_iter().map(CTensor).collect();
 
-            if output_ptr.is_null() {
-                npu_tensor_free(c_tensor);
-                anyhow::bail!("Inference failed");
+            if status != 0 {
+                anyhow::bail!("Inference failed: {}", last_error());
             }
 
-            // Convert output to Rust
-            let data_ptr = npu_tensor_data(output_ptr) as *const f32;
-            let len = 1000; // TODO: get actual size
+            // Convert outputs to Rust
+            self.output_names
+                .iter()
+                .zip(&c_outputs)
+                .map(|(name, output)| output.to_tensor(name))
+                .collect()
+        }
+    }
+
+    /// Run several input sets as one batched submission
+    ///
+    /// Each item holds one tensor per model input (batch dimension first,
+    /// usually 1). Items are packed along the batch dimension, run once, and
+    /// every output is split back so item `i` gets its own outputs.
+    pub fn run_batch(&self, items: &[&[TensorView<'_>]]) -> Result<Vec<Vec<Tensor>>> {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
 
-            let data = std::slice::from_raw_parts(data_ptr, len).to_vec();
+        let ordered = items
+            .iter()
+            .map(|item| order_inputs(&self.input_names, item))
+            .collect::<Result<Vec<_>>>()?;
 
-            npu_tensor_free(c_tensor);
-            npu_tensor_free(output_ptr);
+        // Batch size of each item (all of its inputs must agree)
+        let mut sizes = Vec::with_capacity(ordered.len());
+        for (i, item) in ordered.iter().enumerate() {
+            let size = item.first().and_then(|t| t.shape().first()).copied().unwrap_or(0);
+            if item.iter().any(|t| t.shape().first() != Some(&size)) {
+                anyhow::bail!("Batch item {} has inputs with different batch sizes", i);
+            }
+            sizes.push(size);
+        }
 
-            Ok(Tensor {
-                data,
-                shape: vec![1, len],
+        let packed = (0..self.input_names.len())
+            .map(|j| {
+                let parts: Vec<_> = ordered.iter().map(|item| item[j]).collect();
+                Tensor::concat(&parts).map(|t| t.with_name(self.input_names[j].clone()))
             })
+            .collect::<Result<Vec<_>>>()?;
+        let views: Vec<_> = packed.iter().map(|t| t.view()).collect();
+
+        let mut results = vec![Vec::with_capacity(self.output_names.len()); items.len()];
+        for output in self.run(&views)? {
+            for (result, part) in results.iter_mut().zip(output.split(&sizes)?) {
+                result.push(part);
+            }
         }
+        Ok(results)
     }
 
     /// Get NPU device info
-    pub fn device_info() -> DeviceInfo {
-        unsafe {
-            let info = npu_get_device_info();
-            DeviceInfo {
-                name: std::ffi::CStr::from_ptr(info.name)
-                    .to_string_lossy()
-                    .into_owned(),
-                total_memory: info.total_memory,
-                available_memory: info.available_memory,
-            }
-        }
+    pub fn device_info() -> Result<DeviceInfo> {
+        Ok(Device::open()?.info())
     }
 }
 
@@ -107,42 +209,162 @@ impl Drop for Model {
     }
 }
 
-impl Tensor {
-    pub fn new(data: Vec<f32>, shape: Vec<usize>) -> Self {
-        Self { data, shape }
+/// Owned C tensor, freed on drop
+struct CTensor(*mut c_void);
+
+impl CTensor {
+    /// Copy the tensor out of C memory, checking shape, dtype and size first
+    unsafe fn to_tensor(&self, name: &str) -> Result<Tensor> {
+        if self.0.is_null() {
+            anyhow::bail!("Runtime returned no tensor for output '{}'", name);
+        }
+
+        let ndim = npu_tensor_ndim(self.0);
+        let dims = npu_tensor_shape(self.0);
+        if ndim > 0 && dims.is_null() {
+            anyhow::bail!("Output '{}' has no shape", name);
+        }
+
+        let mut shape = Vec::with_capacity(ndim);
+        for i in 0..ndim {
+            let dim = *dims.add(i);
+            if dim < 0 {
+                anyhow::bail!("Output '{}' has unresolved dimension {} ({})", name, i, dim);
+            }
+            shape.push(dim as usize);
+        }
+
+        let code = npu_tensor_dtype(self.0);
+        let dtype = DataType::from_code(code)
+            .with_context(|| format!("Output '{}' has unsupported dtype {}", name, code))?;
+
+        let len: usize = shape.iter().product();
+        let expected = len * dtype.size_in_bytes();
+        let size = npu_tensor_size(self.0);
+        if size != expected {
+            anyhow::bail!(
+                "Output '{}' holds {} bytes but {:?} shape {:?} needs {}",
+                name,
+                size,
+                dtype,
+                shape,
+                expected
+            );
+        }
+
+        let data_ptr = npu_tensor_data(self.0) as *const u8;
+        if len > 0 && data_ptr.is_null() {
+            anyhow::bail!("Output '{}' has no data", name);
+        }
+        let mut tensor = Tensor::from_raw(name, dtype, data_ptr, shape);
+
+        if dtype.is_quantized() {
+            let (mut scale, mut zero_point) = (0.0f32, 0i32);
+            if npu_tensor_quant_params(self.0, &mut scale, &mut zero_point) == 0 {
+                tensor.set_quant(Some(QuantParams::new(scale, zero_point)));
+            }
+        }
+
+        Ok(tensor)
+    }
+}
+
+impl Drop for CTensor {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { npu_tensor_free(self.0) };
+        }
+    }
+}
+
+/// Match tensors to input names (by name, otherwise by position)
+pub(crate) fn order_inputs<'a>(names: &[String], inputs: &[TensorView<'a>]) -> Result<Vec<TensorView<'a>>> {
+    if inputs.len() != names.len() {
+        anyhow::bail!("Model expects {} input(s), got {}", names.len(), inputs.len());
     }
 
-    pub fn data(&self) -> &[f32] {
-        &self.data
+    let mut ordered = Vec::with_capacity(inputs.len());
+    for (i, expected) in names.iter().enumerate() {
+        let tensor = match inputs.iter().find(|t| t.name() == expected) {
+            Some(t) => *t,
+            None if inputs[i].name().is_empty() => inputs[i],
+            None => anyhow::bail!("Missing input '{}'", expected),
+        };
+        tensor.validate()?;
+        ordered.push(tensor);
     }
 
-    pub fn shape(&self) -> &[usize] {
-        &self.shape
+    Ok(ordered)
+}
+
+/// Last error reported by the C API
+unsafe fn last_error() -> String {
+    let err = npu_get_error();
+    if err.is_null() {
+        "Unknown error".to_string()
+    } else {
+        CStr::from_ptr(err).to_string_lossy().into_owned()
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DeviceInfo {
-    pub name: String,
-    pub total_memory: u64,
-    pub available_memory: u64,
+/// Input/output name from the C API, with a positional fallback
+unsafe fn io_name(ptr: *const c_char, kind: &str, index: usize) -> String {
+    if ptr.is_null() {
+        format!("{}_{}", kind, index)
+    } else {
+        CStr::from_ptr(ptr).to_string_lossy().into_owned()
+    }
 }
 
-// FFI to C API
+// FFI to the ONNX Runtime simple C API (npu_api.cc)
+//
+// Only resolved when a `Model` is actually loaded, so the `onnxruntime`
+// feature is needed just by binaries that call `Model::load`.
+#[cfg_attr(feature = "onnxruntime", link(name = "onnxruntime"))]
 extern "C" {
-    fn npu_load(path: *const i8) -> *mut std::ffi::c_void;
-    fn npu_run(model: *mut std::ffi::c_void, input: *mut std::ffi::c_void) -> *mut std::ffi::c_void;
-    fn npu_free(model: *mut std::ffi::c_void);
-    fn npu_tensor_create(data: *const std::ffi::c_void, shape: *const i64, ndim: usize, dtype: i32) -> *mut std::ffi::c_void;
-    fn npu_tensor_free(tensor: *mut std::ffi::c_void);
-    fn npu_tensor_data(tensor: *const std::ffi::c_void) -> *const std::ffi::c_void;
-    fn npu_get_error() -> *const i8;
-    fn npu_get_device_info() -> CDeviceInfo;
+    fn npu_load(path: *const c_char) -> *mut c_void;
+    fn npu_free(model: *mut c_void);
+    fn npu_input_count(model: *const c_void) -> usize;
+    fn npu_input_name(model: *const c_void, index: usize) -> *const c_char;
+    fn npu_output_count(model: *const c_void) -> usize;
+    fn npu_output_name(model: *const c_void, index: usize) -> *const c_char;
+    fn npu_run_multi(
+        model: *mut c_void,
+        inputs: *const *const c_void,
+        num_inputs: usize,
+        outputs: *mut *mut c_void,
+        num_outputs: usize,
+    ) -> i32;
+    fn npu_tensor_create(data: *const c_void, shape: *const i64, ndim: usize, dtype: i32) -> *mut c_void;
+    fn npu_tensor_free(tensor: *mut c_void);
+    fn npu_tensor_data(tensor: *const c_void) -> *const c_void;
+    fn npu_tensor_ndim(tensor: *const c_void) -> usize;
+    fn npu_tensor_shape(tensor: *const c_void) -> *const i64;
+    fn npu_tensor_dtype(tensor: *const c_void) -> i32;
+    fn npu_tensor_size(tensor: *const c_void) -> usize;
+    fn npu_tensor_set_quant_params(tensor: *mut c_void, scale: f32, zero_point: i32) -> i32;
+    fn npu_tensor_quant_params(tensor: *const c_void, scale: *mut f32, zero_point: *mut i32) -> i32;
+    fn npu_get_error() -> *const c_char;
 }
 
-#[repr(C)]
-struct CDeviceInfo {
-    name: *const i8,
-    total_memory: u64,
-    available_memory: u64,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_tensor() {
+        let t = Tensor::named("images", vec![0.0; 6], vec![1, 2, 3]).unwrap();
+        assert_eq!(t.name(), "images");
+        assert_eq!(t.num_elements(), 6);
+        assert!(t.view().validate().is_ok());
+    }
+
+    #[test]
+    fn test_shape_mismatch_rejected() {
+        assert!(Tensor::new(vec![0.0; 5], vec![2, 3]).is_err());
+
+        let shape = [2, 3];
+        let view = TensorView::new(&[0.0f32; 5], &shape);
+        assert!(view.validate().is_err());
+    }
 }
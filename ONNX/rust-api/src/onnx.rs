@@ -0,0 +1,592 @@
+// Copyright (c) EVA-OS. All rights reserved.
+// Licensed under the MIT License.
+
+//! ONNX model inspection (pure Rust)
+//!
+//! Reads the `ModelProto` protobuf directly, without ONNX Runtime, so models
+//! can be checked against the NPU backend before `Model::load` hands them to
+//! the device. Only the fields needed for the report are decoded; everything
+//! else (weights included) is skipped.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::DataType;
+
+/// Operators the Rodox NPU execution provider can compile
+pub const SUPPORTED_OPS: &[&str] = &[
+    // Conv
+    "Conv", "ConvTranspose",
+    // Activation
+    "Relu", "LeakyRelu", "Sigmoid", "Tanh",
+    // Pooling
+    "MaxPool", "AveragePool", "GlobalAveragePool",
+    // Normalization
+    "BatchNormalization",
+    // Linear
+    "Gemm", "MatMul",
+    // Elementwise
+    "Add", "Sub", "Mul", "Div",
+    // Manipulation
+    "Concat", "Split", "Reshape", "Transpose",
+    // Special
+    "Resize", "Upsample", "Clip", "Pad", "Softmax",
+];
+
+/// Default-domain opset versions the NPU compiler is tested with
+pub const SUPPORTED_OPSETS: RangeInclusive<i64> = 11..=17;
+
+/// One dimension of a tensor shape
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dim {
+    /// Fixed size
+    Fixed(i64),
+    /// Named dynamic dimension (e.g. `batch`)
+    Symbolic(String),
+    /// Dynamic dimension without a name
+    Unknown,
+}
+
+impl fmt::Display for Dim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dim::Fixed(v) => write!(f, "{}", v),
+            Dim::Symbolic(name) => write!(f, "{}", name),
+            Dim::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+/// Graph input or output
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueInfo {
+    pub name: String,
+    /// ONNX `TensorProto.DataType` code (0 if not a tensor)
+    pub elem_type: i32,
+    /// Shape, `None` if the model leaves the rank unspecified
+    pub shape: Option<Vec<Dim>>,
+}
+
+impl ValueInfo {
+    /// Matching NPU dtype, if the element type is one the NPU handles
+    pub fn dtype(&self) -> Option<DataType> {
+        match self.elem_type {
+            1 => Some(DataType::F32),
+            2 => Some(DataType::U8),
+            3 => Some(DataType::I8),
+            6 => Some(DataType::I32),
+            7 => Some(DataType::I64),
+            10 => Some(DataType::F16),
+            _ => None,
+        }
+    }
+
+    /// ONNX name of the element type
+    pub fn type_name(&self) -> &'static str {
+        match self.elem_type {
+            1 => "float32",
+            2 => "uint8",
+            3 => "int8",
+            4 => "uint16",
+            5 => "int16",
+            6 => "int32",
+            7 => "int64",
+            8 => "string",
+            9 => "bool",
+            10 => "float16",
+            11 => "float64",
+            12 => "uint32",
+            13 => "uint64",
+            16 => "bfloat16",
+            _ => "unknown",
+        }
+    }
+
+    /// Whether every dimension is fixed
+    pub fn is_static(&self) -> bool {
+        self.shape
+            .as_ref()
+            .map(|dims| dims.iter().all(|d| matches!(d, Dim::Fixed(_))))
+            .unwrap_or(false)
+    }
+}
+
+impl fmt::Display for ValueInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.type_name())?;
+        match &self.shape {
+            Some(dims) => {
+                let dims: Vec<String> = dims.iter().map(|d| d.to_string()).collect();
+                write!(f, "[{}]", dims.join(", "))
+            }
+            None => write!(f, "[?]"),
+        }
+    }
+}
+
+/// Summary of an ONNX model
+#[derive(Debug, Clone, Default)]
+pub struct ModelInfo {
+    pub ir_version: i64,
+    pub producer: String,
+    /// `(domain, version)` for every imported opset (`""` = default domain)
+    pub opsets: Vec<(String, i64)>,
+    pub inputs: Vec<ValueInfo>,
+    pub outputs: Vec<ValueInfo>,
+    /// Operator counts, keyed by `op_type` (prefixed with `domain.` outside the default domain)
+    pub op_histogram: BTreeMap<String, usize>,
+    /// Number of weight tensors
+    pub initializer_count: usize,
+}
+
+impl ModelInfo {
+    /// Parse an ONNX file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_bytes(&bytes).with_context(|| format!("{} is not a valid ONNX model", path.display()))
+    }
+
+    /// Parse a serialized `ModelProto`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut info = ModelInfo::default();
+        let mut graph = None;
+
+        for field in Reader::new(bytes) {
+            let field = field?;
+            match (field.number, field.value) {
+                (1, Value::Varint(v)) => info.ir_version = v as i64,
+                (2, Value::Bytes(b)) => info.producer = string(b),
+                (7, Value::Bytes(b)) => graph = Some(b),
+                (8, Value::Bytes(b)) => info.opsets.push(parse_opset(b)?),
+                _ => {}
+            }
+        }
+
+        let graph = graph.context("Model has no graph")?;
+        let mut initializers = Vec::new();
+        parse_graph(graph, &mut info, &mut initializers, true)?;
+
+        // Older exporters list weights as graph inputs too
+        info.inputs.retain(|i| !initializers.contains(&i.name));
+        info.initializer_count = initializers.len();
+
+        Ok(info)
+    }
+
+    /// Version of the default (`ai.onnx`) opset
+    pub fn opset_version(&self) -> Option<i64> {
+        self.opsets
+            .iter()
+            .find(|(domain, _)| domain.is_empty() || domain == "ai.onnx")
+            .map(|(_, v)| *v)
+    }
+
+    /// Total number of nodes, including nested subgraphs
+    pub fn node_count(&self) -> usize {
+        self.op_histogram.values().sum()
+    }
+
+    /// Operators the NPU backend cannot run
+    pub fn unsupported_ops(&self) -> Vec<&str> {
+        self.op_histogram
+            .keys()
+            .map(|op| op.as_str())
+            .filter(|op| !SUPPORTED_OPS.contains(op))
+            .collect()
+    }
+
+    /// Problems that would make the model fail on the NPU
+    pub fn npu_issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+
+        match self.opset_version() {
+            Some(v) if !SUPPORTED_OPSETS.contains(&v) => issues.push(format!(
+                "opset {} is outside the supported range {}-{}",
+                v,
+                SUPPORTED_OPSETS.start(),
+                SUPPORTED_OPSETS.end()
+            )),
+            None => issues.push("model does not import the default ONNX opset".to_string()),
+            _ => {}
+        }
+
+        for op in self.unsupported_ops() {
+            issues.push(format!("operator {} ({}x) is not supported", op, self.op_histogram[op]));
+        }
+
+        for value in self.inputs.iter().chain(&self.outputs) {
+            if value.dtype().is_none() {
+                issues.push(format!("{} uses unsupported type {}", value.name, value.type_name()));
+            }
+        }
+
+        issues
+    }
+
+    /// Whether the whole model can run on the NPU
+    pub fn is_npu_compatible(&self) -> bool {
+        self.npu_issues().is_empty()
+    }
+}
+
+impl fmt::Display for ModelInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "IR version: {}", self.ir_version)?;
+        if !self.producer.is_empty() {
+            writeln!(f, "Producer:   {}", self.producer)?;
+        }
+        for (domain, version) in &self.opsets {
+            let domain = if domain.is_empty() { "ai.onnx" } else { domain };
+            writeln!(f, "Opset:      {} v{}", domain, version)?;
+        }
+        writeln!(f, "Nodes:      {} ({} initializers)", self.node_count(), self.initializer_count)?;
+
+        writeln!(f, "\nInputs:")?;
+        for input in &self.inputs {
+            writeln!(f, "  {}", input)?;
+        }
+        writeln!(f, "Outputs:")?;
+        for output in &self.outputs {
+            writeln!(f, "  {}", output)?;
+        }
+
+        writeln!(f, "\nOperators:")?;
+        let mut ops: Vec<_> = self.op_histogram.iter().collect();
+        ops.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (op, count) in ops {
+            let mark = if SUPPORTED_OPS.contains(&op.as_str()) { "✅" } else { "❌" };
+            writeln!(f, "  {} {:<24} {}", mark, op, count)?;
+        }
+
+        let issues = self.npu_issues();
+        if issues.is_empty() {
+            write!(f, "\nNPU: compatible")
+        } else {
+            writeln!(f, "\nNPU: not compatible")?;
+            for issue in &issues {
+                writeln!(f, "  - {}", issue)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn parse_opset(bytes: &[u8]) -> Result<(String, i64)> {
+    let (mut domain, mut version) = (String::new(), 0);
+    for field in Reader::new(bytes) {
+        let field = field?;
+        match (field.number, field.value) {
+            (1, Value::Bytes(b)) => domain = string(b),
+            (2, Value::Varint(v)) => version = v as i64,
+            _ => {}
+        }
+    }
+    Ok((domain, version))
+}
+
+/// Collect nodes, I/O and initializer names from a `GraphProto`
+///
+/// Inputs/outputs are only taken from the top-level graph; nested graphs
+/// (`If`/`Loop`/`Scan` bodies) only contribute to the operator histogram.
+fn parse_graph(bytes: &[u8], info: &mut ModelInfo, initializers: &mut Vec<String>, top_level: bool) -> Result<()> {
+    for field in Reader::new(bytes) {
+        let field = field?;
+        match (field.number, field.value) {
+            (1, Value::Bytes(b)) => parse_node(b, info, initializers)?,
+            (5, Value::Bytes(b)) if top_level => initializers.push(parse_initializer_name(b)?),
+            (11, Value::Bytes(b)) if top_level => info.inputs.push(parse_value_info(b)?),
+            (12, Value::Bytes(b)) if top_level => info.outputs.push(parse_value_info(b)?),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_node(bytes: &[u8], info: &mut ModelInfo, initializers: &mut Vec<String>) -> Result<()> {
+    let (mut op_type, mut domain) = (String::new(), String::new());
+    for field in Reader::new(bytes) {
+        let field = field?;
+        match (field.number, field.value) {
+            (4, Value::Bytes(b)) => op_type = string(b),
+            (7, Value::Bytes(b)) => domain = string(b),
+            // AttributeProto: g = 6, graphs = 11
+            (5, Value::Bytes(attr)) => {
+                for attr_field in Reader::new(attr) {
+                    let attr_field = attr_field?;
+                    if let (6 | 11, Value::Bytes(g)) = (attr_field.number, attr_field.value) {
+                        parse_graph(g, info, initializers, false)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let key = if domain.is_empty() || domain == "ai.onnx" {
+        op_type
+    } else {
+        format!("{}.{}", domain, op_type)
+    };
+    *info.op_histogram.entry(key).or_insert(0) += 1;
+    Ok(())
+}
+
+fn parse_initializer_name(bytes: &[u8]) -> Result<String> {
+    for field in Reader::new(bytes) {
+        let field = field?;
+        if let (8, Value::Bytes(b)) = (field.number, field.value) {
+            return Ok(string(b));
+        }
+    }
+    Ok(String::new())
+}
+
+fn parse_value_info(bytes: &[u8]) -> Result<ValueInfo> {
+    let mut value = ValueInfo { name: String::new(), elem_type: 0, shape: None };
+    for field in Reader::new(bytes) {
+        let field = field?;
+        match (field.number, field.value) {
+            (1, Value::Bytes(b)) => value.name = string(b),
+            // TypeProto.tensor_type = 1
+            (2, Value::Bytes(type_proto)) => {
+                for t in Reader::new(type_proto) {
+                    let t = t?;
+                    if let (1, Value::Bytes(tensor_type)) = (t.number, t.value) {
+                        parse_tensor_type(tensor_type, &mut value)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(value)
+}
+
+fn parse_tensor_type(bytes: &[u8], value: &mut ValueInfo) -> Result<()> {
+    for field in Reader::new(bytes) {
+        let field = field?;
+        match (field.number, field.value) {
+            (1, Value::Varint(v)) => value.elem_type = v as i32,
+            (2, Value::Bytes(shape)) => {
+                let mut dims = Vec::new();
+                for dim in Reader::new(shape) {
+                    let dim = dim?;
+                    if let (1, Value::Bytes(d)) = (dim.number, dim.value) {
+                        dims.push(parse_dim(d)?);
+                    }
+                }
+                value.shape = Some(dims);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_dim(bytes: &[u8]) -> Result<Dim> {
+    let mut dim = Dim::Unknown;
+    for field in Reader::new(bytes) {
+        let field = field?;
+        match (field.number, field.value) {
+            (1, Value::Varint(v)) => dim = Dim::Fixed(v as i64),
+            (2, Value::Bytes(b)) => dim = Dim::Symbolic(string(b)),
+            _ => {}
+        }
+    }
+    Ok(dim)
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Protobuf field value (only the wire types ONNX uses)
+#[derive(Debug)]
+enum Value<'a> {
+    Varint(u64),
+    Fixed64,
+    Bytes(&'a [u8]),
+    Fixed32,
+}
+
+#[derive(Debug)]
+struct Field<'a> {
+    number: u64,
+    value: Value<'a>,
+}
+
+/// Iterator over the fields of one protobuf message
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    failed: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0, failed: false }
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.pos).context("Truncated varint")?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("Varint too long")
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&e| e <= self.buf.len()).context("Truncated field")?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn field(&mut self) -> Result<Field<'a>> {
+        let key = self.varint()?;
+        let number = key >> 3;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed64
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed32
+            }
+            wire => anyhow::bail!("Unsupported protobuf wire type {}", wire),
+        };
+        Ok(Field { number, value })
+    }
+}
+
+impl<'a> Iterator for Reader<'a> {
+    type Item = Result<Field<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.pos >= self.buf.len() {
+            return None;
+        }
+        let field = self.field();
+        self.failed = field.is_err();
+        Some(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Minimal protobuf encoder for building test models
+    fn varint(mut v: u64, out: &mut Vec<u8>) {
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn int_field(number: u64, v: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint(number << 3, &mut out);
+        varint(v, &mut out);
+        out
+    }
+
+    fn bytes_field(number: u64, bytes: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        varint((number << 3) | 2, &mut out);
+        varint(bytes.len() as u64, &mut out);
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    fn value_info(name: &str, elem_type: u64, dims: &[Result<i64, &str>]) -> Vec<u8> {
+        let mut shape = Vec::new();
+        for d in dims {
+            let dim = match d {
+                Ok(v) => int_field(1, *v as u64),
+                Err(p) => bytes_field(2, p.as_bytes()),
+            };
+            shape.extend(bytes_field(1, &dim));
+        }
+        let tensor_type = [int_field(1, elem_type), bytes_field(2, &shape)].concat();
+        let type_proto = bytes_field(1, &tensor_type);
+        [bytes_field(1, name.as_bytes()), bytes_field(2, &type_proto)].concat()
+    }
+
+    fn node(op: &str) -> Vec<u8> {
+        bytes_field(4, op.as_bytes())
+    }
+
+    fn test_model(ops: &[&str], opset: u64) -> Vec<u8> {
+        let mut graph = Vec::new();
+        for op in ops {
+            graph.extend(bytes_field(1, &node(op)));
+        }
+        graph.extend(bytes_field(5, &bytes_field(8, b"conv.weight")));
+        graph.extend(bytes_field(11, &value_info("images", 1, &[Err("batch"), Ok(3), Ok(640), Ok(640)])));
+        graph.extend(bytes_field(11, &value_info("conv.weight", 1, &[Ok(16), Ok(3), Ok(3), Ok(3)])));
+        graph.extend(bytes_field(12, &value_info("output0", 1, &[Ok(1), Ok(84), Ok(8400)])));
+
+        let opset_import = [bytes_field(1, b""), int_field(2, opset)].concat();
+        [
+            int_field(1, 8),
+            bytes_field(2, b"pytorch"),
+            bytes_field(7, &graph),
+            bytes_field(8, &opset_import),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_parse_model() {
+        let info = ModelInfo::from_bytes(&test_model(&["Conv", "Relu", "Conv"], 13)).unwrap();
+
+        assert_eq!(info.ir_version, 8);
+        assert_eq!(info.producer, "pytorch");
+        assert_eq!(info.opset_version(), Some(13));
+        assert_eq!(info.op_histogram["Conv"], 2);
+        assert_eq!(info.node_count(), 3);
+
+        // Initializer listed as input is dropped
+        assert_eq!(info.inputs.len(), 1);
+        assert_eq!(info.inputs[0].shape.as_ref().unwrap()[0], Dim::Symbolic("batch".into()));
+        assert!(!info.inputs[0].is_static());
+        assert_eq!(info.outputs[0].to_string(), "output0: float32[1, 84, 8400]");
+        assert_eq!(info.outputs[0].dtype(), Some(DataType::F32));
+        assert!(info.is_npu_compatible());
+    }
+
+    #[test]
+    fn test_unsupported_ops_reported() {
+        let info = ModelInfo::from_bytes(&test_model(&["Conv", "NonMaxSuppression"], 19)).unwrap();
+
+        assert_eq!(info.unsupported_ops(), vec!["NonMaxSuppression"]);
+        let issues = info.npu_issues();
+        assert_eq!(issues.len(), 2); // opset 19 + NMS
+        assert!(info.to_string().contains("NPU: not compatible"));
+    }
+
+    #[test]
+    fn test_truncated_model_rejected() {
+        let bytes = test_model(&["Conv"], 13);
+        assert!(ModelInfo::from_bytes(&bytes[..bytes.len() - 3]).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+// Copyright (c) EVA-OS. All rights reserved.
+// Licensed under the MIT License.
+
+//! Pipelined inference on pre-allocated device buffers
+//!
+//! A [`Session`] owns two sets of input/output [`DeviceBuffer`]s and a worker
+//! thread that submits them to the NPU. While frame N executes on one set,
+//! the caller prepares frame N+1 and uploads it into the other, so host
+//! preprocessing overlaps device execution and nothing is allocated per frame.
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use anyhow::{Context, Result};
+
+use crate::{order_inputs, DataType, Device, DeviceBuffer, QuantParams, Tensor, TensorView};
+
+/// Number of buffer sets (double buffering)
+const PIPELINE_DEPTH: usize = 2;
+
+/// Runs one frame on the worker thread
+type Execute = Box<dyn FnMut(&[&DeviceBuffer], &mut [&mut DeviceBuffer]) -> Result<()> + Send>;
+
+/// Name, dtype and fixed shape of a compiled model input or output
+#[derive(Debug, Clone, PartialEq)]
+pub struct TensorSpec {
+    pub name: String,
+    pub dtype: DataType,
+    pub shape: Vec<usize>,
+    /// Quantization of INT8/UINT8 tensors, attached to outputs read back
+    pub quant: Option<QuantParams>,
+}
+
+impl TensorSpec {
+    pub fn new(name: impl Into<String>, dtype: DataType, shape: Vec<usize>) -> Self {
+        Self { name: name.into(), dtype, shape, quant: None }
+    }
+
+    /// Attach quantization parameters (INT8/UINT8 tensors only)
+    pub fn with_quant_params(mut self, params: QuantParams) -> Result<Self> {
+        if !self.dtype.is_quantized() {
+            anyhow::bail!("Quantization parameters need an INT8/UINT8 tensor, '{}' is {:?}", self.name, self.dtype);
+        }
+        self.quant = Some(params);
+        Ok(self)
+    }
+
+    /// Size of the tensor in bytes
+    pub fn byte_len(&self) -> usize {
+        self.shape.iter().product::<usize>() * self.dtype.size_in_bytes()
+    }
+}
+
+/// One set of device buffers
+struct Slot {
+    inputs: Vec<DeviceBuffer>,
+    outputs: Vec<DeviceBuffer>,
+}
+
+/// Double-buffered NPU session for streams of frames
+///
+/// ```rust,ignore
+/// let mut session = Session::new(&device, blob, inputs, outputs)?;
+/// for frame in frames {
+///     let input = preprocess(frame);       // overlaps the previous frame
+///     session.submit(&[input.view()])?;
+///     if session.pending() > 1 {
+///         handle(session.recv()?);
+///     }
+/// }
+/// while session.pending() > 0 {
+///     handle(session.recv()?);
+/// }
+/// ```
+pub struct Session {
+    inputs: Vec<TensorSpec>,
+    input_names: Vec<String>,
+    outputs: Vec<TensorSpec>,
+    free: Vec<Slot>,
+    /// Results collected early because `submit` needed their buffers
+    completed: VecDeque<Result<Vec<Tensor>>>,
+    in_flight: usize,
+    jobs: Option<Sender<Slot>>,
+    done: Receiver<(Slot, Result<()>)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Session {
+    /// Allocate buffers for a compiled NPU blob and start the worker
+    pub fn new(device: &Device, blob: Vec<u8>, inputs: Vec<TensorSpec>, outputs: Vec<TensorSpec>) -> Result<Self> {
+        if blob.is_empty() {
+            anyhow::bail!("Empty NPU blob");
+        }
+        let blob: Arc<[u8]> = blob.into();
+        let worker_device = device.clone();
+        let execute = move |inputs: &[&DeviceBuffer], outputs: &mut [&mut DeviceBuffer]| {
+            worker_device.execute(&blob, inputs, outputs)
+        };
+        Self::with_executor(device, inputs, outputs, Box::new(execute))
+    }
+
+    fn with_executor(device: &Device, inputs: Vec<TensorSpec>, outputs: Vec<TensorSpec>, mut execute: Execute) -> Result<Self> {
+        if outputs.is_empty() {
+            anyhow::bail!("Session needs at least one output");
+        }
+
+        let alloc = |specs: &[TensorSpec]| -> Result<Vec<DeviceBuffer>> {
+            specs
+                .iter()
+                .map(|spec| {
+                    device
+                        .alloc(spec.byte_len())
+                        .with_context(|| format!("Failed to allocate buffer for '{}'", spec.name))
+                })
+                .collect()
+        };
+        let free = (0..PIPELINE_DEPTH)
+            .map(|_| Ok(Slot { inputs: alloc(&inputs)?, outputs: alloc(&outputs)? }))
+            .collect::<Result<Vec<_>>>()?;
+
+        let (jobs, job_rx) = mpsc::channel::<Slot>();
+        let (done_tx, done) = mpsc::channel();
+
+        let worker = std::thread::Builder::new()
+            .name("npu-session".to_string())
+            .spawn(move || {
+                for mut slot in job_rx {
+                    let inputs: Vec<&DeviceBuffer> = slot.inputs.iter().collect();
+                    let mut outputs: Vec<&mut DeviceBuffer> = slot.outputs.iter_mut().collect();
+                    let status = execute(&inputs, &mut outputs);
+                    if done_tx.send((slot, status)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .context("Failed to start NPU worker")?;
+
+        Ok(Self {
+            input_names: inputs.iter().map(|s| s.name.clone()).collect(),
+            inputs,
+            outputs,
+            free,
+            completed: VecDeque::new(),
+            in_flight: 0,
+            jobs: Some(jobs),
+            done,
+            worker: Some(worker),
+        })
+    }
+
+    pub fn input_specs(&self) -> &[TensorSpec] {
+        &self.inputs
+    }
+
+    pub fn output_specs(&self) -> &[TensorSpec] {
+        &self.outputs
+    }
+
+    /// Frames submitted but not yet returned by `recv`
+    pub fn pending(&self) -> usize {
+        self.in_flight + self.completed.len()
+    }
+
+    /// Upload a frame and queue it for execution
+    ///
+    /// Returns as soon as the inputs are on the device. If both buffer sets
+    /// are busy, waits for the oldest frame and keeps its outputs for `recv`.
+    pub fn submit(&mut self, inputs: &[TensorView<'_>]) -> Result<()> {
+        let ordered = order_inputs(&self.input_names, inputs)?;
+        for (tensor, spec) in ordered.iter().zip(&self.inputs) {
+            if tensor.dtype() != spec.dtype || tensor.shape() != spec.shape.as_slice() {
+                anyhow::bail!(
+                    "Input '{}' must be {:?}{:?}, got {:?}{:?}",
+                    spec.name,
+                    spec.dtype,
+                    spec.shape,
+                    tensor.dtype(),
+                    tensor.shape()
+                );
+            }
+        }
+
+        if self.free.is_empty() {
+            let result = self.wait()?;
+            self.completed.push_back(result);
+        }
+        let mut slot = self.free.pop().context("No free buffers")?;
+
+        for (buffer, tensor) in slot.inputs.iter_mut().zip(&ordered) {
+            if let Err(e) = buffer.write(tensor.as_bytes()) {
+                self.free.push(slot);
+                return Err(e);
+            }
+        }
+
+        self.jobs
+            .as_ref()
+            .context("Session is closed")?
+            .send(slot)
+            .map_err(|_| anyhow::anyhow!("NPU worker stopped"))?;
+        self.in_flight += 1;
+        Ok(())
+    }
+
+    /// Outputs of the oldest submitted frame, waiting for it if needed
+    pub fn recv(&mut self) -> Result<Vec<Tensor>> {
+        if let Some(result) = self.completed.pop_front() {
+            return result;
+        }
+        if self.in_flight == 0 {
+            anyhow::bail!("No frames submitted");
+        }
+        self.wait()?
+    }
+
+    /// Submit one frame and wait for its outputs
+    pub fn run(&mut self, inputs: &[TensorView<'_>]) -> Result<Vec<Tensor>> {
+        if self.pending() > 0 {
+            anyhow::bail!("Session has {} frame(s) pending; recv them first", self.pending());
+        }
+        self.submit(inputs)?;
+        self.recv()
+    }
+
+    /// Process a stream, preparing each frame while the previous one executes
+    ///
+    /// `prepare` turns a frame into model inputs; `handle` receives the
+    /// outputs in frame order.
+    pub fn run_stream<T, P, H>(&mut self, frames: impl IntoIterator<Item = T>, mut prepare: P, mut handle: H) -> Result<()>
+    where
+        P: FnMut(T) -> Result<Vec<Tensor>>,
+        H: FnMut(Vec<Tensor>) -> Result<()>,
+    {
+        for frame in frames {
+            let inputs = prepare(frame)?;
+            let views: Vec<_> = inputs.iter().map(|t| t.view()).collect();
+            self.submit(&views)?;
+            while self.pending() >= PIPELINE_DEPTH {
+                handle(self.recv()?)?;
+            }
+        }
+        while self.pending() > 0 {
+            handle(self.recv()?)?;
+        }
+        Ok(())
+    }
+
+    /// Wait for the worker to return a slot and read its outputs
+    ///
+    /// The outer error means the worker is gone; the inner one is the frame's.
+    fn wait(&mut self) -> Result<Result<Vec<Tensor>>> {
+        let (slot, status) = self.done.recv().map_err(|_| anyhow::anyhow!("NPU worker stopped"))?;
+        self.in_flight -= 1;
+
+        let result = status.and_then(|_| {
+            slot.outputs
+                .iter()
+                .zip(&self.outputs)
+                .map(|(buffer, spec)| {
+                    let bytes = buffer.to_vec::<u8>()?;
+                    // SAFETY: the buffer was allocated with spec.byte_len() bytes
+                    let mut tensor = unsafe { Tensor::from_raw(&spec.name, spec.dtype, bytes.as_ptr(), spec.shape.clone()) };
+                    tensor.set_quant(spec.quant);
+                    Ok(tensor)
+                })
+                .collect()
+        });
+        self.free.push(slot);
+        Ok(result)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // Closing the job channel stops the worker once in-flight frames finish
+        self.jobs.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let device = Device::open().unwrap();
+        Session::new(
+            &device,
+            b"blob".to_vec(),
+            vec![TensorSpec::new("images", DataType::F32, vec![1, 3, 4, 4])],
+            vec![TensorSpec::new("output0", DataType::F16, vec![1, 10])],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_submit_and_recv_in_order() {
+        let mut session = session();
+        let frames: Vec<Tensor> = (0..5).map(|i| Tensor::new(vec![i as f32; 48], vec![1, 3, 4, 4]).unwrap()).collect();
+
+        for frame in &frames {
+            session.submit(&[frame.view()]).unwrap();
+        }
+        assert_eq!(session.pending(), 5);
+
+        for _ in 0..5 {
+            let outputs = session.recv().unwrap();
+            assert_eq!(outputs[0].name(), "output0");
+            assert_eq!(outputs[0].dtype(), DataType::F16);
+            assert_eq!(outputs[0].shape(), &[1, 10]);
+        }
+        assert_eq!(session.pending(), 0);
+        assert!(session.recv().is_err());
+    }
+
+    #[test]
+    fn test_run_stream() {
+        let mut session = session();
+        let mut handled = 0;
+        session
+            .run_stream(
+                0..6,
+                |i| Ok(vec![Tensor::named("images", vec![i as f32; 48], vec![1, 3, 4, 4])?]),
+                |outputs| {
+                    assert_eq!(outputs.len(), 1);
+                    handled += 1;
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(handled, 6);
+        assert_eq!(session.pending(), 0);
+    }
+
+    /// Session whose "model" copies the input bytes into the output
+    fn echo_session(output: TensorSpec) -> Session {
+        let device = Device::open().unwrap();
+        let echo = |inputs: &[&DeviceBuffer], outputs: &mut [&mut DeviceBuffer]| {
+            let bytes = inputs[0].to_vec::<u8>()?;
+            let len = outputs[0].len().min(bytes.len());
+            outputs[0].write(&bytes[..len])
+        };
+        Session::with_executor(
+            &device,
+            vec![TensorSpec::new("images", DataType::F32, vec![1, 3, 4, 4])],
+            vec![output],
+            Box::new(echo),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_outputs_match_their_frame() {
+        let mut session = echo_session(TensorSpec::new("output0", DataType::F32, vec![1, 48]));
+        let mut seen = Vec::new();
+        session
+            .run_stream(
+                0..7,
+                |i| Ok(vec![Tensor::new(vec![i as f32; 48], vec![1, 3, 4, 4])?]),
+                |outputs| {
+                    let data = outputs[0].data().unwrap();
+                    assert!(data.iter().all(|&v| v == data[0]));
+                    seen.push(data[0]);
+                    Ok(())
+                },
+            )
+            .unwrap();
+        assert_eq!(seen, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn test_quantized_output_keeps_params() {
+        let params = QuantParams::new(0.5, 3);
+        let spec = TensorSpec::new("output0", DataType::U8, vec![1, 8]).with_quant_params(params).unwrap();
+        let mut session = echo_session(spec);
+
+        let input = Tensor::new(vec![0.0; 48], vec![1, 3, 4, 4]).unwrap();
+        let outputs = session.run(&[input.view()]).unwrap();
+        assert_eq!(outputs[0].quant_params(), Some(params));
+        assert_eq!(outputs[0].dequantize().data().unwrap(), &[-1.5; 8]);
+
+        assert!(TensorSpec::new("x", DataType::F32, vec![1]).with_quant_params(params).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_input_rejected() {
+        let mut session = session();
+        let wrong_shape = Tensor::new(vec![0.0; 16], vec![1, 1, 4, 4]).unwrap();
+        assert!(session.submit(&[wrong_shape.view()]).is_err());
+
+        let wrong_dtype = Tensor::zeros(DataType::U8, vec![1, 3, 4, 4]);
+        assert!(session.submit(&[wrong_dtype.view()]).is_err());
+        assert_eq!(session.pending(), 0);
+
+        let ok = Tensor::new(vec![0.0; 48], vec![1, 3, 4, 4]).unwrap();
+        assert!(session.run(&[ok.view()]).is_ok());
+    }
+}
@@ -0,0 +1,845 @@
+// Copyright (c) EVA-OS. All rights reserved.
+// Licensed under the MIT License.
+
+//! Tensors for the Rodox NPU
+//!
+//! A [`Tensor`] owns its data in one of the element types the NPU accepts
+//! (FP32, FP16, INT8/UINT8 quantized, INT32, INT64). A [`TensorView`] borrows
+//! caller memory instead, so large inputs reach the runtime without a copy.
+
+use std::any::{Any, TypeId};
+
+use anyhow::{Context, Result};
+use half::f16;
+
+/// Element type of a tensor
+///
+/// The discriminants are the `NPU_*` dtype codes used by the C API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum DataType {
+    F32 = 0,
+    F16 = 1,
+    I8 = 2,
+    U8 = 3,
+    I32 = 4,
+    I64 = 5,
+}
+
+impl DataType {
+    /// Size of one element in bytes
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            DataType::F32 | DataType::I32 => 4,
+            DataType::F16 => 2,
+            DataType::I8 | DataType::U8 => 1,
+            DataType::I64 => 8,
+        }
+    }
+
+    /// C API dtype code
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Map a C API dtype code back to a type
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(DataType::F32),
+            1 => Some(DataType::F16),
+            2 => Some(DataType::I8),
+            3 => Some(DataType::U8),
+            4 => Some(DataType::I32),
+            5 => Some(DataType::I64),
+            _ => None,
+        }
+    }
+
+    /// Whether values of this type carry quantization parameters
+    pub fn is_quantized(&self) -> bool {
+        matches!(self, DataType::I8 | DataType::U8)
+    }
+}
+
+/// Affine quantization parameters: `real = scale * (q - zero_point)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantParams {
+    pub scale: f32,
+    pub zero_point: i32,
+}
+
+impl QuantParams {
+    pub fn new(scale: f32, zero_point: i32) -> Self {
+        Self { scale, zero_point }
+    }
+
+    /// Parameters covering `[min, max]` with the full range of `dtype`
+    pub fn from_range(min: f32, max: f32, dtype: DataType) -> Result<Self> {
+        let (qmin, qmax) = quant_range(dtype)?;
+        let (min, max) = (min.min(0.0), max.max(0.0));
+        let scale = if max > min { (max - min) / (qmax - qmin) as f32 } else { 1.0 };
+        let zero_point = (qmin as f32 - min / scale).round().clamp(qmin as f32, qmax as f32) as i32;
+        Ok(Self { scale, zero_point })
+    }
+}
+
+fn quant_range(dtype: DataType) -> Result<(i32, i32)> {
+    match dtype {
+        DataType::I8 => Ok((i8::MIN as i32, i8::MAX as i32)),
+        DataType::U8 => Ok((u8::MIN as i32, u8::MAX as i32)),
+        other => anyhow::bail!("{:?} is not a quantized type", other),
+    }
+}
+
+/// Owned, typed tensor storage
+#[derive(Debug, Clone, PartialEq)]
+enum Storage {
+    F32(Vec<f32>),
+    F16(Vec<f16>),
+    I8(Vec<i8>),
+    U8(Vec<u8>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+}
+
+/// Rust element types that map onto a [`DataType`]
+pub trait Element: Copy + Default + 'static + sealed::Sealed {
+    const DTYPE: DataType;
+
+    /// Widen to FP32 (no dequantization)
+    fn to_f32(self) -> f32;
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_element {
+    ($ty:ty, $dtype:ident, $to_f32:expr) => {
+        impl sealed::Sealed for $ty {}
+
+        impl Element for $ty {
+            const DTYPE: DataType = DataType::$dtype;
+
+            fn to_f32(self) -> f32 {
+                let f: fn($ty) -> f32 = $to_f32;
+                f(self)
+            }
+        }
+    };
+}
+
+impl_element!(f32, F32, |v| v);
+impl_element!(f16, F16, |v| v.to_f32());
+impl_element!(i8, I8, |v| v as f32);
+impl_element!(u8, U8, |v| v as f32);
+impl_element!(i32, I32, |v| v as f32);
+impl_element!(i64, I64, |v| v as f32);
+
+impl Storage {
+    fn from_vec<T: Element>(data: Vec<T>) -> Storage {
+        let mut data = Some(data);
+        let any = &mut data as &mut dyn Any;
+
+        macro_rules! take {
+            ($($ty:ty => $variant:ident),*) => {
+                $(if let Some(v) = any.downcast_mut::<Option<Vec<$ty>>>() {
+                    return Storage::$variant(v.take().unwrap_or_default());
+                })*
+            };
+        }
+        take!(f32 => F32, f16 => F16, i8 => I8, u8 => U8, i32 => I32, i64 => I64);
+        unreachable!("Element is only implemented for NPU dtypes")
+    }
+
+    fn as_slice<T: Element>(&self) -> Option<&[T]> {
+        let any: &dyn Any = match self {
+            Storage::F32(v) => v,
+            Storage::F16(v) => v,
+            Storage::I8(v) => v,
+            Storage::U8(v) => v,
+            Storage::I32(v) => v,
+            Storage::I64(v) => v,
+        };
+        any.downcast_ref::<Vec<T>>().map(|v| v.as_slice())
+    }
+}
+
+/// Owned tensor of any supported element type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    name: String,
+    storage: Storage,
+    shape: Vec<usize>,
+    quant: Option<QuantParams>,
+}
+
+impl Tensor {
+    /// Create a FP32 tensor
+    pub fn new(data: Vec<f32>, shape: Vec<usize>) -> Result<Self> {
+        Self::from_vec(data, shape)
+    }
+
+    /// Create a FP32 tensor bound to a named model input
+    pub fn named(name: impl Into<String>, data: Vec<f32>, shape: Vec<usize>) -> Result<Self> {
+        Ok(Self::from_vec(data, shape)?.with_name(name))
+    }
+
+    /// Create a tensor of any element type
+    ///
+    /// Fails if `data` doesn't hold exactly `shape.product()` elements.
+    pub fn from_vec<T: Element>(data: Vec<T>, shape: Vec<usize>) -> Result<Self> {
+        let expected: usize = shape.iter().product();
+        if data.len() != expected {
+            anyhow::bail!("{} values don't fit shape {:?} ({} needed)", data.len(), shape, expected);
+        }
+        Ok(Self::from_vec_unchecked(data, shape))
+    }
+
+    /// `from_vec` for data whose length is known to match the shape
+    fn from_vec_unchecked<T: Element>(data: Vec<T>, shape: Vec<usize>) -> Self {
+        Self {
+            name: String::new(),
+            storage: Storage::from_vec(data),
+            shape,
+            quant: None,
+        }
+    }
+
+    /// Zero-filled tensor
+    pub fn zeros(dtype: DataType, shape: Vec<usize>) -> Self {
+        let len = shape.iter().product();
+        let storage = match dtype {
+            DataType::F32 => Storage::F32(vec![0.0; len]),
+            DataType::F16 => Storage::F16(vec![f16::ZERO; len]),
+            DataType::I8 => Storage::I8(vec![0; len]),
+            DataType::U8 => Storage::U8(vec![0; len]),
+            DataType::I32 => Storage::I32(vec![0; len]),
+            DataType::I64 => Storage::I64(vec![0; len]),
+        };
+        Self { name: String::new(), storage, shape, quant: None }
+    }
+
+    /// Set the model input/output name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Attach quantization parameters (INT8/UINT8 tensors only)
+    pub fn with_quant_params(mut self, params: QuantParams) -> Result<Self> {
+        quant_range(self.dtype())?;
+        self.quant = Some(params);
+        Ok(self)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn dtype(&self) -> DataType {
+        match self.storage {
+            Storage::F32(_) => DataType::F32,
+            Storage::F16(_) => DataType::F16,
+            Storage::I8(_) => DataType::I8,
+            Storage::U8(_) => DataType::U8,
+            Storage::I32(_) => DataType::I32,
+            Storage::I64(_) => DataType::I64,
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn quant_params(&self) -> Option<QuantParams> {
+        self.quant
+    }
+
+    /// FP32 data, if this is a FP32 tensor
+    pub fn data(&self) -> Option<&[f32]> {
+        self.as_slice()
+    }
+
+    /// Typed data, if `T` matches the tensor dtype
+    pub fn as_slice<T: Element>(&self) -> Option<&[T]> {
+        self.storage.as_slice()
+    }
+
+    /// Raw bytes in native endianness
+    pub fn as_bytes(&self) -> &[u8] {
+        self.view().as_bytes()
+    }
+
+    /// Number of elements implied by the shape
+    pub fn num_elements(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    /// Row-major strides, in elements
+    pub fn strides(&self) -> Vec<usize> {
+        strides(&self.shape)
+    }
+
+    /// Same data with a new shape of equal element count
+    pub fn reshape(mut self, shape: Vec<usize>) -> Result<Self> {
+        let len: usize = shape.iter().product();
+        if len != self.num_elements() {
+            anyhow::bail!("Cannot reshape {:?} into {:?}", self.shape, shape);
+        }
+        self.shape = shape;
+        Ok(self)
+    }
+
+    /// Concatenate tensors along the first (batch) dimension
+    ///
+    /// All parts must share dtype, quantization and the trailing dimensions.
+    /// The result takes the name of the first part.
+    pub fn concat(parts: &[TensorView<'_>]) -> Result<Tensor> {
+        let first = parts.first().context("Nothing to concatenate")?;
+        if first.shape().is_empty() {
+            anyhow::bail!("Cannot concatenate scalar tensor '{}'", first.name());
+        }
+
+        let mut bytes = Vec::with_capacity(parts.iter().map(|p| p.as_bytes().len()).sum());
+        let mut batch = 0;
+        for part in parts {
+            part.validate()?;
+            if part.dtype() != first.dtype()
+                || part.quant_params() != first.quant_params()
+                || part.shape().get(1..) != first.shape().get(1..)
+            {
+                anyhow::bail!(
+                    "Cannot concatenate {:?}{:?} with {:?}{:?}",
+                    first.dtype(),
+                    first.shape(),
+                    part.dtype(),
+                    part.shape()
+                );
+            }
+            batch += part.shape()[0];
+            bytes.extend_from_slice(part.as_bytes());
+        }
+
+        let mut shape = first.shape().to_vec();
+        shape[0] = batch;
+        // SAFETY: `bytes` holds exactly `shape.product()` elements of the dtype
+        let mut tensor = unsafe { Tensor::from_raw(first.name(), first.dtype(), bytes.as_ptr(), shape) };
+        tensor.quant = first.quant_params();
+        Ok(tensor)
+    }
+
+    /// Split along the first (batch) dimension into parts of the given sizes
+    pub fn split(&self, sizes: &[usize]) -> Result<Vec<Tensor>> {
+        self.view().validate()?;
+        let batch = self.shape.first().copied().unwrap_or(0);
+        if sizes.iter().sum::<usize>() != batch {
+            anyhow::bail!(
+                "Cannot split '{}' with batch {} into {:?}",
+                self.name,
+                batch,
+                sizes
+            );
+        }
+
+        let row_bytes = self.as_bytes().len() / batch.max(1);
+        let mut offset = 0;
+        let mut parts = Vec::with_capacity(sizes.len());
+        for &size in sizes {
+            let mut shape = self.shape.clone();
+            shape[0] = size;
+            // SAFETY: the data matches the shape and the sizes sum to the batch,
+            // so every part is in bounds
+            let mut part = unsafe {
+                Tensor::from_raw(&self.name, self.dtype(), self.as_bytes()[offset..].as_ptr(), shape)
+            };
+            part.quant = self.quant;
+            parts.push(part);
+            offset += size * row_bytes;
+        }
+        Ok(parts)
+    }
+
+    /// Borrow this tensor as a view
+    pub fn view(&self) -> TensorView<'_> {
+        let data = match &self.storage {
+            Storage::F32(v) => ViewData::F32(v),
+            Storage::F16(v) => ViewData::F16(v),
+            Storage::I8(v) => ViewData::I8(v),
+            Storage::U8(v) => ViewData::U8(v),
+            Storage::I32(v) => ViewData::I32(v),
+            Storage::I64(v) => ViewData::I64(v),
+        };
+        TensorView { name: &self.name, data, shape: &self.shape, quant: self.quant }
+    }
+
+    /// Values as FP32, dequantizing INT8/UINT8 with the tensor's parameters
+    pub fn to_f32_vec(&self) -> Vec<f32> {
+        self.view().to_f32_vec()
+    }
+
+    /// Convert to FP16 (e.g. before submitting to an FP16-compiled model)
+    pub fn to_f16(&self) -> Tensor {
+        let data: Vec<f16> = self.to_f32_vec().into_iter().map(f16::from_f32).collect();
+        Tensor::from_vec_unchecked(data, self.shape.clone()).with_name(self.name.clone())
+    }
+
+    /// Quantize to INT8/UINT8 with the given parameters
+    pub fn quantize(&self, dtype: DataType, params: QuantParams) -> Result<Tensor> {
+        let (qmin, qmax) = quant_range(dtype)?;
+        let q = self.to_f32_vec().into_iter().map(move |v| {
+            ((v / params.scale).round() as i32 + params.zero_point).clamp(qmin, qmax)
+        });
+        let storage = match dtype {
+            DataType::I8 => Storage::I8(q.map(|v| v as i8).collect()),
+            _ => Storage::U8(q.map(|v| v as u8).collect()),
+        };
+        Ok(Tensor {
+            name: self.name.clone(),
+            storage,
+            shape: self.shape.clone(),
+            quant: Some(params),
+        })
+    }
+
+    /// Dequantize (or widen) to a FP32 tensor
+    pub fn dequantize(&self) -> Tensor {
+        Tensor::from_vec_unchecked(self.to_f32_vec(), self.shape.clone()).with_name(self.name.clone())
+    }
+
+    /// Build a tensor by copying `shape.product()` elements of `dtype` from raw memory
+    ///
+    /// # Safety
+    /// `ptr` must be valid for reads of `len * dtype.size_in_bytes()` bytes.
+    pub(crate) unsafe fn from_raw(
+        name: &str,
+        dtype: DataType,
+        ptr: *const u8,
+        shape: Vec<usize>,
+    ) -> Tensor {
+        unsafe fn copy<T: Element>(ptr: *const u8, len: usize) -> Vec<T> {
+            let mut data = vec![T::default(); len];
+            if len > 0 {
+                std::ptr::copy_nonoverlapping(ptr, data.as_mut_ptr() as *mut u8, len * std::mem::size_of::<T>());
+            }
+            data
+        }
+
+        let len = shape.iter().product();
+        let storage = match dtype {
+            DataType::F32 => Storage::F32(copy(ptr, len)),
+            DataType::F16 => Storage::F16(copy(ptr, len)),
+            DataType::I8 => Storage::I8(copy(ptr, len)),
+            DataType::U8 => Storage::U8(copy(ptr, len)),
+            DataType::I32 => Storage::I32(copy(ptr, len)),
+            DataType::I64 => Storage::I64(copy(ptr, len)),
+        };
+        Tensor { name: name.to_string(), storage, shape, quant: None }
+    }
+
+    pub(crate) fn set_quant(&mut self, params: Option<QuantParams>) {
+        self.quant = params;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ViewData<'a> {
+    F32(&'a [f32]),
+    F16(&'a [f16]),
+    I8(&'a [i8]),
+    U8(&'a [u8]),
+    I32(&'a [i32]),
+    I64(&'a [i64]),
+}
+
+/// Tensor borrowing caller memory (no copy on submission)
+#[derive(Debug, Clone, Copy)]
+pub struct TensorView<'a> {
+    name: &'a str,
+    data: ViewData<'a>,
+    shape: &'a [usize],
+    quant: Option<QuantParams>,
+}
+
+impl<'a> TensorView<'a> {
+    /// View a typed slice with the given shape
+    pub fn new<T: Element>(data: &'a [T], shape: &'a [usize]) -> Self {
+        let data = match T::DTYPE {
+            DataType::F32 => ViewData::F32(same_type_slice(data)),
+            DataType::F16 => ViewData::F16(same_type_slice(data)),
+            DataType::I8 => ViewData::I8(same_type_slice(data)),
+            DataType::U8 => ViewData::U8(same_type_slice(data)),
+            DataType::I32 => ViewData::I32(same_type_slice(data)),
+            DataType::I64 => ViewData::I64(same_type_slice(data)),
+        };
+        Self { name: "", data, shape, quant: None }
+    }
+
+    /// Bind the view to a named model input
+    pub fn with_name(mut self, name: &'a str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Attach quantization parameters (INT8/UINT8 views only)
+    pub fn with_quant_params(mut self, params: QuantParams) -> Result<Self> {
+        quant_range(self.dtype())?;
+        self.quant = Some(params);
+        Ok(self)
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub fn shape(&self) -> &'a [usize] {
+        self.shape
+    }
+
+    pub fn quant_params(&self) -> Option<QuantParams> {
+        self.quant
+    }
+
+    pub fn dtype(&self) -> DataType {
+        match self.data {
+            ViewData::F32(_) => DataType::F32,
+            ViewData::F16(_) => DataType::F16,
+            ViewData::I8(_) => DataType::I8,
+            ViewData::U8(_) => DataType::U8,
+            ViewData::I32(_) => DataType::I32,
+            ViewData::I64(_) => DataType::I64,
+        }
+    }
+
+    /// Number of elements actually held
+    pub fn len(&self) -> usize {
+        match self.data {
+            ViewData::F32(v) => v.len(),
+            ViewData::F16(v) => v.len(),
+            ViewData::I8(v) => v.len(),
+            ViewData::U8(v) => v.len(),
+            ViewData::I32(v) => v.len(),
+            ViewData::I64(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Raw bytes in native endianness
+    pub fn as_bytes(&self) -> &'a [u8] {
+        // SAFETY: every element type is plain old data without padding
+        unsafe {
+            match self.data {
+                ViewData::F32(v) => cast_slice(v),
+                ViewData::F16(v) => cast_slice(v),
+                ViewData::I8(v) => cast_slice(v),
+                ViewData::U8(v) => v,
+                ViewData::I32(v) => cast_slice(v),
+                ViewData::I64(v) => cast_slice(v),
+            }
+        }
+    }
+
+    /// Values as FP32, dequantizing INT8/UINT8 when parameters are set
+    pub fn to_f32_vec(&self) -> Vec<f32> {
+        fn widen<T: Element>(v: &[T], quant: Option<QuantParams>) -> Vec<f32> {
+            match quant {
+                Some(q) => v.iter().map(|x| (x.to_f32() - q.zero_point as f32) * q.scale).collect(),
+                None => v.iter().map(|x| x.to_f32()).collect(),
+            }
+        }
+
+        match self.data {
+            ViewData::F32(v) => v.to_vec(),
+            ViewData::F16(v) => widen(v, None),
+            ViewData::I8(v) => widen(v, self.quant),
+            ViewData::U8(v) => widen(v, self.quant),
+            ViewData::I32(v) => widen(v, None),
+            ViewData::I64(v) => widen(v, None),
+        }
+    }
+
+    /// Copy into an owned tensor
+    pub fn to_owned(&self) -> Tensor {
+        let storage = match self.data {
+            ViewData::F32(v) => Storage::F32(v.to_vec()),
+            ViewData::F16(v) => Storage::F16(v.to_vec()),
+            ViewData::I8(v) => Storage::I8(v.to_vec()),
+            ViewData::U8(v) => Storage::U8(v.to_vec()),
+            ViewData::I32(v) => Storage::I32(v.to_vec()),
+            ViewData::I64(v) => Storage::I64(v.to_vec()),
+        };
+        Tensor {
+            name: self.name.to_string(),
+            storage,
+            shape: self.shape.to_vec(),
+            quant: self.quant,
+        }
+    }
+
+    /// Check that the data length matches the shape
+    pub(crate) fn validate(&self) -> Result<()> {
+        let expected: usize = self.shape.iter().product();
+        if self.len() != expected {
+            anyhow::bail!(
+                "Tensor '{}' has {} values but shape {:?} needs {}",
+                self.name,
+                self.len(),
+                self.shape,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a Tensor> for TensorView<'a> {
+    fn from(tensor: &'a Tensor) -> Self {
+        tensor.view()
+    }
+}
+
+/// Re-type a slice whose element type is known to be `U`
+fn same_type_slice<T: 'static, U: 'static>(data: &[T]) -> &[U] {
+    assert_eq!(TypeId::of::<T>(), TypeId::of::<U>());
+    // SAFETY: T and U are the same type
+    unsafe { cast_slice(data) }
+}
+
+/// Reinterpret a slice of plain old data
+///
+/// # Safety
+/// `U` must be valid for every bit pattern of `T`, and `T` must be at least
+/// as aligned as `U`.
+unsafe fn cast_slice<T, U>(data: &[T]) -> &[U] {
+    let bytes = std::mem::size_of_val(data);
+    std::slice::from_raw_parts(data.as_ptr() as *const U, bytes / std::mem::size_of::<U>())
+}
+
+/// Row-major strides for a shape, in elements
+pub fn strides(shape: &[usize]) -> Vec<usize> {
+    let mut strides = vec![1; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1];
+    }
+    strides
+}
+
+/// Memory layout of image tensors
+#[cfg(feature = "image")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageLayout {
+    /// `[1, C, H, W]` (PyTorch/ONNX default)
+    Nchw,
+    /// `[1, H, W, C]` (TFLite-style, common for UINT8 models)
+    Nhwc,
+}
+
+#[cfg(feature = "image")]
+impl Tensor {
+    /// RGB image as a FP32 tensor scaled to `[0, 1]`
+    pub fn from_image(image: &image::DynamicImage, layout: ImageLayout) -> Tensor {
+        let rgb = image.to_rgb8();
+        let data: Vec<f32> = Self::image_values(&rgb, layout).map(|v| v as f32 / 255.0).collect();
+        Tensor::from_vec_unchecked(data, Self::image_shape(&rgb, layout))
+    }
+
+    /// RGB image as a UINT8 tensor (raw pixel values, for quantized models)
+    pub fn from_image_u8(image: &image::DynamicImage, layout: ImageLayout) -> Tensor {
+        let rgb = image.to_rgb8();
+        let data: Vec<u8> = Self::image_values(&rgb, layout).collect();
+        Tensor::from_vec_unchecked(data, Self::image_shape(&rgb, layout))
+    }
+
+    /// Convert a `[1, C, H, W]`/`[1, H, W, C]` tensor (C = 1 or 3) back to an image
+    ///
+    /// FP values are expected in `[0, 1]`; quantized values are dequantized first.
+    pub fn to_image(&self, layout: ImageLayout) -> Result<image::DynamicImage> {
+        let (c, h, w) = match (layout, self.shape.as_slice()) {
+            (ImageLayout::Nchw, &[1, c, h, w]) => (c, h, w),
+            (ImageLayout::Nhwc, &[1, h, w, c]) => (c, h, w),
+            _ => anyhow::bail!("Tensor shape {:?} is not a {:?} image", self.shape, layout),
+        };
+        if c != 1 && c != 3 {
+            anyhow::bail!("Images need 1 or 3 channels, got {}", c);
+        }
+
+        let pixels: Vec<u8> = match self.as_slice::<u8>() {
+            Some(raw) if self.quant.is_none() => raw.to_vec(),
+            _ => self.to_f32_vec().iter().map(|v| (v * 255.0).round().clamp(0.0, 255.0) as u8).collect(),
+        };
+        if pixels.len() != c * h * w {
+            anyhow::bail!("Tensor has {} values but a {}x{}x{} image needs {}", pixels.len(), c, h, w, c * h * w);
+        }
+
+        // Interleave into HWC order
+        let mut hwc = vec![0u8; c * h * w];
+        for y in 0..h {
+            for x in 0..w {
+                for ch in 0..c {
+                    let src = match layout {
+                        ImageLayout::Nchw => ch * h * w + y * w + x,
+                        ImageLayout::Nhwc => (y * w + x) * c + ch,
+                    };
+                    hwc[(y * w + x) * c + ch] = pixels[src];
+                }
+            }
+        }
+
+        let image = if c == 1 {
+            image::GrayImage::from_raw(w as u32, h as u32, hwc).map(image::DynamicImage::ImageLuma8)
+        } else {
+            image::RgbImage::from_raw(w as u32, h as u32, hwc).map(image::DynamicImage::ImageRgb8)
+        };
+        image.ok_or_else(|| anyhow::anyhow!("Image buffer size mismatch"))
+    }
+
+    fn image_shape(rgb: &image::RgbImage, layout: ImageLayout) -> Vec<usize> {
+        let (w, h) = (rgb.width() as usize, rgb.height() as usize);
+        match layout {
+            ImageLayout::Nchw => vec![1, 3, h, w],
+            ImageLayout::Nhwc => vec![1, h, w, 3],
+        }
+    }
+
+    fn image_values(rgb: &image::RgbImage, layout: ImageLayout) -> Box<dyn Iterator<Item = u8> + '_> {
+        match layout {
+            ImageLayout::Nhwc => Box::new(rgb.as_raw().iter().copied()),
+            ImageLayout::Nchw => Box::new((0..3).flat_map(move |ch| rgb.pixels().map(move |p| p.0[ch]))),
+        }
+    }
+}
+
+#[cfg(feature = "ndarray")]
+impl Tensor {
+    /// Copy into an `ndarray` array, if `T` matches the tensor dtype
+    pub fn to_ndarray<T: Element>(&self) -> Result<ndarray::ArrayD<T>> {
+        let data = self
+            .as_slice::<T>()
+            .ok_or_else(|| anyhow::anyhow!("Tensor is {:?}, not {:?}", self.dtype(), T::DTYPE))?;
+        Ok(ndarray::ArrayD::from_shape_vec(self.shape.clone(), data.to_vec())?)
+    }
+
+    /// Build a tensor from any `ndarray` array (copied into row-major order)
+    pub fn from_ndarray<T: Element, D: ndarray::Dimension>(array: &ndarray::Array<T, D>) -> Tensor {
+        let shape = array.shape().to_vec();
+        Tensor::from_vec_unchecked(array.iter().copied().collect(), shape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dtype_codes_roundtrip() {
+        for dtype in [DataType::F32, DataType::F16, DataType::I8, DataType::U8, DataType::I32, DataType::I64] {
+            assert_eq!(DataType::from_code(dtype.code()), Some(dtype));
+        }
+        assert_eq!(DataType::from_code(42), None);
+        assert_eq!(DataType::I64.size_in_bytes(), 8);
+    }
+
+    #[test]
+    fn test_typed_access() {
+        let t = Tensor::from_vec(vec![1i64, 2, 3], vec![3]).unwrap().with_name("input_ids");
+        assert_eq!(t.dtype(), DataType::I64);
+        assert_eq!(t.as_slice::<i64>(), Some(&[1i64, 2, 3][..]));
+        assert!(t.as_slice::<f32>().is_none());
+        assert_eq!(t.as_bytes().len(), 24);
+    }
+
+    #[test]
+    fn test_quantize_roundtrip() {
+        let t = Tensor::new(vec![-1.0, 0.0, 0.5, 1.0], vec![4]).unwrap();
+        let params = QuantParams::from_range(-1.0, 1.0, DataType::I8).unwrap();
+        let q = t.quantize(DataType::I8, params).unwrap();
+        assert_eq!(q.dtype(), DataType::I8);
+
+        for (a, b) in t.to_f32_vec().iter().zip(q.to_f32_vec()) {
+            assert!((a - b).abs() <= params.scale);
+        }
+        assert!(t.quantize(DataType::F16, params).is_err());
+    }
+
+    #[test]
+    fn test_view_borrows_caller_memory() {
+        let data = vec![0u8; 12];
+        let shape = [1, 2, 2, 3];
+        let view = TensorView::new(&data, &shape).with_name("pixels");
+        assert_eq!(view.dtype(), DataType::U8);
+        assert_eq!(view.as_bytes().as_ptr(), data.as_ptr());
+        assert!(view.validate().is_ok());
+    }
+
+    #[test]
+    fn test_strides_and_reshape() {
+        assert_eq!(strides(&[1, 3, 4, 5]), vec![60, 20, 5, 1]);
+
+        let t = Tensor::zeros(DataType::F16, vec![2, 6]);
+        let t = t.reshape(vec![3, 4]).unwrap();
+        assert_eq!(t.shape(), &[3, 4]);
+        assert!(t.reshape(vec![5]).is_err());
+    }
+
+    #[test]
+    fn test_concat_and_split_batch() {
+        let a = Tensor::from_vec(vec![1i32, 2, 3], vec![1, 3]).unwrap().with_name("x");
+        let b = Tensor::from_vec(vec![4i32, 5, 6, 7, 8, 9], vec![2, 3]).unwrap();
+
+        let packed = Tensor::concat(&[a.view(), b.view()]).unwrap();
+        assert_eq!(packed.name(), "x");
+        assert_eq!(packed.shape(), &[3, 3]);
+        assert_eq!(packed.as_slice::<i32>().unwrap(), &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        let parts = packed.split(&[1, 2]).unwrap();
+        assert_eq!(parts[0], a);
+        assert_eq!(parts[1].as_slice::<i32>(), b.as_slice::<i32>());
+        assert!(packed.split(&[1, 1]).is_err());
+
+        let c = Tensor::from_vec(vec![0i32; 4], vec![1, 4]).unwrap();
+        assert!(Tensor::concat(&[a.view(), c.view()]).is_err());
+    }
+
+    #[test]
+    fn test_split_rejects_short_data() {
+        // Only reachable from inside the crate, but split must never read past the data
+        let t = Tensor { shape: vec![2, 3], ..Tensor::from_vec(vec![1i32], vec![1]).unwrap() };
+        assert!(t.split(&[1, 1]).is_err());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_image_roundtrip() {
+        let img = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(4, 2, |x, y| {
+            image::Rgb([x as u8 * 60, y as u8 * 100, 7])
+        }));
+
+        for layout in [ImageLayout::Nchw, ImageLayout::Nhwc] {
+            let t = Tensor::from_image(&img, layout);
+            assert_eq!(t.num_elements(), 24);
+            assert_eq!(t.to_image(layout).unwrap().to_rgb8(), img.to_rgb8());
+        }
+        assert_eq!(Tensor::from_image_u8(&img, ImageLayout::Nhwc).shape(), &[1, 2, 4, 3]);
+    }
+
+    #[test]
+    fn test_constructors_check_length() {
+        assert!(Tensor::from_vec(vec![1i32], vec![2, 3]).is_err());
+        assert!(Tensor::named("x", vec![0.0; 7], vec![2, 3]).is_err());
+        assert!(Tensor::from_vec(Vec::<u8>::new(), vec![0, 3]).is_ok());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_to_image_rejects_short_data() {
+        let t = Tensor { shape: vec![1, 3, 2, 2], ..Tensor::zeros(DataType::U8, vec![5]) };
+        assert!(t.to_image(ImageLayout::Nchw).is_err());
+    }
+}
@@ -5,19 +5,21 @@
 //!
 //! This provides a C-compatible interface for ONNX Runtime to use the NPU.
 
-use std::ffi::CString;
 use std::os::raw::c_char;
 use std::ptr;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
 
 // Static NPU state
-static mut NPU_DEVICE: Option<Mutex<NpuState>> = None;
+static NPU_DEVICE: Mutex<Option<NpuState>> = Mutex::new(None);
 
 struct NpuState {
     initialized: bool,
     allocations: Vec<*mut u8>,
 }
 
+// Allocations are only touched while holding the NPU_DEVICE lock
+unsafe impl Send for NpuState {}
+
 impl NpuState {
     fn new() -> Self {
         Self {
@@ -27,105 +29,116 @@ impl NpuState {
     }
 }
 
+fn device() -> MutexGuard<'static, Option<NpuState>> {
+    NPU_DEVICE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 #[no_mangle]
 pub extern "C" fn eva_npu_init() -> i32 {
-    unsafe {
-        if NPU_DEVICE.is_some() {
-            return 0; // Already initialized
-        }
-
-        let mut state = NpuState::new();
+    let mut device = device();
+    if device.is_some() {
+        return 0; // Already initialized
+    }
 
-        // TODO: Call actual NPU initialization from intel-npu crate
-        #[cfg(target_os = "redox")]
-        {
-            // Real Redox initialization
-            match intel_npu::init_npu() {
-                Ok(_) => state.initialized = true,
-                Err(_) => return -1,
-            }
-        }
+    let mut state = NpuState::new();
 
-        #[cfg(not(target_os = "redox"))]
-        {
-            // Mock for development
-            state.initialized = true;
+    // TODO: Call actual NPU initialization from intel-npu crate
+    #[cfg(target_os = "redox")]
+    {
+        // Real Redox initialization
+        match intel_npu::init_npu() {
+            Ok(_) => state.initialized = true,
+            Err(_) => return -1,
         }
+    }
 
-        NPU_DEVICE = Some(Mutex::new(state));
-        0
+    #[cfg(not(target_os = "redox"))]
+    {
+        // Mock for development
+        state.initialized = true;
     }
+
+    *device = Some(state);
+    0
 }
 
 #[no_mangle]
 pub extern "C" fn eva_npu_shutdown() {
-    unsafe {
-        if let Some(device) = NPU_DEVICE.take() {
-            let mut state = device.lock().unwrap();
-
-            // Free all allocations
-            for ptr in &state.allocations {
+    if let Some(mut state) = device().take() {
+        // Free all allocations
+        for ptr in &state.allocations {
+            unsafe {
+                #[cfg(target_os = "redox")]
+                intel_npu::free_npu_memory(*ptr as *mut libc::c_void);
+
+                #[cfg(not(target_os = "redox"))]
                 libc::free(*ptr as *mut libc::c_void);
             }
-            state.allocations.clear();
-            state.initialized = false;
         }
+        state.allocations.clear();
+        state.initialized = false;
     }
 }
 
 #[no_mangle]
 pub extern "C" fn eva_npu_alloc(size: usize) -> *mut libc::c_void {
+    let mut device = device();
+    let Some(state) = device.as_mut() else {
+        return ptr::null_mut();
+    };
+
     unsafe {
-        if let Some(ref device) = NPU_DEVICE {
-            let mut state = device.lock().unwrap();
-
-            #[cfg(target_os = "redox")]
-            {
-                // Real Redox allocation
-                let ptr = intel_npu::alloc_npu_memory(size);
-                if !ptr.is_null() {
-                    state.allocations.push(ptr as *mut u8);
-                }
-                ptr
+        #[cfg(target_os = "redox")]
+        {
+            // Real Redox allocation
+            let ptr = intel_npu::alloc_npu_memory(size);
+            if !ptr.is_null() {
+                state.allocations.push(ptr as *mut u8);
             }
+            ptr
+        }
 
-            #[cfg(not(target_os = "redox"))]
-            {
-                // Mock: allocate from system heap
-                let ptr = libc::malloc(size);
-                if !ptr.is_null() {
-                    state.allocations.push(ptr as *mut u8);
-                }
-                ptr
+        #[cfg(not(target_os = "redox"))]
+        {
+            // Mock: allocate from system heap
+            let ptr = libc::malloc(size);
+            if !ptr.is_null() {
+                state.allocations.push(ptr as *mut u8);
             }
-        } else {
-            ptr::null_mut()
+            ptr
         }
     }
 }
 
+// Unknown pointers are ignored, so this is safe to call with any value
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
 pub extern "C" fn eva_npu_free(ptr: *mut libc::c_void) {
     if ptr.is_null() {
         return;
     }
 
-    unsafe {
-        if let Some(ref device) = NPU_DEVICE {
-            let mut state = device.lock().unwrap();
+    let mut device = device();
+    let Some(state) = device.as_mut() else {
+        return;
+    };
 
-            // Remove from tracking
-            state.allocations.retain(|&p| p != ptr as *mut u8);
+    // Only free pointers we handed out (and have not freed yet)
+    let before = state.allocations.len();
+    state.allocations.retain(|&p| p != ptr as *mut u8);
+    if state.allocations.len() == before {
+        return;
+    }
 
-            #[cfg(target_os = "redox")]
-            {
-                intel_npu::free_npu_memory(ptr);
-            }
+    unsafe {
+        #[cfg(target_os = "redox")]
+        {
+            intel_npu::free_npu_memory(ptr);
+        }
 
-            #[cfg(not(target_os = "redox"))]
-            {
-                libc::free(ptr);
-            }
+        #[cfg(not(target_os = "redox"))]
+        {
+            libc::free(ptr);
         }
     }
 }
@@ -197,25 +210,24 @@ pub extern "C" fn eva_npu_execute(
         return -1;
     }
 
+    #[cfg(target_os = "redox")]
     unsafe {
-        #[cfg(target_os = "redox")]
-        {
-            let blob_slice = std::slice::from_raw_parts(blob as *const u8, blob_size);
-            let input_slice = std::slice::from_raw_parts(inputs, num_inputs);
-            let output_slice = std::slice::from_raw_parts_mut(outputs, num_outputs);
+        let blob_slice = std::slice::from_raw_parts(blob as *const u8, blob_size);
+        let input_slice = std::slice::from_raw_parts(inputs, num_inputs);
+        let output_slice = std::slice::from_raw_parts_mut(outputs, num_outputs);
 
-            match intel_npu::execute_model(blob_slice, input_slice, output_slice) {
-                Ok(_) => 0,
-                Err(_) => -1,
-            }
+        match intel_npu::execute_model(blob_slice, input_slice, output_slice) {
+            Ok(_) => 0,
+            Err(_) => -1,
         }
+    }
 
-        #[cfg(not(target_os = "redox"))]
-        {
-            // Mock: just simulate execution delay
-            std::thread::sleep(std::time::Duration::from_millis(10));
-            0
-        }
+    #[cfg(not(target_os = "redox"))]
+    {
+        // Mock: just simulate execution delay
+        let _ = (blob_size, inputs, outputs, num_inputs, num_outputs);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        0
     }
 }
 
@@ -3,17 +3,96 @@ use std::collections::VecDeque;
 
 #[cfg(not(target_os = "redox"))]
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+#[cfg(not(target_os = "redox"))]
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+
+
+use crate::audio_io::{AudioSink, AudioSource, NextChunk};
+use crate::audio_processing::{AudioProcessor, EchoReference, ProcessingConfig};
+#[cfg(not(target_os = "redox"))]
+use crate::resample::Resampler;
+use crate::user_profile::UserProfile;
 
 pub const SAMPLE_RATE: u32 = 16000; // Gemini expects 16kHz
 pub const CHANNELS: u16 = 1;
 pub const CHUNK_SIZE: usize = 1600; // 100ms at 16kHz
 
+/// Sample formats we can stream, best first
+#[cfg(not(target_os = "redox"))]
+const PREFERRED_FORMATS: [SampleFormat; 3] = [SampleFormat::F32, SampleFormat::I16, SampleFormat::U16];
+
+/// Longest `capture_chunk` waits for a full chunk before padding with silence
+#[cfg(not(target_os = "redox"))]
+const CAPTURE_TIMEOUT_MS: u64 = 250;
+
+/// Keep at most this much captured audio if nobody is reading (2 seconds)
+const MAX_CAPTURE: usize = SAMPLE_RATE as usize * 2;
+
+/// Which sound devices to open and how to clean up the microphone
+#[derive(Debug, Clone, Default)]
+pub struct AudioConfig {
+    /// Microphone name (exact or case-insensitive substring); `None` = system default
+    pub input_device: Option<String>,
+    /// Speaker name (exact or case-insensitive substring); `None` = system default
+    pub output_device: Option<String>,
+    pub processing: ProcessingConfig,
+}
+
+impl AudioConfig {
+    pub fn from_profile(profile: &UserProfile) -> Self {
+        Self {
+            input_device: profile.input_device.clone(),
+            output_device: profile.output_device.clone(),
+            processing: profile.audio_processing,
+        }
+    }
+}
+
+/// Microphone samples paired with what the speaker played at the same time
+#[derive(Default)]
+struct CaptureBuffer {
+    mic: VecDeque<f32>,
+    reference: VecDeque<f32>,
+}
+
+impl CaptureBuffer {
+    /// Append captured audio, pairing it with the same amount of played audio
+    fn push(&mut self, mic: &[f32], reference: Option<&EchoReference>) {
+        self.mic.extend(mic);
+        match reference {
+            Some(reference) => self.reference.extend(reference.take(mic.len())),
+            None => self.reference.extend(std::iter::repeat(0.0).take(mic.len())),
+        }
+
+        let excess = self.mic.len().saturating_sub(MAX_CAPTURE);
+        self.mic.drain(..excess);
+        self.reference.drain(..excess);
+    }
+
+    /// Take up to `count` samples of microphone and reference audio
+    fn take(&mut self, count: usize) -> (Vec<f32>, Vec<f32>) {
+        let count = count.min(self.mic.len());
+        (self.mic.drain(..count).collect(), self.reference.drain(..count).collect())
+    }
+}
+
 /// Audio device manager
+///
+/// Everything outside this type is mono f32 at `SAMPLE_RATE`; the streams
+/// convert to and from whatever rate, channel count and sample format the
+/// hardware negotiated.
 pub struct AudioDevice {
-    #[cfg(not(target_os = "redox"))]
-    input_buffer: Arc<Mutex<VecDeque<f32>>>,
+    input_buffer: Arc<Mutex<CaptureBuffer>>,
+    /// Playback of the device whose echo the microphone hears (see `set_echo_reference`)
+    reference_link: Arc<Mutex<Option<EchoReference>>>,
+    /// What this device's speaker plays
+    echo_reference: EchoReference,
+    processor: AudioProcessor,
+    /// Mono samples at the output device rate
     #[cfg(not(target_os = "redox"))]
     output_buffer: Arc<Mutex<VecDeque<f32>>>,
+    #[cfg(not(target_os = "redox"))]
+    output_resampler: Resampler,
 
     #[cfg(not(target_os = "redox"))]
     _input_stream: Option<cpal::Stream>,
@@ -27,79 +106,94 @@ pub struct AudioDevice {
 }
 
 impl AudioDevice {
+    /// Open the system default microphone and speaker
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_config(&AudioConfig::default())
+    }
+
+    pub fn with_config(config: &AudioConfig) -> Result<Self, Box<dyn std::error::Error>> {
         #[cfg(not(target_os = "redox"))]
         {
             let host = cpal::default_host();
 
             // INPUT (Microphone)
-            let input_device = host.default_input_device()
-                .ok_or("No input device available")?;
+            let input_device = match &config.input_device {
+                Some(name) => select_device(host.input_devices()?, name, "input"),
+                None => None,
+            };
+            let input_device = match input_device {
+                Some(device) => device,
+                None => host.default_input_device().ok_or("No input device available")?,
+            };
             println!("🎤 Microfone: {}", input_device.name().unwrap_or_default());
 
-            let input_config = input_device.default_input_config()?;
-            let in_sample_rate = input_config.sample_rate().0;
-            let in_channels = input_config.channels() as usize;
-            println!("   Input: {}Hz, {} canais", in_sample_rate, in_channels);
-
-            let input_buffer = Arc::new(Mutex::new(VecDeque::with_capacity(in_sample_rate as usize)));
-            let input_buffer_clone = Arc::clone(&input_buffer);
-
-            let input_stream_config: cpal::StreamConfig = input_config.into();
-            let input_stream = input_device.build_input_stream(
-                &input_stream_config,
-                move |data: &[f32], _: &cpal::InputCallbackInfo| {
-                    if let Ok(mut buffer) = input_buffer_clone.lock() {
-                        for chunk in data.chunks(in_channels) {
-                            let mono: f32 = chunk.iter().sum::<f32>() / in_channels as f32;
-                            buffer.push_back(mono);
-                            if buffer.len() > in_sample_rate as usize * 2 {
-                                buffer.pop_front();
-                            }
-                        }
-                    }
-                },
-                |err| eprintln!("❌ Input error: {}", err),
-                None,
+            let input_config = negotiate_config(
+                input_device.supported_input_configs()?.collect(),
+                input_device.default_input_config().ok(),
             )?;
+            println!(
+                "   Input: {}Hz, {} canais, {}",
+                input_config.sample_rate().0,
+                input_config.channels(),
+                input_config.sample_format()
+            );
+
+            let input_buffer = Arc::new(Mutex::new(CaptureBuffer::default()));
+            let reference_link = Arc::new(Mutex::new(None));
+            let input_stream_config: cpal::StreamConfig = input_config.config();
+            let capture = (Arc::clone(&input_buffer), Arc::clone(&reference_link));
+            let input_stream = match input_config.sample_format() {
+                SampleFormat::F32 => build_input_stream::<f32>(&input_device, &input_stream_config, capture),
+                SampleFormat::I16 => build_input_stream::<i16>(&input_device, &input_stream_config, capture),
+                SampleFormat::U16 => build_input_stream::<u16>(&input_device, &input_stream_config, capture),
+                other => return Err(format!("Unsupported input sample format: {}", other).into()),
+            }?;
             input_stream.play()?;
 
             // OUTPUT (Speaker)
-            let output_device = host.default_output_device()
-                .ok_or("No output device available")?;
+            let output_device = match &config.output_device {
+                Some(name) => select_device(host.output_devices()?, name, "output"),
+                None => None,
+            };
+            let output_device = match output_device {
+                Some(device) => device,
+                None => host.default_output_device().ok_or("No output device available")?,
+            };
             println!("🔊 Speaker: {}", output_device.name().unwrap_or_default());
 
-            let output_config = output_device.default_output_config()?;
+            let output_config = negotiate_config(
+                output_device.supported_output_configs()?.collect(),
+                output_device.default_output_config().ok(),
+            )?;
             let out_sample_rate = output_config.sample_rate().0;
-            let out_channels = output_config.channels() as usize;
-            println!("   Output: {}Hz, {} canais", out_sample_rate, out_channels);
+            println!(
+                "   Output: {}Hz, {} canais, {}",
+                out_sample_rate,
+                output_config.channels(),
+                output_config.sample_format()
+            );
 
             let output_buffer = Arc::new(Mutex::new(VecDeque::with_capacity(out_sample_rate as usize * 2)));
-            let output_buffer_clone = Arc::clone(&output_buffer);
-
-            let output_stream_config: cpal::StreamConfig = output_config.into();
-            let output_stream = output_device.build_output_stream(
-                &output_stream_config,
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                    if let Ok(mut buffer) = output_buffer_clone.lock() {
-                        for frame in data.chunks_mut(out_channels) {
-                            let sample = buffer.pop_front().unwrap_or(0.0);
-                            for s in frame.iter_mut() {
-                                *s = sample;
-                            }
-                        }
-                    }
-                },
-                |err| eprintln!("❌ Output error: {}", err),
-                None,
-            )?;
+            let output_stream_config: cpal::StreamConfig = output_config.config();
+            let echo_reference = EchoReference::new();
+            let playback = (Arc::clone(&output_buffer), echo_reference.clone());
+            let output_stream = match output_config.sample_format() {
+                SampleFormat::F32 => build_output_stream::<f32>(&output_device, &output_stream_config, playback),
+                SampleFormat::I16 => build_output_stream::<i16>(&output_device, &output_stream_config, playback),
+                SampleFormat::U16 => build_output_stream::<u16>(&output_device, &output_stream_config, playback),
+                other => return Err(format!("Unsupported output sample format: {}", other).into()),
+            }?;
             output_stream.play()?;
 
             println!("✅ Áudio iniciado");
 
             Ok(Self {
                 input_buffer,
+                reference_link,
+                echo_reference,
+                processor: AudioProcessor::new(config.processing),
                 output_buffer,
+                output_resampler: Resampler::new(SAMPLE_RATE, out_sample_rate),
                 _input_stream: Some(input_stream),
                 _output_stream: Some(output_stream),
             })
@@ -107,33 +201,59 @@ impl AudioDevice {
 
         #[cfg(target_os = "redox")]
         {
+            // audio: scheme is a single fixed 16kHz mono endpoint
             use std::fs::File;
             let input = File::open("audio:record").ok();
             let output = File::create("audio:play").ok();
-            Ok(Self { input, output })
+            Ok(Self {
+                input_buffer: Arc::new(Mutex::new(CaptureBuffer::default())),
+                reference_link: Arc::new(Mutex::new(None)),
+                echo_reference: EchoReference::new(),
+                processor: AudioProcessor::new(config.processing),
+                input,
+                output,
+            })
         }
     }
 
+    /// What this device plays, for another device's echo canceller
+    pub fn echo_reference(&self) -> EchoReference {
+        self.echo_reference.clone()
+    }
+
+    /// Cancel the echo of `reference` (usually the player's device) from the microphone
+    pub fn set_echo_reference(&mut self, reference: EchoReference) {
+        if let Ok(mut link) = self.reference_link.lock() {
+            *link = Some(reference);
+        }
+        self.processor.reset();
+    }
+
+    /// Next `CHUNK_SIZE` samples from the microphone, after echo cancellation,
+    /// noise suppression and AGC
     pub async fn capture_chunk(&mut self) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let (mut mic, mut reference) = self.capture_raw().await?;
+        mic.resize(CHUNK_SIZE, 0.0);
+        reference.resize(CHUNK_SIZE, 0.0);
+        Ok(self.processor.process(&mic, &reference))
+    }
+
+    /// Unprocessed microphone audio and the matching speaker reference
+    async fn capture_raw(&mut self) -> Result<(Vec<f32>, Vec<f32>), Box<dyn std::error::Error>> {
         #[cfg(not(target_os = "redox"))]
         {
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-
-            let samples = {
-                let mut buffer = self.input_buffer.lock().map_err(|e| format!("Lock: {}", e))?;
-                let len = buffer.len().min(CHUNK_SIZE);
-                let mut samples = Vec::with_capacity(len);
-                for _ in 0..len {
-                    if let Some(s) = buffer.pop_front() {
-                        samples.push(s);
-                    }
+            // The stream delivers SAMPLE_RATE audio, so a full chunk arrives every 100ms
+            let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_millis(CAPTURE_TIMEOUT_MS);
+            loop {
+                let available = self.input_buffer.lock().map(|b| b.mic.len()).unwrap_or(0);
+                if available >= CHUNK_SIZE || tokio::time::Instant::now() >= deadline {
+                    break;
                 }
-                samples
-            };
+                tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+            }
 
-            let mut result = samples;
-            result.resize(CHUNK_SIZE, 0.0);
-            Ok(result)
+            let mut buffer = self.input_buffer.lock().map_err(|e| format!("Lock: {}", e))?;
+            Ok(buffer.take(CHUNK_SIZE))
         }
 
         #[cfg(target_os = "redox")]
@@ -146,20 +266,24 @@ impl AudioDevice {
                     .chunks_exact(2)
                     .map(|c| i16::from_le_bytes([c[0], c[1]]) as f32 / 32768.0)
                     .collect();
-                Ok(samples)
+
+                let mut buffer = self.input_buffer.lock().map_err(|e| format!("Lock: {}", e))?;
+                let link = self.reference_link.lock().map_err(|e| format!("Lock: {}", e))?;
+                buffer.push(&samples, link.as_ref());
+                Ok(buffer.take(CHUNK_SIZE))
             } else {
-                Ok(vec![0.0; CHUNK_SIZE])
+                Ok((vec![0.0; CHUNK_SIZE], vec![0.0; CHUNK_SIZE]))
             }
         }
     }
 
-    pub async fn play(&mut self, samples: &[f32]) -> Result<(), Box<dyn std::error::Error>> {
+    /// Queue samples for the speaker (blocks on Redox until written)
+    fn write_output(&mut self, samples: &[f32]) -> Result<(), Box<dyn std::error::Error>> {
         #[cfg(not(target_os = "redox"))]
         {
+            let resampled = self.output_resampler.process(samples);
             if let Ok(mut buffer) = self.output_buffer.lock() {
-                for &s in samples {
-                    buffer.push_back(s);
-                }
+                buffer.extend(resampled);
             }
             Ok(())
         }
@@ -172,12 +296,212 @@ impl AudioDevice {
                     .iter()
                     .flat_map(|&s| ((s * 32767.0) as i16).to_le_bytes())
                     .collect();
+                // Record before the (blocking) write so the reference leads the echo
+                self.echo_reference.push(samples);
                 output.write_all(&buffer)?;
                 output.flush()?;
             }
             Ok(())
         }
     }
+
+    /// Samples queued for playback but not yet played
+    pub fn queued_output(&self) -> usize {
+        #[cfg(not(target_os = "redox"))]
+        {
+            // Report in SAMPLE_RATE samples, whatever the device rate
+            let queued = self.output_buffer.lock().map(|b| b.len()).unwrap_or(0);
+            let rate = self.output_resampler.output_rate().max(1) as usize;
+            queued * SAMPLE_RATE as usize / rate
+        }
+
+        #[cfg(target_os = "redox")]
+        {
+            0 // Writes to audio:play block until consumed
+        }
+    }
+
+    /// Drop everything queued for playback (stops speech immediately)
+    pub fn clear_output(&mut self) {
+        #[cfg(not(target_os = "redox"))]
+        {
+            if let Ok(mut buffer) = self.output_buffer.lock() {
+                buffer.clear();
+            }
+            self.output_resampler.reset();
+        }
+    }
+}
+
+impl AudioSource for AudioDevice {
+    /// The live microphone never ends
+    fn next_chunk(&mut self) -> NextChunk<'_> {
+        Box::pin(async move { self.capture_chunk().await.map(Some) })
+    }
+}
+
+impl AudioSink for AudioDevice {
+    fn play(&mut self, samples: &[f32]) -> Result<(), Box<dyn std::error::Error>> {
+        self.write_output(samples)
+    }
+
+    fn queued_output(&self) -> usize {
+        AudioDevice::queued_output(self)
+    }
+
+    fn clear_output(&mut self) {
+        AudioDevice::clear_output(self)
+    }
+
+    fn echo_reference(&self) -> EchoReference {
+        AudioDevice::echo_reference(self)
+    }
+}
+
+/// Find a device by exact name, falling back to a case-insensitive substring
+#[cfg(not(target_os = "redox"))]
+fn select_device(devices: impl Iterator<Item = cpal::Device>, wanted: &str, kind: &str) -> Option<cpal::Device> {
+    let devices: Vec<(String, cpal::Device)> = devices
+        .filter_map(|d| d.name().ok().map(|name| (name, d)))
+        .collect();
+    let names: Vec<&str> = devices.iter().map(|(name, _)| name.as_str()).collect();
+
+    match match_device_name(&names, wanted) {
+        Some(index) => devices.into_iter().nth(index).map(|(_, d)| d),
+        None => {
+            println!("⚠️  Dispositivo de {} '{}' não encontrado, usando o padrão", kind, wanted);
+            println!("   Disponíveis: {}", names.join(", "));
+            None
+        }
+    }
+}
+
+/// Index of the device name matching `wanted`
+#[cfg(not(target_os = "redox"))]
+fn match_device_name(names: &[&str], wanted: &str) -> Option<usize> {
+    let wanted = wanted.trim();
+    if wanted.is_empty() {
+        return None;
+    }
+    names.iter().position(|name| *name == wanted).or_else(|| {
+        let wanted = wanted.to_lowercase();
+        names.iter().position(|name| name.to_lowercase().contains(&wanted))
+    })
+}
+
+/// Pick a stream configuration we can handle
+///
+/// Prefers running the hardware at `SAMPLE_RATE` (no resampling), then the
+/// device default, in the best sample format available (f32, i16, u16).
+#[cfg(not(target_os = "redox"))]
+fn negotiate_config(
+    ranges: Vec<cpal::SupportedStreamConfigRange>,
+    default: Option<cpal::SupportedStreamConfig>,
+) -> Result<cpal::SupportedStreamConfig, Box<dyn std::error::Error>> {
+    let rank = |format: SampleFormat| PREFERRED_FORMATS.iter().position(|&f| f == format);
+    let native = cpal::SampleRate(SAMPLE_RATE);
+
+    let mut usable: Vec<_> = ranges.into_iter().filter(|r| rank(r.sample_format()).is_some()).collect();
+    usable.sort_by_key(|r| (rank(r.sample_format()), r.channels()));
+
+    if let Some(range) = usable
+        .iter()
+        .find(|r| r.min_sample_rate() <= native && native <= r.max_sample_rate())
+    {
+        return Ok(range.clone().with_sample_rate(native));
+    }
+
+    if let Some(default) = default {
+        if rank(default.sample_format()).is_some() {
+            return Ok(default);
+        }
+        // Keep the default rate if the best format supports it
+        let rate = default.sample_rate();
+        if let Some(range) = usable.first() {
+            let rate = rate.clamp(range.min_sample_rate(), range.max_sample_rate());
+            return Ok(range.clone().with_sample_rate(rate));
+        }
+    }
+
+    usable
+        .into_iter()
+        .next()
+        .map(|r| r.with_max_sample_rate())
+        .ok_or_else(|| "Device has no f32/i16/u16 stream configuration".into())
+}
+
+/// Capture stream: downmix to mono, convert to f32 and resample to `SAMPLE_RATE`
+#[cfg(not(target_os = "redox"))]
+fn build_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    (buffer, reference): (Arc<Mutex<CaptureBuffer>>, Arc<Mutex<Option<EchoReference>>>),
+) -> Result<cpal::Stream, Box<dyn std::error::Error>>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    let mut resampler = Resampler::new(config.sample_rate.0, SAMPLE_RATE);
+    let mut mono = Vec::new();
+
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            mono.clear();
+            mono.extend(
+                data.chunks(channels)
+                    .map(|frame| frame.iter().map(|&s| f32::from_sample(s)).sum::<f32>() / frame.len() as f32),
+            );
+            let resampled = resampler.process(&mono);
+            // Pair with what the speaker played since the last callback
+            if let (Ok(mut buffer), Ok(reference)) = (buffer.lock(), reference.lock()) {
+                buffer.push(&resampled, reference.as_ref());
+            }
+        },
+        |err| eprintln!("❌ Input error: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Playback stream: copy mono samples (already at the device rate) to every channel
+///
+/// Everything handed to the sound card, silence included, is also recorded
+/// at `SAMPLE_RATE` as the echo reference.
+#[cfg(not(target_os = "redox"))]
+fn build_output_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    (buffer, reference): (Arc<Mutex<VecDeque<f32>>>, EchoReference),
+) -> Result<cpal::Stream, Box<dyn std::error::Error>>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels.max(1) as usize;
+    let mut resampler = Resampler::new(config.sample_rate.0, SAMPLE_RATE);
+    let mut played = Vec::new();
+
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            played.clear();
+            let mut buffer = buffer.lock().ok();
+            for frame in data.chunks_mut(channels) {
+                let sample = buffer.as_mut().and_then(|b| b.pop_front()).unwrap_or(0.0);
+                played.push(sample);
+                let sample = T::from_sample(sample);
+                for s in frame.iter_mut() {
+                    *s = sample;
+                }
+            }
+            drop(buffer);
+            reference.push(&resampler.process(&played));
+        },
+        |err| eprintln!("❌ Output error: {}", err),
+        None,
+    )?;
+    Ok(stream)
 }
 
 pub struct RingBuffer {
@@ -207,3 +531,48 @@ impl RingBuffer {
 
 pub const BUFFER_SIZE: usize = 16000;
 pub const BIT_DEPTH: u16 = 16;
+
+#[cfg(all(test, not(target_os = "redox")))]
+mod tests {
+    use super::*;
+    use cpal::{SampleRate, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange};
+
+    fn range(channels: u16, min: u32, max: u32, format: SampleFormat) -> SupportedStreamConfigRange {
+        SupportedStreamConfigRange::new(channels, SampleRate(min), SampleRate(max), SupportedBufferSize::Unknown, format)
+    }
+
+    #[test]
+    fn test_match_device_name() {
+        let names = ["HDA Intel PCH: ALC257 Analog", "USB Headset Mic", "USB"];
+        assert_eq!(match_device_name(&names, "USB"), Some(2));
+        assert_eq!(match_device_name(&names, "headset"), Some(1));
+        assert_eq!(match_device_name(&names, "alc257"), Some(0));
+        assert_eq!(match_device_name(&names, "Bluetooth"), None);
+        assert_eq!(match_device_name(&names, "  "), None);
+    }
+
+    #[test]
+    fn test_negotiate_prefers_native_rate_and_f32() {
+        let ranges = vec![
+            range(2, 44100, 48000, SampleFormat::F32),
+            range(2, 8000, 48000, SampleFormat::I16),
+            range(1, 8000, 48000, SampleFormat::I16),
+        ];
+        let config = negotiate_config(ranges, None).unwrap();
+        assert_eq!(config.sample_rate().0, SAMPLE_RATE);
+        assert_eq!(config.sample_format(), SampleFormat::I16);
+        assert_eq!(config.channels(), 1);
+    }
+
+    #[test]
+    fn test_negotiate_falls_back_to_default() {
+        let default = SupportedStreamConfig::new(2, SampleRate(48000), SupportedBufferSize::Unknown, SampleFormat::U16);
+        let ranges = vec![range(2, 48000, 48000, SampleFormat::U16)];
+        let config = negotiate_config(ranges, Some(default)).unwrap();
+        assert_eq!(config.sample_rate().0, 48000);
+        assert_eq!(config.sample_format(), SampleFormat::U16);
+
+        // Formats we cannot convert are rejected
+        assert!(negotiate_config(vec![range(2, 48000, 48000, SampleFormat::I32)], None).is_err());
+    }
+}
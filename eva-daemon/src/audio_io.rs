@@ -0,0 +1,306 @@
+//! Audio sources and sinks
+//!
+//! The conversation loop reads microphone chunks from an `AudioSource` and
+//! the player writes to an `AudioSink`. `AudioDevice` is the live
+//! implementation of both; WAV files stand in for them in `--replay` mode,
+//! so wake word, VAD and turn handling can run without sound hardware.
+//! `TurnRecorder` saves every captured turn (`--record-turns`); those files
+//! replay as-is, since they were recorded after echo cancellation and AGC.
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use futures_util::future::LocalBoxFuture;
+
+use crate::audio::{CHUNK_SIZE, SAMPLE_RATE};
+use crate::audio_processing::EchoReference;
+use crate::resample::resample;
+
+/// Pending `AudioSource::next_chunk`
+pub type NextChunk<'a> = LocalBoxFuture<'a, Result<Option<Vec<f32>>, Box<dyn Error>>>;
+
+/// Where microphone audio comes from
+pub trait AudioSource {
+    /// Next `CHUNK_SIZE` samples, mono f32 at `SAMPLE_RATE` (`None` = input ended)
+    fn next_chunk(&mut self) -> NextChunk<'_>;
+}
+
+/// Where EVA's voice goes
+pub trait AudioSink {
+    /// Queue mono f32 samples at `SAMPLE_RATE`
+    fn play(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>>;
+
+    /// Samples queued but not played yet
+    fn queued_output(&self) -> usize;
+
+    /// Drop everything queued (stops speech immediately)
+    fn clear_output(&mut self);
+
+    /// What the sink plays, for the microphone's echo canceller
+    fn echo_reference(&self) -> EchoReference;
+}
+
+/// A WAV file played back as a microphone
+pub struct WavSource {
+    samples: Vec<f32>,
+    position: usize,
+}
+
+impl WavSource {
+    /// Read a 16-bit PCM WAV (any rate, mixed down to mono)
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let (samples, rate) = crate::tts::decode_wav(&fs::read(path)?)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self::from_samples(resample(&samples, rate, SAMPLE_RATE)))
+    }
+
+    /// Samples already at `SAMPLE_RATE`
+    pub fn from_samples(samples: Vec<f32>) -> Self {
+        Self { samples, position: 0 }
+    }
+
+    /// Seconds of audio not yet read
+    pub fn remaining_seconds(&self) -> f32 {
+        (self.samples.len() - self.position) as f32 / SAMPLE_RATE as f32
+    }
+}
+
+impl AudioSource for WavSource {
+    /// Full chunks as fast as they are read; the last one is padded with silence
+    fn next_chunk(&mut self) -> NextChunk<'_> {
+        Box::pin(async move {
+            // Let whatever shares the task (playback, UI) run between chunks
+            tokio::task::yield_now().await;
+
+            if self.position >= self.samples.len() {
+                return Ok(None);
+            }
+            let end = (self.position + CHUNK_SIZE).min(self.samples.len());
+            let mut chunk = self.samples[self.position..end].to_vec();
+            chunk.resize(CHUNK_SIZE, 0.0);
+            self.position = end;
+            Ok(Some(chunk))
+        })
+    }
+}
+
+/// 16-bit mono WAV written incrementally
+///
+/// The header is rewritten after every write, so the file is valid even if
+/// the daemon is killed.
+pub struct WavWriter {
+    file: File,
+    path: PathBuf,
+    data_bytes: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(path)?;
+        file.write_all(&crate::tts::encode_wav(&[], SAMPLE_RATE))?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            data_bytes: 0,
+        })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        let data = crate::tts::samples_to_pcm16(samples);
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&data)?;
+        self.data_bytes += data.len() as u32;
+
+        // RIFF size at byte 4, data size at byte 40
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_bytes).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_bytes.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Speaker that writes to a WAV file; playback is instant
+pub struct WavSink {
+    writer: WavWriter,
+    echo_reference: EchoReference,
+}
+
+impl WavSink {
+    pub fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            writer: WavWriter::create(path)?,
+            echo_reference: EchoReference::new(),
+        })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn play(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        self.writer.write(samples)
+    }
+
+    fn queued_output(&self) -> usize {
+        0
+    }
+
+    fn clear_output(&mut self) {}
+
+    fn echo_reference(&self) -> EchoReference {
+        self.echo_reference.clone()
+    }
+}
+
+/// Saves the audio of each turn to `<dir>/turn-<time>-<n>.wav`
+pub struct TurnRecorder {
+    dir: PathBuf,
+    turns: usize,
+    current: Option<WavWriter>,
+}
+
+impl TurnRecorder {
+    pub fn new(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            turns: 0,
+            current: None,
+        })
+    }
+
+    /// Default location: `~/.eva/recordings`
+    pub fn default_dir() -> Option<PathBuf> {
+        #[cfg(target_os = "windows")]
+        let home = std::env::var("USERPROFILE").ok()?;
+        #[cfg(not(target_os = "windows"))]
+        let home = std::env::var("HOME").ok()?;
+
+        Some(PathBuf::from(home).join(".eva").join("recordings"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Append captured audio to the current turn (starting one if needed)
+    pub fn push(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        if self.current.is_none() {
+            self.turns += 1;
+            let name = format!("turn-{}-{}.wav", chrono::Local::now().format("%Y%m%d-%H%M%S"), self.turns);
+            self.current = Some(WavWriter::create(&self.dir.join(name))?);
+        }
+        match self.current {
+            Some(ref mut writer) => writer.write(samples),
+            None => Ok(()),
+        }
+    }
+
+    /// Close the current turn, returning its file
+    pub fn finish_turn(&mut self) -> Option<PathBuf> {
+        self.current.take().map(|writer| writer.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vad::{self, VadEvent, VoiceActivity};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eva-audio-io-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn tone(seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| (2.0 * std::f32::consts::PI * 300.0 * i as f32 / SAMPLE_RATE as f32).sin() * 0.3)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_wav_source_chunks_and_resamples() {
+        let dir = temp_dir("source");
+        let path = dir.join("in.wav");
+        // 0.25s at 8kHz becomes 4000 samples at 16kHz: 2 full chunks + a padded one
+        fs::write(&path, crate::tts::encode_wav(&vec![0.1; 2000], 8000)).unwrap();
+
+        let mut source = WavSource::open(&path).unwrap();
+        assert!((source.remaining_seconds() - 0.25).abs() < 0.01);
+        let mut chunks = Vec::new();
+        while let Some(chunk) = source.next_chunk().await.unwrap() {
+            assert_eq!(chunk.len(), CHUNK_SIZE);
+            chunks.push(chunk);
+        }
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2][CHUNK_SIZE - 1], 0.0);
+        assert!(source.next_chunk().await.unwrap().is_none());
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_wav_writer_is_valid_after_each_write() {
+        let dir = temp_dir("writer");
+        let path = dir.join("out.wav");
+        let mut writer = WavWriter::create(&path).unwrap();
+
+        writer.write(&[0.5; 100]).unwrap();
+        let (samples, rate) = crate::tts::decode_wav(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!((samples.len(), rate), (100, SAMPLE_RATE));
+
+        writer.write(&[-0.5; 60]).unwrap();
+        let (samples, _) = crate::tts::decode_wav(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(samples.len(), 160);
+        assert!((samples[159] + 0.5).abs() < 1e-3);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_turn_recorder_one_file_per_turn() {
+        let dir = temp_dir("turns");
+        let mut recorder = TurnRecorder::new(&dir).unwrap();
+        assert_eq!(recorder.finish_turn(), None);
+
+        recorder.push(&[0.1; CHUNK_SIZE]).unwrap();
+        recorder.push(&[0.1; CHUNK_SIZE]).unwrap();
+        let first = recorder.finish_turn().unwrap();
+        recorder.push(&[0.1; CHUNK_SIZE]).unwrap();
+        let second = recorder.finish_turn().unwrap();
+
+        assert_ne!(first, second);
+        let (samples, _) = crate::tts::decode_wav(&fs::read(&first).unwrap()).unwrap();
+        assert_eq!(samples.len(), 2 * CHUNK_SIZE);
+        fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_replayed_utterance_drives_vad() {
+        // 1s silence, 1s "speech", 1.5s silence
+        let mut audio = vec![0.0; SAMPLE_RATE as usize];
+        audio.extend(tone(1.0));
+        audio.extend(vec![0.0; SAMPLE_RATE as usize * 3 / 2]);
+        let mut source = WavSource::from_samples(audio);
+        let mut vad = VoiceActivity::new(vad::detector(None).0);
+
+        let mut events = Vec::new();
+        let mut chunk_index = 0;
+        while let Some(chunk) = source.next_chunk().await.unwrap() {
+            if let Some(event) = vad.process(&chunk).event {
+                events.push((event, chunk_index));
+            }
+            chunk_index += 1;
+        }
+
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert_eq!(events[0].0, VadEvent::SpeechStart);
+        assert!((10..14).contains(&events[0].1), "{:?}", events);
+        assert_eq!(events[1].0, VadEvent::SpeechEnd);
+        assert!((20..30).contains(&events[1].1), "{:?}", events);
+    }
+}
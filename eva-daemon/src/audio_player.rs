@@ -1,15 +1,123 @@
-use crate::audio::AudioDevice;
+use std::sync::{Arc, Mutex};
+
+use crate::audio::SAMPLE_RATE;
+use crate::audio_io::AudioSink;
+use crate::audio_processing::EchoReference;
+use crate::playback::{Playback, PlaybackProgress};
+use crate::resample::resample;
+use crate::tts::{self, SpeechInterrupt, TtsEngine, VoiceSettings};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 
+/// Keep about this much synthesized speech queued ahead of the speaker
+const TTS_LOOKAHEAD_SAMPLES: usize = SAMPLE_RATE as usize / 2;
+
 /// Audio player for Gemini responses
 pub struct AudioPlayer {
-    device: AudioDevice,
+    device: Box<dyn AudioSink>,
+    /// Shared with the blocking pool while a sentence is synthesized
+    tts: Arc<Mutex<Box<dyn TtsEngine>>>,
+    voice: VoiceSettings,
+    /// Why the preferred voice could not be used, until the UI takes it
+    tts_warning: Option<String>,
+    interrupt: SpeechInterrupt,
+    /// Jitter buffer for backend answers; every write to the device goes through it
+    playback: Playback,
 }
 
 impl AudioPlayer {
-    /// Create a new audio player
-    pub fn new(device: AudioDevice) -> Result<Self, Box<dyn std::error::Error>> {
-        Ok(Self { device })
+    /// Create a new audio player on a speaker (`AudioDevice`) or a WAV file
+    pub fn new(device: impl AudioSink + 'static) -> Result<Self, Box<dyn std::error::Error>> {
+        let voice = VoiceSettings::default();
+        let (engine, tts_warning) = tts::default_engine(&voice);
+        Ok(Self {
+            device: Box::new(device),
+            tts: Arc::new(Mutex::new(engine)),
+            voice,
+            tts_warning,
+            interrupt: SpeechInterrupt::new(),
+            playback: Playback::new(),
+        })
+    }
+
+    /// Use the profile's language and speed (re-selects the voice if the language changed)
+    pub fn set_voice(&mut self, voice: VoiceSettings) {
+        if voice.language != self.voice.language {
+            let (engine, warning) = tts::default_engine(&voice);
+            self.set_tts_engine(engine);
+            self.tts_warning = warning;
+        }
+        self.voice = voice;
+    }
+
+    /// Current language and speed
+    pub fn voice(&self) -> &VoiceSettings {
+        &self.voice
+    }
+
+    /// Replace the speech synthesizer
+    pub fn set_tts_engine(&mut self, engine: Box<dyn TtsEngine>) {
+        self.tts = Arc::new(Mutex::new(engine));
+    }
+
+    /// Name of the active speech synthesizer
+    pub fn tts_engine_name(&self) -> String {
+        self.tts.lock().map(|engine| engine.name().to_string()).unwrap_or_default()
+    }
+
+    /// Warning from the last voice selection, if any (reported once)
+    pub fn take_tts_warning(&mut self) -> Option<String> {
+        self.tts_warning.take()
+    }
+
+    /// Handle that stops `speak_text` from another task
+    pub fn interrupt_handle(&self) -> SpeechInterrupt {
+        self.interrupt.clone()
+    }
+
+    /// Stop speaking and drop any queued audio (with a short fade)
+    pub fn stop(&mut self) {
+        self.interrupt.interrupt();
+        self.playback.fade_out(&mut *self.device);
+    }
+
+    /// What the player sends to the speaker, for the microphone's echo canceller
+    pub fn echo_reference(&self) -> EchoReference {
+        self.device.echo_reference()
+    }
+
+    /// Whether audio is still queued for the speaker (or an answer is buffering)
+    pub fn is_playing(&self) -> bool {
+        self.device.queued_output() > 0 || self.playback.is_active()
+    }
+
+    /// Get ready to stream the next backend answer
+    pub fn begin_response(&mut self) {
+        self.playback.begin();
+    }
+
+    /// Queue backend answer audio (16-bit PCM at `sample_rate`) in the jitter buffer
+    pub fn queue_response(&mut self, audio_bytes: &[u8], sample_rate: u32) {
+        let samples = self.bytes_to_samples(audio_bytes);
+        self.playback.push(&samples, sample_rate, std::time::Instant::now());
+    }
+
+    /// The backend finished its answer; play out the rest
+    pub fn end_response(&mut self) {
+        self.playback.finish();
+    }
+
+    /// Feed the speaker from the jitter buffer; call every loop iteration
+    pub fn pump_response(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.playback.pump(&mut *self.device)
+    }
+
+    /// The answer is complete and has been played
+    pub fn response_done(&self) -> bool {
+        self.playback.is_done()
+    }
+
+    pub fn response_progress(&self) -> PlaybackProgress {
+        self.playback.progress()
     }
 
     /// Play audio response from base64 encoded data
@@ -21,15 +129,20 @@ impl AudioPlayer {
         let samples = self.bytes_to_samples(&audio_bytes);
         
         // Play through audio device
-        self.device.play(&samples).await?;
+        self.playback.play_now(&mut *self.device, &samples)?;
         
         Ok(())
     }
 
     /// Play raw PCM audio bytes
     pub async fn play_pcm(&mut self, audio_bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.play_pcm_at(audio_bytes, SAMPLE_RATE).await
+    }
+
+    /// Play raw PCM audio bytes recorded at another rate (e.g. Gemini's 24kHz)
+    pub async fn play_pcm_at(&mut self, audio_bytes: &[u8], sample_rate: u32) -> Result<(), Box<dyn std::error::Error>> {
         let samples = self.bytes_to_samples(audio_bytes);
-        self.device.play(&samples).await?;
+        self.playback.play_now(&mut *self.device, &resample(&samples, sample_rate, SAMPLE_RATE))?;
         Ok(())
     }
 
@@ -63,20 +176,70 @@ impl AudioPlayer {
             .collect()
     }
 
-    /// Play text-to-speech (for testing without audio data)
+    /// Speak text with the local TTS engine
+    ///
+    /// Sentences are synthesized one at a time and queued just ahead of the
+    /// speaker, so playback starts after the first sentence and an interrupt
+    /// (see `interrupt_handle`) takes effect within one polling interval.
     pub async fn speak_text(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
         println!("🔊 EVA: {}", text);
-        
-        // TODO: In future, convert text to speech locally
-        // For now, just print the text
-        
+        self.interrupt.reset();
+
+        for sentence in tts::split_sentences(text) {
+            if self.interrupt.is_interrupted() {
+                break;
+            }
+
+            let samples = self.synthesize(sentence).await?;
+            if samples.is_empty() {
+                continue;
+            }
+
+            if !self.wait_for_playback(TTS_LOOKAHEAD_SAMPLES).await {
+                break;
+            }
+            self.play_pcm(&tts::samples_to_pcm16(&samples)).await?;
+        }
+
+        // Let the last sentence finish (or cut it short)
+        self.wait_for_playback(0).await;
+
         Ok(())
     }
+
+    /// Synthesize on the blocking pool, so capture and detection keep running
+    async fn synthesize(&self, sentence: String) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let tts = Arc::clone(&self.tts);
+        let voice = self.voice.clone();
+        let samples = tokio::task::spawn_blocking(move || {
+            let mut engine = tts.lock().map_err(|_| "TTS engine panicked".to_string())?;
+            engine.synthesize(&sentence, &voice).map_err(|e| e.to_string())
+        })
+        .await??;
+        Ok(samples)
+    }
+
+    /// Wait until at most `max_queued` samples are waiting to play
+    ///
+    /// Returns false if speech was interrupted meanwhile (and fades it out).
+    async fn wait_for_playback(&mut self, max_queued: usize) -> bool {
+        loop {
+            if self.interrupt.is_interrupted() {
+                self.playback.fade_out(&mut *self.device);
+                return false;
+            }
+            if self.device.queued_output() <= max_queued {
+                return true;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audio::AudioDevice;
 
     #[test]
     fn test_bytes_to_samples() {
@@ -118,6 +281,20 @@ mod tests {
         assert!(samples.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_plays_into_wav_sink() {
+        let path = std::env::temp_dir().join(format!("eva-player-{}.wav", std::process::id()));
+        let mut player = AudioPlayer::new(crate::audio_io::WavSink::create(&path).unwrap()).unwrap();
+
+        player.play_pcm(&[0x00, 0x00, 0xFF, 0x7F]).await.unwrap();
+        assert!(!player.is_playing());
+
+        let (samples, _) = tts::decode_wav(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert!((samples[1] - 1.0).abs() < 0.01);
+        std::fs::remove_file(path).ok();
+    }
+
     #[tokio::test]
     async fn test_speak_text() {
         let device = AudioDevice::new().unwrap();
@@ -126,4 +303,41 @@ mod tests {
         // Should not panic
         player.speak_text("Hello, world!").await.unwrap();
     }
+
+    /// Takes its time like espeak or Piper on a slow machine
+    struct SlowEngine;
+
+    impl TtsEngine for SlowEngine {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn synthesize(&mut self, _text: &str, _voice: &VoiceSettings) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            Ok(vec![0.0; 160])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_synthesis_does_not_block_local_tasks() {
+        let path = std::env::temp_dir().join(format!("eva-slow-tts-{}.wav", std::process::id()));
+        let mut player = AudioPlayer::new(crate::audio_io::WavSink::create(&path).unwrap()).unwrap();
+        player.set_tts_engine(Box::new(SlowEngine));
+        assert_eq!(player.tts_engine_name(), "slow");
+
+        let local = tokio::task::LocalSet::new();
+        let ticks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = ticks.clone();
+        local.spawn_local(async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                counter.set(counter.get() + 1);
+            }
+        });
+        local.run_until(player.speak_text("Um. Dois.")).await.unwrap();
+
+        // Two sentences of 300ms each; a blocking engine would allow no ticks
+        assert!(ticks.get() >= 20, "only {} ticks while speaking", ticks.get());
+        std::fs::remove_file(path).ok();
+    }
 }
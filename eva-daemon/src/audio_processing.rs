@@ -0,0 +1,611 @@
+//! Capture-side audio processing
+//!
+//! Runs on every microphone chunk before the wake word detector, VAD and
+//! streaming: acoustic echo cancellation against what the speaker is
+//! playing, then noise suppression and automatic gain control.
+//!
+//! Every stage is block based, so each adds a small fixed delay (16ms for
+//! echo cancellation, 32ms for noise suppression) but always returns as
+//! many samples as it was given.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::SAMPLE_RATE;
+use crate::fft::Fft;
+
+/// Echo canceller block size (16ms)
+const AEC_BLOCK: usize = 256;
+/// Filter partitions; AEC_BLOCK * AEC_PARTITIONS samples of echo tail (128ms)
+const AEC_PARTITIONS: usize = 8;
+/// Adaptation step size (0..1, higher = faster but noisier)
+const AEC_STEP: f32 = 0.4;
+/// Smoothing of the reference power estimate
+const AEC_POWER_SMOOTHING: f32 = 0.9;
+/// Near-end speech is assumed when the mic peak exceeds the reference peak
+/// times this (Geigel double-talk detector); adaptation pauses meanwhile
+const DOUBLE_TALK_THRESHOLD: f32 = 1.0;
+/// Reference peak below which there is no echo to learn from
+const SILENT_REFERENCE: f32 = 1e-3;
+
+/// Noise suppressor frame (32ms) and hop (50% overlap)
+const NS_FRAME: usize = 512;
+const NS_HOP: usize = NS_FRAME / 2;
+/// Frames used to seed the noise estimate (~160ms)
+const NS_LEARNING_FRAMES: usize = 10;
+/// Bins below this multiple of the noise estimate are treated as noise
+const NS_NOISE_RATIO: f32 = 4.0;
+/// Per-frame growth of the noise estimate during speech (~3dB/s)
+const NS_NOISE_RISE: f32 = 1.005;
+/// Over-subtraction factor and minimum gain (-20dB)
+const NS_OVERSUBTRACTION: f32 = 1.5;
+const NS_GAIN_FLOOR: f32 = 0.1;
+
+/// AGC analysis block (10ms)
+const AGC_BLOCK: usize = SAMPLE_RATE as usize / 100;
+
+/// Played audio kept for the echo canceller while the mic is not reading
+const MAX_REFERENCE: usize = SAMPLE_RATE as usize;
+/// Reference may run this far ahead of the mic before it is dropped (clock drift)
+const MAX_REFERENCE_LAG: usize = SAMPLE_RATE as usize / 10;
+
+/// Which capture processing stages run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingConfig {
+    /// Remove EVA's own voice from the microphone
+    pub echo_cancellation: bool,
+    /// Attenuate stationary background noise (fans, hum)
+    pub noise_suppression: bool,
+    /// Normalize the microphone level
+    pub auto_gain: bool,
+}
+
+impl Default for ProcessingConfig {
+    fn default() -> Self {
+        Self {
+            echo_cancellation: true,
+            noise_suppression: true,
+            auto_gain: false,
+        }
+    }
+}
+
+/// Speaker output as heard by the echo canceller (mono, `SAMPLE_RATE`)
+///
+/// The output stream pushes what it hands to the sound card; the input
+/// stream takes as many samples as it captured, so both stay aligned.
+#[derive(Clone, Default)]
+pub struct EchoReference {
+    played: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl EchoReference {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record samples as they are handed to the speaker
+    pub fn push(&self, samples: &[f32]) {
+        if let Ok(mut played) = self.played.lock() {
+            played.extend(samples);
+            let excess = played.len().saturating_sub(MAX_REFERENCE);
+            played.drain(..excess);
+        }
+    }
+
+    /// Take the next `count` played samples (zeros if the speaker fell behind)
+    pub fn take(&self, count: usize) -> Vec<f32> {
+        let mut samples = Vec::with_capacity(count);
+        if let Ok(mut played) = self.played.lock() {
+            // The output clock ran ahead of the input clock; skip to stay in the filter's reach
+            let excess = played.len().saturating_sub(count + MAX_REFERENCE_LAG);
+            played.drain(..excess);
+
+            let available = played.len().min(count);
+            samples.extend(played.drain(..available));
+        }
+        samples.resize(count, 0.0);
+        samples
+    }
+}
+
+/// Complex spectrum (real and imaginary parts)
+#[derive(Clone)]
+struct Spectrum {
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl Spectrum {
+    fn zeros(size: usize) -> Self {
+        Self { re: vec![0.0; size], im: vec![0.0; size] }
+    }
+}
+
+/// Acoustic echo canceller
+///
+/// Partitioned-block frequency-domain NLMS filter: models the path from
+/// the speaker to the microphone and subtracts the predicted echo.
+pub struct EchoCanceller {
+    fft: Fft,
+    /// Reference spectra, newest first
+    history: VecDeque<Spectrum>,
+    /// Reference block peaks, newest first (for double-talk detection)
+    peaks: VecDeque<f32>,
+    weights: Vec<Spectrum>,
+    power: Vec<f32>,
+    prev_reference: Vec<f32>,
+    mic_in: Vec<f32>,
+    reference_in: Vec<f32>,
+    output: VecDeque<f32>,
+}
+
+impl EchoCanceller {
+    pub fn new() -> Self {
+        let size = 2 * AEC_BLOCK;
+        Self {
+            fft: Fft::new(size),
+            history: (0..AEC_PARTITIONS).map(|_| Spectrum::zeros(size)).collect(),
+            peaks: VecDeque::from(vec![0.0; AEC_PARTITIONS]),
+            weights: vec![Spectrum::zeros(size); AEC_PARTITIONS],
+            power: vec![0.0; size],
+            prev_reference: vec![0.0; AEC_BLOCK],
+            mic_in: Vec::new(),
+            reference_in: Vec::new(),
+            output: VecDeque::from(vec![0.0; AEC_BLOCK]),
+        }
+    }
+
+    /// Remove the echo of `reference` (played audio) from `mic`
+    pub fn process(&mut self, mic: &[f32], reference: &[f32]) -> Vec<f32> {
+        self.mic_in.extend_from_slice(mic);
+        self.reference_in.extend_from_slice(reference);
+        // A short reference means nothing was playing
+        self.reference_in.resize(self.mic_in.len(), 0.0);
+
+        while self.mic_in.len() >= AEC_BLOCK {
+            let d: Vec<f32> = self.mic_in.drain(..AEC_BLOCK).collect();
+            let x: Vec<f32> = self.reference_in.drain(..AEC_BLOCK).collect();
+            let e = self.process_block(&d, &x);
+            self.output.extend(e);
+        }
+
+        self.output.drain(..mic.len()).collect()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn process_block(&mut self, d: &[f32], x: &[f32]) -> Vec<f32> {
+        let size = 2 * AEC_BLOCK;
+
+        // Overlap-save: transform [previous block, current block]
+        let mut spectrum = Spectrum::zeros(size);
+        spectrum.re[..AEC_BLOCK].copy_from_slice(&self.prev_reference);
+        spectrum.re[AEC_BLOCK..].copy_from_slice(x);
+        self.fft.forward(&mut spectrum.re, &mut spectrum.im);
+        self.prev_reference.copy_from_slice(x);
+
+        self.history.pop_back();
+        self.history.push_front(spectrum);
+        self.peaks.pop_back();
+        self.peaks.push_front(x.iter().fold(0.0f32, |m, s| m.max(s.abs())));
+
+        // Echo estimate
+        let mut y = Spectrum::zeros(size);
+        for (w, xp) in self.weights.iter().zip(&self.history) {
+            for k in 0..size {
+                y.re[k] += w.re[k] * xp.re[k] - w.im[k] * xp.im[k];
+                y.im[k] += w.re[k] * xp.im[k] + w.im[k] * xp.re[k];
+            }
+        }
+        self.fft.inverse(&mut y.re, &mut y.im);
+        let e: Vec<f32> = d.iter().zip(&y.re[AEC_BLOCK..]).map(|(d, y)| d - y).collect();
+
+        // Reference power per bin, over the whole filter length
+        for k in 0..size {
+            let total: f32 = self.history.iter().map(|xp| xp.re[k] * xp.re[k] + xp.im[k] * xp.im[k]).sum();
+            self.power[k] = AEC_POWER_SMOOTHING * self.power[k] + (1.0 - AEC_POWER_SMOOTHING) * total;
+        }
+
+        let reference_peak = self.peaks.iter().fold(0.0f32, |m, &p| m.max(p));
+        let mic_peak = d.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let double_talk = mic_peak > reference_peak * DOUBLE_TALK_THRESHOLD;
+        if reference_peak > SILENT_REFERENCE && !double_talk {
+            self.adapt(&e);
+        }
+
+        e
+    }
+
+    fn adapt(&mut self, e: &[f32]) {
+        let size = 2 * AEC_BLOCK;
+        let regularization = size as f32 * 1e-4;
+
+        let mut error = Spectrum::zeros(size);
+        error.re[AEC_BLOCK..].copy_from_slice(e);
+        self.fft.forward(&mut error.re, &mut error.im);
+
+        for (w, xp) in self.weights.iter_mut().zip(&self.history) {
+            // Gradient: conj(X) * E, normalized per bin
+            let mut gradient = Spectrum::zeros(size);
+            for k in 0..size {
+                let norm = AEC_STEP / (self.power[k] + regularization);
+                gradient.re[k] = (xp.re[k] * error.re[k] + xp.im[k] * error.im[k]) * norm;
+                gradient.im[k] = (xp.re[k] * error.im[k] - xp.im[k] * error.re[k]) * norm;
+            }
+
+            // Keep the filter causal and AEC_BLOCK long (no circular wrap-around)
+            self.fft.inverse(&mut gradient.re, &mut gradient.im);
+            gradient.re[AEC_BLOCK..].iter_mut().for_each(|v| *v = 0.0);
+            gradient.im.iter_mut().for_each(|v| *v = 0.0);
+            self.fft.forward(&mut gradient.re, &mut gradient.im);
+
+            for k in 0..size {
+                w.re[k] += gradient.re[k];
+                w.im[k] += gradient.im[k];
+            }
+        }
+    }
+}
+
+impl Default for EchoCanceller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spectral-subtraction noise suppressor
+///
+/// Tracks the noise level per frequency bin (averaging bins that look like
+/// noise, creeping up slowly otherwise) and attenuates bins close to it.
+pub struct NoiseSuppressor {
+    fft: Fft,
+    window: Vec<f32>,
+    frame: Vec<f32>,
+    overlap: Vec<f32>,
+    noise: Vec<f32>,
+    gains: Vec<f32>,
+    frames_seen: usize,
+    input: Vec<f32>,
+    output: VecDeque<f32>,
+}
+
+impl NoiseSuppressor {
+    pub fn new() -> Self {
+        // sqrt-Hann analysis and synthesis windows sum to one at 50% overlap
+        let window = (0..NS_FRAME)
+            .map(|i| (0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / NS_FRAME as f32).cos()).sqrt())
+            .collect();
+        let bins = NS_FRAME / 2 + 1;
+
+        Self {
+            fft: Fft::new(NS_FRAME),
+            window,
+            frame: vec![0.0; NS_FRAME],
+            overlap: vec![0.0; NS_FRAME],
+            noise: vec![0.0; bins],
+            gains: vec![1.0; bins],
+            frames_seen: 0,
+            input: Vec::new(),
+            output: VecDeque::from(vec![0.0; NS_HOP]),
+        }
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+        while self.input.len() >= NS_HOP {
+            let hop: Vec<f32> = self.input.drain(..NS_HOP).collect();
+            self.process_hop(&hop);
+        }
+        self.output.drain(..samples.len()).collect()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn process_hop(&mut self, hop: &[f32]) {
+        self.frame.copy_within(NS_HOP.., 0);
+        self.frame[NS_FRAME - NS_HOP..].copy_from_slice(hop);
+
+        let mut re: Vec<f32> = self.frame.iter().zip(&self.window).map(|(s, w)| s * w).collect();
+        let mut im = vec![0.0; NS_FRAME];
+        self.fft.forward(&mut re, &mut im);
+
+        let learning = self.frames_seen < NS_LEARNING_FRAMES;
+        for k in 0..self.noise.len() {
+            let power = re[k] * re[k] + im[k] * im[k];
+
+            if learning {
+                // Running mean of the first frames
+                self.noise[k] += (power - self.noise[k]) / (self.frames_seen + 1) as f32;
+            } else if power < NS_NOISE_RATIO * self.noise[k] {
+                self.noise[k] = 0.95 * self.noise[k] + 0.05 * power;
+            } else {
+                self.noise[k] *= NS_NOISE_RISE;
+            }
+
+            let gain = if power > 0.0 {
+                (1.0 - NS_OVERSUBTRACTION * self.noise[k] / power).max(NS_GAIN_FLOOR)
+            } else {
+                NS_GAIN_FLOOR
+            };
+            // Smoothing over time reduces "musical noise"
+            self.gains[k] = 0.7 * gain + 0.3 * self.gains[k];
+
+            let g = self.gains[k];
+            re[k] *= g;
+            im[k] *= g;
+            if k > 0 && k < NS_FRAME / 2 {
+                re[NS_FRAME - k] *= g;
+                im[NS_FRAME - k] *= g;
+            }
+        }
+        self.frames_seen += 1;
+
+        self.fft.inverse(&mut re, &mut im);
+        for (i, (o, s)) in self.overlap.iter_mut().zip(&re).enumerate() {
+            *o += s * self.window[i];
+        }
+
+        self.output.extend(&self.overlap[..NS_HOP]);
+        self.overlap.copy_within(NS_HOP.., 0);
+        self.overlap[NS_FRAME - NS_HOP..].iter_mut().for_each(|v| *v = 0.0);
+    }
+}
+
+impl Default for NoiseSuppressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Automatic gain control
+///
+/// Follows the speech level (fast attack, slow release) and steers the
+/// gain toward a target RMS. Near-silence holds the current gain instead
+/// of amplifying the noise floor.
+pub struct AutomaticGainControl {
+    target: f32,
+    gate: f32,
+    min_gain: f32,
+    max_gain: f32,
+    envelope: f32,
+    gain: f32,
+}
+
+impl AutomaticGainControl {
+    pub fn new() -> Self {
+        Self {
+            target: 0.1,
+            gate: 0.005,
+            min_gain: 0.1,
+            max_gain: 10.0, // +20dB
+            envelope: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for block in samples.chunks_mut(AGC_BLOCK) {
+            let rms = (block.iter().map(|s| s * s).sum::<f32>() / block.len() as f32).sqrt();
+            let rate = if rms > self.envelope { 0.5 } else { 0.05 };
+            self.envelope += (rms - self.envelope) * rate;
+
+            // Only speech-level blocks steer the gain
+            if rms > self.gate && self.envelope > self.gate {
+                let desired = (self.target / self.envelope).clamp(self.min_gain, self.max_gain);
+                self.gain += (desired - self.gain) * 0.1;
+            }
+
+            for s in block.iter_mut() {
+                *s = (*s * self.gain).clamp(-1.0, 1.0);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for AutomaticGainControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Echo cancellation, noise suppression and AGC in one chain
+pub struct AudioProcessor {
+    config: ProcessingConfig,
+    echo: EchoCanceller,
+    noise: NoiseSuppressor,
+    agc: AutomaticGainControl,
+}
+
+impl AudioProcessor {
+    pub fn new(config: ProcessingConfig) -> Self {
+        Self {
+            config,
+            echo: EchoCanceller::new(),
+            noise: NoiseSuppressor::new(),
+            agc: AutomaticGainControl::new(),
+        }
+    }
+
+    /// Clean one microphone chunk; `reference` is what the speaker played meanwhile
+    pub fn process(&mut self, mic: &[f32], reference: &[f32]) -> Vec<f32> {
+        let mut samples = if self.config.echo_cancellation {
+            self.echo.process(mic, reference)
+        } else {
+            mic.to_vec()
+        };
+
+        if self.config.noise_suppression {
+            samples = self.noise.process(&samples);
+        }
+        if self.config.auto_gain {
+            self.agc.process(&mut samples);
+        }
+
+        samples
+    }
+
+    pub fn reset(&mut self) {
+        self.echo.reset();
+        self.noise.reset();
+        self.agc.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic white noise in [-amplitude, amplitude]
+    fn noise(len: usize, amplitude: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                ((state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    fn sine(len: usize, freq: f32, amplitude: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / SAMPLE_RATE as f32).sin() * amplitude)
+            .collect()
+    }
+
+    fn energy(samples: &[f32]) -> f32 {
+        samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32
+    }
+
+    /// Speaker-to-mic path: 3ms delay, attenuation and a short reflection
+    fn room(reference: &[f32]) -> Vec<f32> {
+        (0..reference.len())
+            .map(|i| {
+                let tap = |delay: usize, gain: f32| if i >= delay { reference[i - delay] * gain } else { 0.0 };
+                tap(48, 0.5) + tap(300, -0.2) + tap(900, 0.05)
+            })
+            .collect()
+    }
+
+    fn run_aec(aec: &mut EchoCanceller, mic: &[f32], reference: &[f32]) -> Vec<f32> {
+        mic.chunks(1600)
+            .zip(reference.chunks(1600))
+            .flat_map(|(m, r)| aec.process(m, r))
+            .collect()
+    }
+
+    #[test]
+    fn test_echo_reference_alignment() {
+        let reference = EchoReference::new();
+        reference.push(&[0.1, 0.2, 0.3]);
+        assert_eq!(reference.take(2), vec![0.1, 0.2]);
+        assert_eq!(reference.take(3), vec![0.3, 0.0, 0.0]);
+
+        // Drift: keep only the most recent audio within MAX_REFERENCE_LAG
+        reference.push(&vec![1.0; MAX_REFERENCE_LAG + 500]);
+        reference.take(100);
+        assert_eq!(reference.played.lock().unwrap().len(), MAX_REFERENCE_LAG);
+    }
+
+    #[test]
+    fn test_echo_cancelled() {
+        let far = noise(SAMPLE_RATE as usize * 4, 0.5, 1);
+        let mic = room(&far);
+
+        let mut aec = EchoCanceller::new();
+        let out = run_aec(&mut aec, &mic, &far);
+        assert_eq!(out.len(), mic.len());
+
+        // After convergence the echo is at least 20dB down
+        let tail = mic.len() - SAMPLE_RATE as usize;
+        let erle = energy(&mic[tail..]) / energy(&out[tail..]);
+        assert!(erle > 100.0, "ERLE {:.1}", erle);
+    }
+
+    #[test]
+    fn test_near_end_speech_preserved() {
+        let len = SAMPLE_RATE as usize * 4;
+        let far = noise(len, 0.3, 2);
+        let near = sine(len, 440.0, 0.5);
+        let echo = room(&far);
+
+        // Converge on echo alone, then the user talks over it
+        let half = len / 2;
+        let mic: Vec<f32> = (0..len).map(|i| echo[i] + if i >= half { near[i] } else { 0.0 }).collect();
+
+        let mut aec = EchoCanceller::new();
+        let out = run_aec(&mut aec, &mic, &far);
+
+        let tail = len - SAMPLE_RATE as usize;
+        let level = energy(&out[tail..]) / energy(&near[tail..]);
+        assert!(level > 0.8 && level < 1.25, "near-end level {:.2}", level);
+    }
+
+    #[test]
+    fn test_silent_reference_passes_mic() {
+        let mic = sine(3200, 300.0, 0.3);
+        let mut aec = EchoCanceller::new();
+        let out = run_aec(&mut aec, &mic, &vec![0.0; 3200]);
+        // Delayed by one block, otherwise untouched
+        for i in AEC_BLOCK..mic.len() {
+            assert!((out[i] - mic[i - AEC_BLOCK]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_noise_suppressed_speech_kept() {
+        let mut ns = NoiseSuppressor::new();
+        let len = SAMPLE_RATE as usize;
+
+        let background = noise(len, 0.05, 3);
+        let out = ns.process(&background);
+        assert_eq!(out.len(), len);
+        let reduction = energy(&background[len / 2..]) / energy(&out[len / 2..]);
+        assert!(reduction > 4.0, "noise reduced {:.1}x", reduction);
+
+        let tone = sine(len / 2, 500.0, 0.3);
+        let mixed: Vec<f32> = tone.iter().zip(noise(len / 2, 0.05, 4)).map(|(t, n)| t + n).collect();
+        let out = ns.process(&mixed);
+        let kept = energy(&out[len / 4..]) / energy(&tone[len / 4..]);
+        assert!(kept > 0.8 && kept < 1.2, "tone kept {:.2}", kept);
+    }
+
+    #[test]
+    fn test_agc_levels() {
+        let mut agc = AutomaticGainControl::new();
+        let mut quiet = sine(SAMPLE_RATE as usize * 3, 200.0, 0.02);
+        agc.process(&mut quiet);
+        let rms = energy(&quiet[quiet.len() - 1600..]).sqrt();
+        assert!((rms - 0.1).abs() < 0.02, "{}", rms);
+
+        // Silence does not pump the gain up
+        let gain = agc.gain();
+        let mut silence = vec![0.0001; SAMPLE_RATE as usize];
+        agc.process(&mut silence);
+        assert!((agc.gain() - gain).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_processor_keeps_chunk_size() {
+        let mut processor = AudioProcessor::new(ProcessingConfig { auto_gain: true, ..Default::default() });
+        for _ in 0..5 {
+            assert_eq!(processor.process(&vec![0.01; 1600], &vec![0.0; 1000]).len(), 1600);
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Conversational backends
+//!
+//! The conversation loop talks to whichever service answers the user through
+//! `VoiceBackend`: it streams microphone PCM in and gets audio, text and
+//! control events back. `EvaMindClient` and `GeminiClient` implement it;
+//! `EVA_BACKEND` picks one at startup.
+
+use std::error::Error;
+
+use futures_util::future::LocalBoxFuture;
+
+use crate::codec;
+use crate::credentials::{Credentials, Identity};
+use crate::eva_mind::{EvaMindClient, EvaMindConfig};
+use crate::gemini::{GeminiClient, GeminiConfig};
+use crate::session::Role;
+use crate::tls;
+use crate::tools;
+use crate::websocket::ConnectOptions;
+
+/// Something the backend sent back
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendEvent {
+    /// 16-bit mono PCM to play
+    Audio { pcm: Vec<u8>, sample_rate: u32 },
+    /// Text of the answer (shown, not spoken)
+    Text(String),
+    /// What the user said or what EVA is saying (interim ones are replaced)
+    Transcript { role: Role, text: String, is_final: bool },
+    /// Run a voice command on this machine; answer with `send_command_result`
+    CommandRequest { id: String, command: String },
+    /// Call one of the declared `tools`; answer with `send_command_result`
+    ToolCall { id: String, name: String, args: serde_json::Value },
+    /// The backend finished answering this turn
+    TurnComplete,
+    /// The backend cut its answer short; stop playback
+    Interrupted,
+    /// The backend reported a problem (the connection is still up)
+    Error(String),
+    /// A message the backend's protocol doesn't define, as received
+    Control(serde_json::Value),
+}
+
+/// A service EVA holds a spoken conversation with
+pub trait VoiceBackend {
+    /// Shown in the UI ("EVA-Mind", "Gemini")
+    fn name(&self) -> &'static str;
+
+    fn session_id(&self) -> &str;
+
+    /// Whether answers end with `TurnComplete` (otherwise only silence tells)
+    fn reports_turn_end(&self) -> bool;
+
+    /// Open the conversation (after `connect`, before any audio)
+    fn start_session(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>>;
+
+    /// Stream user audio: 16-bit mono PCM at 16kHz
+    fn send_audio<'a>(&'a mut self, pcm: &'a [u8]) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+
+    /// Send a typed user message
+    fn send_text<'a>(&'a mut self, text: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+
+    /// Next event, waiting briefly (`None` = nothing arrived yet)
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Option<BackendEvent>, Box<dyn Error>>>;
+
+    /// The user stopped talking; answer now
+    fn end_turn(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>>;
+
+    /// Stop the answer in progress and drop what is still in flight
+    fn interrupt(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>>;
+
+    /// Outcome of a `CommandRequest` or `ToolCall`
+    fn send_command_result<'a>(
+        &'a mut self,
+        id: &'a str,
+        success: bool,
+        output: &'a str,
+    ) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+
+    /// Heartbeat; an error means the connection is gone
+    fn ping(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>>;
+
+    /// Hang up
+    fn close(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>>;
+}
+
+/// Which backend to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    EvaMind,
+    Gemini,
+}
+
+impl BackendKind {
+    /// Accepts "eva-mind"/"evamind" and "gemini" (any case)
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "eva-mind" | "evamind" | "eva_mind" => Some(Self::EvaMind),
+            "gemini" => Some(Self::Gemini),
+            _ => None,
+        }
+    }
+
+    /// `EVA_BACKEND`, EVA-Mind when unset
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        match std::env::var("EVA_BACKEND") {
+            Ok(name) => Self::parse(&name).ok_or_else(|| format!("Unknown EVA_BACKEND: {}", name).into()),
+            Err(_) => Ok(Self::EvaMind),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::EvaMind => "EVA-Mind",
+            Self::Gemini => "Gemini",
+        }
+    }
+}
+
+/// Connect to a backend; `languages` are the user's language tags, main one first
+///
+/// `resume` is the id of an earlier session to continue (after a dropped
+/// connection); backends that can't resume start a new one. `network`
+/// says how to reach it; `EVA_MIND_PINS` adds certificate pins for EVA-Mind
+/// and `EVA_AUDIO_CODEC` picks the codec it is offered first.
+pub async fn connect(
+    kind: BackendKind,
+    languages: Vec<String>,
+    credentials: Credentials,
+    network: ConnectOptions,
+    resume: Option<String>,
+) -> Result<Box<dyn VoiceBackend>, Box<dyn Error>> {
+    match kind {
+        BackendKind::EvaMind => {
+            let identity = credentials.identity.unwrap_or_else(|| Identity::new(""));
+            let mut config = EvaMindConfig {
+                languages,
+                session_id: resume,
+                cpf: identity.cpf,
+                user_type: identity.user_type,
+                token: credentials.eva_mind_token,
+                tools: tools::declarations(),
+                audio_codecs: codec::offered(std::env::var("EVA_AUDIO_CODEC").ok().as_deref())?,
+                network: ConnectOptions {
+                    pins: tls::parse_pins(&std::env::var("EVA_MIND_PINS").unwrap_or_default())?,
+                    ..network
+                },
+                ..EvaMindConfig::default()
+            };
+            if let Ok(url) = std::env::var("WS_URL") {
+                config.ws_url = url;
+            }
+            Ok(Box::new(EvaMindClient::connect(config).await?))
+        }
+        BackendKind::Gemini => {
+            let mut config = GeminiConfig {
+                languages,
+                api_key: credentials.google_api_key.unwrap_or_default(),
+                tools: tools::declarations(),
+                network,
+                ..GeminiConfig::default()
+            };
+            if let Ok(model) = std::env::var("MODEL_ID") {
+                config.model = model;
+            }
+            Ok(Box::new(GeminiClient::connect(config).await?))
+        }
+    }
+}
+
+/// Where `connect` will go, for the startup screen
+pub fn endpoint(kind: BackendKind) -> String {
+    match kind {
+        BackendKind::EvaMind => std::env::var("WS_URL").unwrap_or_else(|_| EvaMindConfig::default().ws_url),
+        BackendKind::Gemini => GeminiConfig::default().ws_url,
+    }
+}
+
+/// Sample rate from a mime type like "audio/pcm;rate=24000"
+pub fn pcm_rate(mime_type: &str) -> Option<u32> {
+    mime_type
+        .split(';')
+        .filter_map(|param| param.trim().strip_prefix("rate="))
+        .find_map(|rate| rate.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend_kind() {
+        assert_eq!(BackendKind::parse("gemini"), Some(BackendKind::Gemini));
+        assert_eq!(BackendKind::parse(" EVA-Mind "), Some(BackendKind::EvaMind));
+        assert_eq!(BackendKind::parse("evamind"), Some(BackendKind::EvaMind));
+        assert_eq!(BackendKind::parse("llama"), None);
+    }
+
+    #[test]
+    fn test_pcm_rate() {
+        assert_eq!(pcm_rate("audio/pcm;rate=24000"), Some(24000));
+        assert_eq!(pcm_rate("audio/pcm; rate=16000"), Some(16000));
+        assert_eq!(pcm_rate("audio/pcm"), None);
+    }
+}
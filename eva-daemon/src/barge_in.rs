@@ -0,0 +1,155 @@
+//! Barge-in detection
+//!
+//! Decides whether the user started talking while EVA is speaking. The
+//! microphone also picks up EVA's own voice, so the detector learns that
+//! echo level at the start of playback and only fires on speech clearly
+//! louder than it, sustained for a few chunks.
+
+/// Chunks (~100ms each) of sustained speech needed to interrupt
+const MIN_SPEECH_CHUNKS: usize = 3;
+/// Chunks at the start of playback used only to learn the echo level
+const WARMUP_CHUNKS: usize = 3;
+/// Speech must be this many times louder than the echo
+const ECHO_MARGIN: f32 = 2.5;
+/// Echo level smoothing (closer to 1 = slower)
+const ECHO_SMOOTHING: f32 = 0.9;
+/// Default quietest RMS level that counts as the user talking
+pub const DEFAULT_MIN_ENERGY: f32 = 0.03;
+
+pub struct BargeInDetector {
+    min_energy: f32,
+    echo_level: f32,
+    playing_chunks: usize,
+    speech_chunks: usize,
+}
+
+impl BargeInDetector {
+    pub fn new() -> Self {
+        Self {
+            min_energy: DEFAULT_MIN_ENERGY,
+            echo_level: 0.0,
+            playing_chunks: 0,
+            speech_chunks: 0,
+        }
+    }
+
+    /// Quietest RMS level that can count as the user talking
+    pub fn set_min_energy(&mut self, energy: f32) {
+        self.min_energy = energy.max(0.0);
+    }
+
+    /// Echo level learned for the current playback
+    #[cfg(test)]
+    pub fn echo_level(&self) -> f32 {
+        self.echo_level
+    }
+
+    /// Feed one capture chunk
+    ///
+    /// `eva_speaking` is whether playback is in progress. Returns true once
+    /// when the user barges in.
+    pub fn process(&mut self, chunk: &[f32], eva_speaking: bool) -> bool {
+        if !eva_speaking {
+            self.reset();
+            return false;
+        }
+
+        let energy = rms(chunk);
+        self.playing_chunks += 1;
+
+        if self.playing_chunks <= WARMUP_CHUNKS {
+            // Fast attack: take the loudest echo seen so far
+            self.echo_level = self.echo_level.max(energy);
+            return false;
+        }
+
+        let threshold = self.min_energy.max(self.echo_level * ECHO_MARGIN);
+        if energy > threshold {
+            self.speech_chunks += 1;
+            if self.speech_chunks >= MIN_SPEECH_CHUNKS {
+                self.speech_chunks = 0;
+                return true;
+            }
+        } else {
+            self.speech_chunks = 0;
+            self.echo_level = ECHO_SMOOTHING * self.echo_level + (1.0 - ECHO_SMOOTHING) * energy;
+        }
+
+        false
+    }
+
+    /// Forget the learned echo (call when playback stops)
+    pub fn reset(&mut self) {
+        self.echo_level = 0.0;
+        self.playing_chunks = 0;
+        self.speech_chunks = 0;
+    }
+}
+
+impl Default for BargeInDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(amplitude: f32) -> Vec<f32> {
+        (0..1600).map(|i| (i as f32 * 0.3).sin() * amplitude).collect()
+    }
+
+    #[test]
+    fn test_echo_alone_never_interrupts() {
+        let mut detector = BargeInDetector::new();
+        let echo = tone(0.2);
+        for _ in 0..50 {
+            assert!(!detector.process(&echo, true));
+        }
+        assert!(detector.echo_level() > 0.1);
+    }
+
+    #[test]
+    fn test_user_speech_over_echo_interrupts() {
+        let mut detector = BargeInDetector::new();
+        for _ in 0..10 {
+            detector.process(&tone(0.1), true);
+        }
+
+        let speech = tone(0.6);
+        assert!(!detector.process(&speech, true));
+        assert!(!detector.process(&speech, true));
+        assert!(detector.process(&speech, true));
+    }
+
+    #[test]
+    fn test_short_noise_ignored() {
+        let mut detector = BargeInDetector::new();
+        for _ in 0..5 {
+            detector.process(&tone(0.05), true);
+        }
+
+        // A single loud chunk (a knock, a cough) resets before it counts
+        for _ in 0..10 {
+            assert!(!detector.process(&tone(0.8), true));
+            assert!(!detector.process(&tone(0.05), true));
+        }
+    }
+
+    #[test]
+    fn test_idle_when_not_speaking() {
+        let mut detector = BargeInDetector::new();
+        for _ in 0..10 {
+            assert!(!detector.process(&tone(0.8), false));
+        }
+        assert_eq!(detector.echo_level(), 0.0);
+    }
+}
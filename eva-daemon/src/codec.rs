@@ -0,0 +1,411 @@
+//! Audio codecs for the EVA-Mind stream
+//!
+//! Microphone audio and EVA-Mind's answers travel as binary WebSocket
+//! frames. Raw 16-bit PCM at 16kHz costs 256 kbit/s each way, more than a
+//! mobile hotspot reliably carries; Opus fits the same speech in about
+//! 24 kbit/s. `start_call` offers the codecs this build has (`available`)
+//! and `session_created` names the one the server picked; a server that
+//! names none gets PCM16, so older servers keep working unchanged.
+//!
+//! A PCM16 frame is just the samples. An Opus frame is one 20ms packet
+//! behind a 2-byte big-endian sequence number. The server skips audio that
+//! fell too far behind on a slow link; the numbers show the decoder what
+//! is missing, and it fills short gaps with Opus's in-band FEC or loss
+//! concealment instead of a click.
+//!
+//! Opus needs libopus and is built with `--features opus`. Gemini's Live
+//! API only takes PCM, so Gemini always streams PCM16.
+
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::SAMPLE_RATE;
+
+/// Samples in one Opus packet (20ms)
+pub const FRAME_SAMPLES: usize = SAMPLE_RATE as usize / 50;
+
+/// Longer gaps are a new answer or a stall, not loss; they aren't filled
+const MAX_CONCEALED_FRAMES: u16 = 5;
+
+/// Target Opus bitrate (bit/s), plenty for 16kHz speech
+#[cfg(feature = "opus")]
+const OPUS_BITRATE: i32 = 24_000;
+
+const OPUS_MISSING: &str = "Opus not compiled in (build with --features opus)";
+
+/// How audio is encoded on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    Pcm16,
+    Opus,
+}
+
+impl AudioCodec {
+    /// Name used in `start_call` and `session_created`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pcm16 => "pcm16",
+            Self::Opus => "opus",
+        }
+    }
+
+    /// Accepts "pcm16"/"pcm" and "opus" (any case)
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "pcm16" | "pcm" => Some(Self::Pcm16),
+            "opus" => Some(Self::Opus),
+            _ => None,
+        }
+    }
+
+    /// Whether this build can encode and decode it
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Pcm16 => true,
+            Self::Opus => cfg!(feature = "opus"),
+        }
+    }
+}
+
+/// Codecs this build supports, preferred first
+pub fn available() -> Vec<AudioCodec> {
+    [AudioCodec::Opus, AudioCodec::Pcm16]
+        .into_iter()
+        .filter(AudioCodec::is_available)
+        .collect()
+}
+
+/// Codecs to offer given `EVA_AUDIO_CODEC` (`None` = everything available)
+///
+/// PCM16 stays on the list as the fallback for servers without the
+/// preferred codec.
+pub fn offered(preference: Option<&str>) -> Result<Vec<AudioCodec>, String> {
+    let Some(name) = preference.map(str::trim).filter(|name| !name.is_empty()) else {
+        return Ok(available());
+    };
+    let codec = AudioCodec::parse(name).ok_or_else(|| format!("Unknown audio codec: {}", name))?;
+    if !codec.is_available() {
+        return Err(OPUS_MISSING.to_string());
+    }
+
+    let mut codecs = vec![codec];
+    if codec != AudioCodec::Pcm16 {
+        codecs.push(AudioCodec::Pcm16);
+    }
+    Ok(codecs)
+}
+
+/// Codec to use given the server's pick (`None` = it didn't negotiate)
+pub fn negotiate(offered: &[AudioCodec], chosen: Option<&str>) -> Result<AudioCodec, String> {
+    let Some(name) = chosen else {
+        return Ok(AudioCodec::Pcm16);
+    };
+    AudioCodec::parse(name)
+        .filter(|codec| *codec == AudioCodec::Pcm16 || offered.contains(codec))
+        .ok_or_else(|| format!("EVA-Mind picked audio codec '{}', which wasn't offered", name))
+}
+
+/// Encodes and decodes single fixed-size frames
+trait PacketCodec {
+    fn encode(&mut self, frame: &[i16]) -> Result<Vec<u8>, String>;
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, String>;
+
+    /// Stand-in for one lost frame; `next` is the packet after it, which
+    /// may carry a low-bitrate copy of it (FEC)
+    fn conceal(&mut self, next: Option<&[u8]>) -> Result<Vec<i16>, String>;
+}
+
+fn packet_codec(codec: AudioCodec) -> Result<Option<Box<dyn PacketCodec>>, String> {
+    match codec {
+        AudioCodec::Pcm16 => Ok(None),
+        #[cfg(feature = "opus")]
+        AudioCodec::Opus => Ok(Some(Box::new(OpusPackets::new()?))),
+        #[cfg(not(feature = "opus"))]
+        AudioCodec::Opus => Err(OPUS_MISSING.to_string()),
+    }
+}
+
+/// Turns microphone PCM into frames to send
+pub struct AudioEncoder {
+    codec: AudioCodec,
+    packets: Option<Box<dyn PacketCodec>>,
+    /// Samples waiting for a full packet
+    pending: Vec<i16>,
+    sequence: u16,
+}
+
+impl AudioEncoder {
+    pub fn new(codec: AudioCodec) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::with_packets(codec, packet_codec(codec)?))
+    }
+
+    fn with_packets(codec: AudioCodec, packets: Option<Box<dyn PacketCodec>>) -> Self {
+        Self {
+            codec,
+            packets,
+            pending: Vec::new(),
+            sequence: 0,
+        }
+    }
+
+    pub fn codec(&self) -> AudioCodec {
+        self.codec
+    }
+
+    /// Frames for a chunk of 16-bit little-endian PCM
+    ///
+    /// Opus holds back the samples that don't fill a packet until the next
+    /// chunk, so a frame may carry audio from two chunks (or none yet).
+    pub fn encode(&mut self, pcm: &[u8]) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+        let Some(packets) = self.packets.as_mut() else {
+            return Ok(if pcm.is_empty() { Vec::new() } else { vec![pcm.to_vec()] });
+        };
+
+        self.pending.extend(pcm.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])));
+        let mut frames = Vec::new();
+        while self.pending.len() >= FRAME_SAMPLES {
+            let frame: Vec<i16> = self.pending.drain(..FRAME_SAMPLES).collect();
+            let mut packet = self.sequence.to_be_bytes().to_vec();
+            packet.extend(packets.encode(&frame)?);
+            frames.push(packet);
+            self.sequence = self.sequence.wrapping_add(1);
+        }
+        Ok(frames)
+    }
+}
+
+/// Turns received frames back into PCM, filling in lost packets
+pub struct AudioDecoder {
+    codec: AudioCodec,
+    packets: Option<Box<dyn PacketCodec>>,
+    /// Sequence number of the next packet (`None` = take any)
+    expected: Option<u16>,
+    concealed: u64,
+}
+
+impl AudioDecoder {
+    pub fn new(codec: AudioCodec) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::with_packets(codec, packet_codec(codec)?))
+    }
+
+    fn with_packets(codec: AudioCodec, packets: Option<Box<dyn PacketCodec>>) -> Self {
+        Self {
+            codec,
+            packets,
+            expected: None,
+            concealed: 0,
+        }
+    }
+
+    /// Frames filled in so far
+    pub fn concealed_frames(&self) -> u64 {
+        self.concealed
+    }
+
+    /// Forget the sequence (the answer was interrupted)
+    pub fn reset(&mut self) {
+        self.expected = None;
+    }
+
+    /// 16-bit little-endian PCM for a received frame, concealment first if
+    /// packets before it went missing
+    pub fn decode(&mut self, frame: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let Some(packets) = self.packets.as_mut() else {
+            return Ok(frame.to_vec());
+        };
+        if frame.len() < 2 {
+            return Err(format!("{} frame too short ({} bytes)", self.codec.name(), frame.len()).into());
+        }
+
+        let sequence = u16::from_be_bytes([frame[0], frame[1]]);
+        let packet = &frame[2..];
+        let lost = self.expected.map_or(0, |expected| sequence.wrapping_sub(expected));
+
+        let mut samples = Vec::new();
+        if (1..=MAX_CONCEALED_FRAMES).contains(&lost) {
+            for i in 0..lost {
+                // Only the packet right after the gap has FEC for its last frame
+                let next = (i == lost - 1).then_some(packet);
+                samples.extend(packets.conceal(next)?);
+            }
+            self.concealed += lost as u64;
+        }
+        samples.extend(packets.decode(packet)?);
+        self.expected = Some(sequence.wrapping_add(1));
+
+        Ok(samples.iter().flat_map(|s| s.to_le_bytes()).collect())
+    }
+}
+
+/// libopus, mono 16kHz, tuned for speech on lossy links
+#[cfg(feature = "opus")]
+struct OpusPackets {
+    encoder: opus::Encoder,
+    decoder: opus::Decoder,
+}
+
+#[cfg(feature = "opus")]
+impl OpusPackets {
+    fn new() -> Result<Self, String> {
+        let opus_err = |e: opus::Error| format!("Opus: {}", e);
+        let mut encoder =
+            opus::Encoder::new(SAMPLE_RATE, opus::Channels::Mono, opus::Application::Voip).map_err(opus_err)?;
+        encoder.set_bitrate(opus::Bitrate::Bits(OPUS_BITRATE)).map_err(opus_err)?;
+        encoder.set_inband_fec(true).map_err(opus_err)?;
+        encoder.set_packet_loss_perc(10).map_err(opus_err)?;
+        let decoder = opus::Decoder::new(SAMPLE_RATE, opus::Channels::Mono).map_err(opus_err)?;
+        Ok(Self { encoder, decoder })
+    }
+
+    fn decode_into(&mut self, packet: &[u8], len: usize, fec: bool) -> Result<Vec<i16>, String> {
+        let mut samples = vec![0i16; len];
+        let decoded = self
+            .decoder
+            .decode(packet, &mut samples, fec)
+            .map_err(|e| format!("Opus: {}", e))?;
+        samples.truncate(decoded);
+        Ok(samples)
+    }
+}
+
+#[cfg(feature = "opus")]
+impl PacketCodec for OpusPackets {
+    fn encode(&mut self, frame: &[i16]) -> Result<Vec<u8>, String> {
+        // Way above what 20ms at 24 kbit/s needs
+        let mut packet = vec![0u8; 1275];
+        let len = self.encoder.encode(frame, &mut packet).map_err(|e| format!("Opus: {}", e))?;
+        packet.truncate(len);
+        Ok(packet)
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, String> {
+        // Up to 120ms per packet
+        self.decode_into(packet, FRAME_SAMPLES * 6, false)
+    }
+
+    fn conceal(&mut self, next: Option<&[u8]>) -> Result<Vec<i16>, String> {
+        // An empty packet asks libopus to extrapolate
+        self.decode_into(next.unwrap_or(&[]), FRAME_SAMPLES, next.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packets carry a frame's first sample; concealment says what it used
+    struct Tagged;
+
+    impl PacketCodec for Tagged {
+        fn encode(&mut self, frame: &[i16]) -> Result<Vec<u8>, String> {
+            Ok(frame[0].to_le_bytes().to_vec())
+        }
+
+        fn decode(&mut self, packet: &[u8]) -> Result<Vec<i16>, String> {
+            Ok(vec![i16::from_le_bytes([packet[0], packet[1]]); 2])
+        }
+
+        fn conceal(&mut self, next: Option<&[u8]>) -> Result<Vec<i16>, String> {
+            Ok(vec![if next.is_some() { -2 } else { -1 }; 2])
+        }
+    }
+
+    fn pcm(samples: &[i16]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_le_bytes()).collect()
+    }
+
+    fn frame(sequence: u16, value: i16) -> Vec<u8> {
+        [sequence.to_be_bytes(), value.to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn test_offer_and_negotiate() {
+        assert_eq!(available().last(), Some(&AudioCodec::Pcm16));
+        assert_eq!(available().contains(&AudioCodec::Opus), cfg!(feature = "opus"));
+
+        assert_eq!(offered(None), Ok(available()));
+        assert_eq!(offered(Some(" PCM ")), Ok(vec![AudioCodec::Pcm16]));
+        assert!(offered(Some("speex")).is_err());
+        assert_eq!(offered(Some("opus")).is_ok(), cfg!(feature = "opus"));
+
+        let both = [AudioCodec::Opus, AudioCodec::Pcm16];
+        assert_eq!(negotiate(&both, None), Ok(AudioCodec::Pcm16));
+        assert_eq!(negotiate(&both, Some("opus")), Ok(AudioCodec::Opus));
+        assert_eq!(negotiate(&[AudioCodec::Pcm16], Some("pcm16")), Ok(AudioCodec::Pcm16));
+        assert!(negotiate(&[AudioCodec::Pcm16], Some("opus")).is_err());
+        assert!(negotiate(&both, Some("speex")).is_err());
+    }
+
+    #[test]
+    fn test_pcm16_passes_through() {
+        let mut encoder = AudioEncoder::new(AudioCodec::Pcm16).unwrap();
+        assert_eq!(encoder.encode(&[1, 0, 2, 0]).unwrap(), vec![vec![1, 0, 2, 0]]);
+        assert!(encoder.encode(&[]).unwrap().is_empty());
+
+        let mut decoder = AudioDecoder::new(AudioCodec::Pcm16).unwrap();
+        assert_eq!(decoder.decode(&[1, 0, 2, 0]).unwrap(), vec![1, 0, 2, 0]);
+        assert_eq!(AudioEncoder::new(AudioCodec::Opus).is_ok(), cfg!(feature = "opus"));
+    }
+
+    #[test]
+    fn test_packets_are_numbered_and_split_across_chunks() {
+        let mut encoder = AudioEncoder::with_packets(AudioCodec::Opus, Some(Box::new(Tagged)));
+        let samples: Vec<i16> = (0..FRAME_SAMPLES as i16 * 5 / 2).collect();
+
+        // 2.5 packets' worth: the half waits for the next chunk
+        let frames = encoder.encode(&pcm(&samples)).unwrap();
+        assert_eq!(frames, vec![frame(0, 0), frame(1, FRAME_SAMPLES as i16)]);
+
+        let frames = encoder.encode(&pcm(&samples[..FRAME_SAMPLES / 2])).unwrap();
+        assert_eq!(frames, vec![frame(2, 2 * FRAME_SAMPLES as i16)]);
+    }
+
+    #[test]
+    fn test_lost_packets_are_concealed() {
+        let mut decoder = AudioDecoder::with_packets(AudioCodec::Opus, Some(Box::new(Tagged)));
+        assert_eq!(decoder.decode(&frame(7, 10)).unwrap(), pcm(&[10, 10]));
+        assert_eq!(decoder.decode(&frame(8, 11)).unwrap(), pcm(&[11, 11]));
+
+        // 9 and 10 lost: plain concealment, then FEC from 11
+        assert_eq!(decoder.decode(&frame(11, 12)).unwrap(), pcm(&[-1, -1, -2, -2, 12, 12]));
+        assert_eq!(decoder.concealed_frames(), 2);
+
+        // A jump this big is a new stream, not loss
+        assert_eq!(decoder.decode(&frame(500, 13)).unwrap(), pcm(&[13, 13]));
+        decoder.reset();
+        assert_eq!(decoder.decode(&frame(3, 14)).unwrap(), pcm(&[14, 14]));
+        assert_eq!(decoder.concealed_frames(), 2);
+
+        // Numbers wrap
+        decoder.decode(&frame(u16::MAX, 0)).unwrap();
+        assert_eq!(decoder.decode(&frame(1, 15)).unwrap(), pcm(&[-2, -2, 15, 15]));
+
+        assert!(decoder.decode(&[0]).is_err());
+    }
+
+    #[cfg(feature = "opus")]
+    #[test]
+    fn test_opus_round_trip() {
+        let tone: Vec<i16> = (0..SAMPLE_RATE as usize / 10)
+            .map(|i| ((2.0 * std::f32::consts::PI * 440.0 * i as f32 / SAMPLE_RATE as f32).sin() * 8000.0) as i16)
+            .collect();
+        let mut encoder = AudioEncoder::new(AudioCodec::Opus).unwrap();
+        let frames = encoder.encode(&pcm(&tone)).unwrap();
+        assert_eq!(frames.len(), 5);
+        assert!(frames.iter().map(Vec::len).sum::<usize>() < tone.len() * 2 / 4);
+
+        // Drop the third packet: its 20ms comes back from FEC
+        let mut decoder = AudioDecoder::new(AudioCodec::Opus).unwrap();
+        let decoded: Vec<u8> = frames
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 2)
+            .flat_map(|(_, frame)| decoder.decode(frame).unwrap())
+            .collect();
+        assert_eq!(decoded.len(), tone.len() * 2);
+        assert_eq!(decoder.concealed_frames(), 1);
+    }
+}
@@ -116,6 +116,30 @@ impl CommandExecutor {
         Ok(target_canonical)
     }
 
+    /// Check a program name or URL before it is launched
+    ///
+    /// # Security
+    /// Names come from speech or from the model, so only bare program names
+    /// (found on PATH) and http(s) URLs are accepted: no paths, arguments or
+    /// shell metacharacters (`cmd /C start` would interpret them on Windows).
+    /// The model is further limited to the profile's `allowed_programs`
+    /// (`tools::remote_allowed`).
+    fn validate_program(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if name.trim().is_empty() {
+            return Err("No program given".into());
+        }
+        if name.chars().any(|c| c.is_control() || "&|;<>^\"'`$%!()".contains(c)) {
+            return Err(format!("Refusing to start '{}': shell metacharacters", name).into());
+        }
+
+        let is_url = name.starts_with("http://") || name.starts_with("https://");
+        if !is_url && (name.contains(['/', '\\']) || name.contains(char::is_whitespace)) {
+            return Err(format!("Refusing to start '{}': only program names and URLs are allowed", name).into());
+        }
+
+        Ok(())
+    }
+
     /// Execute file operation
     async fn execute_file_op(&self, op: FileOperation) -> Result<String, Box<dyn std::error::Error>> {
         match op {
@@ -246,6 +270,8 @@ impl CommandExecutor {
             }
             
             ProcessOperation::Start { name } => {
+                Self::validate_program(&name)?;
+
                 // Expanded capability: Open specific apps or URLs
                 // Note: We are trusting the intent classifier to not send malicious commands
                 
@@ -274,6 +300,10 @@ impl CommandExecutor {
             }
             
             ProcessOperation::Kill { pid } => {
+                if pid <= 1 || pid == std::process::id() {
+                    return Err(format!("Refusing to kill process {}", pid).into());
+                }
+
                 // Implement process kill using sysinfo
                 #[cfg(feature = "sysinfo")]
                 {
@@ -483,6 +513,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_program_validation() {
+        assert!(CommandExecutor::validate_program("notepad").is_ok());
+        assert!(CommandExecutor::validate_program("https://example.com/?q=eva").is_ok());
+
+        for name in ["", "notepad & del *", "calc|whoami", "/bin/sh", "..\\cmd.exe", "rm -rf", "$(reboot)"] {
+            assert!(CommandExecutor::validate_program(name).is_err(), "{:?} accepted", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_kill_refuses_init_and_self() {
+        let executor = CommandExecutor::new().unwrap();
+        for pid in [0, 1, std::process::id()] {
+            assert!(executor.execute_process_op(ProcessOperation::Kill { pid }).await.is_err());
+        }
+    }
+
     #[tokio::test]
     async fn test_file_create() {
         let executor = CommandExecutor::new().unwrap();
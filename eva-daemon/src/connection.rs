@@ -0,0 +1,567 @@
+//! Backend connection supervisor
+//!
+//! Keeps the `VoiceBackend` usable across network drops. While connected it
+//! pings on a heartbeat; when a send, receive or ping fails it reconnects
+//! with exponential back-off, registering again and resuming the same
+//! session. Audio captured while reconnecting is buffered and sent once the
+//! connection is back, so a short Wi-Fi drop doesn't lose the request.
+//! After `MAX_RECONNECT_ATTEMPTS` failures the state turns `Offline` (the
+//! daemon answers locally) and retries continue at the longest delay.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+use futures_util::future::LocalBoxFuture;
+use tokio::time::{Duration, Instant};
+
+use crate::backend::{self, BackendEvent, BackendKind, VoiceBackend};
+use crate::credentials::Credentials;
+use crate::websocket::ConnectOptions;
+
+/// How often a connected backend is pinged
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// First retry delay; doubled after every failure up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A connection attempt taking longer than this fails (unless the
+/// network's own connect timeout is longer)
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Failed attempts before the UI reports Offline
+const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Audio kept while reconnecting (10s of 16kHz PCM16); older audio is dropped
+const MAX_BUFFERED_BYTES: usize = 10 * 16000 * 2;
+
+/// Opens a connection, optionally resuming a session id
+pub type Connector =
+    Box<dyn FnMut(Option<String>) -> LocalBoxFuture<'static, Result<Box<dyn VoiceBackend>, Box<dyn Error>>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Retrying; `attempt` failed attempts so far
+    Reconnecting { attempt: u32 },
+    Offline,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionState::Connected => write!(f, "🟢 Connected"),
+            ConnectionState::Reconnecting { attempt: 0 } => write!(f, "🟡 Reconnecting"),
+            ConnectionState::Reconnecting { attempt } => write!(f, "🟡 Reconnecting (attempt {})", attempt + 1),
+            ConnectionState::Offline => write!(f, "🔴 Offline"),
+        }
+    }
+}
+
+/// Exponential back-off between reconnection attempts
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// Delay before the next attempt (doubles each call, up to the maximum)
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    /// Start over after a successful connection
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(INITIAL_BACKOFF, MAX_BACKOFF)
+    }
+}
+
+pub struct ConnectionSupervisor {
+    name: &'static str,
+    connector: Connector,
+    backend: Option<Box<dyn VoiceBackend>>,
+    state: ConnectionState,
+    backoff: Backoff,
+    connect_timeout: Duration,
+    failures: u32,
+    next_attempt: Instant,
+    last_heartbeat: Instant,
+    /// Session to resume on reconnect
+    session_id: Option<String>,
+    last_error: Option<String>,
+    /// Audio captured while reconnecting, oldest first
+    buffered: VecDeque<Vec<u8>>,
+    buffered_bytes: usize,
+    /// The user finished talking while the connection was down
+    end_turn_pending: bool,
+}
+
+impl ConnectionSupervisor {
+    /// Not connected yet; call `connect`
+    pub fn new(name: &'static str, connector: Connector) -> Self {
+        let now = Instant::now();
+        Self {
+            name,
+            connector,
+            backend: None,
+            state: ConnectionState::Offline,
+            backoff: Backoff::default(),
+            connect_timeout: CONNECT_TIMEOUT,
+            failures: 0,
+            next_attempt: now,
+            last_heartbeat: now,
+            session_id: None,
+            last_error: None,
+            buffered: VecDeque::new(),
+            buffered_bytes: 0,
+            end_turn_pending: false,
+        }
+    }
+
+    /// Supervise one of the built-in backends
+    pub fn for_backend(
+        kind: BackendKind,
+        languages: Vec<String>,
+        credentials: Credentials,
+        network: ConnectOptions,
+    ) -> Self {
+        let connect_timeout = network.connect_timeout.max(CONNECT_TIMEOUT);
+        let mut supervisor = Self::new(
+            kind.name(),
+            Box::new(move |resume| {
+                Box::pin(backend::connect(kind, languages.clone(), credentials.clone(), network.clone(), resume))
+            }),
+        );
+        supervisor.connect_timeout = connect_timeout;
+        supervisor
+    }
+
+    #[cfg(test)]
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == ConnectionState::Connected
+    }
+
+    /// Offline means requests are answered locally
+    #[cfg(test)]
+    pub fn is_offline(&self) -> bool {
+        self.state == ConnectionState::Offline
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    /// Why the last attempt (or the connection) failed
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// Whether the backend marks the end of its answers (see `VoiceBackend`)
+    pub fn reports_turn_end(&self) -> bool {
+        self.backend.as_ref().is_none_or(|backend| backend.reports_turn_end())
+    }
+
+    /// Bytes of audio waiting for the connection to come back
+    #[cfg(test)]
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// Connect now; on failure retries are scheduled by `maintain`
+    pub async fn connect(&mut self) -> Result<(), Box<dyn Error>> {
+        let result = self.attempt().await;
+        if let Err(ref e) = result {
+            self.on_failure(e.to_string());
+        }
+        result
+    }
+
+    /// Heartbeat and reconnection; call often (every loop iteration)
+    ///
+    /// Returns the new state when it changed.
+    pub async fn maintain(&mut self) -> Option<ConnectionState> {
+        let before = self.state;
+        let now = Instant::now();
+
+        if let Some(ref mut backend) = self.backend {
+            if now.duration_since(self.last_heartbeat) >= HEARTBEAT_INTERVAL {
+                self.last_heartbeat = now;
+                if let Err(e) = backend.ping().await {
+                    self.on_lost(e.to_string());
+                }
+            }
+        } else if now >= self.next_attempt {
+            if let Err(e) = self.attempt().await {
+                self.on_failure(e.to_string());
+            }
+        }
+
+        (self.state != before).then_some(self.state)
+    }
+
+    /// Stream user audio, buffering it while reconnecting
+    pub async fn send_audio(&mut self, pcm: &[u8]) {
+        if let Some(ref mut backend) = self.backend {
+            match backend.send_audio(pcm).await {
+                Ok(()) => return,
+                Err(e) => self.on_lost(e.to_string()),
+            }
+        }
+        if let ConnectionState::Reconnecting { .. } = self.state {
+            self.buffer(pcm.to_vec());
+        }
+    }
+
+    /// Next event from the backend (`None` while disconnected)
+    ///
+    /// An error means the connection was just lost; reconnection is
+    /// already scheduled.
+    pub async fn receive(&mut self) -> Result<Option<BackendEvent>, Box<dyn Error>> {
+        let Some(ref mut backend) = self.backend else {
+            return Ok(None);
+        };
+        match backend.receive().await {
+            Ok(event) => Ok(event),
+            Err(e) => {
+                self.on_lost(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// The user stopped talking (sent after reconnecting if the connection is down)
+    pub async fn end_turn(&mut self) {
+        if let Some(ref mut backend) = self.backend {
+            match backend.end_turn().await {
+                Ok(()) => return,
+                Err(e) => self.on_lost(e.to_string()),
+            }
+        }
+        if let ConnectionState::Reconnecting { .. } = self.state {
+            self.end_turn_pending = true;
+        }
+    }
+
+    /// Stop the answer in progress
+    pub async fn interrupt(&mut self) -> Result<(), Box<dyn Error>> {
+        self.discard_turn();
+        match self.backend {
+            Some(ref mut backend) => backend.interrupt().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Reply to a `CommandRequest` (lost if the connection dropped meanwhile)
+    pub async fn send_command_result(&mut self, id: &str, success: bool, output: &str) -> Result<(), Box<dyn Error>> {
+        let Some(ref mut backend) = self.backend else {
+            return Err(format!("{} is not connected", self.name).into());
+        };
+        backend.send_command_result(id, success, output).await
+    }
+
+    /// The turn is over; audio still buffered for it is stale
+    pub fn discard_turn(&mut self) {
+        self.buffered.clear();
+        self.buffered_bytes = 0;
+        self.end_turn_pending = false;
+    }
+
+    pub async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.discard_turn();
+        self.state = ConnectionState::Offline;
+        match self.backend.take() {
+            Some(mut backend) => backend.close().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Connect, start (or resume) the session and send what was buffered
+    async fn attempt(&mut self) -> Result<(), Box<dyn Error>> {
+        let connecting = (self.connector)(self.session_id.clone());
+        let mut backend = tokio::time::timeout(self.connect_timeout, connecting)
+            .await
+            .map_err(|_| "connection timed out")??;
+        tokio::time::timeout(self.connect_timeout, backend.start_session())
+            .await
+            .map_err(|_| "session start timed out")??;
+
+        self.session_id = Some(backend.session_id().to_string());
+        self.backend = Some(backend);
+        self.state = ConnectionState::Connected;
+        self.backoff.reset();
+        self.failures = 0;
+        self.last_error = None;
+        self.last_heartbeat = Instant::now();
+
+        while let Some(pcm) = self.buffered.pop_front() {
+            self.buffered_bytes -= pcm.len();
+            self.send_audio(&pcm).await;
+            if !self.is_connected() {
+                return Ok(());
+            }
+        }
+        if std::mem::take(&mut self.end_turn_pending) {
+            self.end_turn().await;
+        }
+        Ok(())
+    }
+
+    /// The connection broke: retry right away
+    fn on_lost(&mut self, error: String) {
+        self.backend = None;
+        self.last_error = Some(error);
+        self.state = ConnectionState::Reconnecting { attempt: 0 };
+        self.next_attempt = Instant::now();
+    }
+
+    /// An attempt failed: wait longer before the next one
+    fn on_failure(&mut self, error: String) {
+        self.failures += 1;
+        self.last_error = Some(error);
+        self.next_attempt = Instant::now() + self.backoff.next_delay();
+        self.state = if self.failures >= MAX_RECONNECT_ATTEMPTS {
+            self.discard_turn();
+            ConnectionState::Offline
+        } else {
+            ConnectionState::Reconnecting { attempt: self.failures }
+        };
+    }
+
+    fn buffer(&mut self, pcm: Vec<u8>) {
+        self.buffered_bytes += pcm.len();
+        self.buffered.push_back(pcm);
+        while self.buffered_bytes > MAX_BUFFERED_BYTES {
+            match self.buffered.pop_front() {
+                Some(old) => self.buffered_bytes -= old.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// What the fake backends saw, shared with the test
+    #[derive(Default)]
+    struct Log {
+        connects: Vec<Option<String>>,
+        audio: Vec<Vec<u8>>,
+        end_turns: usize,
+    }
+
+    struct FakeBackend {
+        log: Rc<RefCell<Log>>,
+        session_id: String,
+        /// Sends fail once this many chunks went through
+        fail_after: Option<usize>,
+        sent: usize,
+    }
+
+    impl VoiceBackend for FakeBackend {
+        fn name(&self) -> &'static str {
+            "Fake"
+        }
+
+        fn session_id(&self) -> &str {
+            &self.session_id
+        }
+
+        fn reports_turn_end(&self) -> bool {
+            true
+        }
+
+        fn start_session(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn send_audio<'a>(&'a mut self, pcm: &'a [u8]) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+            Box::pin(async move {
+                if self.fail_after == Some(self.sent) {
+                    return Err("WebSocket closed".into());
+                }
+                self.sent += 1;
+                self.log.borrow_mut().audio.push(pcm.to_vec());
+                Ok(())
+            })
+        }
+
+        fn send_text<'a>(&'a mut self, _text: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn receive(&mut self) -> LocalBoxFuture<'_, Result<Option<BackendEvent>, Box<dyn Error>>> {
+            Box::pin(async { Ok(None) })
+        }
+
+        fn end_turn(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>> {
+            Box::pin(async move {
+                self.log.borrow_mut().end_turns += 1;
+                Ok(())
+            })
+        }
+
+        fn interrupt(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn send_command_result<'a>(
+            &'a mut self,
+            _id: &'a str,
+            _success: bool,
+            _output: &'a str,
+        ) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn ping(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn close(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn Error>>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    /// Connector whose first backend dies after `fail_after` chunks and
+    /// whose attempts fail while `down` is set
+    fn fake_connector(log: Rc<RefCell<Log>>, down: Rc<RefCell<bool>>, fail_after: Option<usize>) -> Connector {
+        Box::new(move |resume: Option<String>| {
+            let log = log.clone();
+            let down = *down.borrow();
+            Box::pin(async move {
+                let first = log.borrow().connects.is_empty();
+                log.borrow_mut().connects.push(resume.clone());
+                if down {
+                    return Err("network unreachable".into());
+                }
+                Ok(Box::new(FakeBackend {
+                    log: log.clone(),
+                    session_id: resume.unwrap_or_else(|| "session-1".to_string()),
+                    fail_after: if first { fail_after } else { None },
+                    sent: 0,
+                }) as Box<dyn VoiceBackend>)
+            })
+        })
+    }
+
+    fn no_delay() -> Backoff {
+        Backoff::new(Duration::ZERO, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays: Vec<u64> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_goes_offline_after_failed_attempts_then_recovers() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let down = Rc::new(RefCell::new(true));
+        let mut supervisor =
+            ConnectionSupervisor::new("Fake", fake_connector(log.clone(), down.clone(), None)).with_backoff(no_delay());
+
+        assert!(supervisor.connect().await.is_err());
+        assert_eq!(supervisor.state(), ConnectionState::Reconnecting { attempt: 1 });
+        for attempt in 2..MAX_RECONNECT_ATTEMPTS {
+            assert_eq!(supervisor.maintain().await, Some(ConnectionState::Reconnecting { attempt }));
+        }
+        assert_eq!(supervisor.maintain().await, Some(ConnectionState::Offline));
+        assert_eq!(supervisor.last_error(), Some("network unreachable"));
+
+        // Offline keeps retrying
+        *down.borrow_mut() = false;
+        assert_eq!(supervisor.maintain().await, Some(ConnectionState::Connected));
+        assert_eq!(supervisor.session_id(), Some("session-1"));
+        assert_eq!(supervisor.maintain().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_buffers_audio_and_resumes_session_after_drop() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let down = Rc::new(RefCell::new(false));
+        let mut supervisor =
+            ConnectionSupervisor::new("Fake", fake_connector(log.clone(), down.clone(), Some(1))).with_backoff(no_delay());
+        supervisor.connect().await.unwrap();
+
+        // The connection drops on the second chunk; that chunk and the next are kept
+        supervisor.send_audio(&[1]).await;
+        *down.borrow_mut() = true;
+        supervisor.send_audio(&[2]).await;
+        assert_eq!(supervisor.state(), ConnectionState::Reconnecting { attempt: 0 });
+        supervisor.send_audio(&[3]).await;
+        supervisor.end_turn().await;
+        assert_eq!(supervisor.buffered_bytes(), 2);
+        assert_eq!(log.borrow().end_turns, 0);
+
+        supervisor.maintain().await;
+        assert_eq!(supervisor.state(), ConnectionState::Reconnecting { attempt: 1 });
+
+        *down.borrow_mut() = false;
+        assert_eq!(supervisor.maintain().await, Some(ConnectionState::Connected));
+
+        let log = log.borrow();
+        assert_eq!(log.audio, vec![vec![1], vec![2], vec![3]]);
+        assert_eq!(log.end_turns, 1);
+        assert_eq!(
+            log.connects,
+            vec![None, Some("session-1".to_string()), Some("session-1".to_string())]
+        );
+        assert_eq!(supervisor.buffered_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_offline_drops_audio() {
+        let log = Rc::new(RefCell::new(Log::default()));
+        let down = Rc::new(RefCell::new(true));
+        let mut supervisor =
+            ConnectionSupervisor::new("Fake", fake_connector(log.clone(), down.clone(), None)).with_backoff(no_delay());
+        for _ in 0..MAX_RECONNECT_ATTEMPTS {
+            supervisor.maintain().await;
+        }
+        assert!(supervisor.is_offline());
+
+        supervisor.send_audio(&[1, 2]).await;
+        assert_eq!(supervisor.buffered_bytes(), 0);
+    }
+}
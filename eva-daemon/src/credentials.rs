@@ -0,0 +1,398 @@
+//! Identity and secrets
+//!
+//! Who EVA registers as with EVA-Mind (the user's CPF) and the keys the
+//! backends need. They live in `~/.eva/credentials.enc`, encrypted with
+//! AES-256-GCM under a key derived from `EVA_CREDENTIALS_KEY` (or, when that
+//! is unset, from the user and host names). Environment variables win over
+//! the store:
+//!
+//! - `EVA_IDENTITY`: stored identity to use (else the profile's, else the default)
+//! - `EVA_CPF` / `EVA_USER_TYPE`: an identity that isn't stored
+//! - `GOOGLE_API_KEY`, `EVA_MIND_TOKEN`: backend keys
+//!
+//! Every secret is held in a `Secret`, which prints as `[redacted]` and is
+//! scrubbed from log lines by `redact`.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use argon2::Argon2;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::user_profile::UserProfile;
+
+/// Store secret holding the Gemini API key
+pub const GOOGLE_API_KEY: &str = "google_api_key";
+/// Store secret holding the EVA-Mind access token
+pub const EVA_MIND_TOKEN: &str = "eva_mind_token";
+/// Names `--set-secret` accepts
+pub const SECRET_NAMES: [&str; 2] = [GOOGLE_API_KEY, EVA_MIND_TOKEN];
+
+/// Magic bytes + version of the store file
+const MAGIC: &[u8; 4] = b"EVC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Shorter values are not scrubbed (they would match ordinary text)
+const MIN_REDACTED_LEN: usize = 4;
+
+/// Every secret created so far, longest first
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// A value that must never reach a log or the screen
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wrap a value; from now on `redact` scrubs it
+    pub fn new(value: impl Into<String>) -> Self {
+        let value = value.into();
+        if value.len() >= MIN_REDACTED_LEN {
+            let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+            if !secrets.contains(&value) {
+                secrets.push(value.clone());
+                secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+            }
+        }
+        Self(value)
+    }
+
+    /// The value itself, for the wire
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl std::fmt::Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret::new)
+    }
+}
+
+/// `text` with every known secret replaced by `[redacted]`
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    secrets
+        .iter()
+        .fold(text.to_string(), |text, secret| text.replace(secret.as_str(), "[redacted]"))
+}
+
+/// Who EVA registers as with EVA-Mind
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub cpf: Secret,
+    #[serde(default = "default_user_type")]
+    pub user_type: String,
+}
+
+fn default_user_type() -> String {
+    "patient".to_string()
+}
+
+impl Identity {
+    pub fn new(cpf: &str) -> Self {
+        Self {
+            cpf: Secret::new(cpf.trim()),
+            user_type: default_user_type(),
+        }
+    }
+}
+
+/// Contents of `credentials.enc`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CredentialStore {
+    /// Identity used when neither `EVA_IDENTITY` nor the profile names one
+    #[serde(default)]
+    pub default_identity: Option<String>,
+    #[serde(default)]
+    pub identities: BTreeMap<String, Identity>,
+    #[serde(default)]
+    pub secrets: BTreeMap<String, Secret>,
+}
+
+impl CredentialStore {
+    /// `~/.eva/credentials.enc`
+    pub fn path() -> Result<PathBuf, Box<dyn Error>> {
+        #[cfg(target_os = "windows")]
+        let home = std::env::var("USERPROFILE")?;
+        #[cfg(not(target_os = "windows"))]
+        let home = std::env::var("HOME")?;
+
+        Ok(PathBuf::from(home).join(".eva").join("credentials.enc"))
+    }
+
+    /// Load the user's store (empty when there is none yet)
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        Self::load_from(&Self::path()?, &passphrase())
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.save_to(&Self::path()?, &passphrase())
+    }
+
+    pub fn load_from(path: &Path, passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read(path)?;
+        if !content.starts_with(MAGIC) || content.len() < MAGIC.len() + SALT_LEN + NONCE_LEN {
+            return Err(format!("{} is not an EVA credential store", path.display()).into());
+        }
+        let (salt, rest) = content[MAGIC.len()..].split_at(SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let cipher = Aes256Gcm::new(&derive_key(passphrase, salt)?.into());
+        let json = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| {
+            format!(
+                "Could not decrypt {} (was it created with another EVA_CREDENTIALS_KEY or on another machine?)",
+                path.display()
+            )
+        })?;
+
+        Ok(serde_json::from_slice(&json)?)
+    }
+
+    pub fn save_to(&self, path: &Path, passphrase: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let cipher = Aes256Gcm::new(&derive_key(passphrase, &salt)?.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, serde_json::to_vec(self)?.as_slice())
+            .map_err(|e| format!("Encryption error: {}", e))?;
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+        data.extend(ciphertext);
+        fs::write(path, data)?;
+
+        // Only the owner may read it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+
+        Ok(())
+    }
+
+    /// Add or replace an identity; the first one becomes the default
+    pub fn set_identity(&mut self, name: &str, identity: Identity) {
+        self.identities.insert(name.to_string(), identity);
+        if self.default_identity.is_none() {
+            self.default_identity = Some(name.to_string());
+        }
+    }
+
+    pub fn set_secret(&mut self, name: &str, value: &str) {
+        self.secrets.insert(name.to_string(), Secret::new(value.trim()));
+    }
+}
+
+/// Key for the store file: `EVA_CREDENTIALS_KEY`, or derived from the machine
+fn passphrase() -> String {
+    if let Ok(key) = std::env::var("EVA_CREDENTIALS_KEY") {
+        if !key.is_empty() {
+            return key;
+        }
+    }
+
+    let username = std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_else(|_| "eva_user".to_string());
+
+    let hostname = hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "eva_host".to_string());
+
+    format!("eva_credentials_{}_{}", username, hostname)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], Box<dyn Error>> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation error: {}", e))?;
+    Ok(key)
+}
+
+/// What the backends authenticate with, for one user
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub identity: Option<Identity>,
+    pub google_api_key: Option<Secret>,
+    pub eva_mind_token: Option<Secret>,
+}
+
+impl Credentials {
+    /// The profile's credentials: its identity from the store, env overrides on top
+    pub fn load(profile: &UserProfile) -> Result<Self, Box<dyn Error>> {
+        let store = CredentialStore::load()?;
+        Self::resolve(&store, profile.identity.as_deref(), |name| std::env::var(name).ok())
+    }
+
+    /// Pick the identity and secrets out of `store`, `env` taking precedence
+    pub fn resolve(
+        store: &CredentialStore,
+        profile_identity: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let env = |name: &str| env(name).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+
+        let identity = match env("EVA_CPF") {
+            Some(cpf) => Some(Identity {
+                user_type: env("EVA_USER_TYPE").unwrap_or_else(default_user_type),
+                ..Identity::new(&cpf)
+            }),
+            None => match env("EVA_IDENTITY").or_else(|| profile_identity.map(str::to_string)) {
+                // Asked for by name: it has to exist
+                Some(name) => Some(
+                    store
+                        .identities
+                        .get(&name)
+                        .cloned()
+                        .ok_or_else(|| format!("Identity '{}' is not in the credential store", name))?,
+                ),
+                None => store
+                    .default_identity
+                    .as_ref()
+                    .and_then(|name| store.identities.get(name))
+                    .cloned(),
+            },
+        };
+
+        let secret = |env_name: &str, store_name: &str| {
+            env(env_name)
+                .map(Secret::new)
+                .or_else(|| store.secrets.get(store_name).cloned())
+                .filter(|s| !s.is_empty())
+        };
+
+        Ok(Self {
+            identity,
+            google_api_key: secret("GOOGLE_API_KEY", GOOGLE_API_KEY),
+            eva_mind_token: secret("EVA_MIND_TOKEN", EVA_MIND_TOKEN),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let secret = Secret::new("AIzaTestKey123");
+        assert_eq!(format!("{:?} {}", secret, secret), "[redacted] [redacted]");
+        assert_eq!(secret.expose(), "AIzaTestKey123");
+        assert_eq!(
+            redact(r#"wss://host/ws?key=AIzaTestKey123 {"cpf":"AIzaTestKey123"}"#),
+            r#"wss://host/ws?key=[redacted] {"cpf":"[redacted]"}"#
+        );
+
+        // Too short to scrub safely
+        Secret::new("ab");
+        assert_eq!(redact("ab"), "ab");
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let path = std::env::temp_dir().join(format!("eva_credentials_test_{}.enc", std::process::id()));
+        let mut store = CredentialStore::default();
+        store.set_identity("ana", Identity::new("11122233344"));
+        store.set_identity("rui", Identity::new("55566677788"));
+        store.set_secret(GOOGLE_API_KEY, "store-key-1");
+        store.save_to(&path, "test passphrase").unwrap();
+
+        let raw = fs::read(&path).unwrap();
+        assert!(raw.starts_with(MAGIC));
+        assert!(!String::from_utf8_lossy(&raw).contains("11122233344"));
+
+        let loaded = CredentialStore::load_from(&path, "test passphrase").unwrap();
+        assert_eq!(loaded.default_identity.as_deref(), Some("ana"));
+        assert_eq!(loaded.identities["rui"].cpf.expose(), "55566677788");
+        assert_eq!(loaded.secrets[GOOGLE_API_KEY].expose(), "store-key-1");
+
+        assert!(CredentialStore::load_from(&path, "wrong passphrase").is_err());
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        let mut store = CredentialStore::default();
+        store.set_identity("ana", Identity::new("11122233344"));
+        store.set_identity("rui", Identity::new("55566677788"));
+        store.set_secret(GOOGLE_API_KEY, "store-key-2");
+
+        // Store default, store secrets
+        let credentials = Credentials::resolve(&store, None, env(&[])).unwrap();
+        assert_eq!(credentials.identity.unwrap().cpf.expose(), "11122233344");
+        assert_eq!(credentials.google_api_key.unwrap().expose(), "store-key-2");
+        assert!(credentials.eva_mind_token.is_none());
+
+        // The profile picks its own identity; EVA_IDENTITY beats it
+        let credentials = Credentials::resolve(&store, Some("rui"), env(&[])).unwrap();
+        assert_eq!(credentials.identity.unwrap().cpf.expose(), "55566677788");
+        let credentials = Credentials::resolve(&store, Some("rui"), env(&[("EVA_IDENTITY", "ana")])).unwrap();
+        assert_eq!(credentials.identity.unwrap().cpf.expose(), "11122233344");
+
+        // Env values beat everything
+        let credentials = Credentials::resolve(
+            &store,
+            Some("rui"),
+            env(&[("EVA_CPF", "99988877766"), ("EVA_USER_TYPE", "caregiver"), ("GOOGLE_API_KEY", "env-key")]),
+        )
+        .unwrap();
+        let identity = credentials.identity.unwrap();
+        assert_eq!(identity.cpf.expose(), "99988877766");
+        assert_eq!(identity.user_type, "caregiver");
+        assert_eq!(credentials.google_api_key.unwrap().expose(), "env-key");
+
+        // A named identity that doesn't exist is an error, no identity at all is not
+        assert!(Credentials::resolve(&store, Some("bia"), env(&[])).is_err());
+        let empty = Credentials::resolve(&CredentialStore::default(), None, env(&[])).unwrap();
+        assert!(empty.identity.is_none() && empty.google_api_key.is_none());
+    }
+}
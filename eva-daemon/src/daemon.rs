@@ -0,0 +1,1328 @@
+//! The daemon's tasks and the channels between them
+//!
+//! Each subsystem is its own task on a `LocalSet` (audio devices and
+//! backends aren't `Send`), so none of them waits on another:
+//!
+//! - capture: microphone (or replay) chunks → detector
+//! - detector: wake word, end of speech and barge-in → conductor
+//! - backend: heartbeat and reconnection, sends what the conductor asks,
+//!   passes on whatever arrives
+//! - playback: feeds the speaker from the jitter buffer on its own clock
+//!   and speaks offline answers
+//! - UI: redraws the terminal from updates and animation ticks
+//! - Time Machine: screen recording until shutdown
+//!
+//! The conductor owns the `DaemonState` (see `state_machine.rs`) and the
+//! conversation: it routes turn audio, runs commands, keeps the session and
+//! decides when an answer is over. Ctrl-C, or the end of a replay, shuts
+//! the tasks down and saves the session to `session.json`.
+
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::spawn_local;
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::animations::Animation;
+use crate::audio::{CHUNK_SIZE, SAMPLE_RATE};
+use crate::audio_io::{AudioSource, TurnRecorder};
+use crate::audio_player::AudioPlayer;
+use crate::backend::BackendEvent;
+use crate::barge_in::BargeInDetector;
+use crate::command_executor::CommandExecutor;
+use crate::command_parser::{CommandIntent, CommandParser};
+use crate::connection::{ConnectionState, ConnectionSupervisor};
+use crate::custom_commands::CustomCommandManager;
+use crate::language_id::LanguageId;
+use crate::macros::MacroManager;
+use crate::offline_assistant;
+use crate::playback::{PlaybackProgress, PlaybackState};
+use crate::session::{ConversationSession, Role};
+use crate::state_machine::{DaemonState, Trigger};
+use crate::statistics::Statistics;
+use crate::status_indicator::StatusIndicator;
+use crate::stt::{self, Language, StreamingSttSession, SttConfig, SttEngine};
+use crate::terminal_ui::TerminalUI;
+use crate::timemachine::TimeMachine;
+use crate::tools;
+use crate::tts::{SpeechInterrupt, VoiceSettings};
+use crate::user_profile::UserProfile;
+use crate::vad::{VadEvent, VoiceActivity};
+use crate::wake_word::WakeWordDetector;
+
+/// Where the conversation is kept between runs
+pub const SESSION_FILE: &str = "session.json";
+
+/// One microphone chunk of audio; replays are fed at this pace
+const CHUNK_DURATION: Duration = Duration::from_millis(CHUNK_SIZE as u64 * 1000 / SAMPLE_RATE as u64);
+/// Chunks the detector may fall behind the microphone
+const FRAME_QUEUE: usize = 50;
+/// Quiet chunks that end a turn (5s without speech)
+const NO_SPEECH_CHUNKS: u32 = 50;
+/// Longest turn before it is cut off
+const MAX_TURN_SAMPLES: usize = 48000 * 30;
+/// How often the speaker is fed from the jitter buffer
+const PUMP_INTERVAL: Duration = Duration::from_millis(10);
+/// How often the conductor checks on the answer
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// Redraw rate while something changes
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+/// Statistics scan the process table, so they are refreshed less often
+const STATISTICS_INTERVAL: Duration = Duration::from_secs(1);
+/// Nothing from the backend (and nothing playing) this long ends the answer
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(15);
+/// Backends without end-of-turn messages are done after this much quiet
+const LEGACY_TURN_END: Duration = Duration::from_millis(1500);
+/// Retry pace of the backend task while there is no connection
+const DISCONNECTED_POLL: Duration = Duration::from_millis(100);
+/// How long hanging up may take on shutdown
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the tasks tell the conductor
+#[derive(Debug)]
+pub enum Event {
+    /// The wake word was heard, or the user talked over EVA
+    WakeWord,
+    /// Microphone audio of the turn being recorded
+    TurnAudio(Vec<f32>),
+    /// The turn's recording is over
+    EndOfSpeech(TurnEnd),
+    /// The microphone ran out (only a replay does)
+    InputEnded,
+    /// Something the backend sent
+    Backend(BackendEvent),
+    /// The backend connection changed
+    Connection(Link),
+    /// A finished answer has been played to the end
+    AnswerPlayed,
+    /// `PlaybackCommand::Speak` is done (or was interrupted)
+    SpeechFinished,
+}
+
+/// Why a turn's recording ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TurnEnd {
+    SpeechEnd,
+    NoSpeech,
+    MaxLength,
+    InputEnded,
+}
+
+/// The backend connection as the conductor sees it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+    pub state: ConnectionState,
+    pub reports_turn_end: bool,
+}
+
+impl Link {
+    fn of(connection: &ConnectionSupervisor) -> Self {
+        Self {
+            state: connection.state(),
+            reports_turn_end: connection.reports_turn_end(),
+        }
+    }
+}
+
+/// What the conductor asks of the backend task
+#[derive(Debug)]
+pub enum BackendCommand {
+    /// 16-bit PCM of the turn (buffered while reconnecting)
+    Audio(Vec<u8>),
+    EndTurn,
+    Interrupt,
+    CommandResult { id: String, success: bool, output: String },
+    DiscardTurn,
+    /// Hang up and end the task
+    Close,
+}
+
+/// What the conductor asks of the playback task
+#[derive(Debug)]
+pub enum PlaybackCommand {
+    /// A backend answer is coming
+    Begin,
+    /// Answer audio (16-bit PCM at `sample_rate`) for the jitter buffer
+    Queue { pcm: Vec<u8>, sample_rate: u32 },
+    /// The answer is complete; play out the rest
+    End,
+    /// Cut EVA off (with a short fade)
+    Stop,
+    /// Speak an offline answer with the local TTS engine
+    Speak(String),
+    /// Change the TTS voice; replies with the engine now in use
+    SetVoice { voice: VoiceSettings, engine: oneshot::Sender<String> },
+}
+
+/// What the playback task publishes after every command and pump
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerStatus {
+    /// EVA's voice is going out (what barge-in listens against)
+    pub playing: bool,
+    pub progress: PlaybackProgress,
+}
+
+/// Changes for the UI task to show
+#[derive(Debug)]
+pub enum UiUpdate {
+    System(String),
+    User(String),
+    Eva(String),
+    Partial(String),
+    State(DaemonState),
+    Connection(Option<ConnectionState>),
+    Playback(Option<PlaybackProgress>),
+    TurnFinished,
+    CommandsExecuted(usize),
+}
+
+/// Sends `UiUpdate`s; every task gets a clone
+#[derive(Clone)]
+pub struct UiHandle(mpsc::UnboundedSender<UiUpdate>);
+
+impl UiHandle {
+    pub fn send(&self, update: UiUpdate) {
+        // The UI task outlives the others, so this only fails at exit
+        let _ = self.0.send(update);
+    }
+
+    pub fn system(&self, message: impl Into<String>) {
+        self.send(UiUpdate::System(message.into()));
+    }
+}
+
+/// The terminal, status bar and statistics, owned by the UI task
+pub struct Console {
+    pub terminal_ui: TerminalUI,
+    pub status_indicator: StatusIndicator,
+    pub statistics: Statistics,
+    animation: Option<Animation>,
+    next_frame: Instant,
+    next_statistics: Instant,
+}
+
+impl Console {
+    pub fn new(terminal_ui: TerminalUI, status_indicator: StatusIndicator, statistics: Statistics) -> Self {
+        let now = Instant::now();
+        Self {
+            terminal_ui,
+            status_indicator,
+            statistics,
+            animation: None,
+            next_frame: now,
+            next_statistics: now,
+        }
+    }
+
+    pub fn draw(&self) {
+        self.terminal_ui.draw(&self.status_indicator, &self.statistics);
+    }
+
+    fn apply(&mut self, update: UiUpdate) {
+        match update {
+            UiUpdate::System(message) => self.terminal_ui.add_system_message(&message),
+            UiUpdate::User(message) => self.terminal_ui.add_user_message(&message),
+            UiUpdate::Eva(message) => self.terminal_ui.add_eva_message(&message),
+            UiUpdate::Partial(text) => self.terminal_ui.set_partial_transcript(&text),
+            UiUpdate::State(state) => {
+                self.status_indicator.set_status(state.status());
+                self.animation = match state {
+                    DaemonState::Idle => None,
+                    DaemonState::Listening => Some(Animation::listening()),
+                    DaemonState::Thinking => Some(Animation::processing()),
+                    DaemonState::Speaking => Some(Animation::speaking()),
+                    DaemonState::Executing => Some(Animation::executing()),
+                };
+            }
+            UiUpdate::Connection(state) => self.status_indicator.set_connection(state),
+            UiUpdate::Playback(progress) => self.terminal_ui.set_playback(progress.as_ref()),
+            UiUpdate::TurnFinished => self.statistics.increment_turns(),
+            UiUpdate::CommandsExecuted(count) => self.statistics.commands_executed += count,
+        }
+    }
+
+    /// Next animation frame and fresh statistics while EVA is busy;
+    /// true if there is something new to draw
+    fn animate(&mut self, now: Instant) -> bool {
+        let Some(ref mut animation) = self.animation else {
+            return false;
+        };
+        let mut changed = false;
+        if now >= self.next_frame {
+            self.status_indicator.set_symbol(animation.next_frame());
+            self.next_frame = now + animation.frame_duration();
+            changed = true;
+        }
+        if now >= self.next_statistics {
+            self.statistics.update_all();
+            self.next_statistics = now + STATISTICS_INTERVAL;
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Wake word, end of speech and barge-in, from the microphone chunks
+///
+/// Keeps its own copy of the state so it can act on the chunk right after
+/// a detection; the conductor's state replaces it whenever that changes.
+pub struct Detector {
+    wake_word: WakeWordDetector,
+    vad: VoiceActivity,
+    barge_in: BargeInDetector,
+    recorder: Option<TurnRecorder>,
+    state: DaemonState,
+    turn_samples: usize,
+    quiet_chunks: u32,
+}
+
+impl Detector {
+    pub fn new(
+        wake_word: WakeWordDetector,
+        vad: VoiceActivity,
+        barge_in: BargeInDetector,
+        recorder: Option<TurnRecorder>,
+    ) -> Self {
+        Self {
+            wake_word,
+            vad,
+            barge_in,
+            recorder,
+            state: DaemonState::Idle,
+            turn_samples: 0,
+            quiet_chunks: 0,
+        }
+    }
+
+    /// Follow the conductor's state
+    fn enter(&mut self, state: DaemonState, ui: &UiHandle) {
+        if state == self.state {
+            return;
+        }
+        if self.state == DaemonState::Listening {
+            self.save_turn(ui);
+        }
+        match state {
+            DaemonState::Listening => {
+                self.wake_word.reset();
+                self.vad.reset();
+                self.turn_samples = 0;
+                self.quiet_chunks = 0;
+            }
+            DaemonState::Thinking => self.barge_in.reset(),
+            DaemonState::Idle => self.vad.reset(),
+            DaemonState::Speaking | DaemonState::Executing => {}
+        }
+        self.state = state;
+    }
+
+    fn save_turn(&mut self, ui: &UiHandle) {
+        if let Some(path) = self.recorder.as_mut().and_then(|r| r.finish_turn()) {
+            ui.system(format!("🎙️  Turn saved to {}", path.display()));
+        }
+    }
+
+    /// One microphone chunk; `playing` is whether EVA's voice is going out
+    fn process(&mut self, chunk: &[f32], playing: bool, ui: &UiHandle) -> Vec<Event> {
+        let mut events = Vec::new();
+        match self.state {
+            DaemonState::Idle => {
+                // The VAD keeps learning the room's noise floor
+                self.vad.process(chunk);
+                if self.wake_word.detect(chunk) {
+                    self.enter(DaemonState::Listening, ui);
+                    events.push(Event::WakeWord);
+                }
+            }
+            DaemonState::Listening => {
+                if let Some(ref mut recorder) = self.recorder {
+                    if let Err(e) = recorder.push(chunk) {
+                        ui.system(format!("Recording error: {}", e));
+                        self.recorder = None;
+                    }
+                }
+                events.push(Event::TurnAudio(chunk.to_vec()));
+                self.turn_samples += chunk.len();
+
+                // The turn ends with the utterance (or when nobody starts talking)
+                let voice = self.vad.process(chunk);
+                if !voice.in_speech {
+                    self.quiet_chunks += 1;
+                }
+                let end = if voice.event == Some(VadEvent::SpeechEnd) {
+                    Some(TurnEnd::SpeechEnd)
+                } else if self.quiet_chunks > NO_SPEECH_CHUNKS {
+                    Some(TurnEnd::NoSpeech)
+                } else if self.turn_samples > MAX_TURN_SAMPLES {
+                    Some(TurnEnd::MaxLength)
+                } else {
+                    None
+                };
+                if let Some(end) = end {
+                    self.enter(DaemonState::Thinking, ui);
+                    events.push(Event::EndOfSpeech(end));
+                }
+            }
+            DaemonState::Thinking | DaemonState::Speaking => {
+                // Keep listening while EVA speaks (full duplex)
+                if self.barge_in.process(chunk, playing) || self.wake_word.detect(chunk) {
+                    self.enter(DaemonState::Listening, ui);
+                    events.push(Event::WakeWord);
+                }
+            }
+            // Commands aren't interrupted
+            DaemonState::Executing => {}
+        }
+        if let Some(warning) = self.vad.take_warning() {
+            ui.system(warning);
+        }
+        events
+    }
+}
+
+/// Everything the tasks take over once setup is done
+pub struct Daemon {
+    pub audio: Box<dyn AudioSource>,
+    /// Feed `audio` at the microphone's pace (it is a replay)
+    pub replay: bool,
+    pub detector: Detector,
+    pub player: AudioPlayer,
+    pub voice: VoiceSettings,
+    pub connection: Option<ConnectionSupervisor>,
+    pub offline_stt: Option<StreamingSttSession>,
+    pub session: ConversationSession,
+    pub parser: CommandParser,
+    pub executor: CommandExecutor,
+    pub custom_commands: CustomCommandManager,
+    pub macros: MacroManager,
+    pub profile: UserProfile,
+    pub timemachine: Option<Arc<TimeMachine>>,
+}
+
+impl Daemon {
+    /// Run until Ctrl-C (or the end of a replay), then save the session
+    ///
+    /// Must be called inside a `LocalSet`. Returns the console for the
+    /// last words.
+    pub async fn run(self, console: Console) -> Result<Console, Box<dyn Error>> {
+        let (ui_tx, ui_rx) = mpsc::unbounded_channel();
+        let ui = UiHandle(ui_tx);
+        let ui_task = spawn_local(ui_task(console, ui_rx));
+
+        let (event_tx, mut events) = mpsc::unbounded_channel();
+        let (frame_tx, frame_rx) = mpsc::channel(FRAME_QUEUE);
+        let (state_tx, state_rx) = watch::channel(DaemonState::Idle);
+        let (player_tx, player_rx) = watch::channel(PlayerStatus {
+            playing: false,
+            progress: self.player.response_progress(),
+        });
+        let (playback_tx, playback_rx) = mpsc::unbounded_channel();
+
+        let pace = self.replay.then_some(CHUNK_DURATION);
+        let capture = spawn_local(capture_task(self.audio, frame_tx, pace, ui.clone()));
+        let detector = spawn_local(detector_task(
+            self.detector,
+            frame_rx,
+            state_rx,
+            player_rx.clone(),
+            event_tx.clone(),
+            ui.clone(),
+        ));
+        let interrupt = self.player.interrupt_handle();
+        let playback = spawn_local(playback_task(self.player, playback_rx, player_tx, event_tx.clone(), ui.clone()));
+
+        let link = self.connection.as_ref().map(Link::of);
+        let backend_name = self.connection.as_ref().map_or("backend", |c| c.name());
+        let (backend_tx, backend) = match self.connection {
+            Some(connection) => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                let task = spawn_local(backend_task(connection, rx, event_tx.clone(), ui.clone()));
+                (Some(tx), Some(task))
+            }
+            None => (None, None),
+        };
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let timemachine = self.timemachine.map(|tm| spawn_local(time_machine_task(tm, shutdown_rx)));
+        drop(event_tx);
+
+        let mut conductor = Conductor {
+            state: DaemonState::Idle,
+            state_tx,
+            player: player_rx,
+            playback: playback_tx,
+            interrupt,
+            backend: backend_tx,
+            backend_name,
+            link,
+            ui: ui.clone(),
+            session: self.session,
+            parser: self.parser,
+            executor: self.executor,
+            custom_commands: self.custom_commands,
+            macros: self.macros,
+            profile: self.profile,
+            voice: self.voice,
+            offline_stt: self.offline_stt,
+            turn: Turn::new(false),
+            input_ended: false,
+        };
+        conductor.run(&mut events).await;
+
+        // Shut down: the session first, it is what an interrupted run used to lose
+        conductor.save_session();
+        capture.abort();
+        detector.abort();
+        let _ = capture.await;
+        let _ = detector.await;
+        if let Some(tx) = conductor.backend.take() {
+            let _ = tx.send(BackendCommand::Close);
+        }
+        if let Some(backend) = backend {
+            if tokio::time::timeout(CLOSE_TIMEOUT, backend).await.is_err() {
+                ui.system(format!("⚠️  {} did not hang up in time", backend_name));
+            }
+        }
+        let _ = shutdown_tx.send(true);
+        if let Some(timemachine) = timemachine {
+            let _ = timemachine.await;
+        }
+        // Closes the playback channel and the last UI handles
+        drop(conductor);
+        let _ = playback.await;
+        drop(ui);
+        Ok(ui_task.await?)
+    }
+}
+
+/// Bookkeeping for the turn in progress
+struct Turn {
+    /// Streamed to the backend (otherwise recognized locally)
+    online: bool,
+    /// Chunks streamed or recognized
+    streamed: u32,
+    /// Answer audio that arrived while the user was still talking
+    responses: u32,
+    received_audio: bool,
+    /// The backend said the answer is complete
+    complete: bool,
+    barged_in: bool,
+    last_activity: Instant,
+    last_audio: Instant,
+}
+
+impl Turn {
+    fn new(online: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            online,
+            streamed: 0,
+            responses: 0,
+            received_audio: false,
+            complete: false,
+            barged_in: false,
+            last_activity: now,
+            last_audio: now,
+        }
+    }
+}
+
+/// Owns the state machine and everything the conversation needs
+struct Conductor {
+    state: DaemonState,
+    state_tx: watch::Sender<DaemonState>,
+    player: watch::Receiver<PlayerStatus>,
+    playback: mpsc::UnboundedSender<PlaybackCommand>,
+    /// Stops local speech even while the playback task is busy speaking
+    interrupt: SpeechInterrupt,
+    backend: Option<mpsc::UnboundedSender<BackendCommand>>,
+    backend_name: &'static str,
+    link: Option<Link>,
+    ui: UiHandle,
+    session: ConversationSession,
+    parser: CommandParser,
+    executor: CommandExecutor,
+    custom_commands: CustomCommandManager,
+    macros: MacroManager,
+    profile: UserProfile,
+    /// Voice of offline answers (follows the language spoken)
+    voice: VoiceSettings,
+    offline_stt: Option<StreamingSttSession>,
+    turn: Turn,
+    input_ended: bool,
+}
+
+impl Conductor {
+    async fn run(&mut self, events: &mut mpsc::UnboundedReceiver<Event>) {
+        let mut tick = tokio::time::interval(TICK_INTERVAL);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        // Without a signal handler Ctrl-C kills the process as before
+        let ctrl_c = async {
+            if tokio::signal::ctrl_c().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        };
+        tokio::pin!(ctrl_c);
+
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => self.handle(event).await,
+                    None => break,
+                },
+                _ = tick.tick() => self.tick(),
+                _ = &mut ctrl_c => {
+                    self.ui.system("Ctrl-C: shutting down...");
+                    self.interrupt.interrupt();
+                    self.play(PlaybackCommand::Stop);
+                    break;
+                }
+            }
+            // A replay is over once its last turn has been answered
+            if self.input_ended && self.state == DaemonState::Idle {
+                break;
+            }
+        }
+    }
+
+    async fn handle(&mut self, event: Event) {
+        match event {
+            Event::WakeWord => self.start_turn(),
+            Event::TurnAudio(chunk) => self.stream(&chunk),
+            Event::EndOfSpeech(end) => self.end_turn(end).await,
+            Event::InputEnded => {
+                self.input_ended = true;
+                if self.state == DaemonState::Listening {
+                    self.end_turn(TurnEnd::InputEnded).await;
+                }
+            }
+            Event::Backend(event) => self.on_backend_event(event).await,
+            Event::Connection(link) => self.on_connection(link),
+            // Over when the backend says so and all of it has been heard
+            Event::AnswerPlayed => {
+                if self.state.is_answering() && self.turn.complete {
+                    self.finish_answer();
+                }
+            }
+            Event::SpeechFinished => {
+                if self.state == DaemonState::Speaking && !self.turn.online {
+                    self.finish_answer();
+                }
+            }
+        }
+    }
+
+    fn transition(&mut self, trigger: Trigger) {
+        let next = self.state.on(trigger);
+        if next != self.state {
+            self.state = next;
+            self.state_tx.send_replace(next);
+            self.ui.send(UiUpdate::State(next));
+        }
+    }
+
+    /// The detector acted on a stale state; send it the current one again
+    fn resync(&self) {
+        self.state_tx.send_replace(self.state);
+    }
+
+    fn play(&self, command: PlaybackCommand) {
+        let _ = self.playback.send(command);
+    }
+
+    fn send(&self, command: BackendCommand) {
+        if let Some(ref backend) = self.backend {
+            let _ = backend.send(command);
+        }
+    }
+
+    fn start_turn(&mut self) {
+        match self.state {
+            DaemonState::Idle => {
+                // Wake word over the tail of a previous answer also interrupts it
+                if self.player.borrow().playing {
+                    self.interrupt_answer();
+                }
+            }
+            DaemonState::Thinking | DaemonState::Speaking => {
+                self.interrupt_answer();
+                self.ui.system("EVA interrupted");
+                self.turn.barged_in = true;
+                self.finish_answer();
+            }
+            DaemonState::Listening | DaemonState::Executing => {
+                self.resync();
+                return;
+            }
+        }
+
+        self.transition(Trigger::Wake);
+        self.ui.system("Wake word detected! Listening...");
+        self.play(PlaybackCommand::Begin);
+        // While reconnecting, the turn's audio is buffered for the backend;
+        // only an offline backend hands the turn to local recognition
+        let online = self.link.is_some_and(|link| link.state != ConnectionState::Offline);
+        self.turn = Turn::new(online);
+    }
+
+    fn interrupt_answer(&mut self) {
+        self.interrupt.interrupt();
+        self.play(PlaybackCommand::Stop);
+        self.send(BackendCommand::Interrupt);
+    }
+
+    /// Turn audio to the backend, or to local recognition
+    fn stream(&mut self, chunk: &[f32]) {
+        if self.state != DaemonState::Listening {
+            return;
+        }
+        if self.turn.online {
+            let pcm = chunk
+                .iter()
+                .flat_map(|&sample| ((sample * i16::MAX as f32) as i16).to_le_bytes())
+                .collect();
+            self.send(BackendCommand::Audio(pcm));
+            self.turn.streamed += 1;
+        } else if let Some(ref mut stt) = self.offline_stt {
+            // Offline: transcribe locally, showing the words as they come
+            stt.add_audio_f32(chunk);
+            match stt.process() {
+                Ok(Some(partial)) => self.ui.send(UiUpdate::Partial(partial.text)),
+                Ok(None) => {}
+                Err(e) => self.ui.system(format!("STT error: {}", e)),
+            }
+            for warning in stt.take_warnings() {
+                self.ui.system(format!("STT: {}", warning));
+            }
+            self.turn.streamed += 1;
+        }
+    }
+
+    async fn end_turn(&mut self, end: TurnEnd) {
+        if self.state != DaemonState::Listening {
+            self.resync();
+            return;
+        }
+        if end == TurnEnd::MaxLength {
+            self.ui.system("Max recording time reached");
+        }
+        // The user is done; backends without their own end-of-speech detection answer now
+        if self.turn.online {
+            self.send(BackendCommand::EndTurn);
+        }
+        self.ui.system(format!(
+            "Streamed {} chunks, received {} responses",
+            self.turn.streamed, self.turn.responses
+        ));
+        self.ui.send(UiUpdate::TurnFinished);
+
+        self.transition(Trigger::EndOfSpeech);
+        let now = Instant::now();
+        self.turn.last_activity = now;
+        self.turn.last_audio = now;
+        self.turn.received_audio = self.turn.responses > 0;
+        if self.turn.received_audio {
+            self.transition(Trigger::AnswerStarted);
+        }
+
+        if !self.turn.online {
+            self.answer_offline().await;
+        }
+    }
+
+    /// Answer locally from the transcription
+    async fn answer_offline(&mut self) {
+        self.ui.send(UiUpdate::Partial(String::new()));
+        let transcript = match self.offline_stt.as_mut().map(|stt| stt.finalize()) {
+            Some(Ok(result)) => result.text,
+            Some(Err(e)) => {
+                self.ui.system(format!("STT error: {}", e));
+                String::new()
+            }
+            None => String::new(),
+        };
+        for warning in self.offline_stt.as_mut().map(|stt| stt.take_warnings()).unwrap_or_default() {
+            self.ui.system(format!("STT: {}", warning));
+        }
+
+        // Answer in the language the user just spoke
+        let spoken = self
+            .offline_stt
+            .as_ref()
+            .map(|stt| (stt.recognizer().language(), stt.language_guess().map_or(0.0, |g| g.probability)));
+        if let Some((language, confidence)) = spoken {
+            if Language::from_tag(&self.voice.language) != Some(language) {
+                self.voice = VoiceSettings {
+                    language: language.tag().to_string(),
+                    ..VoiceSettings::from_profile(&self.profile)
+                };
+                let (engine_tx, engine_rx) = oneshot::channel();
+                self.play(PlaybackCommand::SetVoice { voice: self.voice.clone(), engine: engine_tx });
+                let engine = engine_rx.await.unwrap_or_default();
+                self.ui.system(format!(
+                    "🌐 Switched to {} ({:.0}%, TTS: {})",
+                    language.name(),
+                    confidence * 100.0,
+                    engine
+                ));
+            }
+        }
+
+        let response = if self.offline_stt.is_none() {
+            "I can't understand speech without a backend until offline recognition is installed.".to_string()
+        } else if transcript.is_empty() {
+            "Sorry, I didn't catch that.".to_string()
+        } else {
+            self.ui.send(UiUpdate::User(transcript.clone()));
+            self.session.add_turn(Role::User, transcript.clone());
+            self.transition(Trigger::CommandRequested);
+
+            let reply = offline_assistant::respond(
+                &transcript,
+                &self.custom_commands,
+                &self.macros,
+                &self.parser,
+                &mut self.executor,
+            )
+            .await;
+            self.ui.send(UiUpdate::CommandsExecuted(reply.commands_executed));
+            self.session.add_turn(Role::Assistant, reply.text.clone());
+            self.transition(Trigger::CommandFinished);
+            reply.text
+        };
+        self.ui.send(UiUpdate::Eva(response.clone()));
+
+        // Spoken by the playback task; the detector listens for barge-in meanwhile
+        self.transition(Trigger::AnswerStarted);
+        self.play(PlaybackCommand::Speak(response));
+    }
+
+    async fn on_backend_event(&mut self, event: BackendEvent) {
+        match event {
+            BackendEvent::Audio { pcm, sample_rate } => match self.state {
+                DaemonState::Listening => {
+                    self.turn.responses += 1;
+                    self.play(PlaybackCommand::Queue { pcm, sample_rate });
+                }
+                DaemonState::Thinking | DaemonState::Speaking => {
+                    self.turn.received_audio = true;
+                    self.turn.last_activity = Instant::now();
+                    self.turn.last_audio = self.turn.last_activity;
+                    // Into the jitter buffer; the playback task feeds the speaker
+                    self.play(PlaybackCommand::Queue { pcm, sample_rate });
+                    self.transition(Trigger::AnswerStarted);
+                }
+                // The rest of an answer that is already over
+                DaemonState::Idle | DaemonState::Executing => {}
+            },
+            BackendEvent::TurnComplete => {
+                if self.state.is_answering() {
+                    self.play(PlaybackCommand::End);
+                    self.turn.complete = true;
+                }
+            }
+            BackendEvent::Interrupted => match self.state {
+                // Still answering the previous turn; that answer is over
+                DaemonState::Listening => self.play(PlaybackCommand::Stop),
+                // The backend stopped its own answer
+                DaemonState::Thinking | DaemonState::Speaking => {
+                    self.play(PlaybackCommand::Stop);
+                    self.finish_answer();
+                }
+                DaemonState::Idle | DaemonState::Executing => {}
+            },
+            event => {
+                self.dispatch(event).await;
+                self.turn.last_activity = Instant::now();
+            }
+        }
+    }
+
+    /// Transcripts go to the session and the screen, command requests and
+    /// tool calls run in the sandboxed executor and their result goes
+    /// back, unknown messages are shown
+    async fn dispatch(&mut self, event: BackendEvent) {
+        match event {
+            BackendEvent::Text(text) => {
+                self.ui.send(UiUpdate::Eva(text.clone()));
+                self.session.add_turn(Role::Assistant, text);
+            }
+            BackendEvent::Transcript { role, text, is_final: false } => {
+                if role == Role::User {
+                    self.ui.send(UiUpdate::Partial(text));
+                }
+            }
+            BackendEvent::Transcript { role, text, is_final: true } => {
+                match role {
+                    Role::User => {
+                        self.ui.send(UiUpdate::Partial(String::new()));
+                        self.ui.send(UiUpdate::User(text.clone()));
+                    }
+                    Role::Assistant => self.ui.send(UiUpdate::Eva(text.clone())),
+                }
+                self.session.add_turn(role, text);
+            }
+            BackendEvent::CommandRequest { id, command } => {
+                self.ui.system(format!("⚙️  {} asked to run: {}", self.backend_name, command));
+                let intent = match self.parser.parse(&command) {
+                    Ok(CommandIntent::Unknown) => Err(format!("Unknown command: {}", command)),
+                    Ok(intent) => tools::remote_allowed(&intent, &self.profile.allowed_programs).map(|_| intent),
+                    Err(e) => Err(e.to_string()),
+                };
+                self.run_command(id, intent).await;
+            }
+            BackendEvent::ToolCall { id, name, args } => {
+                self.ui.system(format!("⚙️  {} called {} {}", self.backend_name, name, args));
+                let intent = tools::intent_from_call(&name, &args, &self.profile.allowed_programs);
+                self.run_command(id, intent).await;
+            }
+            BackendEvent::Error(message) => {
+                self.ui.system(format!("⚠️  {} error: {}", self.backend_name, message));
+            }
+            BackendEvent::Control(msg) => {
+                let msg_type = msg.get("type").and_then(|v| v.as_str()).unwrap_or("(no type)");
+                self.ui.system(format!("Unknown message from {}: {}", self.backend_name, msg_type));
+            }
+            // Handled by `on_backend_event`
+            BackendEvent::Audio { .. } | BackendEvent::TurnComplete | BackendEvent::Interrupted => {}
+        }
+    }
+
+    /// Run a command the backend asked for and send it the outcome
+    async fn run_command(&mut self, id: String, intent: Result<CommandIntent, String>) {
+        self.transition(Trigger::CommandRequested);
+        let result = match intent {
+            Ok(intent) => self.executor.execute(intent).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        let (success, output) = match result {
+            Ok(output) => {
+                self.ui.send(UiUpdate::CommandsExecuted(1));
+                (true, output)
+            }
+            Err(e) => {
+                self.ui.system(format!("Command failed: {}", e));
+                (false, e)
+            }
+        };
+        self.send(BackendCommand::CommandResult { id, success, output });
+        self.transition(Trigger::CommandFinished);
+    }
+
+    fn on_connection(&mut self, link: Link) {
+        self.link = Some(link);
+        if link.state != ConnectionState::Offline {
+            return;
+        }
+        if self.offline_stt.is_none() {
+            self.offline_stt = offline_recognizer(&self.profile, |message| self.ui.system(message));
+        }
+        // Gone mid-answer; nothing more is coming
+        if self.turn.online && self.state.is_answering() {
+            self.finish_answer();
+        }
+    }
+
+    /// Answer progress, the legacy end of turn and the answer timeout
+    fn tick(&mut self) {
+        if !self.turn.online || !self.state.is_answering() {
+            return;
+        }
+        let player = *self.player.borrow();
+        self.ui.send(UiUpdate::Playback(Some(player.progress)));
+
+        // A long answer keeps playing after the last of it arrived
+        if player.progress.state == PlaybackState::Playing {
+            self.turn.last_activity = Instant::now();
+        }
+        // Only silence tells when a legacy backend is done
+        let reports_turn_end = self.link.is_none_or(|link| link.reports_turn_end);
+        if !self.turn.complete
+            && !reports_turn_end
+            && self.turn.received_audio
+            && self.turn.last_audio.elapsed() >= LEGACY_TURN_END
+        {
+            self.play(PlaybackCommand::End);
+            self.turn.complete = true;
+        }
+        if self.turn.last_activity.elapsed() >= ANSWER_TIMEOUT {
+            self.finish_answer();
+        }
+    }
+
+    fn finish_answer(&mut self) {
+        // Whatever arrived still gets played
+        self.play(PlaybackCommand::End);
+        self.ui.send(UiUpdate::Playback(None));
+        self.send(BackendCommand::DiscardTurn);
+        if self.turn.online && !self.turn.received_audio && !self.turn.barged_in {
+            self.ui.system("No audio response received");
+        }
+        self.transition(Trigger::AnswerFinished);
+    }
+
+    fn save_session(&self) {
+        match self.session.save_to_file(SESSION_FILE) {
+            Ok(()) => self.ui.system(format!(
+                "💾 Session saved to {} ({} turns)",
+                SESSION_FILE,
+                self.session.turn_count()
+            )),
+            Err(e) => self.ui.system(format!("⚠️  Could not save the session: {}", e)),
+        }
+    }
+}
+
+/// Microphone (or replay) chunks to the detector
+async fn capture_task(
+    mut audio: Box<dyn AudioSource>,
+    frames: mpsc::Sender<Vec<f32>>,
+    pace: Option<Duration>,
+    ui: UiHandle,
+) {
+    let mut pace = pace.map(tokio::time::interval);
+    loop {
+        if let Some(ref mut pace) = pace {
+            pace.tick().await;
+        }
+        let chunk = match audio.next_chunk().await {
+            Ok(Some(chunk)) => chunk,
+            // Only a replay runs out of audio
+            Ok(None) => break,
+            Err(e) => {
+                ui.system(format!("Audio Error: {}", e));
+                continue;
+            }
+        };
+        if frames.send(chunk).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn detector_task(
+    mut detector: Detector,
+    mut frames: mpsc::Receiver<Vec<f32>>,
+    mut state: watch::Receiver<DaemonState>,
+    player: watch::Receiver<PlayerStatus>,
+    events: mpsc::UnboundedSender<Event>,
+    ui: UiHandle,
+) {
+    while let Some(chunk) = frames.recv().await {
+        if state.has_changed().unwrap_or(false) {
+            let current = *state.borrow_and_update();
+            detector.enter(current, &ui);
+        }
+        let playing = player.borrow().playing;
+        for event in detector.process(&chunk, playing, &ui) {
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+    }
+    detector.save_turn(&ui);
+    let _ = events.send(Event::InputEnded);
+}
+
+async fn backend_task(
+    mut connection: ConnectionSupervisor,
+    mut commands: mpsc::UnboundedReceiver<BackendCommand>,
+    events: mpsc::UnboundedSender<Event>,
+    ui: UiHandle,
+) {
+    let mut reported = connection.state();
+    loop {
+        // Heartbeat and reconnection
+        connection.maintain().await;
+        if connection.state() != reported {
+            reported = connection.state();
+            show_connection_state(&connection, &ui);
+            let _ = events.send(Event::Connection(Link::of(&connection)));
+        }
+
+        tokio::select! {
+            biased;
+
+            command = commands.recv() => match command {
+                Some(BackendCommand::Close) | None => break,
+                Some(command) => run_backend_command(&mut connection, command, &ui).await,
+            },
+            // Only waits on the socket, so a command coming first loses nothing
+            received = next_backend_event(&mut connection) => {
+                // Errors are connection losses, reported above
+                if let Ok(Some(event)) = received {
+                    if events.send(Event::Backend(event)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    connection.close().await.ok();
+}
+
+async fn next_backend_event(connection: &mut ConnectionSupervisor) -> Result<Option<BackendEvent>, Box<dyn Error>> {
+    if connection.is_connected() {
+        connection.receive().await
+    } else {
+        tokio::time::sleep(DISCONNECTED_POLL).await;
+        Ok(None)
+    }
+}
+
+async fn run_backend_command(connection: &mut ConnectionSupervisor, command: BackendCommand, ui: &UiHandle) {
+    match command {
+        BackendCommand::Audio(pcm) => connection.send_audio(&pcm).await,
+        BackendCommand::EndTurn => connection.end_turn().await,
+        BackendCommand::Interrupt => {
+            if let Err(e) = connection.interrupt().await {
+                ui.system(format!("Interrupt error: {}", e));
+            }
+        }
+        BackendCommand::CommandResult { id, success, output } => {
+            if let Err(e) = connection.send_command_result(&id, success, &output).await {
+                ui.system(format!("Could not send command result: {}", e));
+            }
+        }
+        BackendCommand::DiscardTurn => connection.discard_turn(),
+        // Ends the task; see `backend_task`
+        BackendCommand::Close => {}
+    }
+}
+
+/// Show a backend connection change in the status bar and the log
+fn show_connection_state(connection: &ConnectionSupervisor, ui: &UiHandle) {
+    ui.send(UiUpdate::Connection(Some(connection.state())));
+    let reason = connection.last_error().unwrap_or("unknown error");
+    match connection.state() {
+        ConnectionState::Connected => ui.system(format!(
+            "✅ Reconnected to {} (session {})",
+            connection.name(),
+            connection.session_id().unwrap_or_default()
+        )),
+        ConnectionState::Reconnecting { attempt: 0 } => {
+            ui.system(format!("⚠️  Lost {}: {}; reconnecting...", connection.name(), reason))
+        }
+        // Further attempts only update the status bar
+        ConnectionState::Reconnecting { .. } => {}
+        ConnectionState::Offline => ui.system(format!(
+            "⚠️  {} unreachable ({}); running offline, still retrying",
+            connection.name(),
+            reason
+        )),
+    }
+}
+
+async fn playback_task(
+    mut player: AudioPlayer,
+    mut commands: mpsc::UnboundedReceiver<PlaybackCommand>,
+    status: watch::Sender<PlayerStatus>,
+    events: mpsc::UnboundedSender<Event>,
+    ui: UiHandle,
+) {
+    let mut pump = tokio::time::interval(PUMP_INTERVAL);
+    pump.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    // The answer being played has been reported as done
+    let mut reported = true;
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(PlaybackCommand::Begin) => {
+                    player.begin_response();
+                    reported = false;
+                }
+                Some(PlaybackCommand::Queue { pcm, sample_rate }) => player.queue_response(&pcm, sample_rate),
+                Some(PlaybackCommand::End) => player.end_response(),
+                Some(PlaybackCommand::Stop) => player.stop(),
+                Some(PlaybackCommand::Speak(text)) => {
+                    status.send_replace(PlayerStatus { playing: true, progress: player.response_progress() });
+                    if let Err(e) = player.speak_text(&text).await {
+                        ui.system(format!("TTS Error: {}", e));
+                    }
+                    let _ = events.send(Event::SpeechFinished);
+                }
+                Some(PlaybackCommand::SetVoice { voice, engine }) => {
+                    player.set_voice(voice);
+                    if let Some(warning) = player.take_tts_warning() {
+                        ui.system(format!("TTS: {}", warning));
+                    }
+                    let _ = engine.send(player.tts_engine_name());
+                }
+                None => break,
+            },
+            _ = pump.tick() => {
+                if let Err(e) = player.pump_response() {
+                    ui.system(format!("Audio Playback Error: {}", e));
+                }
+                if !reported && player.response_done() {
+                    reported = true;
+                    let _ = events.send(Event::AnswerPlayed);
+                }
+            }
+        }
+        status.send_replace(PlayerStatus {
+            playing: player.is_playing(),
+            progress: player.response_progress(),
+        });
+    }
+}
+
+/// Redraws from updates, and for the animation while EVA is busy
+async fn ui_task(mut console: Console, mut updates: mpsc::UnboundedReceiver<UiUpdate>) -> Console {
+    let mut frame = tokio::time::interval(FRAME_INTERVAL);
+    frame.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut dirty = true;
+
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Some(update) => {
+                    console.apply(update);
+                    dirty = true;
+                }
+                None => break,
+            },
+            _ = frame.tick() => {
+                if console.animate(Instant::now()) || dirty {
+                    console.draw();
+                    dirty = false;
+                }
+            }
+        }
+    }
+    console
+}
+
+async fn time_machine_task(timemachine: Arc<TimeMachine>, mut shutdown: watch::Receiver<bool>) {
+    tokio::select! {
+        _ = timemachine.start_recording() => {}
+        _ = shutdown.changed() => timemachine.stop_recording(),
+    }
+}
+
+/// Local speech recognition for answering without a backend
+pub fn offline_recognizer(profile: &UserProfile, mut report: impl FnMut(String)) -> Option<StreamingSttSession> {
+    let language = Language::from_tag(&profile.language).unwrap_or(Language::EnglishUS);
+    let stt_config = SttConfig {
+        language,
+        ..SttConfig::default()
+    };
+    let instructions = SttEngine::with_config(stt_config.clone()).get_download_instructions();
+    match stt::default_recognizer(stt_config, &mut report) {
+        Ok(recognizer) => {
+            report(format!(
+                "✅ Offline speech recognition ready ({}, {})",
+                recognizer.name(),
+                language.code()
+            ));
+            let mut stt = StreamingSttSession::new(recognizer);
+            if let Some(language_id) = LanguageId::from_profile(profile) {
+                let codes: Vec<&str> = language_id.allowed().iter().map(|l| l.code()).collect();
+                report(format!("🌐 Language identification: {}", codes.join(", ")));
+                stt.set_language_id(Some(language_id));
+            }
+            Some(stt)
+        }
+        Err(e) => {
+            report(format!("⚠️  Offline speech recognition unavailable: {}", e));
+            report(instructions);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vad;
+
+    fn detector() -> (Detector, UiHandle, mpsc::UnboundedReceiver<UiUpdate>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let detector = Detector::new(
+            WakeWordDetector::new(),
+            VoiceActivity::new(vad::detector(None).0),
+            BargeInDetector::new(),
+            None,
+        );
+        (detector, UiHandle(tx), rx)
+    }
+
+    fn tone() -> Vec<f32> {
+        (0..CHUNK_SIZE)
+            .map(|i| (2.0 * std::f32::consts::PI * 300.0 * i as f32 / SAMPLE_RATE as f32).sin() * 0.3)
+            .collect()
+    }
+
+    #[test]
+    fn test_detector_ends_turn_after_utterance() {
+        let (mut detector, ui, _updates) = detector();
+        detector.enter(DaemonState::Listening, &ui);
+
+        // 1s silence, 1s "speech", then silence until the VAD calls it
+        let chunks = std::iter::repeat_n(vec![0.0; CHUNK_SIZE], 10)
+            .chain(std::iter::repeat_n(tone(), 10))
+            .chain(std::iter::repeat_n(vec![0.0; CHUNK_SIZE], 20));
+        let mut streamed = 0;
+        let mut end = None;
+        for chunk in chunks {
+            for event in detector.process(&chunk, false, &ui) {
+                match event {
+                    Event::TurnAudio(_) => streamed += 1,
+                    Event::EndOfSpeech(why) => end = Some(why),
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+            if end.is_some() {
+                break;
+            }
+        }
+
+        assert_eq!(end, Some(TurnEnd::SpeechEnd));
+        assert!(streamed > 20, "the whole utterance is streamed ({} chunks)", streamed);
+        assert_eq!(detector.state, DaemonState::Thinking);
+    }
+
+    #[test]
+    fn test_detector_gives_up_without_speech() {
+        let (mut detector, ui, _updates) = detector();
+        detector.enter(DaemonState::Listening, &ui);
+
+        let silence = vec![0.0; CHUNK_SIZE];
+        let chunks = (1..=NO_SPEECH_CHUNKS + 1)
+            .find(|_| {
+                detector
+                    .process(&silence, false, &ui)
+                    .iter()
+                    .any(|event| matches!(event, Event::EndOfSpeech(TurnEnd::NoSpeech)))
+            })
+            .expect("turn ended");
+        assert_eq!(chunks, NO_SPEECH_CHUNKS + 1);
+
+        // Silence while EVA answers is no barge-in
+        assert!(detector.process(&silence, true, &ui).is_empty());
+    }
+
+    #[test]
+    fn test_detector_saves_turn_when_leaving_listening() {
+        let dir = std::env::temp_dir().join(format!("eva-daemon-turns-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (mut detector, ui, mut updates) = detector();
+        detector.recorder = Some(TurnRecorder::new(&dir).unwrap());
+
+        // Idle audio isn't recorded
+        detector.process(&[0.0; CHUNK_SIZE], false, &ui);
+        detector.enter(DaemonState::Listening, &ui);
+        detector.process(&[0.0; CHUNK_SIZE], false, &ui);
+        detector.enter(DaemonState::Idle, &ui);
+
+        match updates.try_recv() {
+            Ok(UiUpdate::System(message)) => assert!(message.starts_with("🎙️  Turn saved to"), "{}", message),
+            other => panic!("expected the saved turn, got {:?}", other),
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -1,6 +1,14 @@
-use crate::websocket::WebSocketClient;
+use crate::audio::SAMPLE_RATE;
+use crate::backend::{BackendEvent, VoiceBackend};
+use crate::codec::{self, AudioCodec, AudioDecoder, AudioEncoder};
+use crate::credentials::{self, Secret};
+use crate::protocol::{self, ClientMessage, ParseError, ServerMessage, Speaker};
+use crate::session::Role;
+use crate::tools::ToolDeclaration;
+use crate::websocket::{ConnectOptions, WebSocketClient};
+use futures_util::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use std::collections::VecDeque;
 use std::fs::OpenOptions;
 use std::io::Write;
 
@@ -11,47 +19,100 @@ fn log_debug(msg: &str) {
         .open("eva_debug.log")
     {
         let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
-        writeln!(file, "[{}] {}", timestamp, msg).ok();
+        writeln!(file, "[{}] {}", timestamp, credentials::redact(msg)).ok();
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EvaMindConfig {
     pub ws_url: String,
-    pub cpf: String,
+    /// Identity to register as (see `credentials`)
+    pub cpf: Secret,
+    #[serde(default = "default_user_type")]
+    pub user_type: String,
+    /// Sent as `Authorization: Bearer` when the server requires one
+    #[serde(default)]
+    pub token: Option<Secret>,
+    /// Declared with `start_call` so the model can act on the machine
+    #[serde(default)]
+    pub tools: Vec<ToolDeclaration>,
+    /// Offered in `start_call`, preferred first; PCM16 if the server picks none
+    #[serde(default = "codec::available")]
+    pub audio_codecs: Vec<AudioCodec>,
+    /// Proxy, extra CAs, certificate pins, timeouts
+    #[serde(skip)]
+    pub network: ConnectOptions,
+    /// Languages the user speaks ("pt-BR", "en-US"...), main one first;
+    /// sent with `start_call` so EVA-Mind answers in the one being spoken
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Continue this session instead of starting a new one (reconnects)
+    #[serde(default)]
+    pub session_id: Option<String>,
 }
 
 impl Default for EvaMindConfig {
     fn default() -> Self {
         Self {
             ws_url: "wss://eva-ia.org:8090/ws/pcm".to_string(),
-            cpf: "64525430249".to_string(), // Creator CPF
+            cpf: Secret::default(),
+            user_type: default_user_type(),
+            token: None,
+            tools: Vec::new(),
+            audio_codecs: codec::available(),
+            network: ConnectOptions::default(),
+            languages: Vec::new(),
+            session_id: None,
         }
     }
 }
 
+fn default_user_type() -> String {
+    "patient".to_string()
+}
+
 pub struct EvaMindClient {
     ws: WebSocketClient,
     config: EvaMindConfig,
     session_id: String,
     connected: bool,
+    /// Negotiated in the handshake (see `protocol`)
+    protocol_version: u32,
+    /// Negotiated in `session_created` (see `codec`)
+    encoder: AudioEncoder,
+    decoder: AudioDecoder,
+    /// Messages read while dropping audio in `interrupt`, returned first by `receive`
+    pending: VecDeque<EvaMindResponse>,
 }
 
 impl EvaMindClient {
     /// Connect to EVA-Mind WebSocket
     pub async fn connect(config: EvaMindConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        if config.cpf.is_empty() {
+            return Err("No EVA-Mind identity: set EVA_CPF or run eva-daemon --add-identity <name>".into());
+        }
+
         log_debug(&format!("🤖 Conectando ao EVA-Mind: {}", config.ws_url));
 
-        let ws = WebSocketClient::connect(&config.ws_url).await?;
+        let authorization = config.token.as_ref().map(|token| format!("Bearer {}", token.expose()));
+        let headers: Vec<(&str, &str)> = authorization.iter().map(|value| ("Authorization", value.as_str())).collect();
+        let ws = WebSocketClient::connect_with(&config.ws_url, &headers, &config.network).await?;
         log_debug("✅ WebSocket conectado");
 
-        let session_id = format!("eva-os-{}", chrono::Local::now().timestamp_millis());
+        let session_id = config
+            .session_id
+            .clone()
+            .unwrap_or_else(|| format!("eva-os-{}", chrono::Local::now().timestamp_millis()));
 
         let mut client = Self {
             ws,
             config,
             session_id,
             connected: false,
+            protocol_version: protocol::MIN_PROTOCOL_VERSION,
+            encoder: AudioEncoder::new(AudioCodec::Pcm16)?,
+            decoder: AudioDecoder::new(AudioCodec::Pcm16)?,
+            pending: VecDeque::new(),
         };
 
         // Register client
@@ -60,16 +121,31 @@ impl EvaMindClient {
         Ok(client)
     }
 
+    /// Send a protocol message, if the negotiated version has it
+    async fn send(&mut self, message: &ClientMessage) -> Result<(), Box<dyn std::error::Error>> {
+        if message.min_version() > self.protocol_version {
+            return Err(format!(
+                "EVA-Mind protocol v{} needed (server speaks v{})",
+                message.min_version(),
+                self.protocol_version
+            )
+            .into());
+        }
+
+        let text = message.to_json();
+        log_debug(&format!("📤 {}", text));
+        self.ws.send_text(&text).await
+    }
+
     /// Register with EVA-Mind
     async fn register(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let register_msg = json!({
-            "type": "register",
-            "user_type": "patient",
-            "cpf": self.config.cpf
-        });
-
-        log_debug(&format!("📤 Register: {}", register_msg));
-        self.ws.send_text(&register_msg.to_string()).await?;
+        let register_msg = ClientMessage::Register {
+            user_type: self.config.user_type.clone(),
+            cpf: self.config.cpf.expose().to_string(),
+            protocol_version: protocol::PROTOCOL_VERSION,
+        };
+
+        self.send(&register_msg).await?;
         log_debug("✅ Register enviado");
 
         Ok(())
@@ -77,14 +153,15 @@ impl EvaMindClient {
 
     /// Start call session
     pub async fn start_call(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let start_msg = json!({
-            "type": "start_call",
-            "cpf": self.config.cpf,
-            "session_id": self.session_id
-        });
-
-        log_debug(&format!("📤 Start call: {}", start_msg));
-        self.ws.send_text(&start_msg.to_string()).await?;
+        let start_msg = ClientMessage::StartCall {
+            cpf: self.config.cpf.expose().to_string(),
+            session_id: self.session_id.clone(),
+            languages: self.config.languages.clone(),
+            tools: self.config.tools.clone(),
+            audio_codecs: self.config.audio_codecs.iter().map(|c| c.name().to_string()).collect(),
+        };
+
+        self.send(&start_msg).await?;
         log_debug("✅ Start call enviado");
 
         // Wait for session_created
@@ -115,18 +192,32 @@ impl EvaMindClient {
                     if let tokio_tungstenite::tungstenite::Message::Text(text) = msg {
                         log_debug(&format!("📥 Msg: {}", &text[..text.len().min(200)]));
 
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                            if json.get("type").and_then(|v| v.as_str()) == Some("session_created") {
-                                log_debug("✅ session_created recebido!");
+                        match ServerMessage::parse(&text) {
+                            Ok(ServerMessage::Registered { protocol_version }) => {
+                                self.protocol_version = protocol::negotiate(protocol_version)?;
+                            }
+                            Ok(ServerMessage::SessionCreated { session_id, protocol_version, audio_codec }) => {
+                                self.protocol_version = protocol::negotiate(protocol_version)?;
+                                let audio_codec = codec::negotiate(&self.config.audio_codecs, audio_codec.as_deref())?;
+                                self.encoder = AudioEncoder::new(audio_codec)?;
+                                self.decoder = AudioDecoder::new(audio_codec)?;
+                                if let Some(id) = session_id {
+                                    self.session_id = id;
+                                }
+                                log_debug(&format!(
+                                    "✅ session_created recebido! (protocolo v{}, áudio {})",
+                                    self.protocol_version,
+                                    audio_codec.name()
+                                ));
                                 self.connected = true;
                                 return Ok(());
                             }
-                            if json.get("type").and_then(|v| v.as_str()) == Some("error") {
-                                let err_msg = json.get("message")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("Unknown error");
+                            Ok(ServerMessage::Error { message, .. }) => {
+                                let err_msg = if message.is_empty() { "Unknown error".to_string() } else { message };
                                 return Err(format!("EVA-Mind error: {}", err_msg).into());
                             }
+                            Ok(other) => log_debug(&format!("⚠️ Ignorado antes da sessão: {:?}", other)),
+                            Err(e) => log_debug(&format!("⚠️ Mensagem não reconhecida ({})", e)),
                         }
                     }
                 }
@@ -144,20 +235,36 @@ impl EvaMindClient {
         }
     }
 
-    /// Send audio data (PCM 16kHz bytes)
+    /// Send audio data (PCM 16kHz bytes), encoded with the negotiated codec
     pub async fn send_audio(&mut self, pcm_data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
         if !self.connected {
             return Err("Not connected to session".into());
         }
 
-        log_debug(&format!("🎤 Enviando áudio: {} bytes", pcm_data.len()));
-        self.ws.send_binary(pcm_data.to_vec()).await?;
-        log_debug("✅ Áudio enviado");
+        let frames = self.encoder.encode(pcm_data)?;
+        let sent: usize = frames.iter().map(Vec::len).sum();
+        for frame in frames {
+            self.ws.send_binary(frame).await?;
+        }
+        log_debug(&format!(
+            "🎤 Áudio enviado: {} bytes PCM, {} bytes {}",
+            pcm_data.len(),
+            sent,
+            self.encoder.codec().name()
+        ));
         Ok(())
     }
 
-    /// Receive audio data (PCM bytes or control messages)
+    /// Receive audio data (PCM bytes or protocol messages)
     pub async fn receive(&mut self) -> Result<Option<EvaMindResponse>, Box<dyn std::error::Error>> {
+        if let Some(response) = self.pending.pop_front() {
+            return Ok(Some(response));
+        }
+        self.read().await
+    }
+
+    /// Next frame from the socket (waits up to 100ms)
+    async fn read(&mut self) -> Result<Option<EvaMindResponse>, Box<dyn std::error::Error>> {
         let receive_timeout = tokio::time::timeout(
             tokio::time::Duration::from_millis(100),
             self.ws.receive()
@@ -168,12 +275,29 @@ impl EvaMindClient {
                 match msg {
                     tokio_tungstenite::tungstenite::Message::Binary(data) => {
                         log_debug(&format!("🔊 Áudio recebido: {} bytes", data.len()));
-                        return Ok(Some(EvaMindResponse::Audio(data)));
+                        let concealed = self.decoder.concealed_frames();
+                        match self.decoder.decode(&data) {
+                            Ok(pcm) => {
+                                if self.decoder.concealed_frames() > concealed {
+                                    log_debug(&format!(
+                                        "🩹 {} quadros perdidos preenchidos",
+                                        self.decoder.concealed_frames() - concealed
+                                    ));
+                                }
+                                return Ok(Some(EvaMindResponse::Audio(pcm)));
+                            }
+                            Err(e) => log_debug(&format!("⚠️ Áudio descartado: {}", e)),
+                        }
                     }
                     tokio_tungstenite::tungstenite::Message::Text(text) => {
                         log_debug(&format!("📥 Msg: {}", &text[..text.len().min(100)]));
-                        if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                            return Ok(Some(EvaMindResponse::Control(json)));
+                        match ServerMessage::parse(&text) {
+                            Ok(message) => return Ok(Some(EvaMindResponse::Message(message))),
+                            Err(ParseError::Unknown(value)) => {
+                                log_debug(&format!("⚠️ Mensagem desconhecida: {}", value));
+                                return Ok(Some(EvaMindResponse::Unknown(value)));
+                            }
+                            Err(e) => log_debug(&format!("⚠️ Mensagem descartada ({}): {}", e, &text[..text.len().min(100)])),
                         }
                     }
                     _ => {}
@@ -193,6 +317,83 @@ impl EvaMindClient {
         Ok(None)
     }
 
+    /// Ask EVA-Mind to stop the response in progress (user barged in)
+    ///
+    /// Audio already in flight is read and dropped so it is not played on
+    /// the next turn; anything else (transcripts, command requests, end of
+    /// turn) is kept for `receive`.
+    pub async fn interrupt(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected to session".into());
+        }
+
+        let interrupt_msg = ClientMessage::Interrupt {
+            session_id: self.session_id.clone(),
+        };
+        self.send(&interrupt_msg).await?;
+
+        let mut dropped = 0usize;
+        while let Some(response) = self.read().await? {
+            match response {
+                EvaMindResponse::Audio(data) => dropped += data.len(),
+                other => self.pending.push_back(other),
+            }
+        }
+        // The next answer starts a new packet sequence
+        self.decoder.reset();
+        log_debug(&format!("✅ Interrupt enviado ({} bytes de áudio descartados)", dropped));
+        Ok(())
+    }
+
+    /// Send a typed user message (protocol v2)
+    pub async fn send_text(&mut self, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.connected {
+            return Err("Not connected to session".into());
+        }
+
+        let text_msg = ClientMessage::Text {
+            session_id: self.session_id.clone(),
+            text: text.to_string(),
+        };
+        self.send(&text_msg).await
+    }
+
+    /// Tell EVA-Mind the user stopped talking
+    ///
+    /// Version 1 servers detect the end of speech themselves, so nothing is sent.
+    pub async fn end_turn(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.protocol_version < 2 {
+            return Ok(());
+        }
+
+        let end_msg = ClientMessage::EndOfTurn {
+            session_id: self.session_id.clone(),
+        };
+        self.send(&end_msg).await
+    }
+
+    /// Reply to a `command_request` (protocol v2)
+    pub async fn send_command_result(&mut self, id: &str, success: bool, output: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let result_msg = ClientMessage::CommandResult {
+            session_id: self.session_id.clone(),
+            id: id.to_string(),
+            success,
+            output: output.to_string(),
+        };
+        self.send(&result_msg).await
+    }
+
+    /// Keep connection alive
+    pub async fn ping(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.ws.ping().await
+    }
+
+    /// Close the WebSocket
+    pub async fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.connected = false;
+        self.ws.close().await
+    }
+
     /// Check if connected
     pub fn is_connected(&self) -> bool {
         self.connected
@@ -202,10 +403,138 @@ impl EvaMindClient {
     pub fn session_id(&self) -> &str {
         &self.session_id
     }
+
+    /// Protocol version agreed with the server
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol_version
+    }
+
+    /// Audio codec agreed with the server
+    pub fn audio_codec(&self) -> AudioCodec {
+        self.encoder.codec()
+    }
 }
 
 #[derive(Debug)]
 pub enum EvaMindResponse {
     Audio(Vec<u8>),
-    Control(serde_json::Value),
+    Message(ServerMessage),
+    /// A text frame outside the protocol (already logged)
+    Unknown(serde_json::Value),
+}
+
+impl VoiceBackend for EvaMindClient {
+    fn name(&self) -> &'static str {
+        "EVA-Mind"
+    }
+
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// `end_of_turn` is a v2 message
+    fn reports_turn_end(&self) -> bool {
+        self.protocol_version >= 2
+    }
+
+    fn start_session(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(self.start_call())
+    }
+
+    fn send_audio<'a>(&'a mut self, pcm: &'a [u8]) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::send_audio(self, pcm))
+    }
+
+    fn send_text<'a>(&'a mut self, text: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::send_text(self, text))
+    }
+
+    /// EVA-Mind sends audio as binary frames (16kHz, PCM or Opus)
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Option<BackendEvent>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let event = match EvaMindClient::receive(self).await? {
+                None => None,
+                Some(EvaMindResponse::Audio(pcm)) => Some(BackendEvent::Audio { pcm, sample_rate: SAMPLE_RATE }),
+                Some(EvaMindResponse::Message(message)) => response_event(message),
+                Some(EvaMindResponse::Unknown(value)) => Some(BackendEvent::Control(value)),
+            };
+            Ok(event)
+        })
+    }
+
+    fn end_turn(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::end_turn(self))
+    }
+
+    fn interrupt(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::interrupt(self))
+    }
+
+    fn send_command_result<'a>(
+        &'a mut self,
+        id: &'a str,
+        success: bool,
+        output: &'a str,
+    ) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::send_command_result(self, id, success, output))
+    }
+
+    fn ping(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::ping(self))
+    }
+
+    fn close(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(EvaMindClient::close(self))
+    }
+}
+
+/// What a server message means to the conversation loop
+fn response_event(message: ServerMessage) -> Option<BackendEvent> {
+    match message {
+        ServerMessage::Transcript { role, text, is_final } => Some(BackendEvent::Transcript {
+            role: match role {
+                Speaker::User => Role::User,
+                Speaker::Assistant => Role::Assistant,
+            },
+            text,
+            is_final,
+        }),
+        ServerMessage::CommandRequest { id, command } => Some(BackendEvent::CommandRequest { id, command }),
+        ServerMessage::ToolCall { id, name, args } => Some(BackendEvent::ToolCall { id, name, args }),
+        ServerMessage::EndOfTurn => Some(BackendEvent::TurnComplete),
+        ServerMessage::Interrupt => Some(BackendEvent::Interrupted),
+        ServerMessage::Error { message, .. } => Some(BackendEvent::Error(message)),
+        // Handshake messages repeated mid-session carry nothing new
+        ServerMessage::Registered { .. } | ServerMessage::SessionCreated { .. } => {
+            log_debug(&format!("⚠️ Ignorado durante a sessão: {:?}", message));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_events() {
+        let transcript = ServerMessage::Transcript {
+            role: Speaker::Assistant,
+            text: "Olá".to_string(),
+            is_final: true,
+        };
+        assert_eq!(
+            response_event(transcript),
+            Some(BackendEvent::Transcript { role: Role::Assistant, text: "Olá".to_string(), is_final: true })
+        );
+        assert_eq!(response_event(ServerMessage::EndOfTurn), Some(BackendEvent::TurnComplete));
+        assert_eq!(
+            response_event(ServerMessage::SessionCreated {
+                session_id: None,
+                protocol_version: Some(2),
+                audio_codec: None,
+            }),
+            None
+        );
+    }
 }
@@ -0,0 +1,351 @@
+//! Speech features
+//!
+//! Log-mel filterbank energies and MFCCs for keyword spotting, following
+//! the usual HTK recipe: 25ms frames every 10ms, pre-emphasis, Hamming
+//! window, triangular mel filters and a DCT-II over the log energies.
+
+use std::f32::consts::PI;
+
+use crate::fft::Fft;
+
+/// Floor applied before taking logs (avoids -inf on digital silence)
+const LOG_FLOOR: f32 = 1e-10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Window {
+    Hamming,
+    /// Periodic Hann (Whisper, librosa)
+    #[cfg_attr(not(feature = "onnx"), allow(dead_code))]
+    Hann,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MelScale {
+    /// `2595 log10(1 + f/700)`, peak-normalized filters
+    Htk,
+    /// Linear below 1kHz, log above, area-normalized filters (librosa default)
+    #[cfg_attr(not(feature = "onnx"), allow(dead_code))]
+    Slaney,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogMelConfig {
+    pub sample_rate: u32,
+    /// Samples per analysis frame (400 = 25ms at 16kHz)
+    pub frame_len: usize,
+    /// Samples between frame starts (160 = 10ms at 16kHz)
+    pub hop_len: usize,
+    /// FFT size, a power of two >= `frame_len`
+    pub fft_size: usize,
+    pub num_mels: usize,
+    pub min_hz: f32,
+    pub max_hz: f32,
+    /// Pre-emphasis coefficient (0 disables)
+    pub preemphasis: f32,
+    pub window: Window,
+    pub mel_scale: MelScale,
+}
+
+impl Default for LogMelConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            frame_len: 400,
+            hop_len: 160,
+            fft_size: 512,
+            num_mels: 40,
+            min_hz: 20.0,
+            max_hz: 7600.0,
+            preemphasis: 0.97,
+            window: Window::Hamming,
+            mel_scale: MelScale::Htk,
+        }
+    }
+}
+
+impl LogMelConfig {
+    /// Whisper's front-end: 80 Slaney mels over 0-8kHz, Hann window
+    #[cfg_attr(not(feature = "onnx"), allow(dead_code))]
+    pub fn whisper() -> Self {
+        Self {
+            num_mels: 80,
+            min_hz: 0.0,
+            max_hz: 8000.0,
+            preemphasis: 0.0,
+            window: Window::Hann,
+            mel_scale: MelScale::Slaney,
+            ..Self::default()
+        }
+    }
+}
+
+pub fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+pub fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+const SLANEY_LINEAR_HZ: f32 = 1000.0;
+const SLANEY_HZ_PER_MEL: f32 = 200.0 / 3.0;
+
+fn slaney_log_step() -> f32 {
+    6.4f32.ln() / 27.0
+}
+
+pub fn hz_to_mel_slaney(hz: f32) -> f32 {
+    if hz < SLANEY_LINEAR_HZ {
+        hz / SLANEY_HZ_PER_MEL
+    } else {
+        SLANEY_LINEAR_HZ / SLANEY_HZ_PER_MEL + (hz / SLANEY_LINEAR_HZ).ln() / slaney_log_step()
+    }
+}
+
+pub fn mel_to_hz_slaney(mel: f32) -> f32 {
+    let linear_mels = SLANEY_LINEAR_HZ / SLANEY_HZ_PER_MEL;
+    if mel < linear_mels {
+        mel * SLANEY_HZ_PER_MEL
+    } else {
+        SLANEY_LINEAR_HZ * (slaney_log_step() * (mel - linear_mels)).exp()
+    }
+}
+
+/// Hz → mel and mel → Hz conversions of one mel scale
+type MelConversion = (fn(f32) -> f32, fn(f32) -> f32);
+
+/// One triangular filter: first FFT bin it covers and the bin weights
+struct MelFilter {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Log-mel filterbank front-end
+pub struct LogMel {
+    config: LogMelConfig,
+    fft: Fft,
+    window: Vec<f32>,
+    filters: Vec<MelFilter>,
+}
+
+impl LogMel {
+    /// # Panics
+    /// If `fft_size` is not a power of two or is shorter than `frame_len`
+    pub fn new(config: LogMelConfig) -> Self {
+        assert!(config.fft_size >= config.frame_len, "FFT size shorter than frame");
+
+        let n = config.frame_len as f32;
+        let window = (0..config.frame_len)
+            .map(|i| match config.window {
+                Window::Hamming => 0.54 - 0.46 * (2.0 * PI * i as f32 / (n - 1.0)).cos(),
+                Window::Hann => 0.5 - 0.5 * (2.0 * PI * i as f32 / n).cos(),
+            })
+            .collect();
+        let filters = mel_filters(&config);
+
+        Self {
+            fft: Fft::new(config.fft_size),
+            config,
+            window,
+            filters,
+        }
+    }
+
+    /// Number of frames `compute` returns for `len` samples
+    pub fn num_frames(&self, len: usize) -> usize {
+        if len < self.config.frame_len {
+            0
+        } else {
+            1 + (len - self.config.frame_len) / self.config.hop_len
+        }
+    }
+
+    /// Log mel energies, one `num_mels` vector per frame
+    pub fn compute(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let n = self.config.fft_size;
+        let mut re = vec![0.0f32; n];
+        let mut im = vec![0.0f32; n];
+
+        (0..self.num_frames(samples.len()))
+            .map(|f| {
+                let start = f * self.config.hop_len;
+                let frame = &samples[start..start + self.config.frame_len];
+                let previous = if start > 0 { samples[start - 1] } else { frame[0] };
+
+                re.iter_mut().for_each(|v| *v = 0.0);
+                im.iter_mut().for_each(|v| *v = 0.0);
+                for (i, (&s, &w)) in frame.iter().zip(&self.window).enumerate() {
+                    let prev = if i > 0 { frame[i - 1] } else { previous };
+                    re[i] = (s - self.config.preemphasis * prev) * w;
+                }
+                self.fft.forward(&mut re, &mut im);
+
+                let power: Vec<f32> = (0..=n / 2).map(|k| re[k] * re[k] + im[k] * im[k]).collect();
+                self.filters
+                    .iter()
+                    .map(|filter| {
+                        let energy: f32 = filter
+                            .weights
+                            .iter()
+                            .zip(&power[filter.start..])
+                            .map(|(w, p)| w * p)
+                            .sum();
+                        energy.max(LOG_FLOOR).ln()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn mel_filters(config: &LogMelConfig) -> Vec<MelFilter> {
+    let bins = config.fft_size / 2 + 1;
+    let bin_hz = config.sample_rate as f32 / config.fft_size as f32;
+    let max_hz = config.max_hz.min(config.sample_rate as f32 / 2.0);
+    let (to_mel, to_hz): MelConversion = match config.mel_scale {
+        MelScale::Htk => (hz_to_mel, mel_to_hz),
+        MelScale::Slaney => (hz_to_mel_slaney, mel_to_hz_slaney),
+    };
+    let (low, high) = (to_mel(config.min_hz), to_mel(max_hz));
+
+    // num_mels + 2 edges: each filter rises from edge i to i+1, falls to i+2
+    let edges: Vec<f32> = (0..config.num_mels + 2)
+        .map(|i| to_hz(low + (high - low) * i as f32 / (config.num_mels + 1) as f32))
+        .collect();
+
+    edges
+        .windows(3)
+        .map(|e| {
+            let (left, center, right) = (e[0], e[1], e[2]);
+            let start = ((left / bin_hz).floor() as usize).min(bins - 1);
+            let end = ((right / bin_hz).ceil() as usize).min(bins - 1);
+            let weights = (start..=end)
+                .map(|k| {
+                    let hz = k as f32 * bin_hz;
+                    if hz <= left || hz >= right {
+                        0.0
+                    } else if hz <= center {
+                        (hz - left) / (center - left)
+                    } else {
+                        (right - hz) / (right - center)
+                    }
+                })
+                .map(|w| match config.mel_scale {
+                    MelScale::Htk => w,
+                    // Area-normalize; the zero-padded FFT has fft_size/frame_len
+                    // times more bins than an n_fft = frame_len analysis
+                    MelScale::Slaney => {
+                        w * 2.0 / (right - left) * config.frame_len as f32 / config.fft_size as f32
+                    }
+                })
+                .collect();
+            MelFilter { start, weights }
+        })
+        .collect()
+}
+
+/// Mel-frequency cepstral coefficients
+pub struct Mfcc {
+    log_mel: LogMel,
+    /// DCT-II basis, `num_coeffs` rows of `num_mels`
+    dct: Vec<Vec<f32>>,
+}
+
+impl Mfcc {
+    pub fn new(config: LogMelConfig, num_coeffs: usize) -> Self {
+        let m = config.num_mels;
+        let dct = (0..num_coeffs.min(m))
+            .map(|k| {
+                let scale = if k == 0 { (1.0 / m as f32).sqrt() } else { (2.0 / m as f32).sqrt() };
+                (0..m)
+                    .map(|n| scale * (PI * k as f32 * (n as f32 + 0.5) / m as f32).cos())
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            log_mel: LogMel::new(config),
+            dct,
+        }
+    }
+
+    /// MFCC vectors, one per frame; coefficient 0 is the log energy
+    pub fn compute(&self, samples: &[f32]) -> Vec<Vec<f32>> {
+        self.log_mel
+            .compute(samples)
+            .iter()
+            .map(|mels| {
+                self.dct
+                    .iter()
+                    .map(|row| row.iter().zip(mels).map(|(b, m)| b * m).sum())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl Default for Mfcc {
+    fn default() -> Self {
+        Self::new(LogMelConfig::default(), 13)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(hz: f32, len: usize) -> Vec<f32> {
+        (0..len).map(|i| (2.0 * PI * hz * i as f32 / 16000.0).sin() * 0.5).collect()
+    }
+
+    #[test]
+    fn test_mel_scale_roundtrip() {
+        for hz in [0.0, 300.0, 1000.0, 4000.0, 8000.0] {
+            assert!((mel_to_hz(hz_to_mel(hz)) - hz).abs() < 0.5);
+        }
+        assert!((hz_to_mel(1000.0) - 1000.0).abs() < 1.0);
+
+        for hz in [0.0, 500.0, 1000.0, 6000.0] {
+            assert!((mel_to_hz_slaney(hz_to_mel_slaney(hz)) - hz).abs() < 0.5);
+        }
+        assert!((hz_to_mel_slaney(1000.0) - 15.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_tone_lands_in_matching_band() {
+        let log_mel = LogMel::new(LogMelConfig::default());
+        for hz in [500.0, 2000.0] {
+            let frames = log_mel.compute(&tone(hz, 4000));
+            assert_eq!(frames.len(), log_mel.num_frames(4000));
+
+            let peak = frames[10]
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(i, _)| i)
+                .unwrap();
+
+            // Centre frequency of the loudest filter is close to the tone
+            let config = LogMelConfig::default();
+            let (low, high) = (hz_to_mel(config.min_hz), hz_to_mel(config.max_hz));
+            let center = mel_to_hz(low + (high - low) * (peak + 1) as f32 / (config.num_mels + 1) as f32);
+            assert!((center - hz).abs() / hz < 0.15, "{} Hz peaked at {} Hz", hz, center);
+        }
+    }
+
+    #[test]
+    fn test_mfcc_shape_and_silence() {
+        let mfcc = Mfcc::default();
+        assert!(mfcc.compute(&[0.0; 100]).is_empty());
+
+        let frames = mfcc.compute(&tone(1000.0, 16000));
+        assert_eq!(frames.len(), 98);
+        assert!(frames.iter().all(|f| f.len() == 13 && f.iter().all(|c| c.is_finite())));
+
+        // Louder input raises c0 (log energy) but leaves the shape alone
+        let quiet = mfcc.compute(&tone(1000.0, 1600).iter().map(|s| s * 0.1).collect::<Vec<_>>());
+        let loud = mfcc.compute(&tone(1000.0, 1600));
+        assert!(loud[3][0] > quiet[3][0]);
+        assert!((loud[3][4] - quiet[3][4]).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,124 @@
+//! Radix-2 FFT
+//!
+//! Small in-place complex FFT for the audio front-end (echo cancellation,
+//! noise suppression, spectral features). Sizes must be powers of two.
+
+use std::f32::consts::PI;
+
+/// Precomputed twiddles and bit-reversal table for one transform size
+pub struct Fft {
+    size: usize,
+    cos: Vec<f32>,
+    sin: Vec<f32>,
+    reversed: Vec<usize>,
+}
+
+impl Fft {
+    /// # Panics
+    /// If `size` is not a power of two
+    pub fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two(), "FFT size must be a power of two, got {}", size);
+
+        let bits = size.trailing_zeros();
+        let reversed = (0..size)
+            .map(|i| if bits == 0 { 0 } else { i.reverse_bits() >> (usize::BITS - bits) })
+            .collect();
+        let (cos, sin) = (0..size / 2)
+            .map(|k| {
+                let angle = -2.0 * PI * k as f32 / size as f32;
+                (angle.cos(), angle.sin())
+            })
+            .unzip();
+
+        Self { size, cos, sin, reversed }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Forward transform in place
+    pub fn forward(&self, re: &mut [f32], im: &mut [f32]) {
+        self.transform(re, im, false);
+    }
+
+    /// Inverse transform in place (scaled by 1/size)
+    pub fn inverse(&self, re: &mut [f32], im: &mut [f32]) {
+        self.transform(re, im, true);
+        let scale = 1.0 / self.size as f32;
+        re.iter_mut().for_each(|v| *v *= scale);
+        im.iter_mut().for_each(|v| *v *= scale);
+    }
+
+    fn transform(&self, re: &mut [f32], im: &mut [f32], inverse: bool) {
+        assert_eq!(re.len(), self.size);
+        assert_eq!(im.len(), self.size);
+
+        for i in 0..self.size {
+            let j = self.reversed[i];
+            if j > i {
+                re.swap(i, j);
+                im.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let half = len / 2;
+            let stride = self.size / len;
+            for start in (0..self.size).step_by(len) {
+                for k in 0..half {
+                    let wr = self.cos[k * stride];
+                    let wi = if inverse { -self.sin[k * stride] } else { self.sin[k * stride] };
+                    let (a, b) = (start + k, start + k + half);
+                    let tr = re[b] * wr - im[b] * wi;
+                    let ti = re[b] * wi + im[b] * wr;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_dft() {
+        let n = 16;
+        let fft = Fft::new(n);
+        let signal: Vec<f32> = (0..n).map(|i| ((i * 7) % 5) as f32 - 2.0).collect();
+
+        let mut re = signal.clone();
+        let mut im = vec![0.0; n];
+        fft.forward(&mut re, &mut im);
+
+        for k in 0..n {
+            let (mut er, mut ei) = (0.0f32, 0.0f32);
+            for (t, &x) in signal.iter().enumerate() {
+                let angle = -2.0 * PI * (k * t) as f32 / n as f32;
+                er += x * angle.cos();
+                ei += x * angle.sin();
+            }
+            assert!((re[k] - er).abs() < 1e-4 && (im[k] - ei).abs() < 1e-4, "bin {}", k);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let fft = Fft::new(512);
+        let signal: Vec<f32> = (0..512).map(|i| (i as f32 * 0.1).sin()).collect();
+        let mut re = signal.clone();
+        let mut im = vec![0.0; 512];
+        fft.forward(&mut re, &mut im);
+        fft.inverse(&mut re, &mut im);
+        for (a, b) in re.iter().zip(&signal) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+}
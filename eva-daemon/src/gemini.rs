@@ -1,7 +1,13 @@
-use crate::websocket::WebSocketClient;
+use crate::backend::{self, BackendEvent, VoiceBackend};
+use crate::credentials::{self, Secret};
+use crate::stt::Language;
+use crate::tools::ToolDeclaration;
+use crate::websocket::{ConnectOptions, WebSocketClient};
+use futures_util::future::LocalBoxFuture;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::collections::{HashMap, VecDeque};
 use std::fs::OpenOptions;
 use std::io::Write;
 
@@ -12,47 +18,102 @@ fn log_debug(msg: &str) {
         .open("eva_debug.log")
     {
         let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
-        writeln!(file, "[{}] {}", timestamp, msg).ok();
+        writeln!(file, "[{}] {}", timestamp, credentials::redact(msg)).ok();
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GeminiConfig {
-    pub api_key: String,
+    /// Sent in the `x-goog-api-key` header (see `credentials`)
+    pub api_key: Secret,
     pub model: String,
     pub ws_url: String,
+    /// Languages the user speaks (tags like "pt-BR"), main one first
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Functions the model may call (sent in the setup)
+    #[serde(default)]
+    pub tools: Vec<ToolDeclaration>,
+    /// Proxy, extra CAs, timeouts
+    #[serde(skip)]
+    pub network: ConnectOptions,
 }
 
 impl Default for GeminiConfig {
     fn default() -> Self {
         Self {
-            api_key: std::env::var("GOOGLE_API_KEY").unwrap_or_default(),
+            api_key: Secret::default(),
             model: "gemini-2.5-flash-native-audio-preview-12-2025".to_string(),
             ws_url: "wss://generativelanguage.googleapis.com/ws/google.ai.generativelanguage.v1beta.GenerativeService.BidiGenerateContent".to_string(),
+            languages: vec!["pt-BR".to_string()],
+            tools: Vec::new(),
+            network: ConnectOptions::default(),
         }
     }
 }
 
+impl GeminiConfig {
+    /// EVA's persona, answering in the language the user speaks
+    pub fn system_instruction(&self) -> String {
+        let names: Vec<&str> = self
+            .languages
+            .iter()
+            .filter_map(|tag| Language::from_tag(tag))
+            .map(|l| l.name())
+            .collect();
+
+        match names.as_slice() {
+            [] | ["Brazilian Portuguese"] => {
+                "Você é EVA, uma assistente de voz amigável. Responda em português brasileiro de forma natural e concisa.".to_string()
+            }
+            [only] => format!("You are EVA, a friendly voice assistant. Reply in {} naturally and concisely.", only),
+            [main, ..] => format!(
+                "You are EVA, a friendly voice assistant. The user speaks {}; always reply in the language they are speaking, naturally and concisely. If unsure, use {}.",
+                names.join(", "),
+                main
+            ),
+        }
+    }
+}
+
+/// Gemini Live answers with 24kHz PCM unless the mime type says otherwise
+const OUTPUT_SAMPLE_RATE: u32 = 24000;
+
 pub struct GeminiClient {
     ws: WebSocketClient,
     config: GeminiConfig,
     setup_complete: bool,
+    session_id: String,
+    /// Events from a response not yet handed out by `VoiceBackend::receive`
+    pending: VecDeque<BackendEvent>,
+    /// Function name of each tool call still waiting for its response
+    tool_calls: HashMap<String, String>,
 }
 
 impl GeminiClient {
     /// Connect to Gemini API via WebSocket
     pub async fn connect(config: GeminiConfig) -> Result<Self, Box<dyn std::error::Error>> {
         if config.api_key.is_empty() {
-            return Err("GOOGLE_API_KEY não configurada".into());
+            return Err("No Gemini API key: set GOOGLE_API_KEY or run eva-daemon --set-secret google_api_key".into());
         }
 
-        let url = format!("{}?key={}", config.ws_url, config.api_key);
-
         log_debug("🤖 Conectando ao Gemini...");
-        let ws = WebSocketClient::connect(&url).await?;
+        let ws = WebSocketClient::connect_with(
+            &config.ws_url,
+            &[("x-goog-api-key", config.api_key.expose())],
+            &config.network,
+        )
+        .await?;
         log_debug("✅ WebSocket conectado");
 
-        let mut client = Self { ws, config, setup_complete: false };
+        let mut client = Self {
+            ws,
+            config,
+            setup_complete: false,
+            session_id: format!("gemini-{}", chrono::Local::now().timestamp_millis()),
+            pending: VecDeque::new(),
+            tool_calls: HashMap::new(),
+        };
 
         // Send setup
         client.send_setup().await?;
@@ -67,7 +128,7 @@ impl GeminiClient {
     async fn send_setup(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         let model_full = format!("models/{}", self.config.model);
 
-        let setup = json!({
+        let mut setup = json!({
             "setup": {
                 "model": model_full,
                 "generation_config": {
@@ -83,12 +144,16 @@ impl GeminiClient {
                 },
                 "system_instruction": {
                     "parts": [{
-                        "text": "Você é EVA, uma assistente de voz amigável. Responda em português brasileiro de forma natural e concisa."
+                        "text": self.config.system_instruction()
                     }]
                 }
             }
         });
 
+        if !self.config.tools.is_empty() {
+            setup["setup"]["tools"] = json!([{ "function_declarations": self.config.tools }]);
+        }
+
         log_debug(&format!("📤 Setup: {}", setup.to_string()));
         self.ws.send_text(&setup.to_string()).await?;
         log_debug("✅ Setup enviado");
@@ -260,6 +325,16 @@ impl GeminiClient {
                         log_debug(&format!("⚠️ Parse error: {}", e));
                     }
                 }
+            } else if json.get("toolCall").is_some() {
+                match serde_json::from_str::<GeminiResponse>(&text) {
+                    Ok(response) => {
+                        log_debug("🛠️ Tool call");
+                        return Ok(Some(response));
+                    }
+                    Err(e) => {
+                        log_debug(&format!("⚠️ Parse error: {}", e));
+                    }
+                }
             } else {
                 log_debug("📥 (non-content msg)");
             }
@@ -304,16 +379,182 @@ impl GeminiClient {
         Ok(None)
     }
 
+    /// Tell Gemini the microphone stream paused, so it answers now
+    pub async fn end_audio_stream(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let message = json!({
+            "realtime_input": {
+                "audio_stream_end": true
+            }
+        });
+
+        self.ws.send_text(&message.to_string()).await?;
+        log_debug("✅ Fim do áudio enviado");
+        Ok(())
+    }
+
+    /// Answer a tool call; failures go back as `error` so the model can say so
+    pub async fn send_tool_response(
+        &mut self,
+        id: &str,
+        name: &str,
+        success: bool,
+        output: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = if success { json!({ "output": output }) } else { json!({ "error": output }) };
+        let msg = json!({
+            "tool_response": {
+                "function_responses": [{ "id": id, "name": name, "response": response }]
+            }
+        });
+
+        self.ws.send_text(&msg.to_string()).await?;
+        log_debug(&format!("✅ Tool response enviado: {}", name));
+
+        Ok(())
+    }
+
     /// Keep connection alive
     pub async fn ping(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.ws.ping().await
     }
+
+    /// Close the WebSocket
+    pub async fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.setup_complete = false;
+        self.ws.close().await
+    }
+}
+
+/// Audio and text parts of a response, then `TurnComplete` if it ends the turn;
+/// a tool call becomes one `ToolCall` per function
+pub fn response_events(response: &GeminiResponse) -> Vec<BackendEvent> {
+    let mut events: Vec<BackendEvent> = response
+        .tool_call
+        .iter()
+        .flat_map(|call| &call.function_calls)
+        .map(|call| BackendEvent::ToolCall {
+            id: call.id.clone(),
+            name: call.name.clone(),
+            args: call.args.clone(),
+        })
+        .collect();
+    let Some(ref content) = response.server_content else {
+        return events;
+    };
+
+    for part in content.model_turn.iter().flat_map(|turn| &turn.parts) {
+        if let Some(ref text) = part.text {
+            events.push(BackendEvent::Text(text.clone()));
+        }
+        if let Some(ref data) = part.inline_data {
+            match BASE64.decode(&data.data) {
+                Ok(pcm) => events.push(BackendEvent::Audio {
+                    pcm,
+                    sample_rate: backend::pcm_rate(&data.mime_type).unwrap_or(OUTPUT_SAMPLE_RATE),
+                }),
+                Err(e) => log_debug(&format!("⚠️ Áudio inválido: {}", e)),
+            }
+        }
+    }
+    if content.turn_complete.unwrap_or(false) {
+        events.push(BackendEvent::TurnComplete);
+    }
+    events
+}
+
+impl VoiceBackend for GeminiClient {
+    fn name(&self) -> &'static str {
+        "Gemini"
+    }
+
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    fn reports_turn_end(&self) -> bool {
+        true
+    }
+
+    /// The session was set up by `connect`
+    fn start_session(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            if !self.setup_complete {
+                return Err("Gemini setup not complete".into());
+            }
+            Ok(())
+        })
+    }
+
+    fn send_audio<'a>(&'a mut self, pcm: &'a [u8]) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(GeminiClient::send_audio(self, pcm))
+    }
+
+    fn send_text<'a>(&'a mut self, text: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(GeminiClient::send_text(self, text))
+    }
+
+    fn receive(&mut self) -> LocalBoxFuture<'_, Result<Option<BackendEvent>, Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            if self.pending.is_empty() {
+                if let Some(response) = self.try_receive().await? {
+                    for event in response_events(&response) {
+                        if let BackendEvent::ToolCall { ref id, ref name, .. } = event {
+                            self.tool_calls.insert(id.clone(), name.clone());
+                        }
+                        self.pending.push_back(event);
+                    }
+                }
+            }
+            Ok(self.pending.pop_front())
+        })
+    }
+
+    fn end_turn(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(self.end_audio_stream())
+    }
+
+    /// Gemini stops answering by itself once the user speaks again; the
+    /// rest of the answer already in flight is read and dropped
+    fn interrupt(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            self.pending.clear();
+            while self.try_receive().await?.is_some() {}
+            log_debug("✅ Resposta interrompida");
+            Ok(())
+        })
+    }
+
+    /// Answers tool calls (Gemini never sends command requests)
+    fn send_command_result<'a>(
+        &'a mut self,
+        id: &'a str,
+        success: bool,
+        output: &'a str,
+    ) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let name = self
+                .tool_calls
+                .remove(id)
+                .ok_or_else(|| format!("No Gemini tool call with id {}", id))?;
+            self.send_tool_response(id, &name, success, output).await
+        })
+    }
+
+    fn ping(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(GeminiClient::ping(self))
+    }
+
+    fn close(&mut self) -> LocalBoxFuture<'_, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(GeminiClient::close(self))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GeminiResponse {
     #[serde(rename = "serverContent")]
     pub server_content: Option<ServerContent>,
+    #[serde(rename = "toolCall")]
+    pub tool_call: Option<ToolCall>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -336,9 +577,72 @@ pub struct Part {
     pub inline_data: Option<InlineData>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ToolCall {
+    #[serde(rename = "functionCalls", default)]
+    pub function_calls: Vec<FunctionCall>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionCall {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub args: Value,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct InlineData {
     #[serde(rename = "mimeType")]
     pub mime_type: String,
     pub data: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_events() {
+        let audio = BASE64.encode([0x00, 0x00, 0xFF, 0x7F]);
+        let text = format!(
+            r#"{{"serverContent": {{"modelTurn": {{"parts": [
+                {{"text": "Olá"}},
+                {{"inlineData": {{"mimeType": "audio/pcm;rate=24000", "data": "{}"}}}}
+            ]}}, "turnComplete": true}}}}"#,
+            audio
+        );
+        let response: GeminiResponse = serde_json::from_str(&text).unwrap();
+
+        assert_eq!(
+            response_events(&response),
+            vec![
+                BackendEvent::Text("Olá".to_string()),
+                BackendEvent::Audio { pcm: vec![0x00, 0x00, 0xFF, 0x7F], sample_rate: 24000 },
+                BackendEvent::TurnComplete,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_call_events() {
+        let text = r#"{"toolCall": {"functionCalls": [
+            {"id": "call-1", "name": "list_files", "args": {"path": "documents"}},
+            {"id": "call-2", "name": "uptime"}
+        ]}}"#;
+        let response: GeminiResponse = serde_json::from_str(text).unwrap();
+
+        assert_eq!(
+            response_events(&response),
+            vec![
+                BackendEvent::ToolCall {
+                    id: "call-1".to_string(),
+                    name: "list_files".to_string(),
+                    args: json!({"path": "documents"}),
+                },
+                BackendEvent::ToolCall { id: "call-2".to_string(), name: "uptime".to_string(), args: Value::Null },
+            ]
+        );
+    }
+}
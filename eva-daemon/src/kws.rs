@@ -0,0 +1,531 @@
+//! Personal wake word by template matching
+//!
+//! The user records their wake phrase a few times (`eva-daemon
+//! --enroll-wake-word "<phrase>"`). Each recording is trimmed to the spoken
+//! part and stored as an MFCC sequence. At runtime the detector slides the
+//! templates over the last two seconds of audio with subsequence DTW and
+//! scores the best alignment against the spread seen during enrollment.
+//!
+//! Templates live in `~/.eva/wake_words/<phrase>.json`.
+//! `DetectionMetrics` measures false accepts / false rejects on a folder of
+//! WAV recordings (`positive/*.wav` and `negative/*.wav`).
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::features::Mfcc;
+use crate::wake_word::WakeWordDetector;
+
+const SAMPLE_RATE: usize = 16000;
+/// Recordings needed before templates are built
+pub const MIN_ENROLLMENT_UTTERANCES: usize = 3;
+const MIN_UTTERANCE_MS: usize = 250;
+/// The detector only keeps two seconds of audio
+const MAX_UTTERANCE_MS: usize = 2000;
+/// Quietest level (RMS) that counts as speech while trimming
+const MIN_SPEECH_RMS: f32 = 0.005;
+/// Silence kept around the trimmed phrase
+const TRIM_PADDING_MS: usize = 30;
+/// Distance, relative to the spread between enrollment recordings, that
+/// scores 0.5
+const HALF_SCORE_DISTANCE: f32 = 2.0;
+
+/// Per-frame features used for matching: MFCCs without c0, so the match
+/// does not depend on how loud the user speaks
+pub fn keyword_features(mfcc: &Mfcc, samples: &[f32]) -> Vec<Vec<f32>> {
+    mfcc.compute(samples)
+        .into_iter()
+        .map(|mut frame| {
+            frame.remove(0);
+            frame
+        })
+        .collect()
+}
+
+fn frame_distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+}
+
+/// DTW distance between two whole sequences, normalized by `a.len() + b.len()`
+pub fn dtw_distance(a: &[Vec<f32>], b: &[Vec<f32>]) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return f32::INFINITY;
+    }
+
+    let m = b.len();
+    let mut prev = vec![f32::INFINITY; m + 1];
+    let mut cur = vec![f32::INFINITY; m + 1];
+    prev[0] = 0.0;
+
+    for frame in a {
+        cur[0] = f32::INFINITY;
+        for j in 1..=m {
+            let best = prev[j].min(cur[j - 1]).min(prev[j - 1]);
+            cur[j] = frame_distance(frame, &b[j - 1]) + best;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+        prev[0] = f32::INFINITY;
+    }
+
+    prev[m] / (a.len() + m) as f32
+}
+
+/// Best DTW match of `template` anywhere inside `stream`
+///
+/// The alignment may start and end at any stream frame; each candidate is
+/// normalized by its own path span, so the result is comparable with
+/// `dtw_distance` between two templates.
+pub fn subsequence_dtw(template: &[Vec<f32>], stream: &[Vec<f32>]) -> f32 {
+    if template.is_empty() || stream.is_empty() {
+        return f32::INFINITY;
+    }
+
+    let m = stream.len();
+    // Accumulated cost and the stream frame where each path started
+    let mut prev: Vec<(f32, usize)> = vec![(f32::INFINITY, 0); m];
+    let mut cur = prev.clone();
+
+    for (i, frame) in template.iter().enumerate() {
+        for j in 0..m {
+            let cost = frame_distance(frame, &stream[j]);
+            cur[j] = if i == 0 {
+                // Free start: the keyword may begin at any frame
+                (cost, j)
+            } else {
+                let mut best = prev[j];
+                if j > 0 {
+                    for candidate in [cur[j - 1], prev[j - 1]] {
+                        if candidate.0 < best.0 {
+                            best = candidate;
+                        }
+                    }
+                }
+                (best.0 + cost, best.1)
+            };
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev.iter()
+        .enumerate()
+        .map(|(end, &(cost, start))| cost / (template.len() + end - start + 1) as f32)
+        .fold(f32::INFINITY, f32::min)
+}
+
+/// Enrolled recordings of one wake phrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordTemplates {
+    pub phrase: String,
+    /// One MFCC sequence (c1..c12 per frame) per recording
+    pub templates: Vec<Vec<Vec<f32>>>,
+    /// Mean DTW distance between the enrolled recordings
+    pub reference_distance: f32,
+}
+
+impl KeywordTemplates {
+    /// Load the templates enrolled for `phrase`
+    pub fn load(phrase: &str) -> Result<Self, Box<dyn Error>> {
+        let path = Self::path_for(phrase)?;
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("No wake word enrolled for '{}' ({}): {}", phrase, path.display(), e))?;
+        let templates: Self = serde_json::from_str(&content)?;
+        if templates.templates.is_empty() {
+            return Err(format!("{} has no templates", path.display()).into());
+        }
+        Ok(templates)
+    }
+
+    pub fn save(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let path = Self::path_for(&self.phrase)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string(self)?)?;
+        Ok(path)
+    }
+
+    /// `~/.eva/wake_words/<phrase>.json`, with the phrase reduced to a file name
+    pub fn path_for(phrase: &str) -> Result<PathBuf, Box<dyn Error>> {
+        #[cfg(target_os = "windows")]
+        let home = std::env::var("USERPROFILE")?;
+        #[cfg(not(target_os = "windows"))]
+        let home = std::env::var("HOME")?;
+
+        Ok(PathBuf::from(home)
+            .join(".eva")
+            .join("wake_words")
+            .join(format!("{}.json", slug(phrase))))
+    }
+
+    /// Lowest distance between any template and the audio features
+    pub fn best_distance(&self, features: &[Vec<f32>]) -> f32 {
+        self.templates
+            .iter()
+            .map(|t| subsequence_dtw(t, features))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// Match confidence in 0..1
+    ///
+    /// Scores 0.5 at `HALF_SCORE_DISTANCE` times the enrollment spread; a
+    /// repeat of the phrase typically lands around 0.8.
+    pub fn score(&self, features: &[Vec<f32>]) -> f32 {
+        let reference = self.reference_distance.max(f32::EPSILON) * HALF_SCORE_DISTANCE;
+        let ratio = self.best_distance(features) / reference;
+        if ratio.is_finite() {
+            1.0 / (1.0 + ratio * ratio)
+        } else {
+            0.0
+        }
+    }
+}
+
+fn slug(phrase: &str) -> String {
+    let slug: String = phrase
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    if slug.is_empty() {
+        "wake_word".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Trim leading and trailing silence from a recording
+pub fn trim_silence(samples: &[f32]) -> &[f32] {
+    let frame = SAMPLE_RATE / 100;
+    let levels: Vec<f32> = samples
+        .chunks(frame)
+        .map(|c| (c.iter().map(|s| s * s).sum::<f32>() / c.len() as f32).sqrt())
+        .collect();
+    let peak = levels.iter().cloned().fold(0.0f32, f32::max);
+    let threshold = (peak * 0.1).max(MIN_SPEECH_RMS);
+
+    let (Some(first), Some(last)) = (
+        levels.iter().position(|&l| l > threshold),
+        levels.iter().rposition(|&l| l > threshold),
+    ) else {
+        return &[];
+    };
+
+    let padding = TRIM_PADDING_MS * SAMPLE_RATE / 1000;
+    let start = (first * frame).saturating_sub(padding);
+    let end = ((last + 1) * frame + padding).min(samples.len());
+    &samples[start..end]
+}
+
+/// Collects recordings of the wake phrase and builds its templates
+pub struct Enrollment {
+    phrase: String,
+    mfcc: Mfcc,
+    utterances: Vec<Vec<Vec<f32>>>,
+}
+
+impl Enrollment {
+    pub fn new(phrase: &str) -> Self {
+        Self {
+            phrase: phrase.trim().to_string(),
+            mfcc: Mfcc::default(),
+            utterances: Vec::new(),
+        }
+    }
+
+    pub fn phrase(&self) -> &str {
+        &self.phrase
+    }
+
+    /// Recordings accepted so far
+    pub fn count(&self) -> usize {
+        self.utterances.len()
+    }
+
+    /// Add one 16kHz recording of the phrase
+    ///
+    /// Rejects recordings that are silent, too short or too long, so the
+    /// caller can ask the user to try again. Returns the new count.
+    pub fn add_utterance(&mut self, samples: &[f32]) -> Result<usize, Box<dyn Error>> {
+        let speech = trim_silence(samples);
+        if speech.is_empty() {
+            return Err("No speech heard".into());
+        }
+
+        let ms = speech.len() * 1000 / SAMPLE_RATE;
+        if ms < MIN_UTTERANCE_MS {
+            return Err(format!("Too short ({}ms)", ms).into());
+        }
+        if ms > MAX_UTTERANCE_MS {
+            return Err(format!("Too long ({}ms, max {}ms)", ms, MAX_UTTERANCE_MS).into());
+        }
+
+        self.utterances.push(keyword_features(&self.mfcc, speech));
+        Ok(self.utterances.len())
+    }
+
+    /// Build the templates; needs `MIN_ENROLLMENT_UTTERANCES` recordings
+    pub fn finish(self) -> Result<KeywordTemplates, Box<dyn Error>> {
+        if self.utterances.len() < MIN_ENROLLMENT_UTTERANCES {
+            return Err(format!(
+                "Need {} recordings, got {}",
+                MIN_ENROLLMENT_UTTERANCES,
+                self.utterances.len()
+            )
+            .into());
+        }
+
+        let mut distances = Vec::new();
+        for (i, a) in self.utterances.iter().enumerate() {
+            for b in &self.utterances[i + 1..] {
+                distances.push(dtw_distance(a, b));
+            }
+        }
+        let reference_distance = distances.iter().sum::<f32>() / distances.len() as f32;
+
+        Ok(KeywordTemplates {
+            phrase: self.phrase,
+            templates: self.utterances,
+            reference_distance,
+        })
+    }
+}
+
+/// False accept / false reject counts over labelled recordings
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DetectionMetrics {
+    pub positives: usize,
+    pub negatives: usize,
+    pub false_rejects: usize,
+    pub false_accepts: usize,
+    /// Total length of the negative recordings
+    pub negative_seconds: f32,
+}
+
+impl DetectionMetrics {
+    /// Run every clip through a fresh copy of the detector state
+    pub fn evaluate(detector: &mut WakeWordDetector, positives: &[Vec<f32>], negatives: &[Vec<f32>]) -> Self {
+        let mut metrics = Self::default();
+        for clip in positives {
+            metrics.positives += 1;
+            if !clip_triggers(detector, clip) {
+                metrics.false_rejects += 1;
+            }
+        }
+        for clip in negatives {
+            metrics.negatives += 1;
+            metrics.negative_seconds += clip.len() as f32 / SAMPLE_RATE as f32;
+            if clip_triggers(detector, clip) {
+                metrics.false_accepts += 1;
+            }
+        }
+        metrics
+    }
+
+    /// Evaluate `dir/positive/*.wav` (the wake phrase) and `dir/negative/*.wav`
+    pub fn evaluate_dir(detector: &mut WakeWordDetector, dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let positives = load_wav_dir(&dir.join("positive"))?;
+        let negatives = load_wav_dir(&dir.join("negative"))?;
+        if positives.is_empty() && negatives.is_empty() {
+            return Err(format!("No WAV files under {}/positive or {}/negative", dir.display(), dir.display()).into());
+        }
+        Ok(Self::evaluate(detector, &positives, &negatives))
+    }
+
+    pub fn false_reject_rate(&self) -> f32 {
+        if self.positives == 0 {
+            0.0
+        } else {
+            self.false_rejects as f32 / self.positives as f32
+        }
+    }
+
+    pub fn false_accept_rate(&self) -> f32 {
+        if self.negatives == 0 {
+            0.0
+        } else {
+            self.false_accepts as f32 / self.negatives as f32
+        }
+    }
+
+    pub fn false_accepts_per_hour(&self) -> f32 {
+        if self.negative_seconds <= 0.0 {
+            0.0
+        } else {
+            self.false_accepts as f32 * 3600.0 / self.negative_seconds
+        }
+    }
+}
+
+/// Feed a recording (plus trailing silence) in capture-sized chunks
+fn clip_triggers(detector: &mut WakeWordDetector, clip: &[f32]) -> bool {
+    detector.reset();
+    let tail = vec![0.0; SAMPLE_RATE / 2];
+    let mut detected = false;
+    for chunk in clip.chunks(SAMPLE_RATE / 10).chain(tail.chunks(SAMPLE_RATE / 10)) {
+        detected |= detector.detect(chunk);
+    }
+    detector.reset();
+    detected
+}
+
+fn load_wav_dir(dir: &Path) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")))
+        .collect();
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let (samples, rate) = crate::tts::decode_wav(&fs::read(path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            Ok(crate::resample::resample(&samples, rate, SAMPLE_RATE as u32))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wake_word::WakeWordConfig;
+    use std::f32::consts::PI;
+
+    /// Vowel-like sound: 120Hz voice shaped by two formants
+    fn vowel(f1: f32, f2: f32, ms: usize, pitch: f32) -> Vec<f32> {
+        let len = ms * SAMPLE_RATE / 1000;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let envelope = (PI * i as f32 / len as f32).sin();
+                let mut s = 0.0;
+                let mut h = 1;
+                while pitch * (h as f32) < 4000.0 {
+                    let f = pitch * h as f32;
+                    let gain = 1.0 / (1.0 + ((f - f1) / 120.0).powi(2)) + 0.6 / (1.0 + ((f - f2) / 180.0).powi(2));
+                    s += gain * (2.0 * PI * f * t).sin();
+                    h += 1;
+                }
+                s * envelope * 0.15
+            })
+            .collect()
+    }
+
+    /// A spoken phrase: vowels (F1, F2) with short gaps, stretched by `speed`
+    fn phrase(vowels: &[(f32, f32)], speed: f32, pitch: f32, seed: u32) -> Vec<f32> {
+        let mut samples = vec![0.0; 4000];
+        for &(f1, f2) in vowels {
+            samples.extend(vowel(f1, f2, (180.0 * speed) as usize, pitch));
+            samples.extend(vec![0.0; (40.0 * speed) as usize * 16]);
+        }
+        samples.extend(vec![0.0; 4000]);
+
+        // A little background noise
+        let mut state = seed.wrapping_mul(2654435761).max(1);
+        for s in &mut samples {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *s += (state as f32 / u32::MAX as f32 - 0.5) * 0.004;
+        }
+        samples
+    }
+
+    const HEY_EVA: [(f32, f32); 3] = [(550.0, 1900.0), (300.0, 2300.0), (750.0, 1200.0)];
+    const OTHER: [(f32, f32); 3] = [(450.0, 800.0), (300.0, 900.0), (500.0, 1500.0)];
+
+    fn enroll(vowels: &[(f32, f32)]) -> KeywordTemplates {
+        let mut enrollment = Enrollment::new("Hey EVA");
+        for (i, (speed, pitch)) in [(1.0, 120.0), (0.9, 125.0), (1.1, 115.0)].into_iter().enumerate() {
+            enrollment.add_utterance(&phrase(vowels, speed, pitch, i as u32 + 1)).unwrap();
+        }
+        enrollment.finish().unwrap()
+    }
+
+    #[test]
+    fn test_dtw_identity_and_warping() {
+        let a: Vec<Vec<f32>> = (0..20).map(|i| vec![(i as f32 * 0.3).sin(), i as f32 * 0.1]).collect();
+        assert!(dtw_distance(&a, &a) < 1e-6);
+
+        // The same trajectory played slower still aligns closely
+        let slow: Vec<Vec<f32>> = (0..30).map(|i| a[i * 2 / 3].clone()).collect();
+        let other: Vec<Vec<f32>> = (0..20).map(|i| vec![(i as f32 * 0.3).cos(), 1.0]).collect();
+        assert!(dtw_distance(&a, &slow) < 0.1);
+        assert!(dtw_distance(&a, &other) > dtw_distance(&a, &slow) * 5.0);
+    }
+
+    #[test]
+    fn test_subsequence_finds_embedded_template() {
+        let template: Vec<Vec<f32>> = (0..10).map(|i| vec![i as f32, 0.0]).collect();
+        let mut stream: Vec<Vec<f32>> = (0..30).map(|_| vec![-5.0, 3.0]).collect();
+        stream.splice(12..12, template.iter().cloned());
+
+        assert!(subsequence_dtw(&template, &stream) < 1e-6);
+        assert!(subsequence_dtw(&template, &stream[..12]) > 1.0);
+    }
+
+    #[test]
+    fn test_trim_silence() {
+        let mut samples = vec![0.0; 8000];
+        samples.extend(vowel(500.0, 1500.0, 300, 120.0));
+        samples.extend(vec![0.0; 8000]);
+
+        let trimmed = trim_silence(&samples);
+        assert!(trimmed.len() < 7000 && trimmed.len() > 4000, "{}", trimmed.len());
+        assert!(trim_silence(&[0.0; 16000]).is_empty());
+    }
+
+    #[test]
+    fn test_enrollment_validation() {
+        let mut enrollment = Enrollment::new("  Hey EVA ");
+        assert_eq!(enrollment.phrase(), "Hey EVA");
+        assert!(enrollment.add_utterance(&[0.0; 16000]).is_err());
+        assert!(enrollment.add_utterance(&vowel(500.0, 1500.0, 100, 120.0)).is_err());
+        assert!(enrollment.add_utterance(&vowel(500.0, 1500.0, 3000, 120.0)).is_err());
+        assert_eq!(enrollment.add_utterance(&phrase(&HEY_EVA, 1.0, 120.0, 1)).unwrap(), 1);
+        assert!(enrollment.finish().is_err());
+    }
+
+    #[test]
+    fn test_templates_separate_phrases() {
+        let templates = enroll(&HEY_EVA);
+        assert_eq!(templates.templates.len(), 3);
+        assert!(templates.reference_distance > 0.0);
+
+        let mfcc = Mfcc::default();
+        let same = keyword_features(&mfcc, &phrase(&HEY_EVA, 0.95, 130.0, 7));
+        let other = keyword_features(&mfcc, &phrase(&OTHER, 1.0, 120.0, 8));
+        assert!(templates.score(&same) > 0.7, "{}", templates.score(&same));
+        assert!(templates.score(&other) < 0.2, "{}", templates.score(&other));
+    }
+
+    #[test]
+    fn test_metrics_from_wav_fixtures() {
+        let dir = std::env::temp_dir().join(format!("eva_kws_{}", std::process::id()));
+        for (label, vowels) in [("positive", HEY_EVA), ("negative", OTHER)] {
+            fs::create_dir_all(dir.join(label)).unwrap();
+            for i in 0..3 {
+                let clip = phrase(&vowels, 0.9 + 0.1 * i as f32, 118.0 + 4.0 * i as f32, 20 + i);
+                let wav = crate::tts::encode_wav(&clip, 16000);
+                fs::write(dir.join(label).join(format!("{}.wav", i)), wav).unwrap();
+            }
+        }
+
+        let mut detector = WakeWordDetector::with_config(WakeWordConfig::default());
+        detector.set_templates(enroll(&HEY_EVA));
+        let metrics = DetectionMetrics::evaluate_dir(&mut detector, &dir).unwrap();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!((metrics.positives, metrics.negatives), (3, 3));
+        assert_eq!(metrics.false_reject_rate(), 0.0, "{:?}", metrics);
+        assert_eq!(metrics.false_accept_rate(), 0.0, "{:?}", metrics);
+        assert!(metrics.negative_seconds > 1.0);
+    }
+}
@@ -0,0 +1,111 @@
+//! Spoken-language identification policy
+//!
+//! Which languages a user may speak and when a detection is trusted enough
+//! to switch. The detection itself is done by the speech recognizer
+//! (`SpeechRecognizer::detect_language`) on the first seconds of each
+//! utterance; `StreamingSttSession` applies the result.
+
+use crate::stt::{Language, LanguageGuess};
+use crate::user_profile::UserProfile;
+
+/// Audio used to identify the language (2s at 16kHz)
+const PROBE_SAMPLES: usize = 2 * 16000;
+
+/// Weaker detections keep the current language
+const MIN_PROBABILITY: f32 = 0.6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageId {
+    /// Candidate languages, the profile's main language first
+    allowed: Vec<Language>,
+    probe_samples: usize,
+    min_probability: f32,
+}
+
+impl LanguageId {
+    /// # Panics
+    /// If `allowed` is empty
+    pub fn new(allowed: Vec<Language>) -> Self {
+        assert!(!allowed.is_empty(), "no allowed languages");
+        Self {
+            allowed,
+            probe_samples: PROBE_SAMPLES,
+            min_probability: MIN_PROBABILITY,
+        }
+    }
+
+    /// Identification over the profile's `spoken_languages`
+    ///
+    /// `None` when the user speaks a single language (nothing to detect).
+    pub fn from_profile(profile: &UserProfile) -> Option<Self> {
+        let allowed = spoken_languages(profile);
+        (allowed.len() > 1).then(|| Self::new(allowed))
+    }
+
+    pub fn allowed(&self) -> &[Language] {
+        &self.allowed
+    }
+
+    pub fn probe_samples(&self) -> usize {
+        self.probe_samples
+    }
+
+    /// Language to transcribe with, given the detector's guess
+    ///
+    /// Switches only to an allowed language detected with enough confidence;
+    /// otherwise stays on `current`, so a mumbled utterance doesn't flip it.
+    pub fn choose(&self, current: Language, guess: Option<LanguageGuess>) -> Language {
+        match guess {
+            Some(guess) if self.allowed.contains(&guess.language) && guess.probability >= self.min_probability => {
+                guess.language
+            }
+            _ => current,
+        }
+    }
+}
+
+/// The profile's `language` followed by its `allowed_languages`
+///
+/// Unknown tags and duplicates are skipped.
+pub fn spoken_languages(profile: &UserProfile) -> Vec<Language> {
+    let main = Language::from_tag(&profile.language).unwrap_or(Language::EnglishUS);
+    let mut languages = vec![main];
+    for language in profile.allowed_languages.iter().filter_map(|tag| Language::from_tag(tag)) {
+        if !languages.contains(&language) {
+            languages.push(language);
+        }
+    }
+    languages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guess(language: Language, probability: f32) -> Option<LanguageGuess> {
+        Some(LanguageGuess { language, probability })
+    }
+
+    #[test]
+    fn test_from_profile() {
+        let mut profile = UserProfile::default();
+        profile.language = "pt-BR".to_string();
+        assert_eq!(LanguageId::from_profile(&profile), None);
+        assert_eq!(spoken_languages(&profile), vec![Language::PortugueseBR]);
+
+        profile.allowed_languages = vec!["en".to_string(), "pt".to_string(), "klingon".to_string()];
+        let language_id = LanguageId::from_profile(&profile).unwrap();
+        assert_eq!(language_id.allowed(), &[Language::PortugueseBR, Language::EnglishUS]);
+    }
+
+    #[test]
+    fn test_choose_needs_confident_allowed_language() {
+        let language_id = LanguageId::new(vec![Language::PortugueseBR, Language::EnglishUS]);
+        let current = Language::PortugueseBR;
+
+        assert_eq!(language_id.choose(current, guess(Language::EnglishUS, 0.9)), Language::EnglishUS);
+        assert_eq!(language_id.choose(current, guess(Language::EnglishUS, 0.55)), current);
+        assert_eq!(language_id.choose(current, guess(Language::Spanish, 0.99)), current);
+        assert_eq!(language_id.choose(Language::EnglishUS, None), Language::EnglishUS);
+    }
+}
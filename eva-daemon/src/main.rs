@@ -1,11 +1,21 @@
 mod tls;
+mod proxy;
 mod websocket;
 mod gemini;
 mod eva_mind;
+mod protocol;
+mod codec;
+#[cfg(test)]
+mod mock_eva_mind;
+mod backend;
+mod connection;
+mod credentials;
 mod audio;
+mod audio_io;
 mod wake_word;
 mod vad;
 mod audio_player;
+mod playback;
 mod session;
 mod command_parser;
 mod command_executor;
@@ -20,29 +30,102 @@ mod terminal_ui;
 mod timemachine;
 mod logging;
 mod stt;
-
-use audio::AudioDevice;
+mod language_id;
+mod whisper;
+mod tts;
+mod resample;
+mod barge_in;
+mod fft;
+mod audio_processing;
+mod features;
+mod kws;
+mod offline_assistant;
+mod tools;
+mod state_machine;
+mod daemon;
+
+use audio::{AudioConfig, AudioDevice};
+use audio_io::{AudioSource, TurnRecorder, WavSink, WavSource};
 use wake_word::WakeWordDetector;
-use vad::VAD;
-use eva_mind::{EvaMindClient, EvaMindConfig, EvaMindResponse};
+use barge_in::BargeInDetector;
+use kws::{DetectionMetrics, Enrollment, KeywordTemplates};
+use vad::{VadEvent, VoiceActivity};
+use backend::BackendKind;
+use connection::ConnectionSupervisor;
+use credentials::{CredentialStore, Credentials, Identity};
+use websocket::ConnectOptions;
 use audio_player::AudioPlayer;
-use session::{ConversationSession, Role};
+use session::ConversationSession;
 use command_parser::CommandParser;
 use command_executor::CommandExecutor;
 use user_profile::UserProfile;
+use tts::VoiceSettings;
+use stt::StreamingSttSession;
 use custom_commands::CustomCommandManager;
 use macros::MacroManager;
 use emotion::EmotionDetector;
 use status_indicator::{StatusIndicator, EvaStatus};
 use statistics::Statistics;
 use terminal_ui::TerminalUI;
-use animations::Animation;
+use daemon::{Console, Daemon, Detector};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // One-shot tools that run instead of the assistant
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--enroll-wake-word") => {
+            let mut profile = UserProfile::load()?;
+            let phrase = args
+                .get(1)
+                .cloned()
+                .or_else(|| profile.custom_wake_word.clone())
+                .unwrap_or_else(|| "Hey EVA".to_string());
+            return enroll_wake_word(&phrase, &mut profile).await;
+        }
+        Some("--wake-word-metrics") => {
+            let dir = args.get(1).ok_or("Usage: eva-daemon --wake-word-metrics <dir with positive/ and negative/ WAVs>")?;
+            let profile = UserProfile::load()?;
+            let (mut detector, warning) = wake_word_detector(&profile);
+            if let Some(warning) = warning {
+                println!("⚠️  {}", warning);
+            }
+            let metrics = DetectionMetrics::evaluate_dir(&mut detector, std::path::Path::new(dir))?;
+            println!("Wake word '{}' (sensitivity {:.2})", detector.phrase(), detector.get_sensitivity());
+            println!("  False rejects: {}/{} ({:.1}%)", metrics.false_rejects, metrics.positives, metrics.false_reject_rate() * 100.0);
+            println!(
+                "  False accepts: {}/{} ({:.1}%, {:.1}/hour)",
+                metrics.false_accepts,
+                metrics.negatives,
+                metrics.false_accept_rate() * 100.0,
+                metrics.false_accepts_per_hour()
+            );
+            return Ok(());
+        }
+        Some("--add-identity" | "--use-identity" | "--set-secret" | "--credentials") => {
+            return manage_credentials(&args);
+        }
+        _ => {}
+    }
+
+    // --replay <file.wav>: the WAV stands in for the microphone, EVA's
+    // replies go to <file>.reply.wav, and the daemon exits at its end
+    let replay_path = flag_value(&args, "--replay").map(std::path::PathBuf::from);
+    if replay_path.is_none() && args.iter().any(|a| a == "--replay") {
+        return Err("Usage: eva-daemon --replay <file.wav> [--record-turns [dir]]".into());
+    }
+    // --record-turns [dir]: save every captured turn as a WAV
+    let record_dir = args.iter().position(|a| a == "--record-turns").map(|i| {
+        args.get(i + 1)
+            .filter(|dir| !dir.starts_with("--"))
+            .map(std::path::PathBuf::from)
+            .or_else(TurnRecorder::default_dir)
+            .unwrap_or_else(|| std::path::PathBuf::from("recordings"))
+    });
+
     // Initialize UI components first
     let mut status_indicator = StatusIndicator::new();
-    let mut statistics = Statistics::new();
+    let statistics = Statistics::new();
     let mut terminal_ui = TerminalUI::new()?;
 
     // Initial draw
@@ -52,36 +135,79 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize components
     terminal_ui.add_system_message("[1/13] Initializing audio device...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let mut audio = AudioDevice::new()?;
-    terminal_ui.add_system_message("✅ Audio device ready");
+    // Device names come from the profile; it is reported in step 8
+    let profile = UserProfile::load()?;
+    let audio_config = AudioConfig::from_profile(&profile);
+    let (microphone, replay_source) = match replay_path {
+        Some(ref path) => {
+            let source = WavSource::open(path)?;
+            terminal_ui.add_system_message(&format!("✅ Replaying {} ({:.1}s)", path.display(), source.remaining_seconds()));
+            (None, Some(source))
+        }
+        None => {
+            let microphone = AudioDevice::with_config(&audio_config)?;
+            terminal_ui.add_system_message("✅ Audio device ready");
+            (Some(microphone), None)
+        }
+    };
+    let turn_recorder = match record_dir {
+        Some(ref dir) => {
+            let recorder = TurnRecorder::new(dir)?;
+            terminal_ui.add_system_message(&format!("🎙️  Recording turns to {}", recorder.dir().display()));
+            Some(recorder)
+        }
+        None => None,
+    };
     terminal_ui.draw(&status_indicator, &statistics);
     
 
 
     terminal_ui.add_system_message("[2/13] Initializing wake word detector...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let mut wake_word = WakeWordDetector::new();
-    wake_word.set_sensitivity(0.6);
-    terminal_ui.add_system_message("✅ Wake word detector ready (sensitivity: 0.6)");
+    let (wake_word, wake_word_warning) = wake_word_detector(&profile);
+    if let Some(warning) = wake_word_warning {
+        terminal_ui.add_system_message(&format!("⚠️  {}", warning));
+    }
+    terminal_ui.add_system_message(&format!(
+        "✅ Wake word detector ready ('{}', sensitivity: {:.2})",
+        wake_word.phrase(),
+        wake_word.get_sensitivity()
+    ));
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[3/13] Initializing Voice Activity Detection...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let mut vad = VAD::new();
-    terminal_ui.add_system_message("✅ VAD ready");
+    let (detector, vad_warning) = vad::detector(profile.vad.as_deref());
+    if let Some(warning) = vad_warning {
+        terminal_ui.add_system_message(&format!("⚠️  {}", warning));
+    }
+    let vad = VoiceActivity::new(detector);
+    terminal_ui.add_system_message(&format!("✅ VAD ready ({}, barge-in enabled)", vad.detector_name()));
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[4/13] Initializing audio player...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let audio_device_clone = AudioDevice::new()?;
-    let mut audio_player = AudioPlayer::new(audio_device_clone)?;
-    terminal_ui.add_system_message("✅ Audio player ready");
+    let replay_output = replay_path.as_ref().map(|path| path.with_extension("reply.wav"));
+    let mut audio_player = match replay_output {
+        Some(ref path) => AudioPlayer::new(WavSink::create(path)?)?,
+        None => AudioPlayer::new(AudioDevice::with_config(&audio_config)?)?,
+    };
+    let audio: Box<dyn AudioSource> = match (microphone, replay_source) {
+        (Some(mut microphone), _) => {
+            // The microphone hears the player's speaker; cancel that echo
+            microphone.set_echo_reference(audio_player.echo_reference());
+            Box::new(microphone)
+        }
+        (None, Some(source)) => Box::new(source),
+        (None, None) => unreachable!("either a microphone or a replay file"),
+    };
+    terminal_ui.add_system_message(&format!("✅ Audio player ready (TTS: {})", audio_player.tts_engine_name()));
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[5/13] Initializing conversation session...");
     terminal_ui.draw(&status_indicator, &statistics);
     // Load session from file or create new
-    let mut session = ConversationSession::load_from_file("session.json").unwrap_or_else(|_| {
+    let session = ConversationSession::load_from_file(daemon::SESSION_FILE).unwrap_or_else(|_| {
         terminal_ui.add_system_message("No previous session found, starting new.");
         ConversationSession::new()
     });
@@ -96,26 +222,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     terminal_ui.add_system_message("[7/13] Initializing command executor...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let mut command_executor = CommandExecutor::new()?;
+    let command_executor = CommandExecutor::new()?;
     terminal_ui.add_system_message("✅ Command executor ready (sandbox enabled)");
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[8/13] Loading user profile...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let _profile = UserProfile::load()?;
-    terminal_ui.add_system_message(&format!("✅ User profile loaded (User: {}, Language: {})", _profile.name, _profile.language));
+    let voice = VoiceSettings::from_profile(&profile);
+    audio_player.set_voice(voice.clone());
+    if let Some(warning) = audio_player.take_tts_warning() {
+        terminal_ui.add_system_message(&format!("⚠️  TTS: {}", warning));
+    }
+    terminal_ui.add_system_message(&format!("✅ User profile loaded (User: {}, Language: {})", profile.name, profile.language));
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[9/13] Initializing custom commands...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let mut _custom_commands = CustomCommandManager::new()?;
-    terminal_ui.add_system_message(&format!("✅ Custom commands ready ({} commands)", _custom_commands.count()));
+    let custom_commands = CustomCommandManager::new()?;
+    terminal_ui.add_system_message(&format!("✅ Custom commands ready ({} commands)", custom_commands.count()));
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[10/13] Initializing macros...");
     terminal_ui.draw(&status_indicator, &statistics);
-    let mut _macros = MacroManager::new()?;
-    terminal_ui.add_system_message(&format!("✅ Macros ready ({} macros)", _macros.count()));
+    let macros = MacroManager::new()?;
+    terminal_ui.add_system_message(&format!("✅ Macros ready ({} macros)", macros.count()));
     terminal_ui.draw(&status_indicator, &statistics);
 
     terminal_ui.add_system_message("[11/13] Initializing emotion detection...");
@@ -124,67 +254,73 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     terminal_ui.add_system_message("✅ Emotion detection ready");
     terminal_ui.draw(&status_indicator, &statistics);
 
-    // Initialize animations
-    let mut anim_listening = Animation::listening();
-    let mut anim_processing = Animation::processing();
-    let mut anim_speaking = Animation::speaking();
-
-    terminal_ui.add_system_message("[12/13] Connecting to EVA-Mind...");
+    // EVA_BACKEND picks who answers (EVA-Mind unless set)
+    let backend_kind = BackendKind::from_env()?;
+    terminal_ui.add_system_message(&format!("[12/13] Connecting to {}...", backend_kind.name()));
+    terminal_ui.add_system_message(&format!("   URL: {}", backend::endpoint(backend_kind)));
     terminal_ui.draw(&status_indicator, &statistics);
 
-    let eva_config = EvaMindConfig::default();
-    terminal_ui.add_system_message(&format!("   URL: {}", eva_config.ws_url));
-    terminal_ui.draw(&status_indicator, &statistics);
+    // The backend answers in whichever of the user's languages is spoken
+    let languages = language_id::spoken_languages(&profile).iter().map(|l| l.tag().to_string()).collect();
 
-    let mut eva_mind: Option<EvaMindClient> = match EvaMindClient::connect(eva_config).await {
-        Ok(mut client) => {
-            terminal_ui.add_system_message("✅ Connected to EVA-Mind");
-            terminal_ui.draw(&status_indicator, &statistics);
-
-            // Start call session
-            match client.start_call().await {
-                Ok(_) => {
-                    terminal_ui.add_system_message(&format!("✅ Session started: {}", client.session_id()));
-                    terminal_ui.draw(&status_indicator, &statistics);
-                    Some(client)
+    // Replays stay offline so they run the same every time
+    let connection: Option<ConnectionSupervisor> = match replay_path {
+        Some(_) => {
+            terminal_ui.add_system_message(&format!("⚠️  {} disabled while replaying", backend_kind.name()));
+            None
+        }
+        None => {
+            // The profile's identity from ~/.eva/credentials.enc, env overrides on top
+            let credentials = Credentials::load(&profile)?;
+            // Proxy, private CAs and timeouts from the environment
+            let network = ConnectOptions::from_env()?;
+            // Drops are handled by the supervisor: back-off, heartbeat, session resume
+            let mut supervisor = ConnectionSupervisor::for_backend(backend_kind, languages, credentials, network);
+            match supervisor.connect().await {
+                Ok(()) => {
+                    terminal_ui.add_system_message(&format!("✅ Connected to {}", supervisor.name()));
+                    terminal_ui.add_system_message(&format!(
+                        "✅ Session started: {}",
+                        supervisor.session_id().unwrap_or_default()
+                    ));
                 }
                 Err(e) => {
-                    terminal_ui.add_system_message(&format!("⚠️  Could not start session: {}", e));
-                    terminal_ui.add_system_message("   Running in demo mode");
-                    terminal_ui.draw(&status_indicator, &statistics);
-                    None
+                    terminal_ui.add_system_message(&format!("⚠️  Could not connect to {}: {}", supervisor.name(), e));
+                    terminal_ui.add_system_message("   Retrying in the background");
                 }
             }
-        }
-        Err(e) => {
-            terminal_ui.add_system_message(&format!("⚠️  Could not connect to EVA-Mind: {}", e));
-            terminal_ui.add_system_message("   Running in demo mode");
-            terminal_ui.draw(&status_indicator, &statistics);
-            None
+            Some(supervisor)
         }
     };
+    status_indicator.set_connection(connection.as_ref().map(|c| c.state()));
+    terminal_ui.draw(&status_indicator, &statistics);
+
+    // Without a backend, requests are transcribed locally and answered with voice commands
+    let online_at_start = connection.as_ref().is_some_and(|c| c.is_connected());
+    let mut offline_stt: Option<StreamingSttSession> = None;
+    if !online_at_start {
+        offline_stt = daemon::offline_recognizer(&profile, |message| terminal_ui.add_system_message(&message));
+        terminal_ui.draw(&status_indicator, &statistics);
+    }
 
     // [13/13] Initialize Time Machine
     terminal_ui.add_system_message("[13/13] Initializing Time Machine (NPU)...");
     terminal_ui.draw(&status_indicator, &statistics);
     
     #[cfg(feature = "timemachine")]
-    let timemachine_res = crate::timemachine::TimeMachine::new().await;
+    let timemachine_res = match replay_path {
+        Some(_) => Err("disabled while replaying".into()),
+        None => crate::timemachine::TimeMachine::new().await,
+    };
     
     #[cfg(not(feature = "timemachine"))]
     let timemachine_res: Result<crate::timemachine::TimeMachine, Box<dyn std::error::Error>> = Err("Feature disabled".into());
 
-    let _timemachine = match timemachine_res {
+    let timemachine = match timemachine_res {
         Ok(tm) => {
             terminal_ui.add_system_message("✅ Time Machine ready (Encrypted & Local)");
-            let tm_arc = std::sync::Arc::new(tm);
-            let tm_clone = tm_arc.clone();
-            
-            // Start background recording
-            tokio::spawn(async move {
-                tm_clone.start_recording().await;
-            });
-            Some(tm_arc)
+            // Records in its own task until shutdown
+            Some(std::sync::Arc::new(tm))
         },
         Err(e) => {
             terminal_ui.add_system_message(&format!("⚠️ Time Machine disabled: {}", e));
@@ -197,202 +333,252 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     terminal_ui.add_system_message("EVA OS Started");
     terminal_ui.add_system_message(&format!("Session ID: {}", session.session_id()));
 
-    if eva_mind.is_none() {
-        terminal_ui.add_system_message("Running in DEMO MODE (No Connection)");
+    if !online_at_start {
+        terminal_ui.add_system_message("Running OFFLINE (local speech recognition + voice commands)");
     }
 
     status_indicator.set_status(EvaStatus::Idle);
     terminal_ui.draw(&status_indicator, &statistics);
 
     // Pronto para receber áudio
-    if eva_mind.is_some() {
-        terminal_ui.add_system_message("🎤 Diga 'Hey EVA' para começar...");
+    if online_at_start || offline_stt.is_some() {
+        terminal_ui.add_system_message(&format!("🎤 Diga '{}' para começar...", wake_word.phrase()));
     }
     terminal_ui.draw(&status_indicator, &statistics);
 
-    // Main conversation loop
-    let mut frame_count = 0u64;
-    loop {
-        // Reset animations
-        anim_listening.reset();
-        anim_processing.reset();
-        anim_speaking.reset();
-
-        // 1. Capture audio chunk
-        let chunk = match audio.capture_chunk().await {
-            Ok(c) => c,
-            Err(e) => {
-                terminal_ui.add_system_message(&format!("Audio Error: {}", e));
-                continue;
+    // Capture, detection, backend, playback, UI and Time Machine run as
+    // their own tasks from here on; Ctrl-C stops them and saves the session
+    let mut barge_in = BargeInDetector::new();
+    barge_in.set_min_energy(profile.barge_in_energy);
+    let daemon = Daemon {
+        audio,
+        replay: replay_path.is_some(),
+        detector: Detector::new(wake_word, vad, barge_in, turn_recorder),
+        player: audio_player,
+        voice,
+        connection,
+        offline_stt,
+        session,
+        parser: command_parser,
+        executor: command_executor,
+        custom_commands,
+        macros,
+        profile,
+        timemachine,
+    };
+    let console = Console::new(terminal_ui, status_indicator, statistics);
+    let mut console = tokio::task::LocalSet::new().run_until(daemon.run(console)).await?;
+
+    let summary = format!(
+        "{} turns, {} commands executed",
+        console.statistics.turns, console.statistics.commands_executed
+    );
+    match replay_output {
+        Some(path) => {
+            console.terminal_ui.add_system_message(&format!("Replay finished ({})", summary));
+            console.terminal_ui.add_system_message(&format!("EVA's replies saved to {}", path.display()));
+        }
+        None => console.terminal_ui.add_system_message(&format!("EVA OS stopped ({})", summary)),
+    }
+    console.status_indicator.set_status(EvaStatus::Idle);
+    console.draw();
+    Ok(())
+}
+
+/// Value following `flag` on the command line
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let i = args.iter().position(|a| a == flag)?;
+    args.get(i + 1).map(String::as_str)
+}
+
+/// Wake word detector for the profile: the enrolled phrase when
+/// `custom_wake_word` is set, otherwise the built-in "Hey EVA"
+fn wake_word_detector(profile: &UserProfile) -> (WakeWordDetector, Option<String>) {
+    let mut detector = WakeWordDetector::new();
+    detector.set_sensitivity(profile.wake_word_sensitivity);
+
+    let warning = match profile.custom_wake_word.as_deref() {
+        Some(phrase) => match KeywordTemplates::load(phrase) {
+            Ok(templates) => {
+                detector.set_templates(templates);
+                None
             }
-        };
-
-        // Silently process audio
-        frame_count += 1;
-
-        // 2. Check for wake word
-        if wake_word.detect(&chunk) {
-            status_indicator.set_status(EvaStatus::Listening);
-            terminal_ui.add_system_message("Wake word detected! Listening...");
-            statistics.update_all();
-            terminal_ui.draw(&status_indicator, &statistics);
-            
-            wake_word.reset();
-            
-            // 3. Capture and STREAM audio in real-time (like EVA-Mobile)
-            let mut total_samples = 0usize;
-            let mut silence_count = 0;
-            let mut chunk_count = 0u32;
-            let mut response_chunks = 0u32;
-
-            loop {
-                let audio_chunk = match audio.capture_chunk().await {
-                    Ok(c) => c,
-                    Err(_) => break,
-                };
-
-                // Stream audio to EVA-Mind in real-time (like EVA-Mobile)
-                if let Some(ref mut eva_client) = eva_mind {
-                    // Convert f32 samples to PCM16 bytes
-                    let audio_bytes: Vec<u8> = audio_chunk
-                        .iter()
-                        .flat_map(|&sample| {
-                            let sample_i16 = (sample * i16::MAX as f32) as i16;
-                            sample_i16.to_le_bytes()
-                        })
-                        .collect();
-
-                    // Send immediately (streaming)
-                    if let Err(e) = eva_client.send_audio(&audio_bytes).await {
-                        terminal_ui.add_system_message(&format!("Stream error: {}", e));
-                        break;
-                    }
-                    chunk_count += 1;
-
-                    // Also check for incoming audio response (non-blocking)
-                    match eva_client.receive().await {
-                        Ok(Some(EvaMindResponse::Audio(audio_data))) => {
-                            response_chunks += 1;
-                            if let Err(e) = audio_player.play_pcm(&audio_data).await {
-                                terminal_ui.add_system_message(&format!("Playback error: {}", e));
-                            }
-                        }
-                        Ok(Some(EvaMindResponse::Control(msg))) => {
-                            if let Some(msg_type) = msg.get("type").and_then(|v| v.as_str()) {
-                                terminal_ui.add_system_message(&format!("Control: {}", msg_type));
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+            Err(e) => Some(format!(
+                "{}; using 'Hey EVA' (run `eva-daemon --enroll-wake-word \"{}\"`)",
+                e, phrase
+            )),
+        },
+        None => None,
+    };
 
-                total_samples += audio_chunk.len();
-
-                // Check for silence to end turn
-                if vad.is_speech(&audio_chunk) {
-                    silence_count = 0;
-                } else {
-                    silence_count += 1;
-                    if silence_count > 15 {
-                        // End of speech - stop streaming
-                        break;
-                    }
-                }
+    (detector, warning)
+}
 
-                if total_samples > 48000 * 30 {
-                    terminal_ui.add_system_message("Max recording time reached");
-                    break;
-                }
+/// Recordings taken by `--enroll-wake-word`
+const ENROLLMENT_RECORDINGS: usize = 5;
 
-                // Animate listening
-                if chunk_count % 5 == 0 {
-                    statistics.update_all();
-                    status_indicator.set_symbol(anim_listening.next_frame());
-                    terminal_ui.draw(&status_indicator, &statistics);
-                }
-            }
+/// `--enroll-wake-word "<phrase>"`: record the phrase a few times, save its
+/// templates and make it the profile's wake word
+async fn enroll_wake_word(phrase: &str, profile: &mut UserProfile) -> Result<(), Box<dyn std::error::Error>> {
+    let mut audio = AudioDevice::with_config(&AudioConfig::from_profile(profile))?;
+    let (detector, warning) = vad::detector(profile.vad.as_deref());
+    if let Some(warning) = warning {
+        println!("⚠️  {}", warning);
+    }
+    let mut vad = VoiceActivity::new(detector);
+    let mut enrollment = Enrollment::new(phrase);
+
+    println!("🎙️  Enrolling wake word '{}'", enrollment.phrase());
+    println!("   Stay quiet for a second...");
+    for _ in 0..10 {
+        let chunk = audio.capture_chunk().await?;
+        vad.process(&chunk);
+    }
 
-            terminal_ui.add_system_message(&format!("Streamed {} chunks, received {} responses", chunk_count, response_chunks));
-            statistics.turns += 1;
-            terminal_ui.draw(&status_indicator, &statistics);
-
-            // 4. Wait for response audio
-            if let Some(ref mut eva_client) = eva_mind {
-                status_indicator.set_status(EvaStatus::Speaking);
-                terminal_ui.draw(&status_indicator, &statistics);
-
-                let timeout = tokio::time::Duration::from_secs(15);
-                let start = tokio::time::Instant::now();
-                let mut received_audio = false;
-
-                while start.elapsed() < timeout {
-                    // Animate while waiting
-                    statistics.update_all();
-                    status_indicator.set_symbol(anim_speaking.next_frame());
-                    terminal_ui.draw(&status_indicator, &statistics);
-
-                    // Try to receive audio
-                    match eva_client.receive().await {
-                        Ok(Some(EvaMindResponse::Audio(audio_data))) => {
-                            received_audio = true;
-                            // Play audio (raw PCM bytes from EVA-Mind)
-                            if let Err(e) = audio_player.play_pcm(&audio_data).await {
-                                terminal_ui.add_system_message(&format!("Audio Playback Error: {}", e));
-                            }
-                        }
-                        Ok(Some(EvaMindResponse::Control(msg))) => {
-                            // Handle control messages
-                            if let Some(msg_type) = msg.get("type").and_then(|v| v.as_str()) {
-                                terminal_ui.add_system_message(&format!("Control: {}", msg_type));
-                            }
-                        }
-                        Ok(None) => {
-                            // No message, continue
-                            if received_audio {
-                                // Got audio, small pause then continue
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            terminal_ui.add_system_message(&format!("Receive Error: {}", e));
-                            break;
-                        }
-                    }
-
-                    tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
-                }
+    let mut attempts = 0;
+    while enrollment.count() < ENROLLMENT_RECORDINGS {
+        attempts += 1;
+        if attempts > ENROLLMENT_RECORDINGS * 2 {
+            return Err("Too many failed recordings, enrollment aborted".into());
+        }
 
-                if !received_audio {
-                    terminal_ui.add_system_message("No audio response received");
-                }
+        println!("   [{}/{}] Say '{}'", enrollment.count() + 1, ENROLLMENT_RECORDINGS, enrollment.phrase());
+        let recording = record_utterance(&mut audio, &mut vad).await?;
+        match enrollment.add_utterance(&recording) {
+            Ok(_) => println!("   ✅ Got it"),
+            Err(e) => println!("   ⚠️  {}, let's try that again", e),
+        }
+    }
+
+    let templates = enrollment.finish()?;
+    let path = templates.save()?;
+    profile.set_custom_wake_word(Some(templates.phrase.clone()));
+    profile.save()?;
+    println!("✅ Wake word '{}' saved to {}", templates.phrase, path.display());
+    Ok(())
+}
+
+/// Credential store commands (values are typed at a prompt, never passed as
+/// arguments, so they stay out of shell history):
+///
+/// - `--add-identity <name> [user type]`: store a CPF under `name`
+/// - `--use-identity <name>`: make `name` this profile's identity
+/// - `--set-secret <google_api_key|eva_mind_token>`
+/// - `--credentials`: list what is stored (values redacted)
+fn manage_credentials(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut store = CredentialStore::load()?;
+    let name = args.get(1).map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+
+    match args[0].as_str() {
+        "--add-identity" => {
+            let name = name.ok_or("Usage: eva-daemon --add-identity <name> [user type]")?;
+            let cpf = prompt(&format!("CPF for '{}': ", name))?;
+            if cpf.is_empty() {
+                return Err("No CPF given".into());
+            }
+            let mut identity = Identity::new(&cpf);
+            if let Some(user_type) = args.get(2) {
+                identity.user_type = user_type.clone();
+            }
+            store.set_identity(&name, identity);
+            store.save()?;
+            println!("✅ Identity '{}' saved to {}", name, CredentialStore::path()?.display());
+        }
+        "--use-identity" => {
+            let name = name.ok_or("Usage: eva-daemon --use-identity <name>")?;
+            if !store.identities.contains_key(&name) {
+                return Err(format!("Identity '{}' is not in the credential store (add it with --add-identity)", name).into());
+            }
+            let mut profile = UserProfile::load()?;
+            profile.identity = Some(name.clone());
+            profile.save()?;
+            println!("✅ Profile '{}' now registers as '{}'", profile.name, name);
+        }
+        "--set-secret" => {
+            let name = name
+                .filter(|n| credentials::SECRET_NAMES.contains(&n.as_str()))
+                .ok_or_else(|| format!("Usage: eva-daemon --set-secret <{}>", credentials::SECRET_NAMES.join("|")))?;
+            let value = prompt(&format!("{}: ", name))?;
+            if value.is_empty() {
+                store.secrets.remove(&name);
+                println!("✅ '{}' removed", name);
             } else {
-                // Demo mode logic
-                terminal_ui.add_system_message("Processing (Demo Mode)...");
-                tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
-                
-                let demo_response = "I heard you! This is a demo response.";
-                terminal_ui.add_eva_message(demo_response);
-                
-                status_indicator.set_status(EvaStatus::Speaking);
-                
-                // Simulate speaking time with animation
-                let speak_duration = tokio::time::Duration::from_millis(2000);
-                let start_time = tokio::time::Instant::now();
-                
-                while start_time.elapsed() < speak_duration {
-                    statistics.update_all();
-                    status_indicator.set_symbol(anim_speaking.next_frame());
-                    terminal_ui.draw(&status_indicator, &statistics);
-                    tokio::time::sleep(anim_speaking.frame_duration()).await;
+                store.set_secret(&name, &value);
+                println!("✅ '{}' saved", name);
+            }
+            store.save()?;
+        }
+        _ => {
+            let profile = UserProfile::load()?;
+            println!("🔑 {}", CredentialStore::path()?.display());
+            for (name, identity) in &store.identities {
+                let mut notes = Vec::new();
+                if store.default_identity.as_ref() == Some(name) {
+                    notes.push("default");
+                }
+                if profile.identity.as_ref() == Some(name) {
+                    notes.push("this profile");
+                }
+                println!("   👤 {} ({}, CPF {}) {}", name, identity.user_type, identity.cpf, notes.join(", "));
+            }
+            for name in store.secrets.keys() {
+                println!("   🔒 {} [redacted]", name);
+            }
+            for var in ["EVA_IDENTITY", "EVA_CPF", "GOOGLE_API_KEY", "EVA_MIND_TOKEN"] {
+                if std::env::var(var).is_ok() {
+                    println!("   ⚠️  {} is set and overrides the store", var);
                 }
             }
-            
-            // Reset to idle
-            status_indicator.set_status(EvaStatus::Idle);
-            vad.reset();
-            terminal_ui.draw(&status_indicator, &statistics);
         }
+    }
+
+    Ok(())
+}
+
+/// Ask for a line on stdin
+fn prompt(question: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::Write;
+    print!("{}", question);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
 
-        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+/// Record one utterance, from the VAD's speech start to its speech end
+///
+/// Returns an empty recording after 5s without speech.
+async fn record_utterance(audio: &mut AudioDevice, vad: &mut VoiceActivity) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    vad.reset();
+    // Keep a little audio from before the VAD triggers so the onset isn't cut
+    let mut pre_roll: std::collections::VecDeque<Vec<f32>> = std::collections::VecDeque::new();
+    let mut recording = Vec::new();
+    let mut waiting_chunks = 0;
+
+    loop {
+        let chunk = audio.capture_chunk().await?;
+        let voice = vad.process(&chunk);
+
+        if recording.is_empty() && !voice.in_speech {
+            pre_roll.push_back(chunk);
+            if pre_roll.len() > 3 {
+                pre_roll.pop_front();
+            }
+            waiting_chunks += 1;
+            if waiting_chunks > 50 {
+                return Ok(Vec::new());
+            }
+            continue;
+        }
+
+        if recording.is_empty() {
+            recording.extend(pre_roll.drain(..).flatten());
+        }
+        recording.extend_from_slice(&chunk);
+
+        // 3s cap: longer than any wake phrase the detector can hold
+        if voice.event == Some(VadEvent::SpeechEnd) || recording.len() > 3 * 16000 {
+            return Ok(recording);
+        }
     }
 }
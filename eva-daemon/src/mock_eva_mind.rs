@@ -0,0 +1,629 @@
+//! Local EVA-Mind stand-in for tests
+//!
+//! A WebSocket server on 127.0.0.1 that speaks the `protocol` handshake
+//! (register, start_call, session_created), answers audio by echoing it or
+//! with a synthesized tone, and records everything the client sent. Tests
+//! inject server messages, errors and disconnects on cue, so
+//! `EvaMindClient` and the connection supervisor run without the
+//! production endpoint.
+
+use std::sync::{Arc, Mutex};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::credentials::Secret;
+use crate::eva_mind::EvaMindConfig;
+use crate::protocol::{self, ClientMessage, ServerMessage, Speaker};
+
+/// How the server answers the user's audio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    /// Send every audio frame straight back
+    Echo,
+    /// On `end_of_turn`, send this many 100ms frames of a 440Hz tone
+    Synthesize { frames: usize },
+    /// Never answer with audio
+    Silent,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// Version announced in `registered`/`session_created` (`None` = legacy server)
+    pub protocol_version: Option<u32>,
+    pub reply: Reply,
+    /// Answer `start_call` with this error instead of a session
+    pub reject_start_call: Option<String>,
+    /// Close the first connection after this many audio frames
+    pub disconnect_after_audio: Option<usize>,
+    /// Final user transcript sent on `end_of_turn`
+    pub transcript: Option<String>,
+    /// Codec named in `session_created` (`None` = server doesn't negotiate)
+    pub audio_codec: Option<String>,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            protocol_version: Some(protocol::PROTOCOL_VERSION),
+            reply: Reply::Echo,
+            reject_start_call: None,
+            disconnect_after_audio: None,
+            transcript: None,
+            audio_codec: None,
+        }
+    }
+}
+
+/// Everything clients sent, across connections
+#[derive(Debug, Clone, Default)]
+pub struct Recorded {
+    pub connections: usize,
+    /// `Authorization` header of each handshake (`None` = not sent)
+    pub authorization: Vec<Option<String>>,
+    pub messages: Vec<ClientMessage>,
+    pub audio: Vec<Vec<u8>>,
+    /// Text frames that aren't `ClientMessage`s
+    pub unknown: Vec<String>,
+}
+
+impl Recorded {
+    /// Session ids of every `start_call`, in order
+    pub fn started_sessions(&self) -> Vec<&str> {
+        self.messages
+            .iter()
+            .filter_map(|m| match m {
+                ClientMessage::StartCall { session_id, .. } => Some(session_id.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Sent to every open connection
+#[derive(Debug, Clone)]
+enum Cue {
+    Send(String),
+    Disconnect,
+}
+
+pub struct MockEvaMind {
+    url: String,
+    recorded: Arc<Mutex<Recorded>>,
+    cues: broadcast::Sender<Cue>,
+    task: JoinHandle<()>,
+}
+
+impl MockEvaMind {
+    /// Listen on a free local port
+    pub async fn start(config: MockConfig) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind mock EVA-Mind");
+        let url = format!("ws://{}", listener.local_addr().expect("mock address"));
+        let recorded = Arc::new(Mutex::new(Recorded::default()));
+        let (cues, _) = broadcast::channel(16);
+
+        let task = tokio::spawn(accept_loop(listener, config, recorded.clone(), cues.clone()));
+        Self { url, recorded, cues, task }
+    }
+
+    /// Client configuration pointing at this server
+    pub fn client_config(&self) -> EvaMindConfig {
+        EvaMindConfig {
+            ws_url: self.url.clone(),
+            cpf: Secret::new("00000000000"),
+            ..EvaMindConfig::default()
+        }
+    }
+
+    pub fn recorded(&self) -> Recorded {
+        self.recorded.lock().unwrap().clone()
+    }
+
+    /// Wait (up to 2s) until the record satisfies `done`
+    pub async fn wait_for(&self, done: impl Fn(&Recorded) -> bool) -> Recorded {
+        for _ in 0..200 {
+            let recorded = self.recorded();
+            if done(&recorded) {
+                return recorded;
+            }
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+        panic!("mock EVA-Mind never got there: {:?}", self.recorded());
+    }
+
+    /// Push a message to the connected clients
+    pub fn send(&self, message: &ServerMessage) {
+        self.send_raw(&serde_json::to_string(message).unwrap());
+    }
+
+    /// Push any text frame (e.g. a message the protocol doesn't define)
+    pub fn send_raw(&self, text: &str) {
+        self.cues.send(Cue::Send(text.to_string())).ok();
+    }
+
+    /// Close every open connection
+    pub fn disconnect(&self) {
+        self.cues.send(Cue::Disconnect).ok();
+    }
+}
+
+impl Drop for MockEvaMind {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    config: MockConfig,
+    recorded: Arc<Mutex<Recorded>>,
+    cues: broadcast::Sender<Cue>,
+) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let connection = {
+            let mut recorded = recorded.lock().unwrap();
+            recorded.connections += 1;
+            recorded.connections
+        };
+        tokio::spawn(serve(stream, connection, config.clone(), recorded.clone(), cues.subscribe()));
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    connection: usize,
+    config: MockConfig,
+    recorded: Arc<Mutex<Recorded>>,
+    mut cues: broadcast::Receiver<Cue>,
+) {
+    // The signature is tungstenite's
+    #[allow(clippy::result_large_err)]
+    let handshake = |request: &Request, response: Response| {
+        let authorization = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        recorded.lock().unwrap().authorization.push(authorization);
+        Ok(response)
+    };
+    let Ok(mut ws) = tokio_tungstenite::accept_hdr_async(stream, handshake).await else {
+        return;
+    };
+    let mut audio_frames = 0;
+
+    loop {
+        tokio::select! {
+            frame = ws.next() => {
+                let replies = match frame {
+                    Some(Ok(Message::Binary(pcm))) => {
+                        recorded.lock().unwrap().audio.push(pcm.clone());
+                        audio_frames += 1;
+                        if connection == 1 && config.disconnect_after_audio == Some(audio_frames) {
+                            ws.close(None).await.ok();
+                            return;
+                        }
+                        match config.reply {
+                            Reply::Echo => vec![Message::Binary(pcm)],
+                            _ => Vec::new(),
+                        }
+                    }
+                    Some(Ok(Message::Text(text))) => answer(&text, &config, &recorded),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => Vec::new(),
+                };
+                for reply in replies {
+                    if ws.send(reply).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            cue = cues.recv() => match cue {
+                Ok(Cue::Send(text)) => {
+                    if ws.send(Message::Text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(Cue::Disconnect) | Err(broadcast::error::RecvError::Closed) => {
+                    ws.close(None).await.ok();
+                    return;
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+            },
+        }
+    }
+}
+
+/// Record a client text frame and build the server's answer
+fn answer(text: &str, config: &MockConfig, recorded: &Mutex<Recorded>) -> Vec<Message> {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(_) => {
+            recorded.lock().unwrap().unknown.push(text.to_string());
+            return Vec::new();
+        }
+    };
+    recorded.lock().unwrap().messages.push(message.clone());
+
+    let json = |message: ServerMessage| Message::Text(serde_json::to_string(&message).unwrap());
+    match message {
+        ClientMessage::Register { .. } => match config.protocol_version {
+            Some(version) => vec![json(ServerMessage::Registered { protocol_version: Some(version) })],
+            None => Vec::new(),
+        },
+        ClientMessage::StartCall { .. } => match config.reject_start_call {
+            Some(ref error) => vec![json(ServerMessage::Error { message: error.clone(), code: None })],
+            None => vec![json(ServerMessage::SessionCreated {
+                session_id: None,
+                protocol_version: config.protocol_version,
+                audio_codec: config.audio_codec.clone(),
+            })],
+        },
+        ClientMessage::EndOfTurn { .. } => {
+            let mut replies = Vec::new();
+            if let Some(ref text) = config.transcript {
+                replies.push(json(ServerMessage::Transcript {
+                    role: Speaker::User,
+                    text: text.clone(),
+                    is_final: true,
+                }));
+            }
+            if let Reply::Synthesize { frames } = config.reply {
+                replies.extend((0..frames).map(|_| Message::Binary(tone_frame())));
+            }
+            replies.push(json(ServerMessage::EndOfTurn));
+            replies
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// 100ms of 440Hz at 16kHz, 16-bit PCM
+fn tone_frame() -> Vec<u8> {
+    let samples: Vec<f32> = (0..1600)
+        .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 16000.0).sin() * 0.3)
+        .collect();
+    crate::tts::samples_to_pcm16(&samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{BackendEvent, VoiceBackend};
+    use crate::codec::{self, AudioCodec};
+    use crate::command_executor::CommandExecutor;
+    use crate::connection::{Backoff, ConnectionState, ConnectionSupervisor, Connector};
+    use crate::eva_mind::{EvaMindClient, EvaMindResponse};
+    use crate::session::Role;
+    use crate::tools;
+    use tokio::time::Duration;
+
+    async fn connected_client(mock: &MockEvaMind) -> EvaMindClient {
+        let mut client = EvaMindClient::connect(mock.client_config()).await.unwrap();
+        client.start_call().await.unwrap();
+        client
+    }
+
+    /// Events until `TurnComplete` (or 2s)
+    async fn events_until_turn_complete(client: &mut EvaMindClient) -> Vec<BackendEvent> {
+        let mut events = Vec::new();
+        for _ in 0..20 {
+            if let Some(event) = VoiceBackend::receive(client).await.unwrap() {
+                let done = event == BackendEvent::TurnComplete;
+                events.push(event);
+                if done {
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_handshake_and_echo() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let mut client = connected_client(&mock).await;
+        assert_eq!(client.protocol_version(), 2);
+
+        client.send_audio(&[1, 0, 2, 0]).await.unwrap();
+        let mut echoed = None;
+        for _ in 0..20 {
+            if let Some(EvaMindResponse::Audio(pcm)) = client.receive().await.unwrap() {
+                echoed = Some(pcm);
+                break;
+            }
+        }
+        assert_eq!(echoed, Some(vec![1, 0, 2, 0]));
+
+        let recorded = mock.recorded();
+        assert!(matches!(
+            recorded.messages[0],
+            ClientMessage::Register { protocol_version: 2, .. }
+        ));
+        assert_eq!(recorded.started_sessions(), vec![client.session_id()]);
+        assert_eq!(recorded.audio, vec![vec![1, 0, 2, 0]]);
+    }
+
+    #[tokio::test]
+    async fn test_token_sent_as_header() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        connected_client(&mock).await;
+        let config = EvaMindConfig {
+            token: Some(Secret::new("mock-token-123")),
+            ..mock.client_config()
+        };
+        EvaMindClient::connect(config).await.unwrap();
+
+        let recorded = mock.wait_for(|r| r.authorization.len() == 2).await;
+        assert_eq!(recorded.authorization, vec![None, Some("Bearer mock-token-123".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_missing_identity_is_refused() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let config = EvaMindConfig {
+            cpf: Secret::default(),
+            ..mock.client_config()
+        };
+        assert!(EvaMindClient::connect(config).await.is_err());
+        assert_eq!(mock.recorded().connections, 0);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_server_negotiates_v1() {
+        let mock = MockEvaMind::start(MockConfig { protocol_version: None, ..MockConfig::default() }).await;
+        let mut client = connected_client(&mock).await;
+        assert_eq!(client.protocol_version(), 1);
+
+        // v2 messages aren't sent to a v1 server
+        client.end_turn().await.unwrap();
+        assert!(client.send_text("hello").await.is_err());
+        client.interrupt().await.unwrap();
+
+        let recorded = mock.wait_for(|r| r.messages.len() == 3).await;
+        assert!(matches!(recorded.messages[2], ClientMessage::Interrupt { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_audio_codec_negotiation() {
+        // A server that doesn't negotiate gets raw PCM
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let client = connected_client(&mock).await;
+        assert_eq!(client.audio_codec(), AudioCodec::Pcm16);
+        let recorded = mock.recorded();
+        let Some(ClientMessage::StartCall { audio_codecs, .. }) = recorded.messages.get(1) else {
+            panic!("no start_call: {:?}", recorded.messages);
+        };
+        let offered: Vec<_> = codec::available().iter().map(|c| c.name().to_string()).collect();
+        assert_eq!(audio_codecs, &offered);
+
+        let mock = MockEvaMind::start(MockConfig {
+            audio_codec: Some("pcm16".to_string()),
+            ..MockConfig::default()
+        })
+        .await;
+        assert_eq!(connected_client(&mock).await.audio_codec(), AudioCodec::Pcm16);
+
+        // Picking something that wasn't offered fails the call
+        let mock = MockEvaMind::start(MockConfig {
+            audio_codec: Some("opus".to_string()),
+            ..MockConfig::default()
+        })
+        .await;
+        let config = EvaMindConfig {
+            audio_codecs: vec![AudioCodec::Pcm16],
+            ..mock.client_config()
+        };
+        let mut client = EvaMindClient::connect(config).await.unwrap();
+        let err = client.start_call().await.unwrap_err();
+        assert_eq!(err.to_string(), "EVA-Mind picked audio codec 'opus', which wasn't offered");
+    }
+
+    #[tokio::test]
+    async fn test_start_call_error() {
+        let mock = MockEvaMind::start(MockConfig {
+            reject_start_call: Some("unknown patient".to_string()),
+            ..MockConfig::default()
+        })
+        .await;
+        let mut client = EvaMindClient::connect(mock.client_config()).await.unwrap();
+
+        let err = client.start_call().await.unwrap_err();
+        assert_eq!(err.to_string(), "EVA-Mind error: unknown patient");
+        assert!(!client.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_synthesized_reply_with_transcript() {
+        let mock = MockEvaMind::start(MockConfig {
+            reply: Reply::Synthesize { frames: 3 },
+            transcript: Some("que horas são".to_string()),
+            ..MockConfig::default()
+        })
+        .await;
+        let mut client = connected_client(&mock).await;
+
+        client.send_audio(&[0; 320]).await.unwrap();
+        VoiceBackend::end_turn(&mut client).await.unwrap();
+        let events = events_until_turn_complete(&mut client).await;
+
+        assert_eq!(
+            events[0],
+            BackendEvent::Transcript { role: Role::User, text: "que horas são".to_string(), is_final: true }
+        );
+        let audio: Vec<_> = events.iter().filter(|e| matches!(e, BackendEvent::Audio { .. })).collect();
+        assert_eq!(audio.len(), 3);
+        assert_eq!(events.last(), Some(&BackendEvent::TurnComplete));
+    }
+
+    #[tokio::test]
+    async fn test_command_request_and_unknown_message() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let mut client = connected_client(&mock).await;
+
+        mock.send(&ServerMessage::CommandRequest { id: "42".to_string(), command: "show memory".to_string() });
+        mock.send_raw(r#"{"type": "weather", "temp": 21}"#);
+
+        let mut events = Vec::new();
+        for _ in 0..20 {
+            if let Some(event) = VoiceBackend::receive(&mut client).await.unwrap() {
+                events.push(event);
+                if events.len() == 2 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(
+            events[0],
+            BackendEvent::CommandRequest { id: "42".to_string(), command: "show memory".to_string() }
+        );
+        assert_eq!(events[1], BackendEvent::Control(serde_json::json!({"type": "weather", "temp": 21})));
+
+        client.send_command_result("42", true, "Memory: 1GB").await.unwrap();
+        let recorded = mock.wait_for(|r| r.messages.len() == 3).await;
+        assert_eq!(
+            recorded.messages[2],
+            ClientMessage::CommandResult {
+                session_id: client.session_id().to_string(),
+                id: "42".to_string(),
+                success: true,
+                output: "Memory: 1GB".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_keeps_messages_after_audio() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let mut client = connected_client(&mock).await;
+
+        // Three echoed frames still in flight, then a command request
+        for _ in 0..3 {
+            client.send_audio(&[1; 320]).await.unwrap();
+        }
+        mock.wait_for(|r| r.audio.len() == 3).await;
+        mock.send(&ServerMessage::CommandRequest { id: "7".to_string(), command: "open browser".to_string() });
+
+        client.interrupt().await.unwrap();
+        let recorded = mock.wait_for(|r| r.messages.iter().any(|m| matches!(m, ClientMessage::Interrupt { .. }))).await;
+        assert_eq!(recorded.audio.len(), 3);
+
+        assert_eq!(
+            VoiceBackend::receive(&mut client).await.unwrap(),
+            Some(BackendEvent::CommandRequest { id: "7".to_string(), command: "open browser".to_string() })
+        );
+        assert_eq!(VoiceBackend::receive(&mut client).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tool_call_round_trip() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let config = EvaMindConfig {
+            tools: tools::declarations(),
+            ..mock.client_config()
+        };
+        let mut client = EvaMindClient::connect(config).await.unwrap();
+        client.start_call().await.unwrap();
+
+        let recorded = mock.recorded();
+        let Some(ClientMessage::StartCall { tools: declared, .. }) = recorded.messages.get(1) else {
+            panic!("no start_call: {:?}", recorded.messages);
+        };
+        assert_eq!(declared, &tools::declarations());
+
+        mock.send(&ServerMessage::ToolCall {
+            id: "t1".to_string(),
+            name: "memory_info".to_string(),
+            args: serde_json::json!({}),
+        });
+        let mut call = None;
+        for _ in 0..20 {
+            if let Some(event) = VoiceBackend::receive(&mut client).await.unwrap() {
+                call = Some(event);
+                break;
+            }
+        }
+        let Some(BackendEvent::ToolCall { id, name, args }) = call else {
+            panic!("expected a tool call, got {:?}", call);
+        };
+
+        // What the daemon does with it: intent, sandboxed executor, result back
+        let intent = tools::intent_from_call(&name, &args, &[]).unwrap();
+        let output = CommandExecutor::new().unwrap().execute(intent).await.unwrap();
+        client.send_command_result(&id, true, &output).await.unwrap();
+
+        let recorded = mock.wait_for(|r| r.messages.len() == 3).await;
+        assert_eq!(
+            recorded.messages[2],
+            ClientMessage::CommandResult {
+                session_id: client.session_id().to_string(),
+                id: "t1".to_string(),
+                success: true,
+                output,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_resumes_after_disconnect() {
+        let mock = MockEvaMind::start(MockConfig {
+            reply: Reply::Silent,
+            disconnect_after_audio: Some(1),
+            ..MockConfig::default()
+        })
+        .await;
+        let config = mock.client_config();
+        let connector: Connector = Box::new(move |resume| {
+            let config = EvaMindConfig {
+                session_id: resume,
+                ws_url: config.ws_url.clone(),
+                cpf: config.cpf.clone(),
+                ..EvaMindConfig::default()
+            };
+            Box::pin(async move { Ok(Box::new(EvaMindClient::connect(config).await?) as Box<dyn VoiceBackend>) })
+        });
+        let mut supervisor = ConnectionSupervisor::new("EVA-Mind", connector)
+            .with_backoff(Backoff::new(Duration::ZERO, Duration::ZERO));
+        supervisor.connect().await.unwrap();
+
+        // The server hangs up after the first frame
+        supervisor.send_audio(&[1, 0]).await;
+        for _ in 0..20 {
+            if supervisor.receive().await.is_err() {
+                break;
+            }
+        }
+        assert_eq!(supervisor.state(), ConnectionState::Reconnecting { attempt: 0 });
+
+        // Captured during the outage, delivered after reconnecting
+        supervisor.send_audio(&[2, 0]).await;
+        assert_eq!(supervisor.maintain().await, Some(ConnectionState::Connected));
+
+        let recorded = mock.wait_for(|r| r.audio.len() == 2).await;
+        assert_eq!(recorded.connections, 2);
+        assert_eq!(recorded.audio, vec![vec![1, 0], vec![2, 0]]);
+        let sessions = recorded.started_sessions();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0], sessions[1]);
+    }
+
+    #[tokio::test]
+    async fn test_server_disconnect_is_reported() {
+        let mock = MockEvaMind::start(MockConfig::default()).await;
+        let mut client = connected_client(&mock).await;
+
+        mock.disconnect();
+        let mut result = Ok(None);
+        for _ in 0..20 {
+            result = client.receive().await;
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Offline assistant
+//!
+//! Answers a transcribed request when EVA-Mind is unreachable: the user's
+//! custom commands first, then the built-in command parser, always through
+//! the sandboxed `CommandExecutor` (custom "shell" actions are parsed as
+//! voice commands, never handed to a real shell).
+
+use crate::command_executor::CommandExecutor;
+use crate::command_parser::{CommandIntent, CommandParser};
+use crate::custom_commands::{CommandAction, CustomCommand, CustomCommandManager};
+use crate::macros::MacroManager;
+
+/// What EVA says back, and how many commands ran to produce it
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineReply {
+    pub text: String,
+    pub commands_executed: usize,
+}
+
+impl OfflineReply {
+    fn say(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            commands_executed: 0,
+        }
+    }
+}
+
+/// Handle one transcribed utterance
+pub async fn respond(
+    text: &str,
+    custom_commands: &CustomCommandManager,
+    macros: &MacroManager,
+    parser: &CommandParser,
+    executor: &mut CommandExecutor,
+) -> OfflineReply {
+    match custom_commands.find_command(text) {
+        Some(command) => run_custom_command(command, macros, parser, executor).await,
+        None => run_builtin(text, parser, executor).await,
+    }
+}
+
+/// Run a user-defined command
+pub async fn run_custom_command(
+    command: &CustomCommand,
+    macros: &MacroManager,
+    parser: &CommandParser,
+    executor: &mut CommandExecutor,
+) -> OfflineReply {
+    match &command.action {
+        CommandAction::SendText(text) => OfflineReply::say(text.as_str()),
+        CommandAction::ExecuteShell(line) => run_builtin(line, parser, executor).await,
+        CommandAction::RunMacro(name) => {
+            let steps = match macros.play_macro(name).await {
+                Ok(steps) => steps,
+                Err(e) => return OfflineReply::say(format!("Macro error: {}", e)),
+            };
+
+            let mut reply = OfflineReply::say("");
+            let mut lines = Vec::new();
+            for step in &steps {
+                let result = run_builtin(step, parser, executor).await;
+                reply.commands_executed += result.commands_executed;
+                lines.push(result.text);
+            }
+            reply.text = if lines.is_empty() {
+                format!("Macro '{}' has no steps", name)
+            } else {
+                lines.join("\n")
+            };
+            reply
+        }
+        CommandAction::Custom(action) => {
+            OfflineReply::say(format!("'{}' needs EVA-Mind, which is offline", action))
+        }
+    }
+}
+
+/// Parse and execute a built-in command (files, processes, system info...)
+pub async fn run_builtin(text: &str, parser: &CommandParser, executor: &mut CommandExecutor) -> OfflineReply {
+    match parser.parse(text) {
+        Ok(CommandIntent::Unknown) => {
+            OfflineReply::say("I'm offline, so I can only run commands right now (files, processes, system info).")
+        }
+        Ok(intent) => match executor.execute(intent).await {
+            Ok(output) => OfflineReply {
+                text: output,
+                commands_executed: 1,
+            },
+            Err(e) => OfflineReply::say(format!("Command failed: {}", e)),
+        },
+        Err(e) => OfflineReply::say(format!("I couldn't understand that command: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unknown_request_explains_offline_mode() {
+        let parser = CommandParser::new();
+        let mut executor = CommandExecutor::new().unwrap();
+
+        let reply = run_builtin("tell me a joke", &parser, &mut executor).await;
+        assert_eq!(reply.commands_executed, 0);
+        assert!(reply.text.contains("offline"));
+    }
+
+    #[tokio::test]
+    async fn test_builtin_command_runs_in_sandbox() {
+        let parser = CommandParser::new();
+        let mut executor = CommandExecutor::new().unwrap();
+
+        let reply = run_builtin("create a file called offline_reply_test.txt", &parser, &mut executor).await;
+        assert_eq!(reply.commands_executed, 1, "{}", reply.text);
+        assert!(reply.text.contains("offline_reply_test.txt"));
+
+        executor
+            .execute(CommandIntent::File(crate::command_parser::FileOperation::Delete {
+                path: "offline_reply_test.txt".to_string(),
+            }))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_custom_text_and_unsupported_actions() {
+        let parser = CommandParser::new();
+        let mut executor = CommandExecutor::new().unwrap();
+        let macros = MacroManager::new().unwrap();
+
+        let greeting = CustomCommand {
+            trigger: "good morning".to_string(),
+            action: CommandAction::SendText("Good morning!".to_string()),
+            description: String::new(),
+        };
+        let reply = run_custom_command(&greeting, &macros, &parser, &mut executor).await;
+        assert_eq!(reply, OfflineReply::say("Good morning!"));
+
+        let custom = CustomCommand {
+            action: CommandAction::Custom("order pizza".to_string()),
+            ..greeting
+        };
+        let reply = run_custom_command(&custom, &macros, &parser, &mut executor).await;
+        assert!(reply.text.contains("order pizza") && reply.commands_executed == 0);
+    }
+}
@@ -0,0 +1,481 @@
+//! Streaming playback of backend answers
+//!
+//! Answer audio arrives in network-sized bursts with uneven gaps between
+//! them. Written straight to the speaker, every late burst is a gap in the
+//! middle of a word. `Playback` holds an answer in a jitter buffer until
+//! it covers the expected lateness (`target`), then streams it to the
+//! speaker; when the speaker runs dry anyway it rebuffers and raises the
+//! target for the rest of the conversation. The target follows the
+//! measured arrival jitter and slowly shrinks again on a steady link.
+//!
+//! The answer is over when the backend says so (`finish`, on
+//! `TurnComplete`) and everything has been played, not after a quiet
+//! poll. Starts, resumptions and interruptions are faded so they don't
+//! click; for that `Playback` mirrors what the speaker still has queued.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+use crate::audio::SAMPLE_RATE;
+use crate::audio_io::AudioSink;
+use crate::resample::Resampler;
+
+const MS: usize = SAMPLE_RATE as usize / 1000;
+
+/// Buffer bounds (samples)
+const MIN_TARGET: usize = 60 * MS;
+const INITIAL_TARGET: usize = 120 * MS;
+const MAX_TARGET: usize = 600 * MS;
+
+/// Added to the target when the speaker runs dry mid-answer
+const UNDERRUN_STEP: usize = 80 * MS;
+
+/// Taken off again after an answer that played without a gap
+const RECOVERY_STEP: usize = 20 * MS;
+
+/// Length of fade-ins and fade-outs
+const FADE_SAMPLES: usize = 10 * MS;
+
+/// Weight of each new lateness sample in the jitter estimate (RFC 3550)
+const JITTER_GAIN: f32 = 1.0 / 16.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaybackState {
+    /// No answer in progress
+    Idle,
+    /// Filling the jitter buffer (at the start, or after running dry)
+    Buffering,
+    /// Streaming to the speaker
+    Playing,
+}
+
+/// Where the current answer is, for the UI
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackProgress {
+    pub state: PlaybackState,
+    /// Seconds of the answer played so far
+    pub played: f32,
+    /// Seconds received but not heard yet
+    pub buffered: f32,
+    /// Seconds buffered before playing starts
+    pub target: f32,
+    /// Times the speaker ran dry mid-answer
+    pub underruns: u32,
+}
+
+impl fmt::Display for PlaybackProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.state {
+            PlaybackState::Idle => write!(f, "🔇 {:.1}s played", self.played)?,
+            PlaybackState::Buffering => write!(f, "⏳ Buffering {:.2}s / {:.2}s", self.buffered, self.target)?,
+            PlaybackState::Playing => write!(f, "🔊 {:.1}s played, {:.2}s buffered", self.played, self.buffered)?,
+        }
+        if self.underruns > 0 {
+            write!(f, " ({} underruns)", self.underruns)?;
+        }
+        Ok(())
+    }
+}
+
+/// Jitter buffer and speaker queue for one answer at a time
+pub struct Playback {
+    state: PlaybackState,
+    /// Received, not given to the speaker yet
+    buffer: VecDeque<f32>,
+    /// Given to the speaker and not played yet (its queue, as far as we know)
+    in_device: VecDeque<f32>,
+    /// Answers at another rate (Gemini's 24kHz) are converted as they stream
+    resampler: Option<Resampler>,
+    /// The backend said the answer is complete
+    finished: bool,
+    /// The speaker ran dry; counted as an underrun if more audio comes
+    starved: bool,
+    /// Fade-in samples still to apply
+    fade_in: usize,
+    /// Smoothed lateness of bursts, in seconds
+    jitter: f32,
+    /// Previous burst: arrival time and length
+    last_arrival: Option<(Instant, usize)>,
+    /// Lower bound for the target, raised by underruns
+    floor: usize,
+    played: usize,
+    underruns: u32,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self {
+            state: PlaybackState::Idle,
+            buffer: VecDeque::new(),
+            in_device: VecDeque::new(),
+            resampler: None,
+            finished: false,
+            starved: false,
+            fade_in: 0,
+            jitter: 0.0,
+            last_arrival: None,
+            floor: INITIAL_TARGET,
+            played: 0,
+            underruns: 0,
+        }
+    }
+
+    pub fn state(&self) -> PlaybackState {
+        self.state
+    }
+
+    /// Samples buffered before playing starts
+    pub fn target(&self) -> usize {
+        let jitter = (2.0 * self.jitter * SAMPLE_RATE as f32) as usize;
+        self.floor.max(MIN_TARGET + jitter).clamp(MIN_TARGET, MAX_TARGET)
+    }
+
+    /// Get ready for the next answer (a new turn)
+    ///
+    /// Audio still in the speaker keeps playing. The jitter estimate
+    /// carries over, since the link is the same.
+    pub fn begin(&mut self) {
+        if self.played > 0 && self.underruns == 0 {
+            self.floor = self.floor.saturating_sub(RECOVERY_STEP).max(INITIAL_TARGET);
+        }
+        self.state = PlaybackState::Idle;
+        self.buffer.clear();
+        self.resampler = None;
+        self.finished = false;
+        self.starved = false;
+        self.last_arrival = None;
+        self.played = 0;
+        self.underruns = 0;
+    }
+
+    /// Answer audio that arrived at `now`
+    pub fn push(&mut self, samples: &[f32], sample_rate: u32, now: Instant) {
+        if self.resampler.as_ref().map(Resampler::input_rate) != Some(sample_rate) {
+            self.resampler = Some(Resampler::new(sample_rate, SAMPLE_RATE));
+        }
+        let samples = self.resampler.as_mut().map(|r| r.process(samples)).unwrap_or_default();
+        if samples.is_empty() {
+            return;
+        }
+
+        // Lateness: how much longer the gap was than the audio before it
+        if let Some((previous, length)) = self.last_arrival {
+            let late = now.duration_since(previous).as_secs_f32() - length as f32 / SAMPLE_RATE as f32;
+            self.jitter += (late.max(0.0) - self.jitter) * JITTER_GAIN;
+        }
+        self.last_arrival = Some((now, samples.len()));
+
+        if std::mem::take(&mut self.starved) {
+            self.underruns += 1;
+            self.floor = (self.floor + UNDERRUN_STEP).min(MAX_TARGET);
+        }
+        if self.state == PlaybackState::Idle {
+            self.state = PlaybackState::Buffering;
+            self.fade_in = FADE_SAMPLES;
+        }
+        self.buffer.extend(samples);
+    }
+
+    /// The backend finished the answer: play out whatever is buffered
+    pub fn finish(&mut self) {
+        if let Some(mut resampler) = self.resampler.take().filter(|r| !r.is_passthrough()) {
+            // Flush the tail held back for look-ahead
+            let tail = resampler.process(&vec![0.0; resampler.latency() + 1]);
+            self.buffer.extend(tail);
+        }
+        self.finished = true;
+    }
+
+    /// Move audio along; call every loop iteration while an answer plays
+    pub fn pump(&mut self, sink: &mut dyn AudioSink) -> Result<(), Box<dyn Error>> {
+        self.sync(sink);
+
+        match self.state {
+            PlaybackState::Playing if self.buffer.is_empty() && self.in_device.is_empty() => {
+                if self.finished {
+                    self.state = PlaybackState::Idle;
+                } else {
+                    // Ran dry before the answer ended: rebuffer
+                    self.state = PlaybackState::Buffering;
+                    self.starved = true;
+                    self.fade_in = FADE_SAMPLES;
+                }
+            }
+            PlaybackState::Buffering if self.finished && self.buffer.is_empty() => {
+                self.state = PlaybackState::Idle;
+            }
+            PlaybackState::Buffering if self.finished || self.buffer.len() >= self.target() => {
+                self.state = PlaybackState::Playing;
+            }
+            _ => {}
+        }
+
+        if self.state == PlaybackState::Playing && !self.buffer.is_empty() {
+            let mut samples: Vec<f32> = self.buffer.drain(..).collect();
+            for (i, sample) in samples.iter_mut().take(self.fade_in).enumerate() {
+                *sample *= (FADE_SAMPLES - self.fade_in + i) as f32 / FADE_SAMPLES as f32;
+            }
+            self.fade_in = self.fade_in.saturating_sub(samples.len());
+            self.play_now(sink, &samples)?;
+        }
+        Ok(())
+    }
+
+    /// Play audio right away, outside the jitter buffer (local speech)
+    pub fn play_now(&mut self, sink: &mut dyn AudioSink, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        sink.play(samples)?;
+        self.in_device.extend(samples);
+        self.sync(sink);
+        Ok(())
+    }
+
+    /// Stop everything with a short fade instead of a click
+    ///
+    /// The answer counts as finished; later audio for it starts a new one.
+    pub fn fade_out(&mut self, sink: &mut dyn AudioSink) {
+        self.sync(sink);
+        let tail: Vec<f32> = self
+            .in_device
+            .iter()
+            .take(FADE_SAMPLES)
+            .enumerate()
+            .map(|(i, sample)| sample * (FADE_SAMPLES - i) as f32 / FADE_SAMPLES as f32)
+            .collect();
+
+        sink.clear_output();
+        self.in_device.clear();
+        // Without the tail it's a hard stop, which is still a stop
+        self.play_now(sink, &tail).ok();
+
+        self.state = PlaybackState::Idle;
+        self.buffer.clear();
+        self.resampler = None;
+        self.finished = true;
+        self.starved = false;
+    }
+
+    /// Audio received or queued that hasn't been heard yet (samples)
+    pub fn queued(&self) -> usize {
+        self.buffer.len() + self.in_device.len()
+    }
+
+    /// Whether an answer is still coming or playing
+    pub fn is_active(&self) -> bool {
+        self.state != PlaybackState::Idle || !self.in_device.is_empty()
+    }
+
+    /// The backend finished the answer and all of it has been played
+    pub fn is_done(&self) -> bool {
+        self.finished && self.state == PlaybackState::Idle && self.queued() == 0
+    }
+
+    pub fn progress(&self) -> PlaybackProgress {
+        let seconds = |samples: usize| samples as f32 / SAMPLE_RATE as f32;
+        PlaybackProgress {
+            state: self.state,
+            played: seconds(self.played),
+            buffered: seconds(self.queued()),
+            target: seconds(self.target()),
+            underruns: self.underruns,
+        }
+    }
+
+    /// Drop what the speaker has played since the last look
+    fn sync(&mut self, sink: &dyn AudioSink) {
+        let queued = sink.queued_output();
+        if self.in_device.len() > queued {
+            let played = self.in_device.len() - queued;
+            self.in_device.drain(..played);
+            self.played += played;
+        }
+    }
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_processing::EchoReference;
+    use std::time::Duration;
+
+    /// Speaker whose playback is advanced by hand
+    #[derive(Default)]
+    struct FakeSink {
+        queue: VecDeque<f32>,
+        heard: Vec<f32>,
+    }
+
+    impl FakeSink {
+        fn advance(&mut self, samples: usize) {
+            let n = samples.min(self.queue.len());
+            self.heard.extend(self.queue.drain(..n));
+        }
+    }
+
+    impl AudioSink for FakeSink {
+        fn play(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+            self.queue.extend(samples);
+            Ok(())
+        }
+
+        fn queued_output(&self) -> usize {
+            self.queue.len()
+        }
+
+        fn clear_output(&mut self) {
+            self.queue.clear();
+        }
+
+        fn echo_reference(&self) -> EchoReference {
+            EchoReference::new()
+        }
+    }
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn test_buffers_to_target_then_plays_to_the_end() {
+        let mut playback = Playback::new();
+        let mut sink = FakeSink::default();
+        let start = Instant::now();
+        playback.begin();
+
+        // 100ms isn't enough to start
+        playback.push(&[0.5; 100 * MS], SAMPLE_RATE, start);
+        playback.pump(&mut sink).unwrap();
+        assert_eq!(playback.state(), PlaybackState::Buffering);
+        assert_eq!(sink.queued_output(), 0);
+
+        playback.push(&[0.5; 100 * MS], SAMPLE_RATE, start + ms(100));
+        playback.pump(&mut sink).unwrap();
+        assert_eq!(playback.state(), PlaybackState::Playing);
+        assert_eq!(sink.queued_output(), 200 * MS);
+        // Faded in
+        assert_eq!(sink.queue[0], 0.0);
+        assert_eq!(sink.queue[FADE_SAMPLES], 0.5);
+
+        // Backend done, speaker still going: not done yet
+        playback.finish();
+        sink.advance(150 * MS);
+        playback.pump(&mut sink).unwrap();
+        assert!(!playback.is_done());
+        assert!((playback.progress().played - 0.15).abs() < 1e-3);
+
+        sink.advance(50 * MS);
+        playback.pump(&mut sink).unwrap();
+        assert!(playback.is_done());
+        assert_eq!(playback.progress().underruns, 0);
+    }
+
+    #[test]
+    fn test_short_answer_plays_when_finished() {
+        let mut playback = Playback::new();
+        let mut sink = FakeSink::default();
+        playback.begin();
+
+        playback.push(&[0.5; 30 * MS], SAMPLE_RATE, Instant::now());
+        playback.finish();
+        playback.pump(&mut sink).unwrap();
+        assert_eq!(sink.queued_output(), 30 * MS);
+
+        sink.advance(30 * MS);
+        playback.pump(&mut sink).unwrap();
+        assert!(playback.is_done());
+    }
+
+    #[test]
+    fn test_underrun_rebuffers_and_raises_target() {
+        let mut playback = Playback::new();
+        let mut sink = FakeSink::default();
+        let start = Instant::now();
+        playback.begin();
+        let initial = playback.target();
+
+        playback.push(&vec![0.5; initial], SAMPLE_RATE, start);
+        playback.pump(&mut sink).unwrap();
+        sink.advance(initial);
+        playback.pump(&mut sink).unwrap();
+        assert_eq!(playback.state(), PlaybackState::Buffering);
+
+        // The rest arrives 400ms late
+        playback.push(&[0.5; 50 * MS], SAMPLE_RATE, start + ms(520));
+        assert_eq!(playback.progress().underruns, 1);
+        assert!(playback.target() > initial);
+        playback.pump(&mut sink).unwrap();
+        assert_eq!(sink.queued_output(), 0, "waits for the bigger target");
+
+        playback.finish();
+        playback.pump(&mut sink).unwrap();
+        assert_eq!(sink.queue[0], 0.0, "faded back in");
+        assert_eq!(sink.queued_output(), 50 * MS);
+    }
+
+    #[test]
+    fn test_target_follows_jitter_and_recovers() {
+        let mut playback = Playback::new();
+        let start = Instant::now();
+        playback.begin();
+        let steady = playback.target();
+
+        // 20ms packets arriving up to 200ms late
+        for i in 0..50u64 {
+            playback.push(&[0.0; 20 * MS], SAMPLE_RATE, start + ms(i * 20 + (i % 2) * 200));
+        }
+        let jittery = playback.target();
+        assert!(jittery > steady);
+        assert!(jittery <= MAX_TARGET);
+
+        // Underrun floors decay after clean answers
+        playback.floor = MAX_TARGET;
+        playback.jitter = 0.0;
+        playback.played = 1;
+        playback.begin();
+        assert_eq!(playback.target(), MAX_TARGET - RECOVERY_STEP);
+    }
+
+    #[test]
+    fn test_fade_out_and_resampled_answers() {
+        let mut playback = Playback::new();
+        let mut sink = FakeSink::default();
+        playback.begin();
+
+        // Gemini's 24kHz comes out at 16kHz
+        playback.push(&[0.5; 24000], 24000, Instant::now());
+        playback.finish();
+        playback.pump(&mut sink).unwrap();
+        let queued = sink.queued_output() as i64;
+        assert!((queued - SAMPLE_RATE as i64).abs() <= 2, "{}", queued);
+
+        sink.advance(100 * MS);
+        playback.fade_out(&mut sink);
+        assert_eq!(sink.queued_output(), FADE_SAMPLES);
+        assert!(sink.queue[0] > 0.4);
+        assert!(sink.queue[FADE_SAMPLES - 1] < 0.1);
+        assert!(playback.is_active());
+
+        sink.advance(FADE_SAMPLES);
+        playback.pump(&mut sink).unwrap();
+        assert!(playback.is_done());
+    }
+
+    #[test]
+    fn test_progress_display() {
+        let progress = PlaybackProgress {
+            state: PlaybackState::Playing,
+            played: 2.4,
+            buffered: 0.3,
+            target: 0.12,
+            underruns: 1,
+        };
+        assert_eq!(progress.to_string(), "🔊 2.4s played, 0.30s buffered (1 underruns)");
+    }
+}
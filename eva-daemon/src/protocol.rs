@@ -0,0 +1,261 @@
+//! EVA-Mind wire protocol
+//!
+//! Every JSON message on the EVA-Mind WebSocket, in both directions, tagged
+//! by its `type` field; audio travels separately as binary PCM frames.
+//!
+//! Version 1 is the original handshake (register, start_call,
+//! session_created, error, interrupt). Version 2 adds typed text input,
+//! end_of_turn, transcripts, command requests and tool calls (against the
+//! tools declared in `start_call`). The client announces the
+//! version it speaks in `register`; the server answers with the one it will
+//! use in `session_created` (servers that predate negotiation send none and
+//! are treated as version 1). The audio codec is negotiated the same way:
+//! offered in `start_call`, picked in `session_created`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::tools::ToolDeclaration;
+
+/// Version this client speaks
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest server version still supported
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Messages EVA sends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Register {
+        user_type: String,
+        cpf: String,
+        protocol_version: u32,
+    },
+    StartCall {
+        cpf: String,
+        session_id: String,
+        /// Languages the user speaks, main one first
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        languages: Vec<String>,
+        /// Functions EVA-Mind may call with `tool_call` (v2)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tools: Vec<ToolDeclaration>,
+        /// Audio codecs EVA can stream, preferred first (see `codec`)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        audio_codecs: Vec<String>,
+    },
+    /// Typed user input (v2)
+    Text { session_id: String, text: String },
+    /// The user stopped talking (v2)
+    EndOfTurn { session_id: String },
+    /// The user barged in; stop the answer
+    Interrupt { session_id: String },
+    /// Outcome of a `command_request` or `tool_call` (v2)
+    CommandResult {
+        session_id: String,
+        id: String,
+        success: bool,
+        output: String,
+    },
+}
+
+impl ClientMessage {
+    /// Oldest protocol version that has this message
+    pub fn min_version(&self) -> u32 {
+        match self {
+            ClientMessage::Register { .. } | ClientMessage::StartCall { .. } | ClientMessage::Interrupt { .. } => 1,
+            ClientMessage::Text { .. } | ClientMessage::EndOfTurn { .. } | ClientMessage::CommandResult { .. } => 2,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Plain strings and numbers only, so serialization can't fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Who a transcript belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Speaker {
+    User,
+    Assistant,
+}
+
+/// Messages EVA-Mind sends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// `register` accepted (v2)
+    Registered {
+        #[serde(default)]
+        protocol_version: Option<u32>,
+    },
+    SessionCreated {
+        /// Set when the server assigned or resumed a different session
+        #[serde(default)]
+        session_id: Option<String>,
+        #[serde(default)]
+        protocol_version: Option<u32>,
+        /// Codec picked from `audio_codecs` (`None` = raw PCM16)
+        #[serde(default)]
+        audio_codec: Option<String>,
+    },
+    Error {
+        #[serde(default)]
+        message: String,
+        #[serde(default)]
+        code: Option<String>,
+    },
+    /// What the user said or what EVA is saying (v2)
+    Transcript {
+        role: Speaker,
+        text: String,
+        /// Interim transcripts are replaced by later ones
+        #[serde(default = "default_final")]
+        is_final: bool,
+    },
+    /// Run a voice command on this machine and reply with `command_result` (v2)
+    CommandRequest { id: String, command: String },
+    /// Call one of the declared tools and reply with `command_result` (v2)
+    ToolCall {
+        id: String,
+        name: String,
+        #[serde(default)]
+        args: Value,
+    },
+    /// EVA-Mind finished answering (v2)
+    EndOfTurn,
+    /// EVA-Mind cut its own answer short (e.g. it heard the user)
+    Interrupt,
+}
+
+fn default_final() -> bool {
+    true
+}
+
+/// A text frame that isn't a `ServerMessage`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// Not JSON at all
+    Invalid(String),
+    /// JSON with an unknown `type` (or missing fields); kept for logging
+    Unknown(Value),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Invalid(e) => write!(f, "invalid JSON: {}", e),
+            ParseError::Unknown(value) => match value.get("type").and_then(|t| t.as_str()) {
+                Some(kind) => write!(f, "unknown message type '{}'", kind),
+                None => write!(f, "message without a type"),
+            },
+        }
+    }
+}
+
+impl ServerMessage {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let value: Value = serde_json::from_str(text).map_err(|e| ParseError::Invalid(e.to_string()))?;
+        Self::deserialize(&value).map_err(|_| ParseError::Unknown(value))
+    }
+}
+
+/// Version to speak given the server's answer
+pub fn negotiate(server_version: Option<u32>) -> Result<u32, String> {
+    match server_version {
+        None => Ok(MIN_PROTOCOL_VERSION),
+        Some(version) if (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) => Ok(version),
+        Some(version) => Err(format!(
+            "EVA-Mind protocol v{} not supported (this client speaks v{} to v{})",
+            version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_client_messages_match_wire_format() {
+        let register = ClientMessage::Register {
+            user_type: "patient".to_string(),
+            cpf: "123".to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        };
+        assert_eq!(
+            serde_json::to_value(&register).unwrap(),
+            json!({"type": "register", "user_type": "patient", "cpf": "123", "protocol_version": 2})
+        );
+
+        let start = ClientMessage::StartCall {
+            cpf: "123".to_string(),
+            session_id: "s1".to_string(),
+            languages: Vec::new(),
+            tools: Vec::new(),
+            audio_codecs: Vec::new(),
+        };
+        assert_eq!(
+            serde_json::to_value(&start).unwrap(),
+            json!({"type": "start_call", "cpf": "123", "session_id": "s1"})
+        );
+
+        let end = ClientMessage::EndOfTurn { session_id: "s1".to_string() };
+        assert_eq!(end.to_json(), r#"{"type":"end_of_turn","session_id":"s1"}"#);
+        assert_eq!(end.min_version(), 2);
+    }
+
+    #[test]
+    fn test_parse_server_messages() {
+        assert_eq!(
+            ServerMessage::parse(r#"{"type": "session_created"}"#),
+            Ok(ServerMessage::SessionCreated { session_id: None, protocol_version: None, audio_codec: None })
+        );
+        assert_eq!(
+            ServerMessage::parse(r#"{"type": "session_created", "protocol_version": 2, "audio_codec": "opus"}"#),
+            Ok(ServerMessage::SessionCreated {
+                session_id: None,
+                protocol_version: Some(2),
+                audio_codec: Some("opus".to_string()),
+            })
+        );
+        assert_eq!(
+            ServerMessage::parse(r#"{"type": "transcript", "role": "user", "text": "oi", "is_final": false}"#),
+            Ok(ServerMessage::Transcript { role: Speaker::User, text: "oi".to_string(), is_final: false })
+        );
+        assert_eq!(
+            ServerMessage::parse(r#"{"type": "command_request", "id": "7", "command": "list files"}"#),
+            Ok(ServerMessage::CommandRequest { id: "7".to_string(), command: "list files".to_string() })
+        );
+        assert_eq!(
+            ServerMessage::parse(r#"{"type": "tool_call", "id": "8", "name": "list_files", "args": {"path": "docs"}}"#),
+            Ok(ServerMessage::ToolCall {
+                id: "8".to_string(),
+                name: "list_files".to_string(),
+                args: json!({"path": "docs"}),
+            })
+        );
+        assert_eq!(ServerMessage::parse(r#"{"type": "end_of_turn"}"#), Ok(ServerMessage::EndOfTurn));
+    }
+
+    #[test]
+    fn test_unknown_messages_are_kept() {
+        let err = ServerMessage::parse(r#"{"type": "weather", "temp": 21}"#).unwrap_err();
+        assert_eq!(err, ParseError::Unknown(json!({"type": "weather", "temp": 21})));
+        assert_eq!(err.to_string(), "unknown message type 'weather'");
+
+        assert!(matches!(ServerMessage::parse("not json"), Err(ParseError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(None), Ok(1));
+        assert_eq!(negotiate(Some(2)), Ok(2));
+        assert!(negotiate(Some(3)).is_err());
+        assert!(negotiate(Some(0)).is_err());
+    }
+}
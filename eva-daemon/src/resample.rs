@@ -0,0 +1,239 @@
+//! Sample-rate conversion
+//!
+//! Streaming windowed-sinc resampler used between the sound card's native
+//! rate (usually 44.1/48 kHz) and the 16 kHz EVA works with internally.
+//! The kernel is tabulated once (polyphase table with linear interpolation
+//! between phases), so per-sample cost is one multiply-add per tap.
+
+use std::f64::consts::PI;
+
+/// Zero crossings of the sinc on each side of the center
+const ZERO_CROSSINGS: usize = 16;
+/// Kernel table resolution (entries per input sample)
+const TABLE_RESOLUTION: usize = 128;
+/// Passband edge as a fraction of the output Nyquist frequency
+const ROLLOFF: f64 = 0.95;
+
+/// Streaming resampler for mono f32 audio
+pub struct Resampler {
+    from_rate: u32,
+    to_rate: u32,
+    /// Input samples advanced per output sample
+    step: f64,
+    /// Kernel half-width in input samples
+    radius: f64,
+    /// Kernel values for distances 0..=radius, `TABLE_RESOLUTION` per sample
+    table: Vec<f32>,
+    /// Input not yet fully consumed (starts with `radius` zeros of history)
+    history: Vec<f32>,
+    /// Position of the next output sample, in `history` indices
+    pos: f64,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        let from = from_rate.max(1) as f64;
+        let to = to_rate.max(1) as f64;
+
+        // Cut off below the lower of the two Nyquist frequencies
+        let cutoff = (to / from).min(1.0) * ROLLOFF;
+        let radius = ZERO_CROSSINGS as f64 / cutoff;
+
+        let table_len = (radius * TABLE_RESOLUTION as f64).ceil() as usize + 2;
+        let table = (0..table_len)
+            .map(|i| {
+                let d = i as f64 / TABLE_RESOLUTION as f64;
+                if d > radius {
+                    return 0.0;
+                }
+                let x = cutoff * d;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                // Blackman window over [-radius, radius]
+                let u = d / radius;
+                let window = 0.42 + 0.5 * (PI * u).cos() + 0.08 * (2.0 * PI * u).cos();
+                (cutoff * sinc * window) as f32
+            })
+            .collect();
+
+        let mut resampler = Self {
+            from_rate,
+            to_rate,
+            step: from / to,
+            radius,
+            table,
+            history: Vec::new(),
+            pos: 0.0,
+        };
+        resampler.reset();
+        resampler
+    }
+
+    pub fn input_rate(&self) -> u32 {
+        self.from_rate
+    }
+
+    pub fn output_rate(&self) -> u32 {
+        self.to_rate
+    }
+
+    /// Whether the rates are equal (input passes through untouched)
+    pub fn is_passthrough(&self) -> bool {
+        self.from_rate == self.to_rate
+    }
+
+    /// Input samples buffered before output is produced
+    pub fn latency(&self) -> usize {
+        if self.is_passthrough() {
+            0
+        } else {
+            self.radius.ceil() as usize
+        }
+    }
+
+    /// Forget buffered input (e.g. after a stream restart)
+    pub fn reset(&mut self) {
+        let pad = self.radius.ceil() as usize;
+        self.history = vec![0.0; pad];
+        self.pos = pad as f64;
+    }
+
+    /// Convert the next block of input; output length tracks the rate ratio
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.is_passthrough() {
+            return input.to_vec();
+        }
+
+        self.history.extend_from_slice(input);
+
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step).ceil() as usize + 1);
+        let last = self.history.len() as f64 - 1.0;
+        while self.pos + self.radius <= last {
+            output.push(self.sample_at(self.pos));
+            self.pos += self.step;
+        }
+
+        // Keep only the history the next output still needs
+        let consumed = (self.pos - self.radius).floor().max(0.0) as usize;
+        let consumed = consumed.min(self.history.len());
+        self.history.drain(..consumed);
+        self.pos -= consumed as f64;
+
+        output
+    }
+
+    /// Interpolated output value at fractional input position `t`
+    fn sample_at(&self, t: f64) -> f32 {
+        let first = (t - self.radius).ceil().max(0.0) as usize;
+        let last = ((t + self.radius).floor() as usize).min(self.history.len() - 1);
+
+        let mut acc = 0.0f32;
+        let mut weight = 0.0f32;
+        for n in first..=last {
+            let k = self.kernel((t - n as f64).abs());
+            acc += self.history[n] * k;
+            weight += k;
+        }
+
+        // Normalizing keeps DC gain exactly 1 regardless of phase
+        if weight.abs() > f32::EPSILON {
+            acc / weight
+        } else {
+            0.0
+        }
+    }
+
+    fn kernel(&self, distance: f64) -> f32 {
+        let idx = distance * TABLE_RESOLUTION as f64;
+        let i = idx as usize;
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+        let frac = (idx - i as f64) as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * frac
+    }
+}
+
+/// Resample a complete buffer
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let mut resampler = Resampler::new(from_rate, to_rate);
+    let mut output = resampler.process(samples);
+    // Flush the tail held back for look-ahead
+    output.extend(resampler.process(&vec![0.0; resampler.latency() + 1]));
+
+    let expected = (samples.len() as f64 * to_rate as f64 / from_rate as f64).round() as usize;
+    output.truncate(expected);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_output_length() {
+        assert_eq!(resample(&vec![0.0; 48000], 48000, 16000).len(), 16000);
+        assert_eq!(resample(&vec![0.0; 44100], 44100, 16000).len(), 16000);
+        assert_eq!(resample(&vec![0.0; 16000], 16000, 48000).len(), 48000);
+        assert_eq!(resample(&vec![0.0; 100], 16000, 16000).len(), 100);
+    }
+
+    #[test]
+    fn test_dc_preserved() {
+        let output = resample(&vec![0.5; 4800], 48000, 16000);
+        // Skip the edges, where the signal starts/stops
+        for &s in &output[100..1500] {
+            assert!((s - 0.5).abs() < 1e-4, "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_tone_preserved_and_alias_rejected() {
+        // 1 kHz passes through 48k -> 16k with its amplitude
+        let output = resample(&sine(1000.0, 48000, 48000), 48000, 16000);
+        let level = rms(&output[1000..15000]);
+        assert!((level - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.02, "{}", level);
+
+        // 12 kHz cannot be represented at 16 kHz and must not alias down to 4 kHz
+        let output = resample(&sine(12000.0, 48000, 48000), 48000, 16000);
+        assert!(rms(&output[1000..15000]) < 0.01);
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let input = sine(440.0, 44100, 4410);
+        let mut resampler = Resampler::new(44100, 16000);
+
+        let mut streamed = Vec::new();
+        for chunk in input.chunks(137) {
+            streamed.extend(resampler.process(chunk));
+        }
+
+        let whole = Resampler::new(44100, 16000).process(&input);
+        assert_eq!(streamed.len(), whole.len());
+        for (a, b) in streamed.iter().zip(&whole) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_passthrough() {
+        let mut resampler = Resampler::new(16000, 16000);
+        assert!(resampler.is_passthrough());
+        assert_eq!(resampler.process(&[0.1, 0.2]), vec![0.1, 0.2]);
+        assert_eq!(resampler.latency(), 0);
+    }
+}
@@ -0,0 +1,128 @@
+//! The daemon's conversation state
+//!
+//! Only the conductor in `daemon.rs` changes the state; the other tasks
+//! watch it. A turn goes Idle → Listening (wake word) → Thinking (end of
+//! speech) → Speaking (first answer audio) → Idle, with Executing while a
+//! command the backend asked for runs. Talking over EVA goes straight back
+//! to Listening.
+
+use crate::status_indicator::EvaStatus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonState {
+    /// Waiting for the wake word
+    Idle,
+    /// Recording (and streaming) the user's turn
+    Listening,
+    /// The turn is over, no answer audio yet
+    Thinking,
+    /// Playing the answer
+    Speaking,
+    /// Running a command for the backend
+    Executing,
+}
+
+/// What moves the state machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The wake word, or the user talking over EVA
+    Wake,
+    /// The user stopped talking
+    EndOfSpeech,
+    /// The first of the answer is playing
+    AnswerStarted,
+    /// The backend asked for a command
+    CommandRequested,
+    /// That command is done
+    CommandFinished,
+    /// The answer was heard, interrupted or never came
+    AnswerFinished,
+}
+
+impl DaemonState {
+    /// State after `trigger` (unchanged if it doesn't apply here)
+    pub fn on(self, trigger: Trigger) -> DaemonState {
+        use DaemonState::*;
+        match (self, trigger) {
+            (Idle | Thinking | Speaking, Trigger::Wake) => Listening,
+            (Listening, Trigger::EndOfSpeech) => Thinking,
+            (Thinking, Trigger::AnswerStarted) => Speaking,
+            // Asked for while Listening, a command runs without ending the recording
+            (Thinking | Speaking, Trigger::CommandRequested) => Executing,
+            (Executing, Trigger::CommandFinished) => Thinking,
+            (Thinking | Speaking | Executing, Trigger::AnswerFinished) => Idle,
+            (state, _) => state,
+        }
+    }
+
+    /// Whether an answer is on its way or playing
+    pub fn is_answering(self) -> bool {
+        matches!(self, DaemonState::Thinking | DaemonState::Speaking)
+    }
+
+    /// What the status bar shows
+    pub fn status(self) -> EvaStatus {
+        match self {
+            DaemonState::Idle => EvaStatus::Idle,
+            DaemonState::Listening => EvaStatus::Listening,
+            DaemonState::Thinking => EvaStatus::Processing,
+            DaemonState::Speaking => EvaStatus::Speaking,
+            DaemonState::Executing => EvaStatus::Executing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DaemonState::*;
+
+    fn run(start: DaemonState, triggers: &[Trigger]) -> DaemonState {
+        triggers.iter().fold(start, |state, &trigger| state.on(trigger))
+    }
+
+    #[test]
+    fn test_full_turn() {
+        let turn = [
+            Trigger::Wake,
+            Trigger::EndOfSpeech,
+            Trigger::CommandRequested,
+            Trigger::CommandFinished,
+            Trigger::AnswerStarted,
+            Trigger::AnswerFinished,
+        ];
+        let mut state = Idle;
+        let mut seen = vec![state];
+        for trigger in turn {
+            state = state.on(trigger);
+            seen.push(state);
+        }
+        assert_eq!(seen, vec![Idle, Listening, Thinking, Executing, Thinking, Speaking, Idle]);
+    }
+
+    #[test]
+    fn test_barge_in_starts_listening() {
+        assert_eq!(run(Idle, &[Trigger::Wake, Trigger::EndOfSpeech, Trigger::AnswerStarted, Trigger::Wake]), Listening);
+        assert_eq!(run(Idle, &[Trigger::Wake, Trigger::EndOfSpeech, Trigger::Wake]), Listening);
+    }
+
+    #[test]
+    fn test_ignored_triggers() {
+        // A command while recording doesn't stop the recording
+        assert_eq!(Listening.on(Trigger::CommandRequested), Listening);
+        // Nobody interrupts a command
+        assert_eq!(Executing.on(Trigger::Wake), Executing);
+        // Stale events from a finished turn
+        assert_eq!(Idle.on(Trigger::EndOfSpeech), Idle);
+        assert_eq!(Idle.on(Trigger::AnswerStarted), Idle);
+        assert_eq!(Idle.on(Trigger::AnswerFinished), Idle);
+        assert_eq!(Speaking.on(Trigger::AnswerStarted), Speaking);
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(Thinking.status(), EvaStatus::Processing);
+        assert!(Thinking.is_answering() && Speaking.is_answering());
+        assert!(!Executing.is_answering());
+    }
+}
@@ -27,6 +27,7 @@ impl fmt::Display for EvaStatus {
     }
 }
 
+use crate::connection::ConnectionState;
 use crate::emotion::Emotion;
 
 /// Status indicator with history
@@ -36,6 +37,8 @@ pub struct StatusIndicator {
     status_history: Vec<(EvaStatus, SystemTime)>,
     max_history: usize,
     override_symbol: Option<String>,
+    /// Backend connection, shown next to the status (`None` = no backend)
+    connection: Option<ConnectionState>,
 }
 
 impl StatusIndicator {
@@ -47,6 +50,7 @@ impl StatusIndicator {
             status_history: Vec::new(),
             max_history: 100,
             override_symbol: None,
+            connection: None,
         }
     }
 
@@ -75,6 +79,11 @@ impl StatusIndicator {
         self.current_emotion
     }
 
+    /// Set the backend connection state
+    pub fn set_connection(&mut self, connection: Option<ConnectionState>) {
+        self.connection = connection;
+    }
+
     /// Set a temporary symbol override (for animations)
     pub fn set_symbol(&mut self, symbol: &str) {
         self.override_symbol = Some(symbol.to_string());
@@ -87,11 +96,14 @@ impl StatusIndicator {
 
     /// Get status as string
     pub fn get_status_string(&self) -> String {
-        let emotion_str = if self.current_emotion != Emotion::Neutral {
+        let mut details = if self.current_emotion != Emotion::Neutral {
             format!(" | Emotion: {}", self.current_emotion)
         } else {
             String::new()
         };
+        if let Some(connection) = self.connection {
+            details.push_str(&format!(" | {}", connection));
+        }
 
         if let Some(ref symbol) = self.override_symbol {
             // Use the status name but replace the icon with the animation frame
@@ -104,9 +116,9 @@ impl StatusIndicator {
                 EvaStatus::Executing => "Executing",
                 EvaStatus::Error => "Error",
             };
-            format!("{} {}{}", symbol, status_name, emotion_str)
+            format!("{} {}{}", symbol, status_name, details)
         } else {
-            format!("{}{}", self.current_status, emotion_str)
+            format!("{}{}", self.current_status, details)
         }
     }
 
@@ -165,6 +177,17 @@ mod tests {
         assert_eq!(indicator.get_status_string(), "💤 Idle");
     }
 
+    #[test]
+    fn test_connection_display() {
+        let mut indicator = StatusIndicator::new();
+        indicator.set_status(EvaStatus::Idle);
+        indicator.set_connection(Some(ConnectionState::Reconnecting { attempt: 2 }));
+        assert_eq!(indicator.get_status_string(), "💤 Idle | 🟡 Reconnecting (attempt 3)");
+
+        indicator.set_connection(None);
+        assert_eq!(indicator.get_status_string(), "💤 Idle");
+    }
+
     #[test]
     fn test_color_names() {
         let mut indicator = StatusIndicator::new();
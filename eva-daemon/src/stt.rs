@@ -1,12 +1,24 @@
-//! Offline Speech-to-Text using Vosk
+//! Offline Speech-to-Text
 //!
 //! Provides local, privacy-preserving speech recognition without
 //! requiring internet connectivity. Supports multiple languages.
+//!
+//! Backends implement `SpeechRecognizer`:
+//! - Whisper encoder/decoder ONNX models (`onnx` feature, files in
+//!   `~/.eva/models/whisper/`, see `whisper.rs`)
+//! - Vosk small models (`offline-stt` feature, `~/.eva/models/<model>`)
+//!
+//! `default_recognizer` picks the first one available. Only Whisper can
+//! identify the spoken language; with Vosk the session stays on the
+//! configured one.
 
+use std::error::Error;
 use std::path::Path;
 
+use crate::language_id::LanguageId;
+
 #[cfg(feature = "offline-stt")]
-use vosk::{Model, Recognizer};
+use vosk::{CompleteResult, Model, Recognizer};
 
 /// Supported languages for STT
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -34,6 +46,19 @@ pub enum Language {
 }
 
 impl Language {
+    pub const ALL: [Language; 10] = [
+        Language::EnglishUS,
+        Language::PortugueseBR,
+        Language::Spanish,
+        Language::French,
+        Language::German,
+        Language::Italian,
+        Language::Russian,
+        Language::Chinese,
+        Language::Japanese,
+        Language::Korean,
+    ];
+
     /// Get the Vosk model name for this language
     pub fn model_name(&self) -> &'static str {
         match self {
@@ -58,6 +83,44 @@ impl Language {
         )
     }
 
+    /// Language for a profile tag such as `pt-BR` or `en`
+    pub fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.to_lowercase();
+        Self::ALL.into_iter().find(|l| l.code() == primary)
+    }
+
+    /// BCP-47 tag used by profiles and TTS voices
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Language::EnglishUS => "en-US",
+            Language::PortugueseBR => "pt-BR",
+            Language::Spanish => "es-ES",
+            Language::French => "fr-FR",
+            Language::German => "de-DE",
+            Language::Italian => "it-IT",
+            Language::Russian => "ru-RU",
+            Language::Chinese => "zh-CN",
+            Language::Japanese => "ja-JP",
+            Language::Korean => "ko-KR",
+        }
+    }
+
+    /// English name (for prompts and the UI)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Language::EnglishUS => "English",
+            Language::PortugueseBR => "Brazilian Portuguese",
+            Language::Spanish => "Spanish",
+            Language::French => "French",
+            Language::German => "German",
+            Language::Italian => "Italian",
+            Language::Russian => "Russian",
+            Language::Chinese => "Chinese",
+            Language::Japanese => "Japanese",
+            Language::Korean => "Korean",
+        }
+    }
+
     /// Get language code (ISO 639-1)
     pub fn code(&self) -> &'static str {
         match self {
@@ -133,6 +196,68 @@ pub struct WordInfo {
     pub confidence: f32,
 }
 
+/// Spoken-language identification result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LanguageGuess {
+    pub language: Language,
+    /// Probability among the candidate languages (0.0 to 1.0)
+    pub probability: f32,
+}
+
+/// A speech-to-text backend fed one utterance at a time
+pub trait SpeechRecognizer: Send {
+    /// Backend name for logs
+    fn name(&self) -> &'static str;
+
+    fn language(&self) -> Language;
+
+    /// Switch language (may reload models)
+    fn set_language(&mut self, language: Language) -> Result<(), Box<dyn Error>>;
+
+    /// Feed 16kHz mono audio of the current utterance
+    fn accept(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>>;
+
+    /// Interim transcription of the utterance so far (`None` = not available yet)
+    fn partial(&mut self) -> Result<Option<RecognitionResult>, Box<dyn Error>>;
+
+    /// Finish the utterance: final text, alternatives and word timings
+    fn finish(&mut self) -> Result<RecognitionResult, Box<dyn Error>>;
+
+    /// Drop the current utterance
+    fn reset(&mut self);
+
+    /// Which of `candidates` is spoken in `samples` (`None` = backend can't tell)
+    fn detect_language(
+        &mut self,
+        _samples: &[f32],
+        _candidates: &[Language],
+    ) -> Result<Option<LanguageGuess>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+/// Best available recognizer: Whisper when its model is installed, else Vosk
+///
+/// `report` hears about an installed Whisper model that failed to load.
+pub fn default_recognizer(
+    config: SttConfig,
+    mut report: impl FnMut(String),
+) -> Result<Box<dyn SpeechRecognizer>, Box<dyn Error>> {
+    #[cfg(feature = "onnx")]
+    if let Some(dir) = crate::whisper::whisper_model_dir() {
+        match crate::whisper::WhisperRecognizer::load(&dir, &config) {
+            Ok(whisper) => return Ok(Box::new(whisper)),
+            Err(e) => report(format!("⚠️  Whisper model {} unavailable: {}", dir.display(), e)),
+        }
+    }
+    #[cfg(not(feature = "onnx"))]
+    let _ = &mut report;
+
+    let mut engine = SttEngine::with_config(config);
+    engine.init()?;
+    Ok(Box::new(engine))
+}
+
 /// Offline Speech-to-Text engine using Vosk
 pub struct SttEngine {
     config: SttConfig,
@@ -205,14 +330,11 @@ impl SttEngine {
             println!("[STT] Vosk model loaded: {}", self.config.language.model_name());
         }
 
-        #[cfg(not(feature = "offline-stt"))]
-        {
-            // Fallback mode - mark as ready but use placeholder
-            println!("[STT] Warning: Vosk feature not enabled, using fallback mode");
-            self.is_ready = true;
+        if cfg!(feature = "offline-stt") {
+            Ok(())
+        } else {
+            Err("Offline STT not compiled in (build with --features offline-stt)".into())
         }
-
-        Ok(())
     }
 
     /// Check if the engine is ready
@@ -220,10 +342,10 @@ impl SttEngine {
         self.is_ready
     }
 
-    /// Recognize speech from audio samples
+    /// Feed audio without finishing the utterance
     ///
     /// Audio should be 16-bit PCM at the configured sample rate
-    pub fn recognize(&mut self, audio: &[i16]) -> Result<RecognitionResult, Box<dyn std::error::Error>> {
+    pub fn accept_pcm(&mut self, audio: &[i16]) -> Result<(), Box<dyn std::error::Error>> {
         if !self.is_ready {
             return Err("STT engine not initialized".into());
         }
@@ -231,20 +353,40 @@ impl SttEngine {
         #[cfg(feature = "offline-stt")]
         {
             if let Some(ref mut recognizer) = self.recognizer {
-                // Convert i16 samples to bytes
-                let bytes: Vec<u8> = audio
-                    .iter()
-                    .flat_map(|&sample| sample.to_le_bytes())
-                    .collect();
+                recognizer.accept_waveform(audio);
+            }
+        }
 
-                // Process audio
-                recognizer.accept_waveform(&bytes);
+        #[cfg(not(feature = "offline-stt"))]
+        let _ = audio;
 
-                // Get result
-                let result_json = recognizer.final_result().single().unwrap_or_default();
+        Ok(())
+    }
 
-                // Parse result
-                return self.parse_result(&result_json.text, false);
+    /// Finish the utterance and return its transcription
+    pub fn final_result(&mut self) -> Result<RecognitionResult, Box<dyn std::error::Error>> {
+        if !self.is_ready {
+            return Err("STT engine not initialized".into());
+        }
+
+        #[cfg(feature = "offline-stt")]
+        {
+            if let Some(ref mut recognizer) = self.recognizer {
+                // Multiple alternatives come back when max_alternatives > 1
+                return match recognizer.final_result() {
+                    CompleteResult::Single(single) => self.parse_result(single.text, false),
+                    CompleteResult::Multiple(multiple) => {
+                        let mut alternatives: Vec<(String, f32)> = multiple
+                            .alternatives
+                            .iter()
+                            .map(|a| (a.text.trim().to_string(), a.confidence))
+                            .collect();
+                        let best = if alternatives.is_empty() { String::new() } else { alternatives.remove(0).0 };
+                        let mut result = self.parse_result(&best, false)?;
+                        result.alternatives = alternatives;
+                        Ok(result)
+                    }
+                };
             }
         }
 
@@ -258,15 +400,17 @@ impl SttEngine {
         })
     }
 
+    /// Recognize a complete utterance
+    ///
+    /// Audio should be 16-bit PCM at the configured sample rate
+    pub fn recognize(&mut self, audio: &[i16]) -> Result<RecognitionResult, Box<dyn std::error::Error>> {
+        self.accept_pcm(audio)?;
+        self.final_result()
+    }
+
     /// Recognize speech from f32 samples (normalized -1.0 to 1.0)
     pub fn recognize_f32(&mut self, audio: &[f32]) -> Result<RecognitionResult, Box<dyn std::error::Error>> {
-        // Convert f32 to i16
-        let i16_samples: Vec<i16> = audio
-            .iter()
-            .map(|&sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
-            .collect();
-
-        self.recognize(&i16_samples)
+        self.recognize(&to_pcm16(audio))
     }
 
     /// Get partial (interim) recognition result
@@ -292,34 +436,6 @@ impl SttEngine {
         })
     }
 
-    /// Reset the recognizer for a new utterance
-    pub fn reset(&mut self) {
-        #[cfg(feature = "offline-stt")]
-        {
-            if let Some(ref mut recognizer) = self.recognizer {
-                recognizer.reset();
-            }
-        }
-    }
-
-    /// Change the recognition language
-    pub fn set_language(&mut self, language: Language) -> Result<(), Box<dyn std::error::Error>> {
-        if language == self.config.language {
-            return Ok(());
-        }
-
-        self.config.language = language;
-        self.is_ready = false;
-
-        // Re-initialize with new language
-        self.init()
-    }
-
-    /// Get current language
-    pub fn language(&self) -> Language {
-        self.config.language
-    }
-
     /// Parse Vosk result JSON
     fn parse_result(&self, text: &str, is_partial: bool) -> Result<RecognitionResult, Box<dyn std::error::Error>> {
         // Clean up the text
@@ -399,96 +515,213 @@ impl Default for SttEngine {
     }
 }
 
+impl SpeechRecognizer for SttEngine {
+    fn name(&self) -> &'static str {
+        "vosk"
+    }
+
+    fn language(&self) -> Language {
+        self.config.language
+    }
+
+    fn set_language(&mut self, language: Language) -> Result<(), Box<dyn Error>> {
+        if language == self.config.language {
+            return Ok(());
+        }
+
+        self.config.language = language;
+        self.is_ready = false;
+
+        // Re-initialize with new language
+        self.init()
+    }
+
+    fn accept(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        self.accept_pcm(&to_pcm16(samples))
+    }
+
+    fn partial(&mut self) -> Result<Option<RecognitionResult>, Box<dyn Error>> {
+        self.get_partial().map(Some)
+    }
+
+    fn finish(&mut self) -> Result<RecognitionResult, Box<dyn Error>> {
+        self.final_result()
+    }
+
+    /// Reset the recognizer for a new utterance
+    fn reset(&mut self) {
+        #[cfg(feature = "offline-stt")]
+        {
+            if let Some(ref mut recognizer) = self.recognizer {
+                recognizer.reset();
+            }
+        }
+    }
+}
+
+fn to_pcm16(samples: &[f32]) -> Vec<i16> {
+    samples
+        .iter()
+        .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+        .collect()
+}
+
 /// Streaming STT session for real-time recognition
+///
+/// With language identification enabled, the first seconds of each
+/// utterance are held back until the language is known, so the recognizer
+/// never sees audio in the wrong language.
 pub struct StreamingSttSession {
-    engine: SttEngine,
+    recognizer: Box<dyn SpeechRecognizer>,
     /// Buffer for accumulating audio
-    audio_buffer: Vec<i16>,
+    audio_buffer: Vec<f32>,
     /// Chunk size for processing
     chunk_size: usize,
     /// Last partial result
     last_partial: String,
+    language_id: Option<LanguageId>,
+    /// Language already identified for the current utterance
+    language_decided: bool,
+    /// Detector output for the current (or last) utterance
+    language_guess: Option<LanguageGuess>,
+    /// Problems worth telling the user about, until `take_warnings`
+    warnings: Vec<String>,
 }
 
 impl StreamingSttSession {
     /// Create a new streaming session
-    pub fn new(mut engine: SttEngine) -> Result<Self, Box<dyn std::error::Error>> {
-        if !engine.is_ready() {
-            engine.init()?;
-        }
-
+    pub fn new(recognizer: Box<dyn SpeechRecognizer>) -> Self {
         // Process in 100ms chunks (1600 samples at 16kHz)
-        let chunk_size = (engine.config.sample_rate as usize) / 10;
+        let chunk_size = 1600;
 
-        Ok(Self {
-            engine,
+        Self {
+            recognizer,
             audio_buffer: Vec::with_capacity(chunk_size * 10),
             chunk_size,
             last_partial: String::new(),
-        })
+            language_id: None,
+            language_decided: false,
+            language_guess: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Backend doing the recognition
+    pub fn recognizer(&self) -> &dyn SpeechRecognizer {
+        self.recognizer.as_ref()
+    }
+
+    /// Identify the spoken language at the start of each utterance (`None` = off)
+    pub fn set_language_id(&mut self, language_id: Option<LanguageId>) {
+        self.language_id = language_id;
+    }
+
+    /// What language identification heard in the current (or last) utterance
+    pub fn language_guess(&self) -> Option<LanguageGuess> {
+        self.language_guess
+    }
+
+    /// Warnings collected since the last call (failed language switches)
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
     }
 
     /// Add audio samples to the session
     pub fn add_audio(&mut self, samples: &[i16]) {
-        self.audio_buffer.extend_from_slice(samples);
+        self.audio_buffer
+            .extend(samples.iter().map(|&s| s as f32 / i16::MAX as f32));
     }
 
     /// Add f32 audio samples
     pub fn add_audio_f32(&mut self, samples: &[f32]) {
-        let i16_samples: Vec<i16> = samples
-            .iter()
-            .map(|&s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
-            .collect();
-        self.add_audio(&i16_samples);
+        self.audio_buffer.extend_from_slice(samples);
     }
 
     /// Process buffered audio and get partial result
     pub fn process(&mut self) -> Result<Option<RecognitionResult>, Box<dyn std::error::Error>> {
-        if self.audio_buffer.len() < self.chunk_size {
+        if !self.identify_language(false) || self.audio_buffer.len() < self.chunk_size {
             return Ok(None);
         }
 
         // Process available chunks
-        while self.audio_buffer.len() >= self.chunk_size {
-            let chunk: Vec<i16> = self.audio_buffer.drain(..self.chunk_size).collect();
-            let _ = self.engine.recognize(&chunk)?;
-        }
-
-        // Get partial result
-        let partial = self.engine.get_partial()?;
-
-        // Only return if changed
-        if partial.text != self.last_partial {
-            self.last_partial = partial.text.clone();
-            Ok(Some(partial))
-        } else {
-            Ok(None)
+        let whole = self.audio_buffer.len() / self.chunk_size * self.chunk_size;
+        let chunk: Vec<f32> = self.audio_buffer.drain(..whole).collect();
+        self.recognizer.accept(&chunk)?;
+
+        // Only return the partial result if it changed
+        match self.recognizer.partial()? {
+            Some(partial) if partial.text != self.last_partial => {
+                self.last_partial = partial.text.clone();
+                Ok(Some(partial))
+            }
+            _ => Ok(None),
         }
     }
 
     /// Finalize and get complete result
     pub fn finalize(&mut self) -> Result<RecognitionResult, Box<dyn std::error::Error>> {
+        // Short utterances are identified from whatever was said
+        self.identify_language(true);
+
         // Process any remaining audio
         if !self.audio_buffer.is_empty() {
-            let remaining: Vec<i16> = self.audio_buffer.drain(..).collect();
-            let _ = self.engine.recognize(&remaining)?;
+            let remaining: Vec<f32> = self.audio_buffer.drain(..).collect();
+            self.recognizer.accept(&remaining)?;
         }
 
         // Get final result
-        let result = self.engine.recognize(&[])?;
+        let result = self.recognizer.finish();
 
         // Reset for next utterance
-        self.engine.reset();
+        self.recognizer.reset();
         self.last_partial.clear();
+        self.language_decided = false;
 
-        Ok(result)
+        result
     }
 
     /// Reset the session
     pub fn reset(&mut self) {
         self.audio_buffer.clear();
         self.last_partial.clear();
-        self.engine.reset();
+        self.recognizer.reset();
+        self.language_decided = false;
+        self.language_guess = None;
+    }
+
+    /// Pick the utterance's language once enough audio is buffered
+    ///
+    /// Returns false while still waiting for audio. Detection errors keep
+    /// the current language and are reported through `take_warnings`.
+    fn identify_language(&mut self, finishing: bool) -> bool {
+        let Some(ref language_id) = self.language_id else {
+            return true;
+        };
+        if self.language_decided {
+            return true;
+        }
+        if self.audio_buffer.is_empty() || (!finishing && self.audio_buffer.len() < language_id.probe_samples()) {
+            return finishing;
+        }
+
+        self.language_decided = true;
+        let probe = &self.audio_buffer[..self.audio_buffer.len().min(language_id.probe_samples())];
+        let current = self.recognizer.language();
+        self.language_guess = match self.recognizer.detect_language(probe, language_id.allowed()) {
+            Ok(guess) => guess,
+            Err(e) => {
+                self.warnings.push(format!("Language identification failed: {}", e));
+                None
+            }
+        };
+
+        let language = language_id.choose(current, self.language_guess);
+        if language != current {
+            if let Err(e) = self.recognizer.set_language(language) {
+                self.warnings.push(format!("Could not switch to {}: {}", language.tag(), e));
+            }
+        }
+        true
     }
 }
 
@@ -503,6 +736,14 @@ mod tests {
         assert_eq!(Language::Spanish.code(), "es");
     }
 
+    #[test]
+    fn test_language_from_tag() {
+        assert_eq!(Language::from_tag("pt-BR"), Some(Language::PortugueseBR));
+        assert_eq!(Language::from_tag("en_US"), Some(Language::EnglishUS));
+        assert_eq!(Language::from_tag("DE"), Some(Language::German));
+        assert_eq!(Language::from_tag("xx"), None);
+    }
+
     #[test]
     fn test_model_names() {
         assert!(Language::EnglishUS.model_name().contains("en-us"));
@@ -553,16 +794,133 @@ mod tests {
         assert!(instructions.contains("vosk-model"));
     }
 
+    /// Records what it is fed and always "hears" English
+    struct ScriptedRecognizer {
+        language: Language,
+        accepted: usize,
+        language_when_fed: Option<Language>,
+        /// Languages without an installed model
+        missing: Vec<Language>,
+    }
+
+    impl SpeechRecognizer for ScriptedRecognizer {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        fn language(&self) -> Language {
+            self.language
+        }
+
+        fn set_language(&mut self, language: Language) -> Result<(), Box<dyn Error>> {
+            if self.missing.contains(&language) {
+                return Err("model not installed".into());
+            }
+            self.language = language;
+            Ok(())
+        }
+
+        fn accept(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+            self.accepted += samples.len();
+            self.language_when_fed.get_or_insert(self.language);
+            Ok(())
+        }
+
+        fn partial(&mut self) -> Result<Option<RecognitionResult>, Box<dyn Error>> {
+            Ok(None)
+        }
+
+        fn finish(&mut self) -> Result<RecognitionResult, Box<dyn Error>> {
+            Ok(RecognitionResult {
+                // Language the audio was first fed in, and how much audio
+                text: format!("{} {}", self.language_when_fed.map_or("-", |l| l.code()), self.accepted),
+                confidence: 1.0,
+                alternatives: Vec::new(),
+                words: Vec::new(),
+                is_partial: false,
+            })
+        }
+
+        fn reset(&mut self) {
+            self.accepted = 0;
+        }
+
+        fn detect_language(&mut self, _samples: &[f32], candidates: &[Language]) -> Result<Option<LanguageGuess>, Box<dyn Error>> {
+            Ok(candidates.contains(&Language::EnglishUS).then_some(LanguageGuess {
+                language: Language::EnglishUS,
+                probability: 0.9,
+            }))
+        }
+    }
+
+    #[test]
+    fn test_language_identified_before_feeding() {
+        let recognizer = ScriptedRecognizer {
+            language: Language::PortugueseBR,
+            accepted: 0,
+            language_when_fed: None,
+            missing: Vec::new(),
+        };
+        let mut session = StreamingSttSession::new(Box::new(recognizer));
+        let language_id = LanguageId::new(vec![Language::PortugueseBR, Language::EnglishUS]);
+        let probe = language_id.probe_samples();
+        session.set_language_id(Some(language_id));
+
+        // Audio is held back until there is enough to identify the language
+        session.add_audio_f32(&vec![0.0; probe / 2]);
+        session.process().unwrap();
+        assert_eq!(session.audio_buffer.len(), probe / 2);
+        assert_eq!(session.language_guess(), None);
+
+        session.add_audio_f32(&vec![0.0; probe / 2]);
+        session.process().unwrap();
+        assert_eq!(session.recognizer().language(), Language::EnglishUS);
+        assert_eq!(session.language_guess().map(|g| g.language), Some(Language::EnglishUS));
+
+        let result = session.finalize().unwrap();
+        assert_eq!(result.text, format!("en {}", probe));
+    }
+
+    #[test]
+    fn test_short_utterance_identified_on_finalize() {
+        let recognizer = ScriptedRecognizer {
+            language: Language::PortugueseBR,
+            accepted: 0,
+            language_when_fed: None,
+            missing: Vec::new(),
+        };
+        let mut session = StreamingSttSession::new(Box::new(recognizer));
+        session.set_language_id(Some(LanguageId::new(vec![Language::PortugueseBR, Language::EnglishUS])));
+
+        session.add_audio_f32(&[0.0; 1600]);
+        assert!(session.process().unwrap().is_none());
+        assert_eq!(session.finalize().unwrap().text, "en 1600");
+    }
+
+    #[test]
+    fn test_failed_language_switch_is_reported() {
+        let recognizer = ScriptedRecognizer {
+            language: Language::PortugueseBR,
+            accepted: 0,
+            language_when_fed: None,
+            missing: vec![Language::EnglishUS],
+        };
+        let mut session = StreamingSttSession::new(Box::new(recognizer));
+        session.set_language_id(Some(LanguageId::new(vec![Language::PortugueseBR, Language::EnglishUS])));
+
+        session.add_audio_f32(&[0.0; 1600]);
+        assert_eq!(session.finalize().unwrap().text, "pt 1600");
+        let warnings = session.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("en-US"));
+        assert!(session.take_warnings().is_empty());
+    }
+
     #[test]
     fn test_streaming_session_buffer() {
         // Create a mock session without initializing (to avoid model dependency)
         let engine = SttEngine::new();
-        let mut session = StreamingSttSession {
-            engine,
-            audio_buffer: Vec::new(),
-            chunk_size: 1600,
-            last_partial: String::new(),
-        };
+        let mut session = StreamingSttSession::new(Box::new(engine));
 
         // Add some audio
         let samples = vec![0i16; 800];
@@ -1,11 +1,16 @@
 use crate::status_indicator::{EvaStatus, StatusIndicator};
 use crate::statistics::Statistics;
+use crate::playback::PlaybackProgress;
 use std::io::{self, Write};
 
 /// Simple terminal UI (without heavy TUI dependencies)
 pub struct TerminalUI {
     conversation_log: Vec<String>,
     max_log_size: usize,
+    /// What the user is saying right now (interim transcription)
+    partial_transcript: Option<String>,
+    /// How far EVA's spoken answer has got
+    playback: Option<String>,
 }
 
 impl TerminalUI {
@@ -14,6 +19,8 @@ impl TerminalUI {
         Ok(Self {
             conversation_log: Vec::new(),
             max_log_size: 50,
+            partial_transcript: None,
+            playback: None,
         })
     }
 
@@ -38,6 +45,9 @@ impl TerminalUI {
         
         println!("┌─ Status ────────────────────────────────────────────────┐");
         println!("│ {}{}\x1B[0m", color, status_str);
+        if let Some(ref playback) = self.playback {
+            println!("│ {}", playback);
+        }
         println!("└─────────────────────────────────────────────────────────┘");
         println!();
     }
@@ -69,7 +79,9 @@ impl TerminalUI {
             println!("│ {}", msg);
         }
         
-        if self.conversation_log.is_empty() {
+        if let Some(ref partial) = self.partial_transcript {
+            println!("│ 👤 User: {} …", partial);
+        } else if self.conversation_log.is_empty() {
             println!("│ (No messages yet)");
         }
         
@@ -106,6 +118,18 @@ impl TerminalUI {
         self.add_message(format!("🤖 EVA: {}", message));
     }
 
+    /// Show the interim transcription of the current utterance
+    /// (empty text clears it)
+    pub fn set_partial_transcript(&mut self, text: &str) {
+        let text = text.trim();
+        self.partial_transcript = if text.is_empty() { None } else { Some(text.to_string()) };
+    }
+
+    /// Show how far the answer has played (`None` hides it)
+    pub fn set_playback(&mut self, progress: Option<&PlaybackProgress>) {
+        self.playback = progress.map(|p| p.to_string());
+    }
+
     /// Add system message
     pub fn add_system_message(&mut self, message: &str) {
         self.add_message(format!("ℹ️  System: {}", message));
@@ -168,6 +192,34 @@ mod tests {
         assert!(ui.conversation_log[0].contains("User:"));
     }
 
+    #[test]
+    fn test_partial_transcript() {
+        let mut ui = TerminalUI::new().unwrap();
+        ui.set_partial_transcript("  open the ");
+        assert_eq!(ui.partial_transcript.as_deref(), Some("open the"));
+
+        ui.set_partial_transcript("");
+        assert!(ui.partial_transcript.is_none());
+        assert!(ui.conversation_log.is_empty());
+    }
+
+    #[test]
+    fn test_playback_progress() {
+        let mut ui = TerminalUI::new().unwrap();
+        let progress = PlaybackProgress {
+            state: crate::playback::PlaybackState::Buffering,
+            played: 0.0,
+            buffered: 0.05,
+            target: 0.12,
+            underruns: 0,
+        };
+        ui.set_playback(Some(&progress));
+        assert_eq!(ui.playback.as_deref(), Some("⏳ Buffering 0.05s / 0.12s"));
+
+        ui.set_playback(None);
+        assert!(ui.playback.is_none());
+    }
+
     #[test]
     fn test_eva_message() {
         let mut ui = TerminalUI::new().unwrap();
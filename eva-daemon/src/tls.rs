@@ -1,5 +1,12 @@
-use rustls::{ClientConfig, RootCertStore};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use sha2::{Digest, Sha256};
+use std::path::Path;
 use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
 use tokio_rustls::{TlsConnector, client::TlsStream};
 
@@ -9,14 +16,21 @@ pub struct TlsManager {
 
 impl TlsManager {
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_options(None, &[])
+    }
+
+    /// Trust the CAs in `ca_bundle` (PEM) on top of the system ones; with
+    /// `pins` ("sha256/<base64>" SPKI hashes) the server's chain must also
+    /// contain one of those keys
+    pub fn with_options(ca_bundle: Option<&Path>, pins: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
         // Carregar certificados raiz (CA certificates)
         let mut root_store = RootCertStore::empty();
-        
+
         // Usar certificados do sistema
         for cert in rustls_native_certs::load_native_certs()? {
             root_store.add(cert).ok();
         }
-        
+
         // Fallback: usar certificados embutidos do webpki
         root_store.extend(
             webpki_roots::TLS_SERVER_ROOTS
@@ -24,10 +38,37 @@ impl TlsManager {
                 .cloned()
         );
 
+        // CAs privadas (redes corporativas, servidores próprios)
+        if let Some(path) = ca_bundle {
+            let pem = std::fs::read(path).map_err(|e| format!("CA bundle {}: {}", path.display(), e))?;
+            let mut added = 0;
+            for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+                root_store.add(cert?)?;
+                added += 1;
+            }
+            if added == 0 {
+                return Err(format!("No certificates in CA bundle {}", path.display()).into());
+            }
+        }
+
         // Configurar cliente TLS
-        let config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+        let config = if pins.is_empty() {
+            ClientConfig::builder()
+                .with_root_certificates(root_store)
+                .with_no_client_auth()
+        } else {
+            for pin in pins {
+                validate_pin(pin)?;
+            }
+            let verifier = PinnedVerifier {
+                inner: WebPkiServerVerifier::builder(Arc::new(root_store)).build()?,
+                pins: pins.to_vec(),
+            };
+            ClientConfig::builder()
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(verifier))
+                .with_no_client_auth()
+        };
 
         let connector = TlsConnector::from(Arc::new(config));
 
@@ -43,23 +84,249 @@ impl TlsManager {
         let addr = format!("{}:{}", domain, port);
         let tcp_stream = TcpStream::connect(&addr).await?;
 
-        // Fazer handshake TLS
-        let server_name = rustls::pki_types::ServerName::try_from(domain.to_string())?;
-        let tls_stream = self.connector.connect(server_name, tcp_stream).await?;
+        self.handshake(domain, tcp_stream).await
+    }
+
+    /// TLS handshake over an already open stream (e.g. a proxy tunnel)
+    pub async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        domain: &str,
+        stream: S,
+    ) -> Result<TlsStream<S>, Box<dyn std::error::Error>> {
+        let server_name = ServerName::try_from(domain.to_string())?;
+        let tls_stream = self.connector.connect(server_name, stream).await?;
 
         Ok(tls_stream)
     }
 }
 
+/// Pin of a DER certificate's public key: "sha256/" + base64 of the
+/// SHA-256 of its SubjectPublicKeyInfo (the format curl and HPKP use)
+pub fn spki_pin(cert_der: &[u8]) -> Result<String, String> {
+    let spki = subject_public_key_info(cert_der).ok_or("Malformed certificate")?;
+    Ok(format!("sha256/{}", BASE64.encode(Sha256::digest(spki))))
+}
+
+/// Comma-separated pins, each checked with `validate_pin`
+pub fn parse_pins(list: &str) -> Result<Vec<String>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|pin| !pin.is_empty())
+        .map(|pin| validate_pin(pin).map(|_| pin.to_string()))
+        .collect()
+}
+
+/// Check that `pin` looks like "sha256/<base64 of 32 bytes>"
+pub fn validate_pin(pin: &str) -> Result<(), String> {
+    let hash = pin
+        .strip_prefix("sha256/")
+        .ok_or_else(|| format!("Pin '{}' must start with sha256/", pin))?;
+    match BASE64.decode(hash) {
+        Ok(bytes) if bytes.len() == 32 => Ok(()),
+        _ => Err(format!("Pin '{}' is not a base64 SHA-256 hash", pin)),
+    }
+}
+
+/// One DER element and what follows it
+struct Der<'a> {
+    tag: u8,
+    /// The whole element, header included
+    element: &'a [u8],
+    content: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn der_element(data: &[u8]) -> Option<Der<'_>> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let count = first & 0x7F;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let len = data.get(2..2 + count)?.iter().fold(0usize, |len, b| (len << 8) | *b as usize);
+        (len, 2 + count)
+    };
+    let end = header.checked_add(len)?;
+    Some(Der {
+        tag,
+        element: &data[..end.min(data.len())],
+        content: data.get(header..end)?,
+        rest: &data[end..],
+    })
+}
+
+/// The SubjectPublicKeyInfo element of a certificate
+///
+/// Certificate = SEQUENCE { tbsCertificate, ... } and tbsCertificate =
+/// SEQUENCE { [0] version (optional), serialNumber, signature, issuer,
+/// validity, subject, subjectPublicKeyInfo, ... }
+fn subject_public_key_info(cert_der: &[u8]) -> Option<&[u8]> {
+    let certificate = der_element(cert_der)?.content;
+    let mut tbs = der_element(certificate)?.content;
+
+    if tbs.first() == Some(&0xA0) {
+        tbs = der_element(tbs)?.rest;
+    }
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        tbs = der_element(tbs)?.rest;
+    }
+    let spki = der_element(tbs)?;
+    (spki.tag == 0x30).then_some(spki.element)
+}
+
+/// Normal chain validation, then the chain must contain a pinned key
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<String>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|cert| spki_pin(cert).ok())
+            .any(|pin| self.pins.contains(&pin));
+        if pinned {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General("server key does not match any pin".to_string()))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    /// A localhost/127.0.0.1 certificate signed by the test CA (`fixtures/tls`)
+    const SERVER_PEM: &str = include_str!("../fixtures/tls/server.pem");
+    const SERVER_KEY: &str = include_str!("../fixtures/tls/server.key");
+    /// `openssl x509 -pubkey | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`
+    pub const SERVER_PIN: &str = "sha256/KX7GyOiBHvkIUAuDc7tydGsctz0exrYVGzGJnI1XoTU=";
+
+    pub fn ca_bundle_path() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/tls/ca.pem")
+    }
+
+    /// Acceptor presenting the fixture certificate
+    pub fn test_acceptor() -> TlsAcceptor {
+        let certs = rustls_pemfile::certs(&mut SERVER_PEM.as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+        let key = rustls_pemfile::private_key(&mut SERVER_KEY.as_bytes()).unwrap().unwrap();
+        let config = rustls::ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap();
+        TlsAcceptor::from(Arc::new(config))
+    }
+
+    /// TLS echo server on a free local port
+    async fn tls_echo_server() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = test_acceptor();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut buf = [0u8; 64];
+                    while let Ok(n) = tls.read(&mut buf).await {
+                        if n == 0 || tls.write_all(&buf[..n]).await.is_err() {
+                            break;
+                        }
+                        tls.flush().await.ok();
+                    }
+                });
+            }
+        });
+        port
+    }
 
     #[tokio::test]
     async fn test_tls_connection() {
         let tls = TlsManager::new().expect("Failed to create TLS manager");
-        
+
         let result = tls.connect("google.com", 443).await;
         assert!(result.is_ok(), "TLS connection should succeed");
     }
+
+    #[test]
+    fn test_spki_pin() {
+        let cert = rustls_pemfile::certs(&mut SERVER_PEM.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(spki_pin(&cert).unwrap(), SERVER_PIN);
+        assert!(spki_pin(&cert[..40]).is_err());
+
+        assert!(validate_pin(SERVER_PIN).is_ok());
+        assert!(validate_pin("sha1/KX7GyOiBHvkIUAuDc7tydGsctz0exrYVGzGJnI1XoTU=").is_err());
+        assert!(validate_pin("sha256/short").is_err());
+        assert_eq!(parse_pins(&format!(" {}, ", SERVER_PIN)), Ok(vec![SERVER_PIN.to_string()]));
+        assert!(parse_pins("sha256/short").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_private_ca_and_pins() {
+        let port = tls_echo_server().await;
+        let ca = ca_bundle_path();
+
+        // Unknown issuer without the bundle
+        assert!(TlsManager::new().unwrap().connect("localhost", port).await.is_err());
+
+        let mut stream = TlsManager::with_options(Some(&ca), &[]).unwrap().connect("localhost", port).await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"ping");
+
+        let pinned = TlsManager::with_options(Some(&ca), &[SERVER_PIN.to_string()]).unwrap();
+        assert!(pinned.connect("localhost", port).await.is_ok());
+
+        let other_pin = format!("sha256/{}", BASE64.encode([7u8; 32]));
+        let mispinned = TlsManager::with_options(Some(&ca), &[other_pin]).unwrap();
+        assert!(mispinned.connect("localhost", port).await.is_err());
+
+        assert!(TlsManager::with_options(Some(Path::new("/nonexistent/ca.pem")), &[]).is_err());
+    }
 }
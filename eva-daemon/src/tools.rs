@@ -0,0 +1,275 @@
+//! Tool calling bridge
+//!
+//! Lets the backend's model act on the machine. Every `CommandIntent`
+//! operation is declared as a function (`declarations`) in the backend
+//! setup; a call coming back becomes the same intent (`intent_from_call`)
+//! and runs through `CommandExecutor`, whose sandbox checks paths and
+//! programs exactly as for spoken commands. The executor's output (or
+//! error) is the call's result.
+//!
+//! The model is remote, so it gets less than the user's voice: it can only
+//! start the programs listed in the profile's `allowed_programs` (or open a
+//! web address), and it can't stop processes (`remote_allowed`).
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::command_parser::{
+    CommandIntent, FileOperation, NetworkOperation, ProcessOperation, SystemOperation, TextOperation,
+};
+
+/// A function the model may call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolDeclaration {
+    pub name: String,
+    pub description: String,
+    /// JSON schema of the arguments (`None` = takes none)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Value>,
+}
+
+/// Argument: (name, schema type, description, required)
+type Param = (&'static str, &'static str, &'static str, bool);
+
+/// One entry per operation; `tool_name` and `intent_from_call` must agree
+const TOOLS: &[(&str, &str, &[Param])] = &[
+    (
+        "create_file",
+        "Create a file in EVA's sandbox folder, optionally with text content",
+        &[
+            ("path", "STRING", "File path inside the sandbox", true),
+            ("content", "STRING", "Text to write", false),
+        ],
+    ),
+    ("delete_file", "Delete a file from the sandbox folder", &[("path", "STRING", "File path inside the sandbox", true)]),
+    (
+        "copy_file",
+        "Copy a file within the sandbox folder",
+        &[("from", "STRING", "Source path", true), ("to", "STRING", "Destination path", true)],
+    ),
+    (
+        "move_file",
+        "Move or rename a file within the sandbox folder",
+        &[("from", "STRING", "Source path", true), ("to", "STRING", "Destination path", true)],
+    ),
+    (
+        "list_files",
+        "List the files in a sandbox folder (the sandbox root when no path is given)",
+        &[("path", "STRING", "Folder inside the sandbox", false)],
+    ),
+    ("read_file", "Read a text file from the sandbox folder", &[("path", "STRING", "File path inside the sandbox", true)]),
+    ("list_processes", "List running processes", &[]),
+    (
+        "start_program",
+        "Open one of the applications the user allowed, by name, or a web address",
+        &[("name", "STRING", "Program name or http(s) URL", true)],
+    ),
+    ("memory_info", "Report memory usage", &[]),
+    ("disk_info", "Report free disk space", &[]),
+    ("cpu_info", "Report the CPU", &[]),
+    ("uptime", "Report how long the system has been up", &[]),
+    ("get_ip_address", "Report this machine's IP address", &[]),
+    ("ping", "Check whether a host answers", &[("host", "STRING", "Host name or address", true)]),
+    ("type_text", "Type text into the focused window", &[("text", "STRING", "Text to type", true)]),
+    ("select_all", "Select everything in the focused window", &[]),
+    ("copy_selection", "Copy the selection to the clipboard", &[]),
+    ("paste", "Paste the clipboard", &[]),
+];
+
+/// Every tool, ready for the backend setup
+pub fn declarations() -> Vec<ToolDeclaration> {
+    TOOLS
+        .iter()
+        .map(|(name, description, params)| ToolDeclaration {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters: (!params.is_empty()).then(|| schema(params)),
+        })
+        .collect()
+}
+
+fn schema(params: &[Param]) -> Value {
+    let properties: Map<String, Value> = params
+        .iter()
+        .map(|(name, kind, description, _)| (name.to_string(), json!({"type": kind, "description": description})))
+        .collect();
+    let required: Vec<&str> = params.iter().filter(|p| p.3).map(|p| p.0).collect();
+    json!({"type": "OBJECT", "properties": properties, "required": required})
+}
+
+/// Tool that performs `intent` (`None` for `Unknown` and stopping a process)
+pub fn tool_name(intent: &CommandIntent) -> Option<&'static str> {
+    Some(match intent {
+        CommandIntent::File(op) => match op {
+            FileOperation::Create { .. } => "create_file",
+            FileOperation::Delete { .. } => "delete_file",
+            FileOperation::Copy { .. } => "copy_file",
+            FileOperation::Move { .. } => "move_file",
+            FileOperation::List { .. } => "list_files",
+            FileOperation::Read { .. } => "read_file",
+        },
+        CommandIntent::Process(op) => match op {
+            ProcessOperation::List => "list_processes",
+            ProcessOperation::Start { .. } => "start_program",
+            ProcessOperation::Kill { .. } => return None,
+        },
+        CommandIntent::System(op) => match op {
+            SystemOperation::MemoryInfo => "memory_info",
+            SystemOperation::DiskInfo => "disk_info",
+            SystemOperation::CpuInfo => "cpu_info",
+            SystemOperation::Uptime => "uptime",
+        },
+        CommandIntent::Network(op) => match op {
+            NetworkOperation::GetIP => "get_ip_address",
+            NetworkOperation::Ping { .. } => "ping",
+        },
+        CommandIntent::Text(op) => match op {
+            TextOperation::Type { .. } => "type_text",
+            TextOperation::Select => "select_all",
+            TextOperation::Copy => "copy_selection",
+            TextOperation::Paste => "paste",
+        },
+        CommandIntent::Unknown => return None,
+    })
+}
+
+/// Whether the model may run `intent`
+///
+/// Programs must be in `allowed_programs` (case-insensitive) unless they are
+/// http(s) URLs; the model never stops processes.
+pub fn remote_allowed(intent: &CommandIntent, allowed_programs: &[String]) -> Result<(), String> {
+    match intent {
+        CommandIntent::Process(ProcessOperation::Start { name }) => {
+            let is_url = name.starts_with("http://") || name.starts_with("https://");
+            if is_url || allowed_programs.iter().any(|p| p.trim().eq_ignore_ascii_case(name)) {
+                Ok(())
+            } else {
+                Err(format!("Refusing to start '{}': not in the profile's allowed_programs", name))
+            }
+        }
+        CommandIntent::Process(ProcessOperation::Kill { .. }) => {
+            Err("Refusing to stop a process for the model".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The intent a tool call asks for, if the model may run it (`remote_allowed`)
+pub fn intent_from_call(name: &str, args: &Value, allowed_programs: &[String]) -> Result<CommandIntent, String> {
+    let optional = |key: &str| {
+        args.get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+    };
+    let required = |key: &str| optional(key).ok_or_else(|| format!("{}: missing '{}'", name, key));
+
+    let intent = match name {
+        "create_file" => CommandIntent::File(FileOperation::Create {
+            path: required("path")?,
+            content: args.get("content").and_then(Value::as_str).map(str::to_string),
+        }),
+        "delete_file" => CommandIntent::File(FileOperation::Delete { path: required("path")? }),
+        "copy_file" => CommandIntent::File(FileOperation::Copy { from: required("from")?, to: required("to")? }),
+        "move_file" => CommandIntent::File(FileOperation::Move { from: required("from")?, to: required("to")? }),
+        "list_files" => CommandIntent::File(FileOperation::List { path: optional("path") }),
+        "read_file" => CommandIntent::File(FileOperation::Read { path: required("path")? }),
+        "list_processes" => CommandIntent::Process(ProcessOperation::List),
+        "start_program" => CommandIntent::Process(ProcessOperation::Start { name: required("name")? }),
+        "memory_info" => CommandIntent::System(SystemOperation::MemoryInfo),
+        "disk_info" => CommandIntent::System(SystemOperation::DiskInfo),
+        "cpu_info" => CommandIntent::System(SystemOperation::CpuInfo),
+        "uptime" => CommandIntent::System(SystemOperation::Uptime),
+        "get_ip_address" => CommandIntent::Network(NetworkOperation::GetIP),
+        "ping" => CommandIntent::Network(NetworkOperation::Ping { host: required("host")? }),
+        "type_text" => CommandIntent::Text(TextOperation::Type { text: required("text")? }),
+        "select_all" => CommandIntent::Text(TextOperation::Select),
+        "copy_selection" => CommandIntent::Text(TextOperation::Copy),
+        "paste" => CommandIntent::Text(TextOperation::Paste),
+        _ => return Err(format!("Unknown tool: {}", name)),
+    };
+    remote_allowed(&intent, allowed_programs)?;
+    Ok(intent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_declaration_maps_to_its_intent() {
+        for declaration in declarations() {
+            // Fill every declared argument with a plausible value
+            let mut args = Map::new();
+            if let Some(properties) = declaration.parameters.as_ref().and_then(|p| p["properties"].as_object()) {
+                for (key, spec) in properties {
+                    let value = if spec["type"] == "INTEGER" { json!(4242) } else { json!("notes.txt") };
+                    args.insert(key.clone(), value);
+                }
+            }
+
+            let intent = intent_from_call(&declaration.name, &Value::Object(args), &["notes.txt".to_string()])
+                .unwrap_or_else(|e| panic!("{}: {}", declaration.name, e));
+            assert_eq!(tool_name(&intent), Some(declaration.name.as_str()));
+        }
+        assert_eq!(tool_name(&CommandIntent::Unknown), None);
+        assert_eq!(tool_name(&CommandIntent::Process(ProcessOperation::Kill { pid: 42 })), None);
+    }
+
+    #[test]
+    fn test_declaration_schema() {
+        let tools = declarations();
+        let create = tools.iter().find(|t| t.name == "create_file").unwrap();
+        let parameters = create.parameters.as_ref().unwrap();
+        assert_eq!(parameters["type"], "OBJECT");
+        assert_eq!(parameters["properties"]["path"]["type"], "STRING");
+        assert_eq!(parameters["required"], json!(["path"]));
+
+        // No arguments, no schema
+        let uptime = serde_json::to_value(tools.iter().find(|t| t.name == "uptime").unwrap()).unwrap();
+        assert_eq!(uptime, json!({"name": "uptime", "description": "Report how long the system has been up"}));
+    }
+
+    #[test]
+    fn test_intent_from_call() {
+        assert_eq!(
+            intent_from_call("list_files", &json!({"path": "documents"}), &[]),
+            Ok(CommandIntent::File(FileOperation::List { path: Some("documents".to_string()) }))
+        );
+        assert_eq!(
+            intent_from_call("list_files", &json!({}), &[]),
+            Ok(CommandIntent::File(FileOperation::List { path: None }))
+        );
+
+        assert!(intent_from_call("read_file", &json!({"path": " "}), &[]).is_err());
+        assert_eq!(intent_from_call("format_disk", &json!({}), &[]), Err("Unknown tool: format_disk".to_string()));
+        assert_eq!(
+            intent_from_call("kill_process", &json!({"pid": 42}), &[]),
+            Err("Unknown tool: kill_process".to_string())
+        );
+    }
+
+    #[test]
+    fn test_start_program_needs_allowed_program() {
+        let allowed = vec!["Firefox".to_string(), "code".to_string()];
+        let start = |name: &str| intent_from_call("start_program", &json!({ "name": name }), &allowed);
+
+        assert_eq!(
+            start("firefox"),
+            Ok(CommandIntent::Process(ProcessOperation::Start { name: "firefox".to_string() }))
+        );
+        assert!(start("https://example.com").is_ok());
+        for name in ["poweroff", "reboot", "shutdown", "halt"] {
+            assert!(start(name).unwrap_err().contains("allowed_programs"), "{}", name);
+        }
+        assert!(intent_from_call("start_program", &json!({"name": "poweroff"}), &[]).is_err());
+    }
+
+    #[test]
+    fn test_remote_allowed() {
+        let kill = CommandIntent::Process(ProcessOperation::Kill { pid: 4242 });
+        assert!(remote_allowed(&kill, &[]).is_err());
+        assert!(remote_allowed(&CommandIntent::System(SystemOperation::Uptime), &[]).is_ok());
+    }
+}
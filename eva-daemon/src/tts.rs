@@ -0,0 +1,542 @@
+//! Local Text-to-Speech
+//!
+//! Gives EVA a voice when EVA-Mind is unreachable (demo/offline mode).
+//! Engines synthesize one sentence at a time to mono f32 PCM at
+//! `audio::SAMPLE_RATE`; `AudioPlayer::speak_text` streams the sentences to
+//! the speaker and can be interrupted between and during them.
+//!
+//! Backends, in order of preference:
+//! - Piper ONNX voice (`~/.eva/voices/<lang>.onnx` + `.onnx.json`, needs the
+//!   `onnx` feature for ONNX Runtime)
+//! - espeak-ng / espeak command-line synthesizer
+//! - text only (prints, no audio)
+
+use std::error::Error;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "onnx")]
+use ort::{session::Session, value::Tensor};
+#[cfg(feature = "onnx")]
+use std::path::{Path, PathBuf};
+
+use crate::audio::SAMPLE_RATE;
+use crate::resample::resample;
+use crate::user_profile::UserProfile;
+
+/// espeak speaking rate (words per minute) at voice speed 1.0
+const ESPEAK_BASE_WPM: f32 = 175.0;
+
+/// Voice parameters taken from the user profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoiceSettings {
+    /// BCP-47 language tag (e.g. "pt-BR")
+    pub language: String,
+    /// Speaking rate multiplier (0.5 - 2.0)
+    pub speed: f32,
+}
+
+impl VoiceSettings {
+    pub fn from_profile(profile: &UserProfile) -> Self {
+        Self {
+            language: profile.language.clone(),
+            speed: profile.voice_speed.clamp(0.5, 2.0),
+        }
+    }
+
+    /// espeak voice name ("pt-BR" -> "pt-br")
+    pub fn espeak_voice(&self) -> String {
+        let lang = self.language.trim().to_lowercase().replace('_', "-");
+        if lang.is_empty() {
+            "en-us".to_string()
+        } else {
+            lang
+        }
+    }
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            language: "en-US".to_string(),
+            speed: 1.0,
+        }
+    }
+}
+
+/// A speech synthesizer
+pub trait TtsEngine: Send {
+    /// Engine name (for logs and the UI)
+    fn name(&self) -> &str;
+
+    /// Synthesize one sentence as mono f32 PCM at `audio::SAMPLE_RATE`
+    fn synthesize(&mut self, text: &str, voice: &VoiceSettings) -> Result<Vec<f32>, Box<dyn Error>>;
+}
+
+/// Pick the best engine available on this machine
+///
+/// Also returns a warning for the UI when a better voice failed to load.
+pub fn default_engine(voice: &VoiceSettings) -> (Box<dyn TtsEngine>, Option<String>) {
+    #[allow(unused_mut)]
+    let mut warning = None;
+    #[cfg(feature = "onnx")]
+    if let Some(path) = piper_voice_path(voice) {
+        match PiperEngine::load(&path) {
+            Ok(engine) => return (Box::new(engine), None),
+            Err(e) => warning = Some(format!("Piper voice {} unavailable: {}", path.display(), e)),
+        }
+    }
+    #[cfg(not(feature = "onnx"))]
+    let _ = voice;
+
+    if let Some(engine) = EspeakEngine::detect() {
+        return (Box::new(engine), warning);
+    }
+
+    (Box::new(TextOnlyEngine), warning)
+}
+
+/// Directory holding downloaded voices (`~/.eva/voices`)
+#[cfg(feature = "onnx")]
+pub fn voices_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let home = std::env::var("USERPROFILE").ok()?;
+    #[cfg(not(target_os = "windows"))]
+    let home = std::env::var("HOME").ok()?;
+
+    Some(PathBuf::from(home).join(".eva").join("voices"))
+}
+
+/// Piper voice for the language: `<lang>.onnx`, then `<primary subtag>.onnx`
+#[cfg(feature = "onnx")]
+pub fn piper_voice_path(voice: &VoiceSettings) -> Option<PathBuf> {
+    let dir = voices_dir()?;
+    let lang = voice.espeak_voice();
+    let primary = lang.split('-').next().unwrap_or(&lang).to_string();
+
+    [lang, primary]
+        .iter()
+        .map(|name| dir.join(format!("{}.onnx", name)))
+        .find(|path| path.exists())
+}
+
+/// Split text into sentences for streaming synthesis
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\n' {
+            push_sentence(&mut sentences, &mut current);
+            continue;
+        }
+
+        current.push(c);
+        if matches!(c, '.' | '!' | '?' | '…' | ';') {
+            // Keep runs like "?!" or "..." together; split only at whitespace
+            match chars.peek() {
+                Some(next) if next.is_whitespace() => push_sentence(&mut sentences, &mut current),
+                None => push_sentence(&mut sentences, &mut current),
+                _ => {}
+            }
+        }
+    }
+    push_sentence(&mut sentences, &mut current);
+
+    sentences
+}
+
+fn push_sentence(sentences: &mut Vec<String>, current: &mut String) {
+    let sentence = current.trim();
+    if sentence.chars().any(|c| c.is_alphanumeric()) {
+        sentences.push(sentence.to_string());
+    }
+    current.clear();
+}
+
+/// Shared flag used to cut speech short
+#[derive(Debug, Clone, Default)]
+pub struct SpeechInterrupt(Arc<AtomicBool>);
+
+impl SpeechInterrupt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the current `speak_text` call to stop
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Clear the flag before speaking again
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Fallback engine: no audio (the text is still shown in the UI)
+pub struct TextOnlyEngine;
+
+impl TtsEngine for TextOnlyEngine {
+    fn name(&self) -> &str {
+        "text"
+    }
+
+    fn synthesize(&mut self, _text: &str, _voice: &VoiceSettings) -> Result<Vec<f32>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+}
+
+/// espeak-ng (or classic espeak) run as a subprocess
+pub struct EspeakEngine {
+    program: String,
+}
+
+impl EspeakEngine {
+    /// Find `espeak-ng` or `espeak` on the PATH
+    pub fn detect() -> Option<Self> {
+        ["espeak-ng", "espeak"].iter().find_map(|program| {
+            let found = Command::new(program)
+                .arg("--version")
+                .output()
+                .map(|out| out.status.success())
+                .unwrap_or(false);
+            found.then(|| Self { program: program.to_string() })
+        })
+    }
+
+    /// espeak `-s` value for a speed multiplier
+    fn words_per_minute(speed: f32) -> u32 {
+        (ESPEAK_BASE_WPM * speed.clamp(0.5, 2.0)).round() as u32
+    }
+
+    /// IPA phonemes for a sentence (used by the Piper backend)
+    #[cfg(feature = "onnx")]
+    pub fn phonemize(&self, text: &str, voice: &str) -> Result<String, Box<dyn Error>> {
+        let output = Command::new(&self.program)
+            .args(["-q", "--ipa", "-v", voice, "--", text])
+            .output()?;
+        if !output.status.success() {
+            return Err(format!("{} --ipa failed: {}", self.program, String::from_utf8_lossy(&output.stderr)).into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+impl TtsEngine for EspeakEngine {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn synthesize(&mut self, text: &str, voice: &VoiceSettings) -> Result<Vec<f32>, Box<dyn Error>> {
+        let output = Command::new(&self.program)
+            .arg("--stdout")
+            .args(["-v", &voice.espeak_voice()])
+            .args(["-s", &Self::words_per_minute(voice.speed).to_string()])
+            .arg("--")
+            .arg(text)
+            .output()?;
+
+        if !output.status.success() {
+            return Err(format!("{} failed: {}", self.program, String::from_utf8_lossy(&output.stderr)).into());
+        }
+
+        let (samples, rate) = decode_wav(&output.stdout)?;
+        Ok(resample(&samples, rate, SAMPLE_RATE))
+    }
+}
+
+/// Piper voice settings (`<voice>.onnx.json`)
+#[cfg(feature = "onnx")]
+struct PiperConfig {
+    sample_rate: u32,
+    espeak_voice: Option<String>,
+    noise_scale: f32,
+    length_scale: f32,
+    noise_w: f32,
+    phoneme_ids: std::collections::HashMap<String, Vec<i64>>,
+}
+
+#[cfg(feature = "onnx")]
+impl PiperConfig {
+    fn from_json(json: &serde_json::Value) -> Result<Self, Box<dyn Error>> {
+        let map = json["phoneme_id_map"].as_object().ok_or("Missing phoneme_id_map")?;
+        let phoneme_ids = map
+            .iter()
+            .map(|(k, v)| {
+                let ids = v.as_array().map(|a| a.iter().filter_map(|i| i.as_i64()).collect()).unwrap_or_default();
+                (k.clone(), ids)
+            })
+            .collect();
+
+        let inference = &json["inference"];
+        Ok(Self {
+            sample_rate: json["audio"]["sample_rate"].as_u64().unwrap_or(22050) as u32,
+            espeak_voice: json["espeak"]["voice"].as_str().map(str::to_string),
+            noise_scale: inference["noise_scale"].as_f64().unwrap_or(0.667) as f32,
+            length_scale: inference["length_scale"].as_f64().unwrap_or(1.0) as f32,
+            noise_w: inference["noise_w"].as_f64().unwrap_or(0.8) as f32,
+            phoneme_ids,
+        })
+    }
+
+    /// Piper id sequence: BOS, each phoneme followed by PAD, EOS
+    fn ids(&self, phonemes: &str) -> Vec<i64> {
+        let lookup = |s: &str| self.phoneme_ids.get(s).cloned().unwrap_or_default();
+        let pad = lookup("_");
+
+        let mut ids = lookup("^");
+        ids.extend(&pad);
+        for c in phonemes.chars() {
+            if let Some(id) = self.phoneme_ids.get(c.to_string().as_str()) {
+                ids.extend(id);
+                ids.extend(&pad);
+            }
+        }
+        ids.extend(lookup("$"));
+        ids
+    }
+}
+
+/// Piper (VITS) ONNX voice
+#[cfg(feature = "onnx")]
+pub struct PiperEngine {
+    session: Session,
+    config: PiperConfig,
+    phonemizer: Option<EspeakEngine>,
+}
+
+#[cfg(feature = "onnx")]
+impl PiperEngine {
+    /// Load `voice.onnx` and its `voice.onnx.json` config
+    pub fn load(model_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let config_path = PathBuf::from(format!("{}.json", model_path.display()));
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&config_path)?)?;
+        let config = PiperConfig::from_json(&json)?;
+
+        let session = Session::builder()?.commit_from_file(model_path)?;
+
+        Ok(Self {
+            session,
+            config,
+            phonemizer: EspeakEngine::detect(),
+        })
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl TtsEngine for PiperEngine {
+    fn name(&self) -> &str {
+        "piper"
+    }
+
+    fn synthesize(&mut self, text: &str, voice: &VoiceSettings) -> Result<Vec<f32>, Box<dyn Error>> {
+        // Voices trained on raw text (or no espeak installed) take characters directly
+        let phonemes = match &self.phonemizer {
+            Some(espeak) => {
+                let espeak_voice = self.config.espeak_voice.clone().unwrap_or_else(|| voice.espeak_voice());
+                espeak.phonemize(text, &espeak_voice)?
+            }
+            None => text.to_lowercase(),
+        };
+
+        let ids = self.config.ids(&phonemes);
+        let len = ids.len() as i64;
+        let scales = vec![
+            self.config.noise_scale,
+            self.config.length_scale / voice.speed.clamp(0.5, 2.0),
+            self.config.noise_w,
+        ];
+
+        let outputs = self.session.run(ort::inputs![
+            "input" => Tensor::from_array(([1usize, ids.len()], ids))?,
+            "input_lengths" => Tensor::from_array(([1usize], vec![len]))?,
+            "scales" => Tensor::from_array(([3usize], scales))?,
+        ])?;
+        let (_, audio) = outputs[0].try_extract_tensor::<f32>()?;
+
+        Ok(resample(audio, self.config.sample_rate, SAMPLE_RATE))
+    }
+}
+
+/// Decode a 16-bit PCM WAV file to mono f32 samples and its sample rate
+///
+/// Streaming writers (espeak `--stdout`) leave the RIFF/data sizes unset,
+/// so an oversized data chunk is read to the end of the buffer.
+pub fn decode_wav(bytes: &[u8]) -> Result<(Vec<f32>, u32), Box<dyn Error>> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a WAV file".into());
+    }
+
+    let mut pos = 12;
+    let mut format: Option<(u16, u16, u32, u16)> = None;
+
+    while pos + 8 <= bytes.len() {
+        let id = &bytes[pos..pos + 4];
+        let size = u32::from_le_bytes([bytes[pos + 4], bytes[pos + 5], bytes[pos + 6], bytes[pos + 7]]) as usize;
+        let body_start = pos + 8;
+        let body_end = body_start.saturating_add(size).min(bytes.len());
+        let body = &bytes[body_start..body_end];
+
+        match id {
+            b"fmt " if body.len() >= 16 => {
+                format = Some((
+                    u16::from_le_bytes([body[0], body[1]]),
+                    u16::from_le_bytes([body[2], body[3]]),
+                    u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+                    u16::from_le_bytes([body[14], body[15]]),
+                ));
+            }
+            b"data" => {
+                let (audio_format, channels, rate, bits) = format.ok_or("WAV data before fmt chunk")?;
+                if audio_format != 1 || bits != 16 || channels == 0 {
+                    return Err(format!("Unsupported WAV format {} ({} bits)", audio_format, bits).into());
+                }
+
+                let frame = 2 * channels as usize;
+                let samples = body
+                    .chunks_exact(frame)
+                    .map(|f| {
+                        let sum: f32 = f
+                            .chunks_exact(2)
+                            .map(|s| i16::from_le_bytes([s[0], s[1]]) as f32 / i16::MAX as f32)
+                            .sum();
+                        sum / channels as f32
+                    })
+                    .collect();
+                return Ok((samples, rate));
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        pos = body_start.saturating_add(size + (size & 1));
+    }
+
+    Err("WAV file has no data chunk".into())
+}
+
+/// Encode mono f32 samples as a 16-bit PCM WAV file
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data = samples_to_pcm16(samples);
+    let mut out = Vec::with_capacity(44 + data.len());
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&data);
+    out
+}
+
+/// Convert f32 samples to 16-bit little-endian PCM
+pub fn samples_to_pcm16(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|&s| ((s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav(rate: u32, channels: u16, samples: &[i16], data_size: Option<u32>) -> Vec<u8> {
+        let data: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&rate.to_le_bytes());
+        out.extend_from_slice(&(rate * 2 * channels as u32).to_le_bytes());
+        out.extend_from_slice(&(2 * channels).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_size.unwrap_or(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&data);
+        out
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        let sentences = split_sentences("Olá! Tudo bem? Isto é um teste... Fim\nNova linha");
+        assert_eq!(sentences, vec!["Olá!", "Tudo bem?", "Isto é um teste...", "Fim", "Nova linha"]);
+        assert!(split_sentences("  ... \n").is_empty());
+        assert_eq!(split_sentences("Version 1.5 is out."), vec!["Version 1.5 is out."]);
+    }
+
+    #[test]
+    fn test_voice_from_profile() {
+        let mut profile = UserProfile::default();
+        profile.language = "pt_BR".to_string();
+        profile.voice_speed = 3.0;
+
+        let voice = VoiceSettings::from_profile(&profile);
+        assert_eq!(voice.espeak_voice(), "pt-br");
+        assert_eq!(voice.speed, 2.0);
+        assert_eq!(EspeakEngine::words_per_minute(voice.speed), 350);
+        assert_eq!(EspeakEngine::words_per_minute(1.0), 175);
+    }
+
+    #[test]
+    fn test_decode_wav_stereo_to_mono() {
+        let bytes = wav(22050, 2, &[i16::MAX, 0, -i16::MAX, -i16::MAX], None);
+        let (samples, rate) = decode_wav(&bytes).unwrap();
+        assert_eq!(rate, 22050);
+        assert_eq!(samples.len(), 2);
+        assert!((samples[0] - 0.5).abs() < 1e-3);
+        assert!((samples[1] + 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_decode_streaming_wav() {
+        // espeak --stdout leaves the data size at 0xFFFFFFFF
+        let bytes = wav(16000, 1, &[100, 200, 300], Some(u32::MAX));
+        let (samples, _) = decode_wav(&bytes).unwrap();
+        assert_eq!(samples.len(), 3);
+        assert!(decode_wav(b"not a wav").is_err());
+    }
+
+    #[test]
+    fn test_encode_wav_roundtrip() {
+        let bytes = encode_wav(&[0.5, -0.25, 0.0], 16000);
+        let (samples, rate) = decode_wav(&bytes).unwrap();
+        assert_eq!(rate, 16000);
+        assert_eq!(samples.len(), 3);
+        assert!((samples[0] - 0.5).abs() < 1e-3 && (samples[1] + 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_speech_interrupt() {
+        let interrupt = SpeechInterrupt::new();
+        let handle = interrupt.clone();
+        assert!(!interrupt.is_interrupted());
+        handle.interrupt();
+        assert!(interrupt.is_interrupted());
+        interrupt.reset();
+        assert!(!handle.is_interrupted());
+    }
+
+    #[test]
+    fn test_text_only_engine() {
+        let mut engine = TextOnlyEngine;
+        assert!(engine.synthesize("Hello", &VoiceSettings::default()).unwrap().is_empty());
+        assert_eq!(samples_to_pcm16(&[1.0, -2.0]), vec![0xFF, 0x7F, 0x01, 0x80]);
+    }
+}
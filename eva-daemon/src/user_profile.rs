@@ -3,14 +3,45 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
+use crate::audio_processing::ProcessingConfig;
+use crate::barge_in;
+
 /// User profile with preferences and settings
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserProfile {
     pub name: String,
     pub language: String,
+    /// Other languages the user speaks (tags like "en-US"); EVA detects
+    /// which one is spoken and switches recognition and voice
+    #[serde(default)]
+    pub allowed_languages: Vec<String>,
     pub voice_speed: f32,
     pub wake_word_sensitivity: f32,
+    /// Quietest microphone level (RMS) that can interrupt EVA while she speaks
+    #[serde(default = "default_barge_in_energy")]
+    pub barge_in_energy: f32,
+    /// Enrolled wake phrase (`--enroll-wake-word`); `None` = "Hey EVA"
     pub custom_wake_word: Option<String>,
+    /// Voice activity detector: "adaptive", "silero" or "energy"
+    /// (`None` = best available)
+    #[serde(default)]
+    pub vad: Option<String>,
+    /// Microphone to open by name (`None` = system default)
+    #[serde(default)]
+    pub input_device: Option<String>,
+    /// Speaker to open by name (`None` = system default)
+    #[serde(default)]
+    pub output_device: Option<String>,
+    /// Echo cancellation, noise suppression and AGC switches
+    #[serde(default)]
+    pub audio_processing: ProcessingConfig,
+    /// Identity from the credential store this user registers as
+    /// (`None` = the store's default)
+    #[serde(default)]
+    pub identity: Option<String>,
+    /// Programs EVA-Mind/Gemini may start (voice commands aren't limited)
+    #[serde(default)]
+    pub allowed_programs: Vec<String>,
     pub preferences: HashMap<String, String>,
 }
 
@@ -20,9 +51,17 @@ impl UserProfile {
         Self {
             name: "User".to_string(),
             language: "en-US".to_string(),
+            allowed_languages: Vec::new(),
             voice_speed: 1.0,
             wake_word_sensitivity: 0.6,
+            barge_in_energy: barge_in::DEFAULT_MIN_ENERGY,
             custom_wake_word: None,
+            vad: None,
+            input_device: None,
+            output_device: None,
+            audio_processing: ProcessingConfig::default(),
+            identity: None,
+            allowed_programs: Vec::new(),
             preferences: HashMap::new(),
         }
     }
@@ -85,11 +124,26 @@ impl UserProfile {
         self.language = language.to_string();
     }
 
+    /// Set the other languages the user speaks
+    pub fn set_allowed_languages(&mut self, languages: &[&str]) {
+        self.allowed_languages = languages
+            .iter()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+
     /// Update wake word sensitivity
     pub fn set_wake_word_sensitivity(&mut self, sensitivity: f32) {
         self.wake_word_sensitivity = sensitivity.clamp(0.0, 1.0);
     }
 
+    /// Update how loud the user must be to interrupt EVA
+    pub fn set_barge_in_energy(&mut self, energy: f32) {
+        self.barge_in_energy = energy.clamp(0.0, 1.0);
+    }
+
     /// Update voice speed
     pub fn set_voice_speed(&mut self, speed: f32) {
         self.voice_speed = speed.clamp(0.5, 2.0);
@@ -99,6 +153,17 @@ impl UserProfile {
     pub fn set_custom_wake_word(&mut self, wake_word: Option<String>) {
         self.custom_wake_word = wake_word;
     }
+
+    /// Set the microphone and speaker by name (empty = system default)
+    pub fn set_audio_devices(&mut self, input: Option<&str>, output: Option<&str>) {
+        let name = |n: Option<&str>| n.map(str::trim).filter(|n| !n.is_empty()).map(str::to_string);
+        self.input_device = name(input);
+        self.output_device = name(output);
+    }
+}
+
+fn default_barge_in_energy() -> f32 {
+    barge_in::DEFAULT_MIN_ENERGY
 }
 
 impl Default for UserProfile {
@@ -136,6 +201,9 @@ mod tests {
         let mut profile = UserProfile::default();
         profile.set_language("pt-BR");
         assert_eq!(profile.language, "pt-BR");
+
+        profile.set_allowed_languages(&["en-US", " "]);
+        assert_eq!(profile.allowed_languages, vec!["en-US".to_string()]);
     }
 
     #[test]
@@ -147,6 +215,9 @@ mod tests {
         
         profile.set_wake_word_sensitivity(-0.5);
         assert_eq!(profile.wake_word_sensitivity, 0.0);
+
+        profile.set_barge_in_energy(2.0);
+        assert_eq!(profile.barge_in_energy, 1.0);
     }
 
     #[test]
@@ -159,4 +230,21 @@ mod tests {
         profile.set_voice_speed(0.1);
         assert_eq!(profile.voice_speed, 0.5);
     }
+
+    #[test]
+    fn test_audio_devices_optional_in_saved_profile() {
+        let json = r#"{"name":"Ana","language":"pt-BR","voice_speed":1.0,"wake_word_sensitivity":0.6,"custom_wake_word":null,"preferences":{}}"#;
+        let mut profile: UserProfile = serde_json::from_str(json).unwrap();
+        assert_eq!(profile.input_device, None);
+        assert_eq!(profile.identity, None);
+        assert_eq!(profile.vad, None);
+        assert_eq!(profile.barge_in_energy, 0.03);
+        assert!(profile.allowed_languages.is_empty());
+        assert!(profile.allowed_programs.is_empty());
+        assert!(profile.audio_processing.echo_cancellation);
+
+        profile.set_audio_devices(Some("USB Headset"), Some(" "));
+        assert_eq!(profile.input_device.as_deref(), Some("USB Headset"));
+        assert_eq!(profile.output_device, None);
+    }
 }
@@ -1,4 +1,429 @@
-/// Voice Activity Detection (VAD) module
+//! Voice activity detection
+//!
+//! A `VoiceActivityDetector` turns audio into a speech probability per chunk;
+//! `VoiceActivity` adds utterance endpointing on top (start/end events with
+//! hysteresis and hangover). Backends: `AdaptiveVad` (spectral, adaptive
+//! noise floor), `SileroVad` (ONNX, with the `onnx` feature) and the
+//! original energy + ZCR `VAD`, picked with the profile's `vad` setting.
+
+use std::error::Error;
+#[cfg(feature = "onnx")]
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "onnx")]
+use ort::{session::Session, value::Tensor};
+
+use crate::audio::SAMPLE_RATE;
+use crate::fft::Fft;
+
+/// Spectral analysis frame (32ms)
+const FRAME_SIZE: usize = 512;
+/// Speech band used for the energy measurement
+const BAND_LOW_HZ: f32 = 250.0;
+const BAND_HIGH_HZ: f32 = 4000.0;
+/// Frames used to seed the noise floor (~320ms)
+const LEARNING_FRAMES: usize = 10;
+/// SNR (dB) at which speech probability is 0.5, and the slope around it
+const SNR_MIDPOINT_DB: f32 = 6.0;
+const SNR_SLOPE_DB: f32 = 2.0;
+/// Band energy below this is silence whatever the noise floor (RMS ~0.001)
+const MIN_SPEECH_ENERGY: f32 = 1e-6;
+/// Lowest noise floor (keeps the SNR finite in digital silence)
+const MIN_NOISE_FLOOR: f32 = 1e-9;
+/// Per-frame growth of the noise floor during speech (~2dB/s)
+const NOISE_RISE: f32 = 1.015;
+
+/// Speech probability for audio frames
+pub trait VoiceActivityDetector: Send {
+    fn name(&self) -> &str;
+
+    /// Probability (0..1) that `samples` (mono, `SAMPLE_RATE`) contain speech
+    fn speech_probability(&mut self, samples: &[f32]) -> Result<f32, Box<dyn Error>>;
+
+    /// Forget adapted state (noise floor, recurrent state)
+    fn reset(&mut self);
+}
+
+/// Detector by name ("adaptive", "silero" or "energy")
+///
+/// `None` picks the best available: Silero if its model is installed, else
+/// `AdaptiveVad`. Also returns a warning for the UI when the requested
+/// detector could not be used.
+pub fn detector(name: Option<&str>) -> (Box<dyn VoiceActivityDetector>, Option<String>) {
+    let warning = match name {
+        Some("adaptive") => return (Box::new(AdaptiveVad::new()), None),
+        Some("energy") => return (Box::new(VAD::new()), None),
+        None | Some("silero") => match silero() {
+            Ok(Some(vad)) => return (vad, None),
+            Ok(None) if name.is_none() => None,
+            Ok(None) => Some("Silero VAD is not installed".to_string()),
+            Err(e) => Some(e),
+        },
+        Some(other) => Some(format!("Unknown VAD '{}'", other)),
+    };
+
+    (Box::new(AdaptiveVad::new()), warning.map(|w| format!("{}; using adaptive VAD", w)))
+}
+
+/// Silero VAD, if this build supports it and its model is installed
+fn silero() -> Result<Option<Box<dyn VoiceActivityDetector>>, String> {
+    #[cfg(feature = "onnx")]
+    if let Some(path) = silero_model_path() {
+        return match SileroVad::load(&path) {
+            Ok(vad) => Ok(Some(Box::new(vad))),
+            Err(e) => Err(format!("Silero model {} unavailable: {}", path.display(), e)),
+        };
+    }
+
+    Ok(None)
+}
+
+/// Silero VAD model location (`~/.eva/models/silero_vad.onnx`), if installed
+#[cfg(feature = "onnx")]
+pub fn silero_model_path() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let home = std::env::var("USERPROFILE").ok()?;
+    #[cfg(not(target_os = "windows"))]
+    let home = std::env::var("HOME").ok()?;
+
+    let path = PathBuf::from(home).join(".eva").join("models").join("silero_vad.onnx");
+    path.exists().then_some(path)
+}
+
+/// Utterance boundary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VadEvent {
+    SpeechStart,
+    SpeechEnd,
+}
+
+/// Result of one chunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VadFrame {
+    pub probability: f32,
+    /// Inside an utterance (including the hangover after it)
+    pub in_speech: bool,
+    pub event: Option<VadEvent>,
+}
+
+/// Endpointing thresholds
+#[derive(Debug, Clone, Copy)]
+pub struct EndpointConfig {
+    /// Probability that counts as speech when idle
+    pub start_threshold: f32,
+    /// Probability below which an utterance counts as paused (lower = stickier)
+    pub end_threshold: f32,
+    /// Speech needed before `SpeechStart` (ignores clicks and coughs)
+    pub min_speech_ms: u32,
+    /// Pause needed before `SpeechEnd` (lets the user breathe mid-sentence)
+    pub hangover_ms: u32,
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            start_threshold: 0.6,
+            end_threshold: 0.35,
+            min_speech_ms: 200,
+            hangover_ms: 700,
+        }
+    }
+}
+
+/// Turns per-chunk probabilities into utterance start/end events
+pub struct Endpointer {
+    config: EndpointConfig,
+    in_speech: bool,
+    speech_ms: u32,
+    silence_ms: u32,
+}
+
+impl Endpointer {
+    pub fn new(config: EndpointConfig) -> Self {
+        Self { config, in_speech: false, speech_ms: 0, silence_ms: 0 }
+    }
+
+    /// Feed the probability of the next `duration_ms` of audio
+    pub fn process(&mut self, probability: f32, duration_ms: u32) -> VadFrame {
+        let mut event = None;
+
+        if !self.in_speech {
+            if probability >= self.config.start_threshold {
+                self.speech_ms += duration_ms;
+                if self.speech_ms >= self.config.min_speech_ms {
+                    self.in_speech = true;
+                    self.silence_ms = 0;
+                    event = Some(VadEvent::SpeechStart);
+                }
+            } else {
+                self.speech_ms = 0;
+            }
+        } else if probability < self.config.end_threshold {
+            self.silence_ms += duration_ms;
+            if self.silence_ms >= self.config.hangover_ms {
+                self.in_speech = false;
+                self.speech_ms = 0;
+                event = Some(VadEvent::SpeechEnd);
+            }
+        } else {
+            self.silence_ms = 0;
+        }
+
+        VadFrame { probability, in_speech: self.in_speech, event }
+    }
+
+    pub fn in_speech(&self) -> bool {
+        self.in_speech
+    }
+
+    pub fn reset(&mut self) {
+        self.in_speech = false;
+        self.speech_ms = 0;
+        self.silence_ms = 0;
+    }
+}
+
+/// Detector plus endpointing, as used by the conversation loop
+pub struct VoiceActivity {
+    detector: Box<dyn VoiceActivityDetector>,
+    endpointer: Endpointer,
+    /// Why the detector was replaced, until the UI takes it
+    warning: Option<String>,
+}
+
+impl VoiceActivity {
+    pub fn new(detector: Box<dyn VoiceActivityDetector>) -> Self {
+        Self::with_config(detector, EndpointConfig::default())
+    }
+
+    pub fn with_config(detector: Box<dyn VoiceActivityDetector>, config: EndpointConfig) -> Self {
+        Self { detector, endpointer: Endpointer::new(config), warning: None }
+    }
+
+    pub fn detector_name(&self) -> &str {
+        self.detector.name()
+    }
+
+    /// Classify one chunk and report utterance boundaries
+    ///
+    /// If the detector fails (e.g. a broken ONNX model) it is replaced by
+    /// `AdaptiveVad` so endpointing keeps working; see `take_warning`.
+    pub fn process(&mut self, samples: &[f32]) -> VadFrame {
+        let probability = match self.detector.speech_probability(samples) {
+            Ok(p) => p,
+            Err(e) => {
+                self.warning = Some(format!("VAD {} failed ({}), switching to adaptive VAD", self.detector.name(), e));
+                self.detector = Box::new(AdaptiveVad::new());
+                self.detector.speech_probability(samples).unwrap_or(0.0)
+            }
+        };
+
+        let duration_ms = (samples.len() as u64 * 1000 / SAMPLE_RATE as u64) as u32;
+        self.endpointer.process(probability, duration_ms)
+    }
+
+    pub fn is_speaking(&self) -> bool {
+        self.endpointer.in_speech()
+    }
+
+    /// Warning from a detector failure, if any (reported once)
+    pub fn take_warning(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+
+    /// Start a new utterance (the learned noise floor is kept)
+    pub fn reset(&mut self) {
+        self.endpointer.reset();
+    }
+}
+
+/// Spectral VAD with an adaptive noise floor
+///
+/// Measures speech-band energy in 32ms frames against a noise floor that
+/// follows the quiet frames, so it works for quiet speakers and in noisy
+/// rooms alike. Spectral flatness tells voiced speech from broadband noise.
+pub struct AdaptiveVad {
+    fft: Fft,
+    window: Vec<f32>,
+    window_power: f32,
+    band: (usize, usize),
+    pending: Vec<f32>,
+    noise_floor: f32,
+    frames_seen: usize,
+    last_probability: f32,
+}
+
+impl AdaptiveVad {
+    pub fn new() -> Self {
+        let window: Vec<f32> = (0..FRAME_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+        let bin_hz = SAMPLE_RATE as f32 / FRAME_SIZE as f32;
+
+        Self {
+            fft: Fft::new(FRAME_SIZE),
+            window_power: window.iter().map(|w| w * w).sum(),
+            window,
+            band: ((BAND_LOW_HZ / bin_hz).round() as usize, (BAND_HIGH_HZ / bin_hz).round() as usize),
+            pending: Vec::new(),
+            noise_floor: 0.0,
+            frames_seen: 0,
+            last_probability: 0.0,
+        }
+    }
+
+    /// Current noise floor (mean-square speech-band energy)
+    pub fn noise_floor(&self) -> f32 {
+        self.noise_floor
+    }
+
+    fn frame_probability(&mut self, frame: &[f32]) -> f32 {
+        let mut re: Vec<f32> = frame.iter().zip(&self.window).map(|(s, w)| s * w).collect();
+        let mut im = vec![0.0; FRAME_SIZE];
+        self.fft.forward(&mut re, &mut im);
+
+        let powers: Vec<f32> = (self.band.0..=self.band.1)
+            .map(|k| re[k] * re[k] + im[k] * im[k])
+            .collect();
+        // Mean-square energy of the band-limited signal (Parseval)
+        let energy = 2.0 * powers.iter().sum::<f32>() / (FRAME_SIZE as f32 * self.window_power);
+
+        // Spectral flatness: ~0.5 for white noise, well below for voiced speech
+        let mean = powers.iter().sum::<f32>() / powers.len() as f32;
+        let log_mean = powers.iter().map(|p| (p + 1e-12).ln()).sum::<f32>() / powers.len() as f32;
+        let flatness = if mean > 0.0 { log_mean.exp() / mean } else { 1.0 };
+        let tonality = ((0.6 - flatness) / 0.4).clamp(0.0, 1.0);
+
+        let probability = if self.frames_seen < LEARNING_FRAMES || energy < MIN_SPEECH_ENERGY {
+            0.0
+        } else {
+            let snr_db = 10.0 * (energy / self.noise_floor.max(MIN_NOISE_FLOOR)).log10();
+            let snr = 1.0 / (1.0 + (-(snr_db - SNR_MIDPOINT_DB) / SNR_SLOPE_DB).exp());
+            snr * (0.6 + 0.4 * tonality)
+        };
+
+        // Noise floor: seed with the first frames, then fast down / slow up
+        if self.frames_seen < LEARNING_FRAMES {
+            self.noise_floor += (energy - self.noise_floor) / (self.frames_seen + 1) as f32;
+        } else if energy < self.noise_floor {
+            self.noise_floor = 0.8 * self.noise_floor + 0.2 * energy;
+        } else if probability < 0.5 {
+            self.noise_floor = 0.95 * self.noise_floor + 0.05 * energy;
+        } else {
+            self.noise_floor *= NOISE_RISE;
+        }
+        self.noise_floor = self.noise_floor.max(MIN_NOISE_FLOOR);
+        self.frames_seen += 1;
+
+        probability
+    }
+}
+
+impl Default for AdaptiveVad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VoiceActivityDetector for AdaptiveVad {
+    fn name(&self) -> &str {
+        "adaptive"
+    }
+
+    fn speech_probability(&mut self, samples: &[f32]) -> Result<f32, Box<dyn Error>> {
+        self.pending.extend_from_slice(samples);
+
+        let mut total = 0.0;
+        let mut frames = 0;
+        while self.pending.len() >= FRAME_SIZE {
+            let frame: Vec<f32> = self.pending.drain(..FRAME_SIZE).collect();
+            total += self.frame_probability(&frame);
+            frames += 1;
+        }
+
+        if frames > 0 {
+            self.last_probability = total / frames as f32;
+        }
+        Ok(self.last_probability)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Silero window at 16kHz and recurrent state size (v5 models)
+#[cfg(feature = "onnx")]
+const SILERO_WINDOW: usize = 512;
+#[cfg(feature = "onnx")]
+const SILERO_STATE: usize = 2 * 128;
+
+/// Silero VAD (v5 ONNX model)
+#[cfg(feature = "onnx")]
+pub struct SileroVad {
+    session: Session,
+    state: Vec<f32>,
+    pending: Vec<f32>,
+    last_probability: f32,
+}
+
+#[cfg(feature = "onnx")]
+impl SileroVad {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let session = Session::builder()?.commit_from_file(path)?;
+        Ok(Self {
+            session,
+            state: vec![0.0; SILERO_STATE],
+            pending: Vec::new(),
+            last_probability: 0.0,
+        })
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl VoiceActivityDetector for SileroVad {
+    fn name(&self) -> &str {
+        "silero"
+    }
+
+    fn speech_probability(&mut self, samples: &[f32]) -> Result<f32, Box<dyn Error>> {
+        self.pending.extend_from_slice(samples);
+
+        // Report the most confident window so short words are not averaged away
+        let mut best: Option<f32> = None;
+        while self.pending.len() >= SILERO_WINDOW {
+            let window: Vec<f32> = self.pending.drain(..SILERO_WINDOW).collect();
+            let outputs = self.session.run(ort::inputs![
+                "input" => Tensor::from_array(([1usize, SILERO_WINDOW], window))?,
+                "state" => Tensor::from_array(([2usize, 1, 128], self.state.clone()))?,
+                "sr" => Tensor::from_array(([0usize; 0], vec![SAMPLE_RATE as i64]))?,
+            ])?;
+
+            let (_, probability) = outputs["output"].try_extract_tensor::<f32>()?;
+            let probability = probability.first().copied().unwrap_or(0.0);
+            let (_, state) = outputs["stateN"].try_extract_tensor::<f32>()?;
+            self.state.copy_from_slice(state);
+
+            best = Some(best.map_or(probability, |b: f32| b.max(probability)));
+        }
+
+        if let Some(probability) = best {
+            self.last_probability = probability;
+        }
+        Ok(self.last_probability)
+    }
+
+    fn reset(&mut self) {
+        self.state.iter_mut().for_each(|s| *s = 0.0);
+        self.pending.clear();
+        self.last_probability = 0.0;
+    }
+}
+
+/// Original energy + zero-crossing-rate detector (`"energy"`)
+///
+/// Kept for comparison; `AdaptiveVad` handles quiet speakers, voiced vowels
+/// and noisy rooms that fixed thresholds miss.
 pub struct VAD {
     energy_threshold: f32,
     zcr_threshold: f32,
@@ -104,6 +529,22 @@ impl Default for VAD {
     }
 }
 
+impl VoiceActivityDetector for VAD {
+    fn name(&self) -> &str {
+        "energy"
+    }
+
+    fn speech_probability(&mut self, samples: &[f32]) -> Result<f32, Box<dyn Error>> {
+        let active = self.calculate_energy(samples) > self.energy_threshold
+            && self.zero_crossing_rate(samples) > self.zcr_threshold;
+        Ok(if active { 1.0 } else { 0.0 })
+    }
+
+    fn reset(&mut self) {
+        VAD::reset(self);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -207,4 +648,122 @@ mod tests {
         assert_eq!(vad.current_silence, 0);
         assert_eq!(vad.current_speech, 0);
     }
+
+    /// Voiced vowel: 150Hz fundamental with harmonics (low ZCR)
+    fn vowel(len: usize, amplitude: f32) -> Vec<f32> {
+        use std::f32::consts::PI;
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                (1..=12).map(|h| (2.0 * PI * 150.0 * h as f32 * t).sin() / h as f32).sum::<f32>() * amplitude / 2.0
+            })
+            .collect()
+    }
+
+    fn white_noise(len: usize, amplitude: f32, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                ((state >> 8) as f32 / (1u32 << 24) as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_adaptive_vad_quiet_vowel_in_noise() {
+        let mut vad = AdaptiveVad::new();
+        let chunk = 1600;
+
+        // Learn a noisy room
+        for i in 0..20 {
+            let p = vad.speech_probability(&white_noise(chunk, 0.01, i)).unwrap();
+            if i > 5 {
+                assert!(p < 0.3, "noise scored {}", p);
+            }
+        }
+
+        // A quiet vowel; its low zero-crossing rate defeats the energy+ZCR VAD
+        let mut legacy = VAD::new();
+        assert_eq!(legacy.speech_probability(&vowel(chunk, 0.1)).unwrap(), 0.0);
+
+        let noise = white_noise(chunk, 0.01, 99);
+        let speech: Vec<f32> = vowel(chunk, 0.1).iter().zip(&noise).map(|(v, n)| v + n).collect();
+        assert!(vad.speech_probability(&speech).unwrap() > 0.8);
+    }
+
+    #[test]
+    fn test_adaptive_vad_follows_noise_level() {
+        let mut vad = AdaptiveVad::new();
+        for i in 0..10 {
+            vad.speech_probability(&white_noise(1600, 0.005, i)).unwrap();
+        }
+        // The room gets louder (fan turned on); after a while it is noise again
+        let mut last = 1.0;
+        for i in 0..100 {
+            last = vad.speech_probability(&white_noise(1600, 0.02, 100 + i)).unwrap();
+        }
+        assert!(last < 0.3, "{}", last);
+    }
+
+    #[test]
+    fn test_endpointer_events() {
+        let mut endpointer = Endpointer::new(EndpointConfig::default());
+
+        // A single loud chunk is not an utterance
+        assert_eq!(endpointer.process(0.9, 100).event, None);
+        assert_eq!(endpointer.process(0.1, 100).event, None);
+
+        assert_eq!(endpointer.process(0.9, 100).event, None);
+        assert_eq!(endpointer.process(0.9, 100).event, Some(VadEvent::SpeechStart));
+
+        // Short pauses and uncertain chunks stay inside the utterance
+        for _ in 0..6 {
+            assert!(endpointer.process(0.1, 100).in_speech);
+        }
+        assert!(endpointer.process(0.5, 100).in_speech);
+        for _ in 0..6 {
+            assert_eq!(endpointer.process(0.1, 100).event, None);
+        }
+        let frame = endpointer.process(0.1, 100);
+        assert_eq!(frame.event, Some(VadEvent::SpeechEnd));
+        assert!(!frame.in_speech);
+    }
+
+    struct Broken;
+
+    impl VoiceActivityDetector for Broken {
+        fn name(&self) -> &str {
+            "broken"
+        }
+        fn speech_probability(&mut self, _samples: &[f32]) -> Result<f32, Box<dyn Error>> {
+            Err("model error".into())
+        }
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn test_voice_activity_falls_back_on_error() {
+        let mut voice = VoiceActivity::new(Box::new(Broken));
+        let frame = voice.process(&vec![0.0; 1600]);
+        assert_eq!(frame.probability, 0.0);
+        assert_eq!(voice.detector_name(), "adaptive");
+        assert!(voice.take_warning().unwrap().contains("broken"));
+        assert_eq!(voice.take_warning(), None);
+    }
+
+    #[test]
+    fn test_detector_by_name() {
+        let (vad, warning) = detector(Some("energy"));
+        assert_eq!(vad.name(), "energy");
+        assert_eq!(warning, None);
+
+        let (vad, warning) = detector(Some("adaptive"));
+        assert_eq!(vad.name(), "adaptive");
+        assert_eq!(warning, None);
+
+        let (vad, warning) = detector(Some("webrtc"));
+        assert_eq!(vad.name(), "adaptive");
+        assert!(warning.unwrap().contains("webrtc"));
+    }
 }
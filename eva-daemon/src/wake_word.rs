@@ -1,7 +1,16 @@
 use std::collections::VecDeque;
 
-#[cfg(feature = "timemachine")]
-use ort::{Session, Value};
+#[cfg(feature = "onnx")]
+use ort::{session::Session, value::Tensor};
+
+use crate::features::Mfcc;
+use crate::kws::{self, KeywordTemplates};
+
+/// Samples fed to an ONNX keyword model (1.5s at 16kHz)
+#[cfg(feature = "onnx")]
+const ONNX_WINDOW: usize = 24000;
+/// Frames quieter than this (RMS) are not worth matching against templates
+const MIN_KEYWORD_RMS: f32 = 0.01;
 
 /// Detection strategy for wake word
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -12,6 +21,8 @@ pub enum DetectionStrategy {
     Mfcc,
     /// ONNX model-based detection (best accuracy, requires model)
     Onnx,
+    /// DTW against the user's enrolled recordings (see `kws`)
+    Template,
 }
 
 /// Configuration for wake word detection
@@ -52,12 +63,18 @@ pub struct WakeWordDetector {
     energy_pattern: Vec<f32>,
     /// MFCC feature buffer
     mfcc_buffer: VecDeque<Vec<f32>>,
+    /// Log-mel MFCC front-end
+    mfcc: Mfcc,
+    /// Enrolled personal wake phrase
+    templates: Option<KeywordTemplates>,
+    /// Confidence of the last template/ONNX evaluation (0..1)
+    last_score: f32,
     /// Last detection timestamp
-    last_detection_ms: u64,
+    last_detection_ms: Option<u64>,
     /// Running timestamp
     current_ms: u64,
     /// ONNX session (optional)
-    #[cfg(feature = "timemachine")]
+    #[cfg(feature = "onnx")]
     onnx_session: Option<Session>,
     /// Detection count (for anti-spam)
     detection_count: u32,
@@ -90,16 +107,19 @@ impl WakeWordDetector {
             buffer: VecDeque::with_capacity(buffer_size),
             energy_pattern,
             mfcc_buffer: VecDeque::with_capacity(50), // ~500ms of MFCC frames
-            last_detection_ms: 0,
+            mfcc: Mfcc::default(),
+            templates: None,
+            last_score: 0.0,
+            last_detection_ms: None,
             current_ms: 0,
-            #[cfg(feature = "timemachine")]
+            #[cfg(feature = "onnx")]
             onnx_session: None,
             detection_count: 0,
         }
     }
 
     /// Load ONNX model for ML-based detection
-    #[cfg(feature = "timemachine")]
+    #[cfg(feature = "onnx")]
     pub fn load_model(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let session = Session::builder()?.commit_from_file(path)?;
         self.onnx_session = Some(session);
@@ -107,6 +127,22 @@ impl WakeWordDetector {
         Ok(())
     }
 
+    /// Use the user's enrolled wake phrase instead of "Hey EVA"
+    pub fn set_templates(&mut self, templates: KeywordTemplates) {
+        self.templates = Some(templates);
+        self.config.strategy = DetectionStrategy::Template;
+    }
+
+    /// Phrase the detector is listening for
+    pub fn phrase(&self) -> &str {
+        self.templates.as_ref().map(|t| t.phrase.as_str()).unwrap_or("Hey EVA")
+    }
+
+    /// Confidence of the last template or ONNX evaluation (0..1)
+    pub fn last_score(&self) -> f32 {
+        self.last_score
+    }
+
     /// Detect wake word in audio samples
     ///
     /// Returns true if "Hey EVA" is detected
@@ -116,8 +152,10 @@ impl WakeWordDetector {
         self.current_ms += samples_ms;
 
         // Check cooldown
-        if self.current_ms - self.last_detection_ms < self.config.cooldown_ms as u64 {
-            return false;
+        if let Some(last) = self.last_detection_ms {
+            if self.current_ms - last < self.config.cooldown_ms as u64 {
+                return false;
+            }
         }
 
         // Add samples to buffer
@@ -142,10 +180,11 @@ impl WakeWordDetector {
             DetectionStrategy::Energy => self.detect_energy(),
             DetectionStrategy::Mfcc => self.detect_mfcc(),
             DetectionStrategy::Onnx => self.detect_onnx(),
+            DetectionStrategy::Template => self.detect_template(),
         };
 
         if detected {
-            self.last_detection_ms = self.current_ms;
+            self.last_detection_ms = Some(self.current_ms);
             self.detection_count += 1;
             self.buffer.clear();
             true
@@ -186,7 +225,7 @@ impl WakeWordDetector {
     }
 
     /// MFCC-based detection
-    fn detect_mfcc(&self) -> bool {
+    fn detect_mfcc(&mut self) -> bool {
         if self.buffer.len() < self.config.sample_rate as usize / 2 {
             return false;
         }
@@ -206,8 +245,8 @@ impl WakeWordDetector {
 
         let mut score = 0.0;
 
-        // Check energy progression (first MFCC coefficient is energy)
-        let energies: Vec<f32> = mfcc.iter().map(|m| m.get(0).copied().unwrap_or(0.0)).collect();
+        // Check energy progression (RMS per analysis hop)
+        let energies = self.frame_levels();
 
         // Look for the "Hey EVA" pattern:
         // 1. Initial rise (Hey)
@@ -240,80 +279,75 @@ impl WakeWordDetector {
         score > self.config.threshold
     }
 
-    /// ONNX model-based detection
-    fn detect_onnx(&self) -> bool {
-        #[cfg(feature = "timemachine")]
-        {
-            if let Some(ref session) = self.onnx_session {
-                // Prepare input tensor
-                let samples: Vec<f32> = self.buffer.iter().cloned().collect();
-
-                // Resample to model input size (typically 16000 * 1.5 = 24000 samples)
-                let model_input_size = 24000;
-                let resampled = self.resample(&samples, model_input_size);
-
-                // Create tensor
-                if let Ok(tensor) = Value::from_array((vec![1, model_input_size], resampled)) {
-                    if let Ok(outputs) = session.run(vec![tensor]) {
-                        if let Some(output) = outputs.get(0) {
-                            // Extract probability
-                            // Placeholder: in production, properly extract from output tensor
-                            return true; // Model detected wake word
-                        }
-                    }
-                }
-            }
+    /// Template (DTW) detection against the enrolled phrase
+    fn detect_template(&mut self) -> bool {
+        if self.templates.is_none() {
+            return self.detect_mfcc();
         }
 
-        // Fallback to MFCC if ONNX not available
-        self.detect_mfcc()
-    }
-
-    /// Compute simplified MFCC features
-    fn compute_mfcc(&self) -> Vec<Vec<f32>> {
-        let frame_size = 512;
-        let hop_size = 256;
-        let num_mfcc = 13;
-
-        let samples: Vec<f32> = self.buffer.iter().cloned().collect();
-        let mut mfcc_frames = Vec::new();
-
-        for start in (0..samples.len().saturating_sub(frame_size)).step_by(hop_size) {
-            let frame = &samples[start..start + frame_size];
-
-            // Apply Hamming window
-            let windowed: Vec<f32> = frame
-                .iter()
-                .enumerate()
-                .map(|(i, &s)| {
-                    let window = 0.54 - 0.46 * (2.0 * std::f32::consts::PI * i as f32 / frame_size as f32).cos();
-                    s * window
-                })
-                .collect();
-
-            // Compute energy in frequency bands (simplified mel filterbank)
-            let mut mfcc = vec![0.0f32; num_mfcc];
-
-            // Energy (zeroth coefficient)
-            mfcc[0] = windowed.iter().map(|s| s * s).sum::<f32>().sqrt();
-
-            // Simplified spectral features (using time-domain approximations)
-            for (i, coef) in mfcc.iter_mut().enumerate().skip(1) {
-                let freq = (i as f32 / num_mfcc as f32) * 0.5;
-                let phase = 2.0 * std::f32::consts::PI * freq;
-                *coef = windowed
-                    .iter()
-                    .enumerate()
-                    .map(|(j, &s)| s * (phase * j as f32).cos())
-                    .sum::<f32>()
-                    .abs()
-                    / frame_size as f32;
+        // Skip the DTW while the room is quiet
+        if self.frame_levels().iter().all(|&l| l < MIN_KEYWORD_RMS) {
+            self.last_score = 0.0;
+            return false;
+        }
+
+        let features = kws::keyword_features(&self.mfcc, self.buffer.make_contiguous());
+        self.last_score = self.templates.as_ref().map_or(0.0, |t| t.score(&features));
+        self.last_score > self.config.threshold
+    }
+
+    /// ONNX model-based detection
+    fn detect_onnx(&mut self) -> bool {
+        #[cfg(feature = "onnx")]
+        match self.onnx_probability() {
+            Ok(Some(probability)) => {
+                self.last_score = probability;
+                return probability > self.config.threshold;
             }
+            Ok(None) => {}
+            Err(e) => eprintln!("[WakeWord] ONNX inference failed: {}", e),
+        }
 
-            mfcc_frames.push(mfcc);
+        // Fall back to the enrolled phrase, then the MFCC heuristic
+        if self.templates.is_some() {
+            self.detect_template()
+        } else {
+            self.detect_mfcc()
         }
+    }
+
+    /// Keyword probability from the ONNX model for the last 1.5s of audio
+    ///
+    /// The model takes raw 16kHz samples `[1, 24000]` (left-padded with
+    /// silence) and returns scores decoded by `keyword_probability`.
+    #[cfg(feature = "onnx")]
+    fn onnx_probability(&mut self) -> Result<Option<f32>, Box<dyn std::error::Error>> {
+        let Some(session) = self.onnx_session.as_mut() else {
+            return Ok(None);
+        };
 
-        mfcc_frames
+        let available = self.buffer.len().min(ONNX_WINDOW);
+        let mut window = vec![0.0f32; ONNX_WINDOW - available];
+        window.extend(self.buffer.iter().skip(self.buffer.len() - available));
+
+        let outputs = session.run(ort::inputs![Tensor::from_array(([1usize, ONNX_WINDOW], window))?])?;
+        let (_, scores) = outputs[0].try_extract_tensor::<f32>()?;
+        Ok(keyword_probability(scores))
+    }
+
+    /// RMS level of each 10ms hop in the buffer
+    fn frame_levels(&mut self) -> Vec<f32> {
+        let hop = self.config.sample_rate as usize / 100;
+        self.buffer
+            .make_contiguous()
+            .chunks(hop)
+            .map(|c| (c.iter().map(|s| s * s).sum::<f32>() / c.len() as f32).sqrt())
+            .collect()
+    }
+
+    /// MFCCs (log-mel, 25ms frames every 10ms) of the buffered audio
+    fn compute_mfcc(&mut self) -> Vec<Vec<f32>> {
+        self.mfcc.compute(self.buffer.make_contiguous())
     }
 
     /// Compute spectral variance across MFCC frames
@@ -365,21 +399,6 @@ impl WakeWordDetector {
         max_corr
     }
 
-    /// Resample audio to target length
-    fn resample(&self, samples: &[f32], target_len: usize) -> Vec<f32> {
-        if samples.is_empty() {
-            return vec![0.0; target_len];
-        }
-
-        let ratio = samples.len() as f32 / target_len as f32;
-        (0..target_len)
-            .map(|i| {
-                let src_idx = (i as f32 * ratio) as usize;
-                samples.get(src_idx).copied().unwrap_or(0.0)
-            })
-            .collect()
-    }
-
     /// Set detection sensitivity (0.0 to 1.0)
     ///
     /// Lower values = more sensitive (more false positives)
@@ -408,10 +427,37 @@ impl WakeWordDetector {
     pub fn reset(&mut self) {
         self.buffer.clear();
         self.mfcc_buffer.clear();
+        self.last_detection_ms = None;
+        self.last_score = 0.0;
         self.detection_count = 0;
     }
 }
 
+/// Decode a keyword model's output into a probability
+///
+/// A single value is the keyword score (logits are squashed with a
+/// sigmoid). Several values are class scores with class 0 as background,
+/// so the keyword probability is everything that is not background.
+pub fn keyword_probability(scores: &[f32]) -> Option<f32> {
+    match scores {
+        [] => None,
+        [score] if (0.0..=1.0).contains(score) => Some(*score),
+        [logit] => Some(1.0 / (1.0 + (-logit).exp())),
+        _ => {
+            let sum: f32 = scores.iter().sum();
+            let is_distribution = scores.iter().all(|s| (0.0..=1.0).contains(s)) && (sum - 1.0).abs() < 1e-3;
+            let background = if is_distribution {
+                scores[0]
+            } else {
+                let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                let exp_sum: f32 = scores.iter().map(|s| (s - max).exp()).sum();
+                (scores[0] - max).exp() / exp_sum
+            };
+            Some((1.0 - background).clamp(0.0, 1.0))
+        }
+    }
+}
+
 impl Default for WakeWordDetector {
     fn default() -> Self {
         Self::new()
@@ -544,27 +590,6 @@ mod tests {
         assert_eq!(detector.detection_count(), 0);
     }
 
-    #[test]
-    fn test_resampling() {
-        let detector = WakeWordDetector::new();
-
-        let original = vec![0.0, 0.5, 1.0, 0.5, 0.0];
-        let resampled = detector.resample(&original, 10);
-
-        assert_eq!(resampled.len(), 10);
-    }
-
-    #[test]
-    fn test_resample_empty() {
-        let detector = WakeWordDetector::new();
-
-        let empty: Vec<f32> = vec![];
-        let resampled = detector.resample(&empty, 10);
-
-        assert_eq!(resampled.len(), 10);
-        assert!(resampled.iter().all(|&x| x == 0.0));
-    }
-
     #[test]
     fn test_spectral_variance() {
         let detector = WakeWordDetector::new();
@@ -639,4 +664,36 @@ mod tests {
         // Should not detect anything with insufficient duration
         assert!(!result);
     }
+
+    #[test]
+    fn test_keyword_probability_decoding() {
+        assert_eq!(keyword_probability(&[]), None);
+        assert_eq!(keyword_probability(&[0.8]), Some(0.8));
+        assert!((keyword_probability(&[0.0]).unwrap() - 0.0).abs() < 1e-6);
+        assert!((keyword_probability(&[3.0]).unwrap() - 0.9526).abs() < 1e-3);
+
+        // Softmax output: [background, keyword]
+        assert!((keyword_probability(&[0.1, 0.9]).unwrap() - 0.9).abs() < 1e-6);
+        // Raw logits are normalized first
+        assert!(keyword_probability(&[4.0, -2.0]).unwrap() < 0.01);
+        assert!(keyword_probability(&[-2.0, 4.0]).unwrap() > 0.99);
+    }
+
+    #[test]
+    fn test_templates_switch_strategy() {
+        let mut detector = WakeWordDetector::new();
+        assert_eq!(detector.phrase(), "Hey EVA");
+
+        detector.set_templates(KeywordTemplates {
+            phrase: "Computer".to_string(),
+            templates: vec![vec![vec![0.0; 12]; 40]],
+            reference_distance: 1.0,
+        });
+        assert_eq!(detector.config.strategy, DetectionStrategy::Template);
+        assert_eq!(detector.phrase(), "Computer");
+
+        // Quiet audio never reaches the DTW
+        assert!(!detector.detect(&vec![0.001; 16000]));
+        assert_eq!(detector.last_score(), 0.0);
+    }
 }
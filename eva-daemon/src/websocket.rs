@@ -1,23 +1,171 @@
-use tokio_tungstenite::{connect_async, tungstenite::Message, WebSocketStream, MaybeTlsStream};
+use tokio_tungstenite::{client_async, tungstenite::Message, WebSocketStream};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
+use tokio::time::{Duration, Instant};
 use futures_util::{SinkExt, StreamExt};
+use std::path::PathBuf;
 use url::Url;
 
+use crate::credentials;
+use crate::proxy::Proxy;
+use crate::tls::TlsManager;
+
+/// Any byte stream a WebSocket runs over (TCP, TLS, proxy tunnel)
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// How to reach the server
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    /// HTTP (`CONNECT`) or SOCKS5 proxy to tunnel through
+    pub proxy: Option<Proxy>,
+    /// Hosts reached directly despite `proxy` ("localhost", ".corp.example", "*")
+    pub no_proxy: Vec<String>,
+    /// Extra PEM CAs trusted on top of the system ones
+    pub ca_bundle: Option<PathBuf>,
+    /// "sha256/<base64>" SPKI pins; when set the server's chain must contain one
+    pub pins: Vec<String>,
+    /// Limit for TCP + proxy + TLS + WebSocket handshake
+    pub connect_timeout: Duration,
+    /// `receive` fails when nothing arrived for this long (`None` = no limit)
+    pub read_timeout: Option<Duration>,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            proxy: None,
+            no_proxy: Vec::new(),
+            ca_bundle: None,
+            pins: Vec::new(),
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: None,
+        }
+    }
+}
+
+impl ConnectOptions {
+    /// `EVA_PROXY` (else `HTTPS_PROXY`/`ALL_PROXY`), `NO_PROXY`,
+    /// `EVA_CA_BUNDLE`, `EVA_CONNECT_TIMEOUT`/`EVA_READ_TIMEOUT` (seconds);
+    /// pins are per backend (see `backend::connect`)
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let var = |names: &[&str]| names.iter().find_map(|name| var(name)).filter(|v| !v.trim().is_empty());
+        let seconds = |name: &str| -> Result<Option<Duration>, String> {
+            var(&[name])
+                .map(|v| {
+                    v.trim()
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|s| *s > 0.0)
+                        .map(Duration::from_secs_f32)
+                        .ok_or_else(|| format!("{} must be a number of seconds, got '{}'", name, v))
+                })
+                .transpose()
+        };
+
+        let defaults = Self::default();
+        Ok(Self {
+            proxy: var(&["EVA_PROXY", "HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"])
+                .map(|url| Proxy::parse(&url))
+                .transpose()?,
+            no_proxy: var(&["NO_PROXY", "no_proxy"])
+                .map(|list| list.split(',').map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()).collect())
+                .unwrap_or_default(),
+            ca_bundle: var(&["EVA_CA_BUNDLE"]).map(PathBuf::from),
+            pins: Vec::new(),
+            connect_timeout: seconds("EVA_CONNECT_TIMEOUT")?.unwrap_or(defaults.connect_timeout),
+            read_timeout: seconds("EVA_READ_TIMEOUT")?,
+        })
+    }
+
+    /// The proxy to use for `host`, unless `no_proxy` lists it
+    pub fn proxy_for(&self, host: &str) -> Option<&Proxy> {
+        let host = host.to_lowercase();
+        let bypass = self.no_proxy.iter().any(|entry| {
+            let domain = entry.trim_start_matches('*').trim_start_matches('.');
+            entry == "*" || host == domain || host.ends_with(&format!(".{}", domain))
+        });
+        if bypass {
+            None
+        } else {
+            self.proxy.as_ref()
+        }
+    }
+}
+
 pub struct WebSocketClient {
-    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    stream: WebSocketStream<Box<dyn Transport>>,
+    read_timeout: Option<Duration>,
+    last_received: Instant,
 }
 
 impl WebSocketClient {
     /// Connect to a WebSocket server with automatic TLS support
     pub async fn connect(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        println!("🔗 Conectando ao WebSocket: {}", url);
+        Self::connect_with(url, &[], &ConnectOptions::default()).await
+    }
+
+    /// Connect through `options` (proxy, CAs, pins, timeouts), sending extra
+    /// handshake headers (auth tokens stay out of the URL)
+    pub async fn connect_with(
+        url: &str,
+        headers: &[(&str, &str)],
+        options: &ConnectOptions,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        println!("🔗 Conectando ao WebSocket: {}", credentials::redact(url));
+
+        let parsed = Url::parse(url)?;
+        let secure = match parsed.scheme() {
+            "wss" => true,
+            "ws" => false,
+            other => return Err(format!("Not a WebSocket URL (scheme '{}')", other).into()),
+        };
+        let host = parsed
+            .host_str()
+            .ok_or("WebSocket URL without a host")?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = parsed.port_or_known_default().ok_or("WebSocket URL without a port")?;
+
+        let mut request = parsed.as_str().into_client_request()?;
+        for (name, value) in headers {
+            let mut value = HeaderValue::from_str(value)?;
+            value.set_sensitive(true);
+            request.headers_mut().insert(HeaderName::from_bytes(name.as_bytes())?, value);
+        }
 
-        let url = Url::parse(url)?;
-        let (ws_stream, response) = connect_async(url).await?;
+        let handshake = async {
+            let tcp = match options.proxy_for(&host) {
+                Some(proxy) => proxy.connect(&host, port).await?,
+                None => TcpStream::connect((host.as_str(), port)).await?,
+            };
+            let transport: Box<dyn Transport> = if secure {
+                let tls = TlsManager::with_options(options.ca_bundle.as_deref(), &options.pins)?;
+                Box::new(tls.handshake(&host, tcp).await?)
+            } else {
+                Box::new(tcp)
+            };
+            Ok::<_, Box<dyn std::error::Error>>(client_async(request, transport).await?)
+        };
+        let (ws_stream, response) = tokio::time::timeout(options.connect_timeout, handshake)
+            .await
+            .map_err(|_| format!("Connecting to {}:{} timed out after {:?}", host, port, options.connect_timeout))??;
 
         println!("✅ WebSocket conectado! Status: {}", response.status());
 
-        Ok(Self { stream: ws_stream })
+        Ok(Self {
+            stream: ws_stream,
+            read_timeout: options.read_timeout,
+            last_received: Instant::now(),
+        })
     }
 
     /// Send text message
@@ -34,8 +182,16 @@ impl WebSocketClient {
 
     /// Receive next message
     pub async fn receive(&mut self) -> Result<Option<Message>, Box<dyn std::error::Error>> {
-        match self.stream.next().await {
+        let next = match self.read_timeout {
+            // Measured from the last frame, so callers may poll with short timeouts
+            Some(limit) => tokio::time::timeout(limit.saturating_sub(self.last_received.elapsed()), self.stream.next())
+                .await
+                .map_err(|_| format!("No data from the server for {:?}", limit))?,
+            None => self.stream.next().await,
+        };
+        match next {
             Some(Ok(msg)) => {
+                self.last_received = Instant::now();
                 // Log message type for debugging
                 match &msg {
                     Message::Text(t) => println!("📥 WS Text: {} bytes", t.len()),
@@ -59,7 +215,7 @@ impl WebSocketClient {
     }
 
     /// Close the WebSocket connection
-    pub async fn close(mut self) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn close(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.stream.close(None).await?;
         Ok(())
     }
@@ -74,20 +230,137 @@ impl WebSocketClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy;
+    use crate::tls::tests::{ca_bundle_path, test_acceptor, SERVER_PIN};
+    use std::collections::HashMap;
+
+    /// Echo server on a free local port
+    async fn local_echo_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                if msg.is_text() || msg.is_binary() {
+                    ws.send(msg).await.ok();
+                }
+            }
+        });
+        url
+    }
 
     #[tokio::test]
     async fn test_websocket_echo() {
-        // Usar servidor de teste público
-        let mut client = WebSocketClient::connect("wss://echo.websocket.org/")
+        let url = local_echo_server().await;
+        let mut client = WebSocketClient::connect(&url)
             .await
             .expect("Failed to connect");
 
         client.send_text("Hello WebSocket!").await.expect("Failed to send");
         
         let response = client.receive().await.expect("Failed to receive");
-        assert!(response.is_some());
+        assert_eq!(response, Some(Message::Text("Hello WebSocket!".to_string())));
         
         client.close().await.expect("Failed to close");
     }
-}
 
+    /// wss:// echo server with the fixture certificate
+    async fn local_tls_echo_server() -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = test_acceptor();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut ws = tokio_tungstenite::accept_async(tls).await.unwrap();
+                    while let Some(Ok(msg)) = ws.next().await {
+                        if msg.is_text() || msg.is_binary() {
+                            ws.send(msg).await.ok();
+                        }
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_wss_with_private_ca_through_proxy() {
+        let port = local_tls_echo_server().await;
+        let url = format!("wss://localhost:{}", port);
+
+        // The test CA isn't trusted by default
+        assert!(WebSocketClient::connect(&url).await.is_err());
+
+        let options = ConnectOptions {
+            proxy: Some(Proxy::parse(&proxy::tests::http_proxy().await).unwrap()),
+            ca_bundle: Some(ca_bundle_path()),
+            pins: vec![SERVER_PIN.to_string()],
+            ..ConnectOptions::default()
+        };
+        let mut client = WebSocketClient::connect_with(&url, &[], &options).await.unwrap();
+        client.send_text("through the tunnel").await.unwrap();
+        assert_eq!(
+            client.receive().await.unwrap(),
+            Some(Message::Text("through the tunnel".to_string()))
+        );
+
+        let mispinned = ConnectOptions {
+            pins: vec![format!("sha256/{}", "A".repeat(43) + "=")],
+            ..options
+        };
+        assert!(WebSocketClient::connect_with(&url, &[], &mispinned).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_read_timeouts() {
+        // Accepts TCP but never answers the handshake
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent = format!("ws://{}", listener.local_addr().unwrap());
+        let options = ConnectOptions {
+            connect_timeout: Duration::from_millis(200),
+            ..ConnectOptions::default()
+        };
+        let err = WebSocketClient::connect_with(&silent, &[], &options).await.err().unwrap();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+
+        // Echo server that never speaks first
+        let options = ConnectOptions {
+            read_timeout: Some(Duration::from_millis(200)),
+            ..ConnectOptions::default()
+        };
+        let mut client = WebSocketClient::connect_with(&local_echo_server().await, &[], &options).await.unwrap();
+        let err = client.receive().await.unwrap_err();
+        assert!(err.to_string().contains("No data"), "{}", err);
+    }
+
+    #[test]
+    fn test_options_from_env() {
+        let vars: HashMap<&str, &str> = [
+            ("HTTPS_PROXY", "http://proxy.corp:3128"),
+            ("NO_PROXY", "localhost, .internal"),
+            ("EVA_CA_BUNDLE", "/etc/eva/ca.pem"),
+            ("EVA_CONNECT_TIMEOUT", "12"),
+        ]
+        .into_iter()
+        .collect();
+        let options = ConnectOptions::from_vars(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(options.proxy.as_ref().map(|p| p.port), Some(3128));
+        assert_eq!(options.ca_bundle, Some(PathBuf::from("/etc/eva/ca.pem")));
+        assert_eq!(options.connect_timeout, Duration::from_secs(12));
+        assert_eq!(options.read_timeout, None);
+
+        assert!(options.proxy_for("eva-ia.org").is_some());
+        assert!(options.proxy_for("localhost").is_none());
+        assert!(options.proxy_for("mind.internal").is_none());
+        assert!(options.proxy_for("internal.evil.com").is_some());
+
+        assert!(ConnectOptions::from_vars(|name| (name == "EVA_READ_TIMEOUT").then(|| "soon".to_string())).is_err());
+        assert!(ConnectOptions::from_vars(|name| (name == "EVA_PROXY").then(|| "ftp://x".to_string())).is_err());
+    }
+}
@@ -0,0 +1,718 @@
+//! Whisper speech recognition (ONNX)
+//!
+//! Runs a Whisper-class encoder/decoder exported to ONNX with Hugging Face
+//! `optimum` (the plain `decoder_model.onnx`, no KV cache). Files go in
+//! `~/.eva/models/whisper/`:
+//! - `encoder_model.onnx`: `input_features [1, 80, 3000]` -> `last_hidden_state`
+//! - `decoder_model.onnx`: `input_ids [b, n]` + `encoder_hidden_states` -> `logits [b, n, vocab]`
+//! - `vocab.json` and `added_tokens.json` from the tokenizer
+//!
+//! Decoding is a small beam search with Whisper's timestamp rules; the
+//! timestamps give segment times, which are spread over the words. Partial
+//! results re-run the model (greedy) after every second of new audio.
+//! Language identification reads the language-token logits the decoder
+//! predicts right after `<|startoftranscript|>`.
+//! Sessions are created in `load_session` so they can be routed through the
+//! NPU delegate once it moves to the current ort API.
+
+#![cfg_attr(not(feature = "onnx"), allow(dead_code))]
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "onnx")]
+use ort::{session::Session, value::Tensor};
+
+use crate::features::LogMel;
+#[cfg(feature = "onnx")]
+use crate::features::LogMelConfig;
+#[cfg(feature = "onnx")]
+use crate::stt::{SpeechRecognizer, SttConfig};
+use crate::stt::{Language, LanguageGuess, RecognitionResult, WordInfo};
+
+const SAMPLE_RATE: usize = 16000;
+/// Whisper always looks at 30s windows
+const WINDOW_SAMPLES: usize = 30 * SAMPLE_RATE;
+const WINDOW_FRAMES: usize = 3000;
+const NUM_MELS: usize = 80;
+/// Seconds per timestamp token
+const TIMESTAMP_STEP: f32 = 0.02;
+/// Latest timestamp allowed for the first segment (1s)
+const MAX_INITIAL_TIMESTAMP: u32 = 50;
+/// The decoder context is 448 tokens; half is plenty for a voice command
+#[cfg(feature = "onnx")]
+const MAX_TOKENS: usize = 224;
+/// Re-run the model for a partial result after this much new audio
+#[cfg(feature = "onnx")]
+const PARTIAL_INTERVAL: usize = SAMPLE_RATE;
+
+/// Whisper model location (`~/.eva/models/whisper`), if installed
+pub fn whisper_model_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "windows")]
+    let home = std::env::var("USERPROFILE").ok()?;
+    #[cfg(not(target_os = "windows"))]
+    let home = std::env::var("HOME").ok()?;
+
+    let dir = PathBuf::from(home).join(".eva").join("models").join("whisper");
+    dir.join("encoder_model.onnx").exists().then_some(dir)
+}
+
+/// Whisper's log-mel input for (the last 30s of) `samples`
+///
+/// `NUM_MELS` rows of `WINDOW_FRAMES` frames, row-major, with Whisper's
+/// log10 / 80dB clamp / scaling applied. `log_mel` must use
+/// `LogMelConfig::whisper()`.
+pub fn log_mel_spectrogram(log_mel: &LogMel, samples: &[f32]) -> Vec<f32> {
+    let mut audio = samples[samples.len().saturating_sub(WINDOW_SAMPLES)..].to_vec();
+    audio.resize(WINDOW_SAMPLES, 0.0);
+
+    // Centered STFT: reflect half a frame on each side
+    let pad = 200;
+    let mut padded = Vec::with_capacity(WINDOW_SAMPLES + 2 * pad);
+    padded.extend((1..=pad).rev().map(|i| audio[i]));
+    padded.extend_from_slice(&audio);
+    padded.extend((0..pad).map(|i| audio[WINDOW_SAMPLES - 2 - i]));
+
+    let mut mel = vec![0.0f32; NUM_MELS * WINDOW_FRAMES];
+    let mut max = f32::NEG_INFINITY;
+    for (t, frame) in log_mel.compute(&padded).iter().take(WINDOW_FRAMES).enumerate() {
+        for (m, &ln) in frame.iter().enumerate().take(NUM_MELS) {
+            let value = ln / std::f32::consts::LN_10;
+            mel[m * WINDOW_FRAMES + t] = value;
+            max = max.max(value);
+        }
+    }
+    for value in &mut mel {
+        *value = (value.max(max - 8.0) + 4.0) / 4.0;
+    }
+    mel
+}
+
+/// Decoding side of Whisper's byte-level BPE tokenizer
+pub struct WhisperTokenizer {
+    /// Token text by id (GPT-2 byte-to-unicode alphabet)
+    tokens: Vec<String>,
+    special: HashMap<String, u32>,
+    bytes: HashMap<char, u8>,
+    eot: u32,
+    sot: u32,
+    timestamp_begin: u32,
+}
+
+impl WhisperTokenizer {
+    /// Read `vocab.json` and `added_tokens.json` from `dir`
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let vocab: HashMap<String, u32> = serde_json::from_str(&std::fs::read_to_string(dir.join("vocab.json"))?)?;
+        let added: HashMap<String, u32> = match std::fs::read_to_string(dir.join("added_tokens.json")) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(_) => HashMap::new(),
+        };
+        Self::new(vocab, added)
+    }
+
+    pub fn new(vocab: HashMap<String, u32>, added: HashMap<String, u32>) -> Result<Self, Box<dyn Error>> {
+        let size = vocab.values().chain(added.values()).max().map_or(0, |&id| id as usize + 1);
+        let mut tokens = vec![String::new(); size];
+        for (token, &id) in vocab.iter().chain(&added) {
+            tokens[id as usize] = token.clone();
+        }
+
+        let mut special = added;
+        for (token, &id) in &vocab {
+            if token.starts_with("<|") && token.ends_with("|>") {
+                special.insert(token.clone(), id);
+            }
+        }
+
+        let id = |name: &str| special.get(name).copied().ok_or_else(|| format!("Tokenizer has no {}", name));
+        let eot = id("<|endoftext|>")?;
+        let sot = id("<|startoftranscript|>")?;
+        let no_timestamps = id("<|notimestamps|>")?;
+
+        Ok(Self {
+            tokens,
+            bytes: byte_decoder(),
+            eot,
+            sot,
+            // Timestamp tokens follow <|notimestamps|> in every Whisper vocabulary
+            timestamp_begin: no_timestamps + 1,
+            special,
+        })
+    }
+
+    /// Decoder prompt: start of transcript, language and task
+    ///
+    /// English-only models have no language/task tokens.
+    pub fn prompt(&self, language: Language) -> Result<Vec<u32>, Box<dyn Error>> {
+        let mut prompt = vec![self.sot];
+        match self.language_token(language) {
+            Some(token) => {
+                prompt.push(token);
+                prompt.extend(self.special.get("<|transcribe|>"));
+            }
+            None if language == Language::EnglishUS && !self.is_multilingual() => {}
+            None => return Err(format!("Whisper model does not support '{}'", language.code()).into()),
+        }
+        Ok(prompt)
+    }
+
+    pub fn language_token(&self, language: Language) -> Option<u32> {
+        self.special.get(&format!("<|{}|>", language.code())).copied()
+    }
+
+    fn is_multilingual(&self) -> bool {
+        self.special.contains_key("<|transcribe|>")
+    }
+
+    pub fn eot(&self) -> u32 {
+        self.eot
+    }
+
+    pub fn sot(&self) -> u32 {
+        self.sot
+    }
+
+    pub fn is_timestamp(&self, id: u32) -> bool {
+        id >= self.timestamp_begin
+    }
+
+    pub fn timestamp_seconds(&self, id: u32) -> f32 {
+        (id - self.timestamp_begin) as f32 * TIMESTAMP_STEP
+    }
+
+    /// Text of the given tokens, skipping special and timestamp tokens
+    pub fn decode(&self, ids: &[u32]) -> String {
+        let bytes: Vec<u8> = ids
+            .iter()
+            .filter(|&&id| id < self.eot)
+            .filter_map(|&id| self.tokens.get(id as usize))
+            .flat_map(|token| token.chars().filter_map(|c| self.bytes.get(&c).copied()))
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    /// Mask tokens Whisper's decoding rules forbid after `generated`
+    ///
+    /// No special tokens; start with a timestamp within the first second;
+    /// timestamps never go backwards and come in pairs around text.
+    pub fn apply_rules(&self, logits: &mut [f32], generated: &[u32]) {
+        let vocab = logits.len() as u32;
+        let ts_begin = self.timestamp_begin.min(vocab);
+        let mut forbid = |range: std::ops::Range<u32>| {
+            for id in range {
+                logits[id as usize] = f32::NEG_INFINITY;
+            }
+        };
+
+        forbid((self.eot + 1).min(ts_begin)..ts_begin);
+
+        let Some(&last) = generated.last() else {
+            forbid(0..ts_begin);
+            forbid((ts_begin + MAX_INITIAL_TIMESTAMP + 1).min(vocab)..vocab);
+            return;
+        };
+
+        if self.is_timestamp(last) {
+            let penultimate_is_timestamp = generated.len() < 2 || self.is_timestamp(generated[generated.len() - 2]);
+            if penultimate_is_timestamp {
+                // A segment just opened: text comes next
+                forbid(ts_begin..vocab);
+            } else {
+                // A segment just closed: open the next one or stop
+                forbid(0..self.eot);
+            }
+        }
+
+        if let Some(&latest) = generated.iter().rev().find(|&&id| self.is_timestamp(id)) {
+            forbid(ts_begin..latest.min(vocab));
+        }
+    }
+}
+
+/// GPT-2's reversible byte <-> printable unicode mapping, reversed
+fn byte_decoder() -> HashMap<char, u8> {
+    let printable = |b: u32| (0x21..=0x7E).contains(&b) || (0xA1..=0xAC).contains(&b) || (0xAE..=0xFF).contains(&b);
+    let mut shifted = 0;
+    (0u32..256)
+        .map(|b| {
+            let c = if printable(b) {
+                b
+            } else {
+                shifted += 1;
+                255 + shifted
+            };
+            (char::from_u32(c).unwrap_or('\0'), b as u8)
+        })
+        .collect()
+}
+
+/// Text between two timestamp tokens
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+}
+
+/// Split decoded tokens into timestamped segments
+///
+/// Text after the last timestamp ends at `duration`.
+pub fn segments(tokenizer: &WhisperTokenizer, ids: &[u32], duration: f32) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut start = 0.0;
+    let mut text: Vec<u32> = Vec::new();
+
+    let mut close = |start: f32, end: f32, text: &mut Vec<u32>| {
+        let decoded = tokenizer.decode(text).trim().to_string();
+        if !decoded.is_empty() {
+            segments.push(Segment { start, end: end.max(start), text: decoded });
+        }
+        text.clear();
+    };
+
+    for &id in ids {
+        if tokenizer.is_timestamp(id) {
+            let time = tokenizer.timestamp_seconds(id);
+            if !text.is_empty() {
+                close(start, time, &mut text);
+            }
+            start = time;
+        } else if id < tokenizer.eot() {
+            text.push(id);
+        }
+    }
+    if !text.is_empty() {
+        close(start, duration, &mut text);
+    }
+
+    segments
+}
+
+/// Word timings spread over each segment in proportion to word length
+pub fn segment_words(segments: &[Segment], confidence: f32) -> Vec<WordInfo> {
+    let mut words = Vec::new();
+    for segment in segments {
+        let parts: Vec<&str> = segment.text.split_whitespace().collect();
+        let total: usize = parts.iter().map(|w| w.chars().count()).sum();
+        let per_char = (segment.end - segment.start) / total.max(1) as f32;
+
+        let mut time = segment.start;
+        for word in parts {
+            let end = time + word.chars().count() as f32 * per_char;
+            words.push(WordInfo {
+                word: word.to_string(),
+                start: time,
+                end,
+                confidence,
+            });
+            time = end;
+        }
+    }
+    words
+}
+
+/// Whisper backend for `SpeechRecognizer`
+#[cfg(feature = "onnx")]
+pub struct WhisperRecognizer {
+    encoder: Session,
+    decoder: Session,
+    tokenizer: WhisperTokenizer,
+    log_mel: LogMel,
+    language: Language,
+    prompt: Vec<u32>,
+    beam_size: usize,
+    partials: bool,
+    /// Current utterance
+    audio: Vec<f32>,
+    /// Samples received since the last partial result
+    since_partial: usize,
+}
+
+#[cfg(feature = "onnx")]
+struct Hypothesis {
+    tokens: Vec<u32>,
+    log_prob: f32,
+}
+
+#[cfg(feature = "onnx")]
+impl Hypothesis {
+    /// Length-normalized score used to rank finished hypotheses
+    fn score(&self, prompt_len: usize) -> f32 {
+        self.log_prob / (self.tokens.len() - prompt_len).max(1) as f32
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl WhisperRecognizer {
+    pub fn load(dir: &Path, config: &SttConfig) -> Result<Self, Box<dyn Error>> {
+        let tokenizer = WhisperTokenizer::load(dir)?;
+        let prompt = tokenizer.prompt(config.language)?;
+
+        Ok(Self {
+            encoder: load_session(&dir.join("encoder_model.onnx"))?,
+            decoder: load_session(&dir.join("decoder_model.onnx"))?,
+            tokenizer,
+            log_mel: LogMel::new(LogMelConfig::whisper()),
+            language: config.language,
+            prompt,
+            beam_size: config.max_alternatives.max(1) as usize,
+            partials: config.partial_results,
+            audio: Vec::new(),
+            since_partial: 0,
+        })
+    }
+
+    fn transcribe(&mut self, beam_size: usize, is_partial: bool) -> Result<RecognitionResult, Box<dyn Error>> {
+        let duration = self.audio.len() as f32 / SAMPLE_RATE as f32;
+        if self.audio.is_empty() {
+            return Ok(empty_result(is_partial));
+        }
+
+        let audio = std::mem::take(&mut self.audio);
+        let encoded = self.encode(&audio);
+        self.audio = audio;
+        let (hidden, frames, dim) = encoded?;
+
+        let mut hypotheses = self.beam_search(&hidden, frames, dim, beam_size)?;
+        let prompt_len = self.prompt.len();
+        hypotheses.sort_by(|a, b| b.score(prompt_len).total_cmp(&a.score(prompt_len)));
+
+        let decoded: Vec<(Vec<u32>, f32)> = hypotheses
+            .iter()
+            .map(|h| (h.tokens[prompt_len..].to_vec(), h.score(prompt_len).exp().clamp(0.0, 1.0)))
+            .collect();
+        let Some((best, confidence)) = decoded.first().cloned() else {
+            return Ok(empty_result(is_partial));
+        };
+
+        let segments = segments(&self.tokenizer, &best, duration);
+        let text = segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" ");
+        let mut alternatives: Vec<(String, f32)> = Vec::new();
+        for (tokens, confidence) in &decoded[1..] {
+            let alternative = self.tokenizer.decode(tokens).trim().to_string();
+            if !alternative.is_empty() && alternative != text && !alternatives.iter().any(|(t, _)| *t == alternative) {
+                alternatives.push((alternative, *confidence));
+            }
+        }
+
+        Ok(RecognitionResult {
+            words: segment_words(&segments, confidence),
+            text,
+            confidence,
+            alternatives,
+            is_partial,
+        })
+    }
+
+    /// Encoder states for `samples`: (values, frames, width)
+    fn encode(&mut self, samples: &[f32]) -> Result<(Vec<f32>, usize, usize), Box<dyn Error>> {
+        let mel = log_mel_spectrogram(&self.log_mel, samples);
+        let outputs = self.encoder.run(ort::inputs![
+            "input_features" => Tensor::from_array(([1usize, NUM_MELS, WINDOW_FRAMES], mel))?,
+        ])?;
+        let (shape, hidden) = outputs[0].try_extract_tensor::<f32>()?;
+        Ok((hidden.to_vec(), shape[1] as usize, shape[2] as usize))
+    }
+
+    /// Beam search over the decoder (all beams in one batch per step)
+    fn beam_search(&mut self, hidden: &[f32], frames: usize, dim: usize, beam_size: usize) -> Result<Vec<Hypothesis>, Box<dyn Error>> {
+        let eot = self.tokenizer.eot();
+        let prompt_len = self.prompt.len();
+        let mut beams = vec![Hypothesis {
+            tokens: self.prompt.clone(),
+            log_prob: 0.0,
+        }];
+        let mut finished = Vec::new();
+
+        for _ in 0..MAX_TOKENS {
+            let (batch, len) = (beams.len(), beams[0].tokens.len());
+            let ids: Vec<i64> = beams.iter().flat_map(|h| h.tokens.iter().map(|&t| t as i64)).collect();
+            let states = hidden.repeat(batch);
+
+            let outputs = self.decoder.run(ort::inputs![
+                "input_ids" => Tensor::from_array(([batch, len], ids))?,
+                "encoder_hidden_states" => Tensor::from_array(([batch, frames, dim], states))?,
+            ])?;
+            let (shape, logits) = outputs[0].try_extract_tensor::<f32>()?;
+            let vocab = shape[2] as usize;
+
+            let mut candidates: Vec<(usize, u32, f32)> = Vec::new();
+            for (b, hypothesis) in beams.iter().enumerate() {
+                let offset = (b * len + len - 1) * vocab;
+                let mut row = logits[offset..offset + vocab].to_vec();
+                self.tokenizer.apply_rules(&mut row, &hypothesis.tokens[prompt_len..]);
+                let log_probs = log_softmax(&row);
+                for (token, log_prob) in top_k(&log_probs, beam_size + 1) {
+                    candidates.push((b, token, hypothesis.log_prob + log_prob));
+                }
+            }
+            candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+            let mut next = Vec::new();
+            for (b, token, log_prob) in candidates {
+                let mut tokens = beams[b].tokens.clone();
+                if token == eot {
+                    finished.push(Hypothesis { tokens, log_prob });
+                } else {
+                    tokens.push(token);
+                    next.push(Hypothesis { tokens, log_prob });
+                }
+                if next.len() == beam_size {
+                    break;
+                }
+            }
+
+            if finished.len() >= beam_size || next.is_empty() {
+                break;
+            }
+            beams = next;
+        }
+
+        if finished.is_empty() {
+            finished = beams;
+        }
+        Ok(finished)
+    }
+}
+
+#[cfg(feature = "onnx")]
+impl SpeechRecognizer for WhisperRecognizer {
+    fn name(&self) -> &'static str {
+        "whisper"
+    }
+
+    fn language(&self) -> Language {
+        self.language
+    }
+
+    fn set_language(&mut self, language: Language) -> Result<(), Box<dyn Error>> {
+        self.prompt = self.tokenizer.prompt(language)?;
+        self.language = language;
+        Ok(())
+    }
+
+    fn accept(&mut self, samples: &[f32]) -> Result<(), Box<dyn Error>> {
+        self.audio.extend_from_slice(samples);
+        self.since_partial += samples.len();
+        Ok(())
+    }
+
+    fn partial(&mut self) -> Result<Option<RecognitionResult>, Box<dyn Error>> {
+        if !self.partials || self.since_partial < PARTIAL_INTERVAL {
+            return Ok(None);
+        }
+        self.since_partial = 0;
+        self.transcribe(1, true).map(Some)
+    }
+
+    fn finish(&mut self) -> Result<RecognitionResult, Box<dyn Error>> {
+        let result = self.transcribe(self.beam_size, false);
+        self.reset();
+        result
+    }
+
+    fn reset(&mut self) {
+        self.audio.clear();
+        self.since_partial = 0;
+    }
+
+    /// Whisper predicts the language token right after `<|startoftranscript|>`
+    fn detect_language(&mut self, samples: &[f32], candidates: &[Language]) -> Result<Option<LanguageGuess>, Box<dyn Error>> {
+        let tokens: Vec<(Language, u32)> = candidates
+            .iter()
+            .filter_map(|&language| self.tokenizer.language_token(language).map(|token| (language, token)))
+            .collect();
+        if tokens.is_empty() || samples.is_empty() {
+            return Ok(None);
+        }
+
+        let (hidden, frames, dim) = self.encode(samples)?;
+        let outputs = self.decoder.run(ort::inputs![
+            "input_ids" => Tensor::from_array(([1usize, 1], vec![self.tokenizer.sot() as i64]))?,
+            "encoder_hidden_states" => Tensor::from_array(([1usize, frames, dim], hidden))?,
+        ])?;
+        let (_, logits) = outputs[0].try_extract_tensor::<f32>()?;
+        Ok(best_language(logits, &tokens))
+    }
+}
+
+#[cfg(feature = "onnx")]
+fn load_session(path: &Path) -> Result<Session, Box<dyn Error>> {
+    Ok(Session::builder()?.commit_from_file(path)?)
+}
+
+/// Most likely language, with its probability among `tokens` only
+pub fn best_language(logits: &[f32], tokens: &[(Language, u32)]) -> Option<LanguageGuess> {
+    let scores: Vec<f32> = tokens
+        .iter()
+        .map(|&(_, token)| logits.get(token as usize).copied().unwrap_or(f32::NEG_INFINITY))
+        .collect();
+    let probabilities: Vec<f32> = log_softmax(&scores).iter().map(|lp| lp.exp()).collect();
+
+    probabilities
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.is_finite())
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, &probability)| LanguageGuess {
+            language: tokens[i].0,
+            probability,
+        })
+}
+
+fn empty_result(is_partial: bool) -> RecognitionResult {
+    RecognitionResult {
+        text: String::new(),
+        confidence: 0.0,
+        alternatives: Vec::new(),
+        words: Vec::new(),
+        is_partial,
+    }
+}
+
+fn log_softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let log_sum = logits.iter().map(|l| (l - max).exp()).sum::<f32>().ln() + max;
+    logits.iter().map(|l| l - log_sum).collect()
+}
+
+/// The `k` most likely tokens (skipping forbidden ones)
+fn top_k(log_probs: &[f32], k: usize) -> Vec<(u32, f32)> {
+    let mut best: Vec<(u32, f32)> = Vec::with_capacity(k + 1);
+    for (id, &lp) in log_probs.iter().enumerate() {
+        if lp == f32::NEG_INFINITY || (best.len() == k && lp <= best[k - 1].1) {
+            continue;
+        }
+        let pos = best.iter().position(|&(_, p)| lp > p).unwrap_or(best.len());
+        best.insert(pos, (id as u32, lp));
+        best.truncate(k);
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::LogMelConfig;
+
+    /// Tiny multilingual vocabulary: a few words, then Whisper's special tokens
+    fn tokenizer() -> WhisperTokenizer {
+        let vocab: HashMap<String, u32> = [("abr", 0), ("a", 1), ("Ġo", 2), ("Ġarquivo", 3), ("Ã§", 4), ("Ġ", 5)]
+            .into_iter()
+            .map(|(t, id)| (t.to_string(), id))
+            .collect();
+        let added: HashMap<String, u32> = [
+            ("<|endoftext|>", 10),
+            ("<|startoftranscript|>", 11),
+            ("<|en|>", 12),
+            ("<|pt|>", 13),
+            ("<|transcribe|>", 14),
+            ("<|notimestamps|>", 15),
+        ]
+        .into_iter()
+        .map(|(t, id)| (t.to_string(), id))
+        .collect();
+        WhisperTokenizer::new(vocab, added).unwrap()
+    }
+
+    /// Timestamp token for `seconds`
+    fn ts(seconds: f32) -> u32 {
+        16 + (seconds / TIMESTAMP_STEP).round() as u32
+    }
+
+    #[test]
+    fn test_decode_byte_level_tokens() {
+        let tokenizer = tokenizer();
+        assert_eq!(tokenizer.decode(&[0, 1, 2, 3]), "abra o arquivo");
+        // "Ã§" is the byte-level spelling of "ç"; special tokens are skipped
+        assert_eq!(tokenizer.decode(&[11, 13, 5, 4, 1, 10]), " ça");
+        assert_eq!(tokenizer.prompt(Language::PortugueseBR).unwrap(), vec![11, 13, 14]);
+        assert!(tokenizer.prompt(Language::German).is_err());
+    }
+
+    #[test]
+    fn test_segments_and_word_times() {
+        let tokenizer = tokenizer();
+        let ids = [ts(0.0), 0, 1, ts(1.0), ts(1.0), 2, 3, ts(2.0)];
+        let segments = segments(&tokenizer, &ids, 3.0);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0], Segment { start: 0.0, end: 1.0, text: "abra".to_string() });
+        assert_eq!(segments[1].text, "o arquivo");
+
+        let words = segment_words(&segments, 0.9);
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[1].word, "o");
+        assert!((words[1].start - 1.0).abs() < 1e-5);
+        // "o" is 1 of 8 letters in a 1s segment
+        assert!((words[1].end - 1.125).abs() < 1e-5);
+        assert!((words[2].end - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_timestamp_rules() {
+        let tokenizer = tokenizer();
+        let vocab = 16 + 100;
+        let allowed = |generated: &[u32]| {
+            let mut logits = vec![0.0; vocab];
+            tokenizer.apply_rules(&mut logits, generated);
+            (0..vocab as u32).filter(|&i| logits[i as usize] > f32::NEG_INFINITY).collect::<Vec<_>>()
+        };
+
+        // Must open with a timestamp in the first second
+        assert_eq!(allowed(&[]), (ts(0.0)..=ts(1.0)).collect::<Vec<_>>());
+        // After an opening timestamp: text or end, nothing special
+        assert_eq!(allowed(&[ts(0.0)]), (0..=10).collect::<Vec<_>>());
+        // After text + timestamp: close/open with a later timestamp, or stop
+        let after_close = allowed(&[ts(0.0), 2, ts(0.5)]);
+        assert_eq!(after_close[0], 10);
+        assert_eq!(after_close[1], ts(0.5));
+        assert!(!after_close.contains(&2));
+    }
+
+    #[test]
+    fn test_log_mel_layout_and_range() {
+        let log_mel = LogMel::new(LogMelConfig::whisper());
+        let tone: Vec<f32> = (0..SAMPLE_RATE)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / SAMPLE_RATE as f32).sin() * 0.5)
+            .collect();
+
+        let mel = log_mel_spectrogram(&log_mel, &tone);
+        assert_eq!(mel.len(), NUM_MELS * WINDOW_FRAMES);
+        let max = mel.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let min = mel.iter().cloned().fold(f32::INFINITY, f32::min);
+        assert!((max - min - 2.0).abs() < 1e-4, "dynamic range is clamped to 8 (/4)");
+
+        // The tone is loud in the first second and the padding is silent
+        let band = (0..NUM_MELS).max_by(|&a, &b| mel[a * WINDOW_FRAMES + 50].total_cmp(&mel[b * WINDOW_FRAMES + 50])).unwrap();
+        assert!(mel[band * WINDOW_FRAMES + 50] > mel[band * WINDOW_FRAMES + 2000] + 1.0);
+    }
+
+    #[test]
+    fn test_best_language_among_candidates() {
+        let tokenizer = tokenizer();
+        let mut logits = vec![0.0; 16];
+        logits[12] = 1.0; // en
+        logits[13] = 3.0; // pt
+        logits[0] = 10.0; // not a language: ignored
+
+        let candidates = [Language::EnglishUS, Language::PortugueseBR, Language::German];
+        let tokens: Vec<(Language, u32)> = candidates
+            .iter()
+            .filter_map(|&l| tokenizer.language_token(l).map(|t| (l, t)))
+            .collect();
+        let guess = best_language(&logits, &tokens).unwrap();
+        assert_eq!(guess.language, Language::PortugueseBR);
+        // softmax([1, 3]) = 0.88
+        assert!((guess.probability - 0.8808).abs() < 1e-3);
+        assert_eq!(best_language(&logits, &[]), None);
+    }
+
+    #[test]
+    fn test_top_k_skips_forbidden() {
+        let log_probs = log_softmax(&[1.0, f32::NEG_INFINITY, 3.0, 2.0]);
+        let best = top_k(&log_probs, 2);
+        assert_eq!(best.iter().map(|b| b.0).collect::<Vec<_>>(), vec![2, 3]);
+        assert!((log_probs.iter().filter(|p| p.is_finite()).map(|p| p.exp()).sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+}